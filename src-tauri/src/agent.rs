@@ -0,0 +1,464 @@
+use crate::{ai, context_window, db, prompt_cache, usage, AIConfig, MCPServerInfo, MCPTool, Message};
+use chrono::Utc;
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+// 默认最大步数（一次模型调用 + 工具执行为一步）
+pub const DEFAULT_AGENT_MAX_STEPS: u32 = 10;
+
+// 一次流式补全的结果
+pub struct StreamedCompletion {
+    pub content: String,
+    pub reasoning: String,
+    pub tool_calls: Vec<ai::ToolCall>,
+    pub finish_reason: Option<String>,
+    pub usage: Option<usage::TokenUsage>,
+}
+
+// Agent 运行参数
+pub struct AgentRunContext {
+    pub app: AppHandle,
+    pub db_path: PathBuf,
+    pub ai_config: AIConfig,
+    pub chat_id: String,
+    pub event_id: String,
+    pub servers: Vec<MCPServerInfo>,
+    pub max_steps: u32,
+    pub current_resource_id: Option<String>,
+    pub current_task_id: Option<String>,
+}
+
+impl AgentRunContext {
+    fn event_name(&self) -> String {
+        format!("ai-chat-stream-{}", self.event_id)
+    }
+
+    // 发送结构化事件（自动附加 event_id）
+    fn emit(&self, mut payload: Value) {
+        if let Some(obj) = payload.as_object_mut() {
+            obj.insert("event_id".to_string(), json!(self.event_id));
+        }
+        if let Err(e) = self.app.emit(&self.event_name(), &payload) {
+            eprintln!("[Agent] 发送事件失败: {}", e);
+        }
+    }
+
+    // 收集可用工具（仅包含已启用且已连接的服务器），返回 (工具列表, 工具名 -> (服务器键名, input_schema))
+    fn collect_tools(&self) -> (Vec<MCPTool>, BTreeMap<String, (String, Value)>) {
+        let mut tools = Vec::new();
+        let mut tool_servers = BTreeMap::new();
+        for server in &self.servers {
+            let enabled = server.config.enabled.unwrap_or(true);
+            if !enabled || server.status != "connected" {
+                continue;
+            }
+            let server_key = server.key.clone().unwrap_or_else(|| server.name.clone());
+            for tool in server.tools.iter().flatten() {
+                // 同名工具以先出现的服务器为准
+                if !tool_servers.contains_key(&tool.name) {
+                    tool_servers.insert(tool.name.clone(), (server_key.clone(), tool.input_schema.clone()));
+                    tools.push(tool.clone());
+                }
+            }
+        }
+        (tools, tool_servers)
+    }
+}
+
+// 将数据库中的消息转换为发送给模型的消息（忽略 reasoning）
+pub fn history_to_chat_messages(messages: &[Message]) -> Vec<ai::ChatMessage> {
+    messages
+        .iter()
+        .map(|m| ai::ChatMessage {
+            role: m.role.clone(),
+            content: Some(m.content.clone()),
+            tool_calls: m
+                .tool_calls
+                .as_deref()
+                .and_then(|json| serde_json::from_str::<Vec<ai::ToolCall>>(json).ok())
+                .filter(|calls| !calls.is_empty()),
+            tool_call_id: m.tool_call_id.clone(),
+            name: m.name.clone(),
+            cache_control: None,
+        })
+        .collect()
+}
+
+// 保存消息到数据库，并更新 chat 的 updated_at
+#[allow(clippy::too_many_arguments)]
+async fn persist_message(
+    db_path: &Path,
+    chat_id: &str,
+    role: &str,
+    content: String,
+    tool_calls: Option<String>,
+    tool_call_id: Option<String>,
+    name: Option<String>,
+    reasoning: Option<String>,
+) -> Result<Message, String> {
+    let db_path = db_path.to_path_buf();
+    let mut message = Message {
+        id: Uuid::new_v4().to_string(),
+        chat_id: chat_id.to_string(),
+        role: role.to_string(),
+        content,
+        tool_calls,
+        tool_call_id,
+        name,
+        reasoning,
+        created_at: Utc::now().to_rfc3339(),
+        parent_message_id: None,
+    };
+
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+
+        // 追加到当前激活分支末尾
+        db::append_message(&conn, &mut message)
+            .map_err(|e| format!("无法保存 message: {}", e))?;
+
+        if let Ok(Some(mut chat)) = db::get_chat(&conn, &message.chat_id) {
+            chat.updated_at = message.created_at.clone();
+            let _ = db::update_chat(&conn, &chat);
+        }
+
+        Ok(message)
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 发送一次流式补全请求，边接收边发送 content/reasoning 事件，返回完整结果
+async fn stream_completion(
+    ctx: &AgentRunContext,
+    step: u32,
+    messages: &[ai::ChatMessage],
+    tools: &[ai::ToolDefinition],
+) -> Result<StreamedCompletion, String> {
+    let openai_tools = if tools.is_empty() { None } else { Some(tools.to_vec()) };
+
+    let request = ai::ChatCompletionRequest {
+        model: ctx.ai_config.model.clone(),
+        messages: messages.to_vec(),
+        tools: openai_tools,
+        tool_choice: if tools.is_empty() { None } else { Some("auto".to_string()) },
+        stream: true,
+        stream_options: ai::StreamOptions::with_usage(),
+        temperature: Some(0.7),
+    };
+
+    let url = ai::build_chat_url(&ctx.ai_config.base_url);
+    let client = reqwest::Client::new();
+    let response = client
+        .post(&url)
+        .header("Authorization", format!("Bearer {}", ctx.ai_config.api_key))
+        .header("Content-Type", "application/json")
+        .header("Accept", "text/event-stream")
+        .json(&request)
+        .send()
+        .await
+        .map_err(|e| format!("发送请求失败: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("AI API 返回错误: {} - {}\n使用的 URL: {}", status, error_text, url));
+    }
+
+    let mut completion = StreamedCompletion {
+        content: String::new(),
+        reasoning: String::new(),
+        tool_calls: Vec::new(),
+        finish_reason: None,
+        usage: None,
+    };
+    // index -> (id, name, arguments)
+    let mut pending_tool_calls: BTreeMap<u32, (String, String, String)> = BTreeMap::new();
+
+    let mut stream = response.bytes_stream();
+    let mut sse = ai::SseLineBuffer::default();
+    let mut finished = false;
+
+    while !finished {
+        match stream.next().await {
+            Some(Ok(chunk)) => sse.push(chunk.as_ref()),
+            Some(Err(e)) => return Err(format!("读取数据块失败: {}", e)),
+            None => {
+                sse.finish();
+                finished = true;
+            }
+        }
+
+        // 按行处理 SSE 数据
+        while let Some(line) = sse.next_line() {
+
+            let data = match line.strip_prefix("data:") {
+                Some(data) => data.trim(),
+                None => continue,
+            };
+
+            if data == "[DONE]" {
+                finished = true;
+                break;
+            }
+
+            let chunk_data = match serde_json::from_str::<ai::ChatCompletionChunk>(data) {
+                Ok(chunk_data) => chunk_data,
+                Err(e) => {
+                    eprintln!("[Agent] JSON 解析失败: {}, 原始数据: {}", e, data);
+                    continue;
+                }
+            };
+
+            // usage 通常在最后一个 choices 为空的块中返回
+            if let Some(token_usage) = chunk_data.usage.as_ref().and_then(usage::parse_usage) {
+                completion.usage = Some(token_usage);
+            }
+
+            let choice = match chunk_data.choices.first() {
+                Some(choice) => choice,
+                None => continue,
+            };
+            let delta = &choice.delta;
+
+            if let Some(content) = delta.content.as_ref().filter(|c| !c.is_empty()) {
+                completion.content.push_str(content);
+                ctx.emit(json!({ "type": "content", "step": step, "content": content }));
+            }
+
+            if let Some(reasoning) = delta.reasoning.as_ref().filter(|r| !r.is_empty()) {
+                completion.reasoning.push_str(reasoning);
+                ctx.emit(json!({ "type": "reasoning", "step": step, "content": reasoning }));
+            }
+
+            // 按 index 拼接工具调用增量
+            if let Some(tool_calls) = &delta.tool_calls {
+                for tool_call_chunk in tool_calls {
+                    let index = tool_call_chunk.index.unwrap_or(0);
+                    let entry = pending_tool_calls
+                        .entry(index)
+                        .or_insert_with(|| (String::new(), String::new(), String::new()));
+                    if let Some(id) = &tool_call_chunk.id {
+                        entry.0 = id.clone();
+                    }
+                    if let Some(function) = &tool_call_chunk.function {
+                        if let Some(name) = &function.name {
+                            entry.1 = name.clone();
+                        }
+                        if let Some(args) = &function.arguments {
+                            entry.2.push_str(args);
+                        }
+                    }
+                }
+            }
+
+            if let Some(finish_reason) = &choice.finish_reason {
+                completion.finish_reason = Some(finish_reason.clone());
+            }
+        }
+    }
+
+    completion.tool_calls = pending_tool_calls
+        .into_values()
+        .filter(|(_, name, _)| !name.is_empty())
+        .map(|(id, name, arguments)| ai::ToolCall {
+            id: if id.is_empty() { format!("call_{}", Uuid::new_v4().simple()) } else { id },
+            call_type: "function".to_string(),
+            function: ai::FunctionCall {
+                name,
+                arguments: if arguments.trim().is_empty() { "{}".to_string() } else { arguments },
+            },
+        })
+        .collect();
+
+    Ok(completion)
+}
+
+// 执行单个工具调用，返回工具消息内容（失败时以错误对象返回给模型）
+async fn execute_tool_call(
+    ctx: &AgentRunContext,
+    tool_servers: &BTreeMap<String, (String, Value)>,
+    tool_call: &ai::ToolCall,
+) -> (String, bool) {
+    let (server_name, input_schema) = match tool_servers.get(&tool_call.function.name) {
+        Some(entry) => entry,
+        None => {
+            let error = format!("找不到工具 {} 对应的服务器", tool_call.function.name);
+            return (json!({ "error": error }).to_string(), true);
+        }
+    };
+
+    let arguments: Value = match serde_json::from_str(&tool_call.function.arguments) {
+        Ok(arguments) => arguments,
+        Err(e) => {
+            let error = format!("工具参数不是合法的 JSON: {}", e);
+            return (json!({ "error": error }).to_string(), true);
+        }
+    };
+
+    match crate::call_mcp_tool_with_policy(
+        &ctx.app,
+        server_name,
+        &tool_call.function.name,
+        arguments,
+        Some(input_schema.clone()),
+        ctx.current_resource_id.clone(),
+        ctx.current_task_id.clone(),
+    )
+    .await
+    {
+        Ok(result) => {
+            // 参数校验失败等情况会以 isError 结果返回
+            let is_error = result.get("isError").and_then(|v| v.as_bool()).unwrap_or(false);
+            (serde_json::to_string(&result).unwrap_or_default(), is_error)
+        }
+        Err(e) => (json!({ "error": e }).to_string(), true),
+    }
+}
+
+// 运行多步 Agent 循环：流式补全 -> 并行执行工具 -> 追加工具消息 -> 重复，直到模型不再调用工具或达到最大步数
+pub async fn run_agent_loop(ctx: AgentRunContext, mut messages: Vec<ai::ChatMessage>) -> Result<(), String> {
+    let (tools, tool_servers) = ctx.collect_tools();
+    let tool_definitions: Vec<ai::ToolDefinition> = tools.iter().map(ai::mcp_tool_to_openai_tool).collect();
+
+    for step in 1..=ctx.max_steps {
+        ctx.emit(json!({ "type": "step_start", "step": step }));
+
+        // 每一步都重新裁剪上下文（完整的消息列表仍保留在 messages 中）
+        let (mut request_messages, fit_report) = context_window::fit_messages(
+            &context_window::ContextFitOptions {
+                db_path: ctx.db_path.clone(),
+                ai_config: &ctx.ai_config,
+                chat_id: Some(ctx.chat_id.clone()),
+                tools: if tool_definitions.is_empty() { None } else { Some(&tool_definitions) },
+            },
+            messages.clone(),
+        )
+        .await;
+        if fit_report.changed() {
+            ctx.emit(json!({ "type": "context_trimmed", "step": step, "report": fit_report }));
+        }
+
+        // 按提供商放置 prompt 缓存断点
+        let mut request_tools = tool_definitions.clone();
+        let cache_plan = prompt_cache::apply_breakpoints(&ctx.ai_config, &mut request_messages, &mut request_tools);
+        if !cache_plan.breakpoints.is_empty() {
+            ctx.emit(json!({ "type": "cache_plan", "step": step, "plan": cache_plan }));
+        }
+        let completion = stream_completion(&ctx, step, &request_messages, &request_tools).await?;
+
+        // 保存 assistant 消息
+        let tool_calls_json = if completion.tool_calls.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(&completion.tool_calls).unwrap_or_default())
+        };
+        let assistant_message = persist_message(
+            &ctx.db_path,
+            &ctx.chat_id,
+            "assistant",
+            completion.content.clone(),
+            tool_calls_json,
+            None,
+            None,
+            if completion.reasoning.is_empty() { None } else { Some(completion.reasoning.clone()) },
+        )
+        .await?;
+
+        if let Some(token_usage) = completion.usage {
+            ctx.emit(json!({ "type": "usage", "step": step, "usage": token_usage }));
+            let scope = usage::UsageScope {
+                chat_id: Some(ctx.chat_id.clone()),
+                message_id: Some(assistant_message.id.clone()),
+                task_id: ctx.current_task_id.clone(),
+                resource_id: ctx.current_resource_id.clone(),
+                event_id: None,
+            };
+            usage::record(ctx.db_path.clone(), &ctx.ai_config, usage::SOURCE_AGENT, scope, token_usage).await;
+        }
+
+        messages.push(ai::ChatMessage {
+            role: "assistant".to_string(),
+            content: Some(completion.content.clone()),
+            tool_calls: if completion.tool_calls.is_empty() { None } else { Some(completion.tool_calls.clone()) },
+            tool_call_id: None,
+            name: None,
+            cache_control: None,
+        });
+
+        if completion.tool_calls.is_empty() {
+            ctx.emit(json!({
+                "type": "step_end",
+                "step": step,
+                "message_id": assistant_message.id,
+                "finish_reason": completion.finish_reason,
+            }));
+            ctx.emit(json!({ "type": "done", "steps": step, "reason": "completed" }));
+            return Ok(());
+        }
+
+        ctx.emit(json!({
+            "type": "tool_calls",
+            "step": step,
+            "message_id": assistant_message.id,
+            "tool_calls": completion.tool_calls,
+        }));
+
+        // 并行执行所有工具调用（结果顺序与调用顺序一致）
+        let results = futures_util::future::join_all(
+            completion
+                .tool_calls
+                .iter()
+                .map(|tool_call| execute_tool_call(&ctx, &tool_servers, tool_call)),
+        )
+        .await;
+
+        for (tool_call, (content, is_error)) in completion.tool_calls.iter().zip(results) {
+            let tool_message = persist_message(
+                &ctx.db_path,
+                &ctx.chat_id,
+                "tool",
+                content.clone(),
+                None,
+                Some(tool_call.id.clone()),
+                Some(tool_call.function.name.clone()),
+                None,
+            )
+            .await?;
+
+            ctx.emit(json!({
+                "type": "tool_result",
+                "step": step,
+                "message_id": tool_message.id,
+                "tool_call_id": tool_call.id,
+                "name": tool_call.function.name,
+                "content": content,
+                "is_error": is_error,
+            }));
+
+            messages.push(ai::ChatMessage {
+                role: "tool".to_string(),
+                content: Some(content),
+                tool_calls: None,
+                tool_call_id: Some(tool_call.id.clone()),
+                name: Some(tool_call.function.name.clone()),
+                cache_control: None,
+            });
+        }
+
+        ctx.emit(json!({
+            "type": "step_end",
+            "step": step,
+            "message_id": assistant_message.id,
+            "finish_reason": completion.finish_reason,
+        }));
+    }
+
+    ctx.emit(json!({ "type": "done", "steps": ctx.max_steps, "reason": "max_steps" }));
+    Ok(())
+}
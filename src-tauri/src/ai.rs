@@ -7,8 +7,17 @@ pub struct CacheControl {
     pub cache_type: String, // "ephemeral"
 }
 
+impl CacheControl {
+    pub fn ephemeral() -> Self {
+        Self {
+            cache_type: "ephemeral".to_string(),
+        }
+    }
+}
+
 // OpenAI 兼容的消息格式
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(into = "WireChatMessage")]
 pub struct ChatMessage {
     pub role: String, // "system" | "user" | "assistant" | "tool"
     pub content: Option<String>,
@@ -22,6 +31,55 @@ pub struct ChatMessage {
     pub cache_control: Option<CacheControl>,
 }
 
+// 消息内容片段（带缓存断点时 content 需要以片段数组的形式发送）
+#[derive(Debug, Serialize)]
+struct ContentPart {
+    #[serde(rename = "type")]
+    part_type: &'static str, // "text"
+    text: String,
+    cache_control: CacheControl,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum WireContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+// 实际发送的消息格式：cache_control 放在内容片段上，而不是消息上
+#[derive(Debug, Serialize)]
+struct WireChatMessage {
+    role: String,
+    content: Option<WireContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<ToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+impl From<ChatMessage> for WireChatMessage {
+    fn from(message: ChatMessage) -> Self {
+        let content = match (message.content, message.cache_control) {
+            (Some(text), Some(cache_control)) if !text.is_empty() => Some(WireContent::Parts(vec![ContentPart {
+                part_type: "text",
+                text,
+                cache_control,
+            }])),
+            (content, _) => content.map(WireContent::Text),
+        };
+        WireChatMessage {
+            role: message.role,
+            content,
+            tool_calls: message.tool_calls,
+            tool_call_id: message.tool_call_id,
+            name: message.name,
+        }
+    }
+}
+
 // 工具调用
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ToolCall {
@@ -44,6 +102,8 @@ pub struct ToolDefinition {
     #[serde(rename = "type")]
     pub tool_type: String, // "function"
     pub function: FunctionDefinition,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
 }
 
 // 函数定义
@@ -65,9 +125,23 @@ pub struct ChatCompletionRequest {
     pub tool_choice: Option<String>, // "auto" | "none" | {"type": "function", "function": {"name": "..."}}
     pub stream: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f64>,
 }
 
+// 流式请求选项（include_usage 让服务端在最后一个块中返回 usage）
+#[derive(Debug, Serialize)]
+pub struct StreamOptions {
+    pub include_usage: bool,
+}
+
+impl StreamOptions {
+    pub fn with_usage() -> Option<Self> {
+        Some(Self { include_usage: true })
+    }
+}
+
 // Chat completion 流式响应块
 #[derive(Debug, Deserialize)]
 pub struct ChatCompletionChunk {
@@ -77,6 +151,8 @@ pub struct ChatCompletionChunk {
     #[serde(default)]
     #[allow(dead_code)]
     pub created: Option<u64>,
+    #[serde(default)]
+    pub usage: Option<serde_json::Value>, // 仅最后一个块包含（需要 stream_options.include_usage）
 }
 
 // 选择块
@@ -136,6 +212,8 @@ pub struct ChatCompletionResponse {
     #[serde(default)]
     #[allow(dead_code)]
     pub created: Option<u64>,
+    #[serde(default)]
+    pub usage: Option<serde_json::Value>,
 }
 
 // 选择响应
@@ -168,6 +246,7 @@ pub fn mcp_tool_to_openai_tool(mcp_tool: &crate::MCPTool) -> ToolDefinition {
             description: mcp_tool.description.clone(),
             parameters: mcp_tool.input_schema.clone(),
         },
+        cache_control: None,
     }
 }
 
@@ -189,22 +268,187 @@ pub fn build_chat_url(base_url: &str) -> String {
     format!("{}/v1/chat/completions", base)
 }
 
-// 应用 cache control 到消息列表
-// 根据 OpenRouter 最佳实践，为工具调用结果添加 ephemeral 缓存标记
-// 这样可以优化缓存使用，特别是对于像 "get task info" 这样的大型工具响应
-// 
-// 注意：此函数是幂等的，即使消息已经有 cache_control 也会被覆盖
-// 这确保了从数据库加载的历史消息也能正确应用缓存控制
-pub fn apply_cache_control(messages: &mut Vec<ChatMessage>) {
-    for msg in messages.iter_mut() {
-        // 为 tool 角色的消息添加 ephemeral 缓存控制
-        // 工具结果通常是短暂的上下文信息，适合使用 ephemeral 缓存
-        // 即使消息来自数据库（没有 cache_control），也会在这里自动添加
-        if msg.role == "tool" {
-            msg.cache_control = Some(CacheControl {
-                cache_type: "ephemeral".to_string(),
-            });
+// 非流式请求的结果
+pub struct CompletionOutput {
+    pub content: String,
+    pub usage: Option<crate::usage::TokenUsage>,
+}
+
+// 发送一次非流式请求，返回文本内容和 token 用量
+pub async fn complete_once(
+    ai_config: &crate::AIConfig,
+    messages: Vec<ChatMessage>,
+    temperature: Option<f64>,
+) -> Result<CompletionOutput, String> {
+    let request = ChatCompletionRequest {
+        model: ai_config.model.clone(),
+        messages,
+        tools: None,
+        tool_choice: None,
+        stream: false,
+        stream_options: None,
+        temperature,
+    };
+
+    let response = reqwest::Client::new()
+        .post(build_chat_url(&ai_config.base_url))
+        .header("Authorization", format!("Bearer {}", ai_config.api_key))
+        .header("Content-Type", "application/json")
+        .json(&request)
+        .send()
+        .await
+        .map_err(|e| format!("发送请求失败: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("AI API 返回错误: {} - {}", status, error_text));
+    }
+
+    let completion_response: ChatCompletionResponse = response
+        .json()
+        .await
+        .map_err(|e| format!("解析响应失败: {}", e))?;
+
+    let content = completion_response
+        .choices
+        .first()
+        .and_then(|choice| choice.message.content.as_ref())
+        .map(|content| content.trim().to_string())
+        .ok_or_else(|| "AI 响应中没有内容".to_string())?;
+
+    Ok(CompletionOutput {
+        content,
+        usage: completion_response.usage.as_ref().and_then(crate::usage::parse_usage),
+    })
+}
+
+// 流式请求被取消时返回的错误
+pub const STREAM_CANCELLED: &str = "请求已取消";
+
+// SSE 行缓冲：按字节累积数据块，只解码完整的行，避免多字节字符被拆到两个数据块时损坏
+#[derive(Default)]
+pub struct SseLineBuffer {
+    buffer: Vec<u8>,
+}
+
+impl SseLineBuffer {
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    // 流结束时补上换行，以便处理没有换行符的最后一行
+    pub fn finish(&mut self) {
+        if !self.buffer.is_empty() {
+            self.buffer.push(b'\n');
         }
     }
+
+    // 取出下一条完整的行（已去除首尾空白），没有完整的行时返回 None
+    pub fn next_line(&mut self) -> Option<String> {
+        let newline_pos = self.buffer.iter().position(|b| *b == b'\n')?;
+        let line_bytes: Vec<u8> = self.buffer.drain(..=newline_pos).collect();
+        Some(String::from_utf8_lossy(&line_bytes).trim().to_string())
+    }
 }
 
+// 发送一次流式请求，每收到一段内容调用 on_delta，返回完整文本和 token 用量
+// cancel 被通知时立即中断 HTTP 流并返回 STREAM_CANCELLED
+pub async fn stream_once(
+    ai_config: &crate::AIConfig,
+    messages: Vec<ChatMessage>,
+    temperature: Option<f64>,
+    cancel: Option<std::sync::Arc<tokio::sync::Notify>>,
+    mut on_delta: impl FnMut(&str),
+) -> Result<CompletionOutput, String> {
+    use futures_util::StreamExt;
+
+    let cancel = cancel.unwrap_or_default();
+    let request = ChatCompletionRequest {
+        model: ai_config.model.clone(),
+        messages,
+        tools: None,
+        tool_choice: None,
+        stream: true,
+        stream_options: StreamOptions::with_usage(),
+        temperature,
+    };
+
+    let send = reqwest::Client::new()
+        .post(build_chat_url(&ai_config.base_url))
+        .header("Authorization", format!("Bearer {}", ai_config.api_key))
+        .header("Content-Type", "application/json")
+        .header("Accept", "text/event-stream")
+        .json(&request)
+        .send();
+    let response = tokio::select! {
+        result = send => result.map_err(|e| format!("发送请求失败: {}", e))?,
+        _ = cancel.notified() => return Err(STREAM_CANCELLED.to_string()),
+    };
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("AI API 返回错误: {} - {}", status, error_text));
+    }
+
+    let mut content = String::new();
+    let mut usage = None;
+    let mut stream = response.bytes_stream();
+    let mut sse = SseLineBuffer::default();
+    let mut finished = false;
+
+    while !finished {
+        let next = tokio::select! {
+            next = stream.next() => next,
+            _ = cancel.notified() => return Err(STREAM_CANCELLED.to_string()),
+        };
+        match next {
+            Some(Ok(chunk)) => sse.push(chunk.as_ref()),
+            Some(Err(e)) => return Err(format!("读取数据块失败: {}", e)),
+            None => {
+                sse.finish();
+                finished = true;
+            }
+        }
+
+        // 按行处理 SSE 数据
+        while let Some(line) = sse.next_line() {
+
+            let data = match line.strip_prefix("data:") {
+                Some(data) => data.trim(),
+                None => continue,
+            };
+            if data == "[DONE]" {
+                finished = true;
+                break;
+            }
+
+            let chunk_data = match serde_json::from_str::<ChatCompletionChunk>(data) {
+                Ok(chunk_data) => chunk_data,
+                Err(e) => {
+                    eprintln!("[AI Stream] JSON 解析失败: {}, 原始数据: {}", e, data);
+                    continue;
+                }
+            };
+            if let Some(token_usage) = chunk_data.usage.as_ref().and_then(crate::usage::parse_usage) {
+                usage = Some(token_usage);
+            }
+            if let Some(delta) = chunk_data
+                .choices
+                .first()
+                .and_then(|choice| choice.delta.content.as_deref())
+                .filter(|delta| !delta.is_empty())
+            {
+                content.push_str(delta);
+                on_delta(delta);
+            }
+        }
+    }
+
+    let content = content.trim().to_string();
+    if content.is_empty() {
+        return Err("AI 响应中没有内容".to_string());
+    }
+    Ok(CompletionOutput { content, usage })
+}
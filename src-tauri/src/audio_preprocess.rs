@@ -0,0 +1,271 @@
+use crate::{seconds_to_timestamp, spawn_stream_reader, AudioPreprocessOptions, RunningTasks};
+use serde_json::Value;
+use std::path::Path;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+// 静音检测阈值和最短时长
+const SILENCE_THRESHOLD: &str = "-50dB";
+const SILENCE_MIN_DURATION: f64 = 0.5;
+// 裁剪静音时在语音前后保留的时长（秒），避免切掉开头和结尾的弱音
+const SILENCE_PADDING: f64 = 0.2;
+
+// 降噪和响度标准化参数
+const NOISE_REDUCTION_FILTER: &str = "afftdn=nf=-25";
+const LOUDNORM_FILTER: &str = "loudnorm=I=-16:TP=-1.5:LRA=11";
+// whisper 使用 16kHz 单声道 16-bit PCM
+const OUTPUT_FORMAT_FILTER: &str = "aresample=16000,aformat=sample_fmts=s16:channel_layouts=mono";
+
+fn trim_filter(start: Option<f64>, end: Option<f64>) -> Option<String> {
+    let mut args = Vec::new();
+    if let Some(start) = start.filter(|s| *s > 0.0) {
+        args.push(format!("start={:.3}", start));
+    }
+    if let Some(end) = end {
+        args.push(format!("end={:.3}", end));
+    }
+    if args.is_empty() {
+        None
+    } else {
+        Some(format!("atrim={},asetpts=PTS-STARTPTS", args.join(":")))
+    }
+}
+
+// 根据选项构建 ffmpeg 滤镜链（start / end 为最终裁剪范围，已包含静音裁剪）
+fn build_filter_chain(options: &AudioPreprocessOptions, start: Option<f64>, end: Option<f64>) -> String {
+    let mut filters = Vec::new();
+    if let Some(trim) = trim_filter(start, end) {
+        filters.push(trim);
+    }
+    if let Some(hz) = options.highpass_hz.filter(|hz| *hz > 0) {
+        filters.push(format!("highpass=f={}", hz));
+    }
+    if options.noise_reduction {
+        filters.push(NOISE_REDUCTION_FILTER.to_string());
+    }
+    if options.loudness_normalization {
+        filters.push(LOUDNORM_FILTER.to_string());
+    }
+    filters.push(OUTPUT_FORMAT_FILTER.to_string());
+    filters.join(",")
+}
+
+fn parse_after(line: &str, key: &str) -> Option<f64> {
+    let start = line.find(key)? + key.len();
+    line[start..].split_whitespace().next()?.parse().ok()
+}
+
+// ffmpeg 进度输出中的时间（time=HH:MM:SS.xx）
+fn parse_progress_time(line: &str) -> Option<f64> {
+    let start = line.rfind("time=")? + "time=".len();
+    let value = line[start..].split_whitespace().next()?;
+    let parts: Vec<f64> = value.split(':').filter_map(|p| p.parse().ok()).collect();
+    match parts.as_slice() {
+        [h, m, s] => Some(h * 3600.0 + m * 60.0 + s),
+        _ => None,
+    }
+}
+
+// 检测裁剪范围内开头和结尾的静音，返回语音的 (开始, 结束) 时间（相对裁剪范围）
+async fn detect_speech_bounds(
+    ffmpeg_path: &Path,
+    input: &Path,
+    start: Option<f64>,
+    end: Option<f64>,
+) -> Result<(Option<f64>, Option<f64>), String> {
+    let mut filters = Vec::new();
+    if let Some(trim) = trim_filter(start, end) {
+        filters.push(trim);
+    }
+    filters.push(format!("silencedetect=n={}:d={}", SILENCE_THRESHOLD, SILENCE_MIN_DURATION));
+
+    let output = tokio::process::Command::new(ffmpeg_path)
+        .arg("-hide_banner")
+        .arg("-stats")
+        .arg("-i")
+        .arg(input)
+        .arg("-af")
+        .arg(filters.join(","))
+        .arg("-f")
+        .arg("null")
+        .arg("-")
+        .output()
+        .await
+        .map_err(|e| format!("无法执行 ffmpeg: {}。请确保工具已正确安装。", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("静音检测失败: {}", stderr.trim()));
+    }
+
+    // 静音区间：(开始, 结束)，结尾的静音可能没有 silence_end
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut silences: Vec<(f64, Option<f64>)> = Vec::new();
+    let mut total: Option<f64> = None;
+    for line in stderr.split(['\n', '\r']) {
+        if let Some(silence_start) = parse_after(line, "silence_start: ") {
+            silences.push((silence_start, None));
+        } else if let Some(silence_end) = parse_after(line, "silence_end: ") {
+            if let Some(last) = silences.last_mut() {
+                last.1 = Some(silence_end);
+            }
+        }
+        if let Some(time) = parse_progress_time(line) {
+            total = Some(time);
+        }
+    }
+
+    let speech_start = silences
+        .first()
+        .filter(|(silence_start, _)| *silence_start <= 0.05)
+        .and_then(|(_, silence_end)| *silence_end)
+        .map(|silence_end| (silence_end - SILENCE_PADDING).max(0.0));
+    let speech_end = silences
+        .last()
+        .filter(|(_, silence_end)| match (silence_end, total) {
+            (None, _) => true,
+            (Some(silence_end), Some(total)) => *silence_end >= total - 0.05,
+            _ => false,
+        })
+        .map(|(silence_start, _)| silence_start + SILENCE_PADDING);
+
+    // 整段都是静音时不裁剪
+    match (speech_start, speech_end) {
+        (Some(s), Some(e)) if e <= s => Ok((None, None)),
+        bounds => Ok(bounds),
+    }
+}
+
+// 执行预处理，返回实际使用的选项（filter_chain、time_offset 和 resolved_end_time 已填写）
+// 已执行过的任务直接使用记录的裁剪范围，不再检测静音，保证重新执行任务时结果可复现
+// 滤镜链始终由 build_filter_chain 根据选项生成，不使用保存的 filter_chain 字符串
+// ffmpeg 进程存储在 RunningTasks 中，stop_transcription_task 可以停止预处理
+pub async fn run(
+    ffmpeg_path: &Path,
+    input: &Path,
+    output: &Path,
+    options: &AudioPreprocessOptions,
+    task_id: &str,
+    app: &AppHandle,
+) -> Result<AudioPreprocessOptions, String> {
+    let stdout_event_name = format!("transcription-stdout-{}", task_id);
+    let stderr_event_name = format!("transcription-stderr-{}", task_id);
+
+    let mut resolved = options.clone();
+    let (start, end) = if options.filter_chain.is_some() {
+        (options.time_offset, options.resolved_end_time)
+    } else {
+        let mut start = options.start_time.filter(|s| *s > 0.0);
+        let mut end = options.end_time;
+        if let (Some(s), Some(e)) = (start, end) {
+            if e <= s {
+                return Err("裁剪结束时间必须晚于开始时间".to_string());
+            }
+        }
+
+        if options.trim_silence {
+            let _ = app.emit(&stdout_event_name, "正在检测开头和结尾的静音...\n");
+            let (speech_start, speech_end) = detect_speech_bounds(ffmpeg_path, input, start, end).await?;
+            let base = start.unwrap_or(0.0);
+            if let Some(speech_end) = speech_end {
+                end = Some(base + speech_end);
+            }
+            if let Some(speech_start) = speech_start {
+                start = Some(base + speech_start);
+            }
+        }
+        (start, end)
+    };
+
+    let filter_chain = build_filter_chain(options, start, end);
+    resolved.filter_chain = Some(filter_chain.clone());
+    resolved.time_offset = start.filter(|s| *s > 0.0);
+    resolved.resolved_end_time = end;
+
+    let _ = app.emit(&stdout_event_name, &format!("正在预处理音频，滤镜链: {}\n", filter_chain));
+
+    let mut cmd = tokio::process::Command::new(ffmpeg_path);
+    cmd.arg("-hide_banner")
+        .arg("-y")
+        .arg("-i")
+        .arg(input)
+        .arg("-vn")
+        .arg("-af")
+        .arg(&filter_chain)
+        .arg("-acodec")
+        .arg("pcm_s16le")
+        .arg(output);
+    cmd.stdout(std::process::Stdio::piped());
+    cmd.stderr(std::process::Stdio::piped());
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("无法执行 ffmpeg: {}。请确保工具已正确安装。", e))?;
+    let stdout = child.stdout.take().ok_or("无法获取 stdout 句柄")?;
+    let stderr = child.stderr.take().ok_or("无法获取 stderr 句柄")?;
+
+    let running_tasks: State<'_, RunningTasks> = app.state();
+    running_tasks.insert(task_id.to_string(), child).await;
+
+    let stdout_handle = spawn_stream_reader(stdout, app.clone(), stdout_event_name, "stdout", false);
+    let stderr_handle = spawn_stream_reader(stderr, app.clone(), stderr_event_name, "stderr", false);
+
+    // 定期检查进程状态，child 不在 RunningTasks 中说明任务已被停止
+    let status = loop {
+        if let Some(child_arc) = running_tasks.get(task_id).await {
+            let mut child_guard = child_arc.lock().await;
+            if let Ok(Some(exit_status)) = child_guard.try_wait() {
+                let _ = running_tasks.remove(task_id).await;
+                break Some(exit_status);
+            }
+            drop(child_guard);
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        } else {
+            break None;
+        }
+    };
+
+    let _ = stdout_handle.await;
+    let stderr_output = stderr_handle.await.unwrap_or_default();
+
+    let status = match status {
+        Some(status) => status,
+        None => {
+            let _ = std::fs::remove_file(output);
+            return Err("任务已被用户停止".to_string());
+        }
+    };
+
+    if !status.success() || !output.exists() {
+        let detail = stderr_output.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+        return Err(if detail.is_empty() {
+            format!("音频预处理失败，退出码: {:?}", status.code())
+        } else {
+            format!("音频预处理失败: {}", detail)
+        });
+    }
+
+    Ok(resolved)
+}
+
+// 裁剪后的音频时间轴从 0 开始，将 whisper 输出的时间加上偏移量还原为原始音频的时间
+// whisper-cli 输出的 offsets 单位为毫秒
+pub fn shift_transcription_json(json_content: &str, offset_seconds: f64) -> Result<String, String> {
+    let mut value: Value = serde_json::from_str(json_content)
+        .map_err(|e| format!("无法解析转写结果: {}", e))?;
+    let offset_ms = offset_seconds * 1000.0;
+
+    if let Some(segments) = value.get_mut("transcription").and_then(|t| t.as_array_mut()) {
+        for segment in segments {
+            for key in ["from", "to"] {
+                let Some(ms) = segment.pointer(&format!("/offsets/{}", key)).and_then(|v| v.as_f64()) else {
+                    continue;
+                };
+                let shifted = (ms + offset_ms).round();
+                segment["offsets"][key] = Value::from(shifted as i64);
+                segment["timestamps"][key] = Value::from(seconds_to_timestamp(shifted / 1000.0));
+            }
+        }
+    }
+
+    serde_json::to_string_pretty(&value).map_err(|e| format!("无法序列化JSON: {}", e))
+}
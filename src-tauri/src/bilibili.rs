@@ -0,0 +1,293 @@
+use crate::{extract_bilibili_video_id, seconds_to_timestamp, subtitle_language_rank};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36";
+const REFERER: &str = "https://www.bilibili.com/";
+
+// 分P信息
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BilibiliPage {
+    pub cid: i64,
+    pub page: u32,
+    pub part: String,  // 分P标题
+    pub duration: f64, // 秒
+}
+
+// 视频元数据
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BilibiliVideoInfo {
+    pub bvid: String,
+    pub aid: i64,
+    pub title: String,
+    pub uploader: Option<String>,
+    pub duration: f64, // 所有分P的总时长（秒）
+    pub cover_url: Option<String>,
+    pub upload_date: Option<String>, // 发布日期（YYYY-MM-DD）
+    pub description: Option<String>,
+    pub pages: Vec<BilibiliPage>,
+}
+
+impl BilibiliVideoInfo {
+    // 按页码获取分P，页码不存在时返回第一个分P
+    pub fn page(&self, page: Option<u32>) -> Option<&BilibiliPage> {
+        let page = page.unwrap_or(1);
+        self.pages.iter().find(|p| p.page == page).or_else(|| self.pages.first())
+    }
+
+    // 资源名称：多P视频附加分P标题
+    pub fn page_title(&self, page: &BilibiliPage) -> String {
+        if self.pages.len() > 1 {
+            format!("{} - P{} {}", self.title, page.page, page.part)
+        } else {
+            self.title.clone()
+        }
+    }
+
+    // 分P的规范 URL
+    pub fn page_url(&self, page: &BilibiliPage) -> String {
+        if self.pages.len() > 1 {
+            format!("https://www.bilibili.com/video/{}?p={}", self.bvid, page.page)
+        } else {
+            format!("https://www.bilibili.com/video/{}", self.bvid)
+        }
+    }
+}
+
+// CC 字幕轨道
+#[derive(Debug, Serialize, Clone)]
+pub struct CcSubtitle {
+    pub language: String, // 如 zh-CN、ai-zh、en-US
+    pub language_name: String,
+    pub url: String,
+    pub is_auto: bool, // AI 生成的字幕
+}
+
+async fn get_json(url: &str) -> Result<Value, String> {
+    let response = reqwest::Client::new()
+        .get(url)
+        .header("User-Agent", USER_AGENT)
+        .header("Referer", REFERER)
+        .send()
+        .await
+        .map_err(|e| format!("请求 Bilibili 失败: {}", e))?;
+    response
+        .json::<Value>()
+        .await
+        .map_err(|e| format!("解析 Bilibili 响应失败: {}", e))
+}
+
+// 检查 API 响应的 code 并返回 data
+fn api_data(value: Value) -> Result<Value, String> {
+    let code = value.get("code").and_then(|c| c.as_i64()).unwrap_or(-1);
+    if code != 0 {
+        let message = value.get("message").and_then(|m| m.as_str()).unwrap_or("未知错误");
+        return Err(format!("Bilibili API 返回错误 ({}): {}", code, message));
+    }
+    value.get("data").cloned().ok_or_else(|| "Bilibili API 响应缺少 data 字段".to_string())
+}
+
+// 解析 b23.tv 短链接，其他 URL 原样返回
+pub async fn resolve_url(url: &str) -> Result<String, String> {
+    if !url.to_lowercase().contains("b23.tv") {
+        return Ok(url.to_string());
+    }
+    let response = reqwest::Client::new()
+        .get(url)
+        .header("User-Agent", USER_AGENT)
+        .send()
+        .await
+        .map_err(|e| format!("无法解析 Bilibili 短链接: {}", e))?;
+    Ok(response.url().to_string())
+}
+
+// 从 URL 中读取分P页码（?p=N）
+pub fn page_number(url: &str) -> Option<u32> {
+    let query = url.split_once('?')?.1;
+    query
+        .split(['&', '#'])
+        .find_map(|pair| pair.strip_prefix("p="))
+        .and_then(|p| p.parse::<u32>().ok())
+        .filter(|p| *p > 0)
+}
+
+// 获取视频元数据（标题、UP 主、时长、封面、分P）
+pub async fn fetch_video_info(url: &str) -> Result<BilibiliVideoInfo, String> {
+    let url = resolve_url(url).await?;
+    let (bvid, aid) = extract_bilibili_video_id(&url)
+        .ok_or_else(|| format!("无法从 URL 中识别 Bilibili 视频: {}", url))?;
+
+    let api_url = if let Some(bv) = bvid {
+        format!("https://api.bilibili.com/x/web-interface/view?bvid={}", bv)
+    } else if let Some(av) = aid {
+        format!("https://api.bilibili.com/x/web-interface/view?aid={}", av)
+    } else {
+        return Err(format!("无法从 URL 中识别 Bilibili 视频: {}", url));
+    };
+
+    let data = api_data(get_json(&api_url).await?)?;
+
+    let pages = data
+        .get("pages")
+        .and_then(|p| p.as_array())
+        .map(|pages| {
+            pages
+                .iter()
+                .filter_map(|p| {
+                    Some(BilibiliPage {
+                        cid: p.get("cid")?.as_i64()?,
+                        page: p.get("page")?.as_u64()? as u32,
+                        part: p.get("part").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+                        duration: p.get("duration").and_then(|v| v.as_f64()).unwrap_or(0.0),
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    if pages.is_empty() {
+        return Err("Bilibili 视频没有可用的分P".to_string());
+    }
+
+    Ok(BilibiliVideoInfo {
+        bvid: data.get("bvid").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+        aid: data.get("aid").and_then(|v| v.as_i64()).unwrap_or_default(),
+        title: data.get("title").and_then(|v| v.as_str()).unwrap_or("Bilibili视频").to_string(),
+        uploader: data
+            .get("owner")
+            .and_then(|o| o.get("name"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string()),
+        duration: data.get("duration").and_then(|v| v.as_f64()).unwrap_or(0.0),
+        cover_url: data.get("pic").and_then(|v| v.as_str()).map(|s| s.to_string()),
+        upload_date: data
+            .get("pubdate")
+            .and_then(|v| v.as_i64())
+            .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+            .map(|dt| dt.format("%Y-%m-%d").to_string()),
+        description: data
+            .get("desc")
+            .and_then(|v| v.as_str())
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty() && s != "-"),
+        pages,
+    })
+}
+
+// 获取分P的 CC 字幕轨道列表
+// 注意：部分视频的字幕需要登录后才能获取，此时返回空列表
+pub async fn fetch_subtitle_tracks(info: &BilibiliVideoInfo, page: &BilibiliPage) -> Result<Vec<CcSubtitle>, String> {
+    let api_url = format!(
+        "https://api.bilibili.com/x/player/v2?bvid={}&cid={}",
+        info.bvid, page.cid
+    );
+    let data = api_data(get_json(&api_url).await?)?;
+
+    let tracks = data
+        .get("subtitle")
+        .and_then(|s| s.get("subtitles"))
+        .and_then(|s| s.as_array())
+        .map(|subtitles| {
+            subtitles
+                .iter()
+                .filter_map(|s| {
+                    let language = s.get("lan")?.as_str()?.to_string();
+                    let url = s.get("subtitle_url")?.as_str()?;
+                    if url.is_empty() {
+                        return None;
+                    }
+                    let url = if url.starts_with("//") { format!("https:{}", url) } else { url.to_string() };
+                    Some(CcSubtitle {
+                        is_auto: language.starts_with("ai-"),
+                        language_name: s.get("lan_doc").and_then(|v| v.as_str()).unwrap_or(&language).to_string(),
+                        language,
+                        url,
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    Ok(tracks)
+}
+
+// 字幕轨道的主语言代码（"ai-zh" -> "zh"，"zh-CN" -> "zh"）
+pub fn track_language(track: &CcSubtitle) -> String {
+    let language = track.language.trim_start_matches("ai-");
+    language.split(['-', '_']).next().unwrap_or(language).to_lowercase()
+}
+
+// 将 Bilibili 字幕 JSON（{"body": [{"from", "to", "content"}]}）转换为转写结果 JSON
+pub fn subtitle_to_transcription_json(subtitle: &Value, language: &str) -> Result<String, String> {
+    let body = subtitle
+        .get("body")
+        .and_then(|b| b.as_array())
+        .ok_or("字幕文件格式无效：缺少 body 字段")?;
+
+    let segments: Vec<Value> = body
+        .iter()
+        .filter_map(|item| {
+            let from = item.get("from")?.as_f64()?;
+            let to = item.get("to")?.as_f64()?;
+            let text = item.get("content")?.as_str()?.trim().replace('\n', " ");
+            if text.is_empty() {
+                return None;
+            }
+            Some(json!({
+                "timestamps": {
+                    "from": seconds_to_timestamp(from),
+                    "to": seconds_to_timestamp(to)
+                },
+                "offsets": {
+                    "from": from,
+                    "to": to
+                },
+                "text": text
+            }))
+        })
+        .collect();
+
+    if segments.is_empty() {
+        return Err("字幕内容为空".to_string());
+    }
+
+    let result = json!({
+        "transcription": segments,
+        "result": {
+            "language": language
+        }
+    });
+
+    serde_json::to_string_pretty(&result).map_err(|e| format!("无法序列化JSON: {}", e))
+}
+
+// 获取 URL 对应分P的 CC 字幕并转换为转写结果 JSON，返回 (字幕, JSON)
+// 按语言偏好排序（同一语言人工字幕优先），用户指定语言时只保留匹配的字幕
+pub async fn fetch_transcription_tracks(
+    url: &str,
+    preferences: &[String],
+    user_specified: bool,
+    allow_auto: bool,
+) -> Result<Vec<(CcSubtitle, String)>, String> {
+    let info = fetch_video_info(url).await?;
+    let page = info.page(page_number(url)).ok_or("Bilibili 视频没有可用的分P")?;
+
+    let mut subtitles: Vec<CcSubtitle> = fetch_subtitle_tracks(&info, page)
+        .await?
+        .into_iter()
+        .filter(|t| allow_auto || !t.is_auto)
+        .filter(|t| !user_specified || subtitle_language_rank(&t.language, preferences) < preferences.len())
+        .collect();
+    if subtitles.is_empty() {
+        return Err("该视频没有符合语言偏好的 CC 字幕（或需要登录才能获取）".to_string());
+    }
+    subtitles.sort_by_key(|t| (subtitle_language_rank(&t.language, preferences), t.is_auto));
+
+    let mut tracks = Vec::with_capacity(subtitles.len());
+    for subtitle in subtitles {
+        let content = get_json(&subtitle.url).await?;
+        let json_content = subtitle_to_transcription_json(&content, &track_language(&subtitle))?;
+        tracks.push((subtitle, json_content));
+    }
+    Ok(tracks)
+}
@@ -0,0 +1,138 @@
+use crate::{db, ChatContext};
+use rusqlite::{Connection, Result as SqlResult};
+use std::path::PathBuf;
+
+// 上下文类型
+pub const CONTEXT_TYPE_RESOURCE: &str = "resource";
+pub const CONTEXT_TYPE_TASK: &str = "task";
+
+pub fn validate_context_type(context_type: &str) -> Result<(), String> {
+    match context_type {
+        CONTEXT_TYPE_RESOURCE | CONTEXT_TYPE_TASK => Ok(()),
+        other => Err(format!("不支持的上下文类型: {}（仅支持 resource 和 task）", other)),
+    }
+}
+
+// 校验上下文指向的资源/任务是否存在
+pub fn ensure_target_exists(conn: &Connection, context_type: &str, context_id: &str) -> Result<(), String> {
+    let exists = match context_type {
+        CONTEXT_TYPE_RESOURCE => db::get_resource(conn, context_id)
+            .map_err(|e| format!("无法获取资源: {}", e))?
+            .is_some(),
+        _ => db::get_task(conn, context_id)
+            .map_err(|e| format!("无法获取任务: {}", e))?
+            .is_some(),
+    };
+    if !exists {
+        return Err(format!("{} {} 不存在", if context_type == CONTEXT_TYPE_RESOURCE { "资源" } else { "任务" }, context_id));
+    }
+    Ok(())
+}
+
+// 解析工具调用使用的当前资源/任务 ID
+// 显式传入的 ID 优先，否则使用 chat 关联的第一个资源/任务；只有任务时使用任务所属的资源
+pub fn resolve_current_ids(
+    conn: &Connection,
+    contexts: &[ChatContext],
+    current_resource_id: Option<String>,
+    current_task_id: Option<String>,
+) -> SqlResult<(Option<String>, Option<String>)> {
+    let task_id = current_task_id.or_else(|| {
+        contexts
+            .iter()
+            .find(|c| c.context_type == CONTEXT_TYPE_TASK)
+            .map(|c| c.context_id.clone())
+    });
+
+    let mut resource_id = current_resource_id.or_else(|| {
+        contexts
+            .iter()
+            .find(|c| c.context_type == CONTEXT_TYPE_RESOURCE)
+            .map(|c| c.context_id.clone())
+    });
+
+    if resource_id.is_none() {
+        if let Some(task_id) = &task_id {
+            resource_id = db::get_task(conn, task_id)?.map(|t| t.resource_id);
+        }
+    }
+
+    Ok((resource_id, task_id))
+}
+
+// 生成追加到 system message 的上下文说明
+pub fn build_context_prompt(conn: &Connection, contexts: &[ChatContext]) -> SqlResult<Option<String>> {
+    let mut lines = Vec::new();
+
+    for context in contexts {
+        match context.context_type.as_str() {
+            CONTEXT_TYPE_RESOURCE => {
+                if let Some(resource) = db::get_resource(conn, &context.context_id)? {
+                    lines.push(format!(
+                        "- 资源「{}」（ID: {}，类型: {}）",
+                        resource.name,
+                        resource.id,
+                        match resource.resource_type {
+                            crate::ResourceType::Audio => "音频",
+                            crate::ResourceType::Video => "视频",
+                        }
+                    ));
+                }
+            }
+            CONTEXT_TYPE_TASK => {
+                if let Some(task) = db::get_task(conn, &context.context_id)? {
+                    lines.push(format!(
+                        "- 转写任务（ID: {}，状态: {}，所属资源ID: {}）",
+                        task.id, task.status, task.resource_id
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if lines.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(format!(
+        "本对话关联的上下文：\n{}\n未指定资源或任务 ID 时，工具调用会默认使用以上资源和任务。",
+        lines.join("\n")
+    )))
+}
+
+// 加载 chat 的上下文，返回解析后的资源/任务 ID 以及 system message 补充说明
+pub async fn load_for_chat(
+    db_path: PathBuf,
+    chat_id: Option<String>,
+    current_resource_id: Option<String>,
+    current_task_id: Option<String>,
+) -> Result<(Option<String>, Option<String>, Option<String>), String> {
+    let chat_id = match chat_id {
+        Some(chat_id) => chat_id,
+        None => return Ok((current_resource_id, current_task_id, None)),
+    };
+
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        let contexts = db::get_chat_contexts(&conn, &chat_id)
+            .map_err(|e| format!("无法获取 chat 上下文: {}", e))?;
+        let (resource_id, task_id) = resolve_current_ids(&conn, &contexts, current_resource_id, current_task_id)
+            .map_err(|e| format!("无法解析 chat 上下文: {}", e))?;
+        let prompt = build_context_prompt(&conn, &contexts)
+            .map_err(|e| format!("无法生成上下文说明: {}", e))?;
+        Ok((resource_id, task_id, prompt))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 将上下文说明追加到 system message
+pub fn append_to_system_message(system_message: Option<String>, prompt: Option<String>) -> Option<String> {
+    match (system_message, prompt) {
+        (Some(message), Some(prompt)) => Some(format!("{}\n\n{}", message, prompt)),
+        (None, Some(prompt)) => Some(prompt),
+        (message, None) => message,
+    }
+}
@@ -0,0 +1,369 @@
+use crate::{chat_context, db, url_metadata, Chat, ChatContext, Message};
+use chrono::Utc;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub const FORMAT_MARKDOWN: &str = "markdown";
+pub const FORMAT_JSON: &str = "json";
+
+// JSON 导出格式版本
+const TRANSCRIPT_VERSION: u32 = 1;
+
+// 导出的 chat（JSON 格式）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatTranscript {
+    pub version: u32,
+    pub title: String,
+    pub created_at: String,
+    pub updated_at: String,
+    pub exported_at: String,
+    #[serde(default)]
+    pub contexts: Vec<ChatTranscriptContext>,
+    #[serde(default)]
+    pub resource_ids: Vec<String>, // 关联的资源 ID（包括关联任务所属的资源）
+    #[serde(default)]
+    pub active_leaf_id: Option<String>,
+    pub messages: Vec<ChatTranscriptMessage>, // 包含所有分支
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatTranscriptContext {
+    pub context_type: String,
+    pub context_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatTranscriptMessage {
+    pub id: String,
+    #[serde(default)]
+    pub parent_message_id: Option<String>,
+    pub role: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub tool_calls: Option<Value>, // 以 JSON 数组形式导出，便于阅读
+    #[serde(default)]
+    pub tool_call_id: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    pub created_at: String,
+}
+
+// 导出结果
+#[derive(Debug, Serialize, Clone)]
+pub struct ChatExportResult {
+    pub format: String,
+    pub content: String,
+    pub file_name: String, // 建议的文件名
+}
+
+// 导入结果
+#[derive(Debug, Serialize, Clone)]
+pub struct ChatImportResult {
+    pub chat: Chat,
+    pub message_count: usize,
+    pub skipped_contexts: Vec<String>, // 本地不存在而未关联的资源/任务
+}
+
+// 生成安全的文件名
+fn file_name_for(title: &str, extension: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| if c.is_control() || "\\/:*?\"<>|".contains(c) { '_' } else { c })
+        .collect();
+    let name = name.trim();
+    format!("{}.{}", if name.is_empty() { "chat" } else { name }, extension)
+}
+
+// 选择不会与内容冲突的代码块围栏
+fn fence_for(content: &str) -> String {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in content.chars() {
+        if c == '`' {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    "`".repeat((longest + 1).max(3))
+}
+
+fn code_block(content: &str, language: &str) -> String {
+    let fence = fence_for(content);
+    format!("{}{}\n{}\n{}", fence, language, content, fence)
+}
+
+fn details(summary: &str, body: &str) -> String {
+    format!("<details>\n<summary>{}</summary>\n\n{}\n\n</details>", summary, body)
+}
+
+// 工具参数/结果如果是 JSON 则格式化输出
+fn pretty_json(content: &str) -> Option<String> {
+    serde_json::from_str::<Value>(content)
+        .ok()
+        .and_then(|v| serde_json::to_string_pretty(&v).ok())
+}
+
+// 收集关联的资源 ID（任务会被解析为所属资源）
+fn collect_resource_ids(conn: &Connection, contexts: &[ChatContext]) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::new();
+    for context in contexts {
+        let resource_id = match context.context_type.as_str() {
+            chat_context::CONTEXT_TYPE_RESOURCE => Some(context.context_id.clone()),
+            chat_context::CONTEXT_TYPE_TASK => db::get_task(conn, &context.context_id)
+                .ok()
+                .flatten()
+                .map(|t| t.resource_id),
+            _ => None,
+        };
+        if let Some(resource_id) = resource_id {
+            if !ids.contains(&resource_id) {
+                ids.push(resource_id);
+            }
+        }
+    }
+    ids
+}
+
+fn render_markdown(conn: &Connection, chat: &Chat, contexts: &[ChatContext], path: &[Message]) -> String {
+    let mut sections = vec![format!("# {}", chat.title)];
+
+    let mut meta = vec![
+        format!("> 创建时间：{}", chat.created_at),
+        format!("> 导出时间：{}", Utc::now().to_rfc3339()),
+    ];
+    // 关联资源的章节作为大纲
+    let mut outlines = Vec::new();
+    for context in contexts {
+        let label = match context.context_type.as_str() {
+            chat_context::CONTEXT_TYPE_RESOURCE => db::get_resource(conn, &context.context_id)
+                .ok()
+                .flatten()
+                .map(|r| {
+                    if let Some(chapters) = r.chapters.as_deref().filter(|c| !c.is_empty()) {
+                        let outline = url_metadata::format_outline(chapters)
+                            .lines()
+                            .map(|line| format!("- {}", line))
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        outlines.push(format!("## 大纲：{}\n\n{}", r.name, outline));
+                    }
+                    format!("> 关联资源：{}（{}）", r.name, r.id)
+                }),
+            chat_context::CONTEXT_TYPE_TASK => Some(format!("> 关联任务：{}", context.context_id)),
+            _ => None,
+        };
+        if let Some(label) = label {
+            meta.push(label);
+        }
+    }
+    sections.push(meta.join("  \n"));
+    sections.extend(outlines);
+
+    for message in path {
+        match message.role.as_str() {
+            "user" => sections.push(format!("## 用户\n\n{}", message.content)),
+            "assistant" => {
+                let mut parts = vec!["## 助手".to_string()];
+                if let Some(reasoning) = message.reasoning.as_deref().filter(|r| !r.trim().is_empty()) {
+                    parts.push(details("思考过程", reasoning));
+                }
+                if !message.content.trim().is_empty() {
+                    parts.push(message.content.clone());
+                }
+                let tool_calls = message
+                    .tool_calls
+                    .as_deref()
+                    .and_then(|json| serde_json::from_str::<Vec<crate::ai::ToolCall>>(json).ok())
+                    .unwrap_or_default();
+                for tool_call in tool_calls {
+                    let arguments = pretty_json(&tool_call.function.arguments)
+                        .unwrap_or_else(|| tool_call.function.arguments.clone());
+                    parts.push(details(
+                        &format!("调用工具：{}", tool_call.function.name),
+                        &code_block(&arguments, "json"),
+                    ));
+                }
+                sections.push(parts.join("\n\n"));
+            }
+            "tool" => {
+                let (content, language) = match pretty_json(&message.content) {
+                    Some(pretty) => (pretty, "json"),
+                    None => (message.content.clone(), ""),
+                };
+                sections.push(details(
+                    &format!("工具结果：{}", message.name.as_deref().unwrap_or("")),
+                    &code_block(&content, language),
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    sections.join("\n\n") + "\n"
+}
+
+// 导出 chat：Markdown 只包含当前激活分支，JSON 包含所有分支
+pub fn export_chat(conn: &Connection, chat_id: &str, format: &str) -> Result<ChatExportResult, String> {
+    let chat = db::get_chat(conn, chat_id)
+        .map_err(|e| format!("无法获取 chat: {}", e))?
+        .ok_or_else(|| "Chat 不存在".to_string())?;
+    let contexts = db::get_chat_contexts(conn, chat_id)
+        .map_err(|e| format!("无法获取 chat 上下文: {}", e))?;
+
+    match format {
+        FORMAT_MARKDOWN => {
+            let path = db::get_messages_by_chat(conn, chat_id)
+                .map_err(|e| format!("无法获取 messages: {}", e))?;
+            Ok(ChatExportResult {
+                format: FORMAT_MARKDOWN.to_string(),
+                content: render_markdown(conn, &chat, &contexts, &path),
+                file_name: file_name_for(&chat.title, "md"),
+            })
+        }
+        FORMAT_JSON => {
+            let messages = db::get_all_messages_by_chat(conn, chat_id)
+                .map_err(|e| format!("无法获取 messages: {}", e))?;
+            let active_leaf_id = db::get_active_leaf_id(conn, chat_id)
+                .map_err(|e| format!("无法获取激活分支: {}", e))?;
+
+            let transcript = ChatTranscript {
+                version: TRANSCRIPT_VERSION,
+                title: chat.title.clone(),
+                created_at: chat.created_at.clone(),
+                updated_at: chat.updated_at.clone(),
+                exported_at: Utc::now().to_rfc3339(),
+                resource_ids: collect_resource_ids(conn, &contexts),
+                contexts: contexts
+                    .iter()
+                    .map(|c| ChatTranscriptContext {
+                        context_type: c.context_type.clone(),
+                        context_id: c.context_id.clone(),
+                    })
+                    .collect(),
+                active_leaf_id,
+                messages: messages
+                    .into_iter()
+                    .map(|m| ChatTranscriptMessage {
+                        tool_calls: m.tool_calls.as_deref().and_then(|json| serde_json::from_str(json).ok()),
+                        id: m.id,
+                        parent_message_id: m.parent_message_id,
+                        role: m.role,
+                        content: m.content,
+                        tool_call_id: m.tool_call_id,
+                        name: m.name,
+                        reasoning: m.reasoning,
+                        created_at: m.created_at,
+                    })
+                    .collect(),
+            };
+
+            Ok(ChatExportResult {
+                format: FORMAT_JSON.to_string(),
+                content: serde_json::to_string_pretty(&transcript)
+                    .map_err(|e| format!("无法序列化 chat: {}", e))?,
+                file_name: file_name_for(&chat.title, "json"),
+            })
+        }
+        other => Err(format!("不支持的导出格式: {}（仅支持 markdown 和 json）", other)),
+    }
+}
+
+// 从 JSON 导出内容重建 chat，所有 ID 都会重新生成，父子关系保持不变
+pub fn import_chat(conn: &Connection, content: &str) -> Result<ChatImportResult, String> {
+    let transcript: ChatTranscript =
+        serde_json::from_str(content).map_err(|e| format!("无法解析 chat 导出文件: {}", e))?;
+    if transcript.version > TRANSCRIPT_VERSION {
+        return Err(format!("不支持的导出文件版本: {}", transcript.version));
+    }
+
+    for message in &transcript.messages {
+        if !matches!(message.role.as_str(), "user" | "assistant" | "tool") {
+            return Err(format!("消息 {} 的角色无效: {}", message.id, message.role));
+        }
+    }
+
+    // 旧 ID -> 新 ID
+    let id_map: HashMap<&str, String> = transcript
+        .messages
+        .iter()
+        .map(|m| (m.id.as_str(), Uuid::new_v4().to_string()))
+        .collect();
+
+    let now = Utc::now().to_rfc3339();
+    let chat = Chat {
+        id: Uuid::new_v4().to_string(),
+        title: if transcript.title.trim().is_empty() { "导入的对话".to_string() } else { transcript.title.clone() },
+        created_at: transcript.created_at.clone(),
+        updated_at: now.clone(),
+    };
+
+    let tx = conn.unchecked_transaction().map_err(|e| format!("无法开始事务: {}", e))?;
+    db::create_chat(&tx, &chat).map_err(|e| format!("无法创建 chat: {}", e))?;
+
+    for message in &transcript.messages {
+        let tool_calls = match &message.tool_calls {
+            Some(Value::String(json)) => Some(json.clone()),
+            Some(Value::Null) | None => None,
+            Some(value) => Some(value.to_string()),
+        };
+        db::create_message(&tx, &Message {
+            id: id_map[message.id.as_str()].clone(),
+            chat_id: chat.id.clone(),
+            role: message.role.clone(),
+            content: message.content.clone(),
+            tool_calls,
+            tool_call_id: message.tool_call_id.clone(),
+            name: message.name.clone(),
+            reasoning: message.reasoning.clone(),
+            created_at: message.created_at.clone(),
+            // 找不到父消息时作为根消息
+            parent_message_id: message
+                .parent_message_id
+                .as_deref()
+                .and_then(|parent| id_map.get(parent).cloned()),
+        })
+        .map_err(|e| format!("无法保存 message: {}", e))?;
+    }
+
+    let active_leaf_id = transcript
+        .active_leaf_id
+        .as_deref()
+        .and_then(|id| id_map.get(id).cloned())
+        .or_else(|| transcript.messages.last().map(|m| id_map[m.id.as_str()].clone()));
+    db::set_active_leaf_id(&tx, &chat.id, active_leaf_id.as_deref())
+        .map_err(|e| format!("无法设置激活分支: {}", e))?;
+
+    // 只关联本地存在的资源/任务
+    let mut skipped_contexts = Vec::new();
+    for context in &transcript.contexts {
+        if chat_context::validate_context_type(&context.context_type).is_err()
+            || chat_context::ensure_target_exists(&tx, &context.context_type, &context.context_id).is_err()
+        {
+            skipped_contexts.push(format!("{}:{}", context.context_type, context.context_id));
+            continue;
+        }
+        db::add_chat_context(&tx, &ChatContext {
+            chat_id: chat.id.clone(),
+            context_type: context.context_type.clone(),
+            context_id: context.context_id.clone(),
+            created_at: now.clone(),
+        })
+        .map_err(|e| format!("无法关联 chat 上下文: {}", e))?;
+    }
+
+    tx.commit().map_err(|e| format!("无法提交事务: {}", e))?;
+
+    Ok(ChatImportResult {
+        chat,
+        message_count: transcript.messages.len(),
+        skipped_contexts,
+    })
+}
@@ -0,0 +1,580 @@
+use crate::CommandExecutionResult;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::{Mutex, Notify};
+use tokio::task::JoinHandle;
+
+// 单个输出流允许捕获的最大字节数，超过后终止进程
+const MAX_OUTPUT_BYTES: usize = 16 * 1024 * 1024;
+// 参数数量和单个参数长度限制
+const MAX_ARG_COUNT: usize = 256;
+const MAX_ARG_LENGTH: usize = 8192;
+// 允许传递给子进程的环境变量，其余全部清除
+const ALLOWED_ENV_VARS: &[&str] = &["PATH", "HOME", "USER", "LANG", "LC_ALL", "LC_CTYPE", "TMPDIR", "TMP", "TEMP", "SYSTEMROOT"];
+
+// 允许执行的程序
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SandboxProgram {
+    WhisperCli,
+    Ffmpeg,
+    YtDlp,
+}
+
+impl SandboxProgram {
+    // 根据命令名识别程序（允许传入名称或内置工具的完整路径）
+    pub fn from_command(command: &str) -> Option<Self> {
+        let name = Path::new(command)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(command)
+            .trim_end_matches(".exe");
+        match name {
+            "whisper-cli" => Some(SandboxProgram::WhisperCli),
+            "ffmpeg" => Some(SandboxProgram::Ffmpeg),
+            "yt-dlp" => Some(SandboxProgram::YtDlp),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SandboxProgram::WhisperCli => "whisper-cli",
+            SandboxProgram::Ffmpeg => "ffmpeg",
+            SandboxProgram::YtDlp => "yt-dlp",
+        }
+    }
+
+    // 默认运行时间上限（秒）
+    pub fn default_timeout_secs(&self) -> u64 {
+        match self {
+            SandboxProgram::WhisperCli => 4 * 60 * 60,
+            SandboxProgram::Ffmpeg => 2 * 60 * 60,
+            SandboxProgram::YtDlp => 60 * 60,
+        }
+    }
+
+    // 允许使用的选项及其参数类型，未列出的选项一律拒绝
+    fn option_kind(&self, option: &str) -> Option<ArgKind> {
+        let kind = match self {
+            SandboxProgram::WhisperCli => match option {
+                "-m" | "--model" | "-f" | "--file" | "-vm" | "--vad-model" => ArgKind::Input,
+                "-of" | "--output-file" => ArgKind::Output,
+                "-l" | "--language" | "-t" | "--threads" | "-p" | "--processors" | "-ot" | "--offset-t"
+                | "-on" | "--offset-n" | "-d" | "--duration" | "-mc" | "--max-context" | "-ml"
+                | "--max-len" | "-bo" | "--best-of" | "-bs" | "--beam-size" | "-wt" | "--word-thold"
+                | "-et" | "--entropy-thold" | "-lpt" | "--logprob-thold" | "--prompt" => ArgKind::Value,
+                "-tr" | "--translate" | "-otxt" | "--output-txt" | "-ovtt" | "--output-vtt" | "-osrt"
+                | "--output-srt" | "-olrc" | "--output-lrc" | "-ocsv" | "--output-csv" | "-oj"
+                | "--output-json" | "-ojf" | "--output-json-full" | "-np" | "--no-prints" | "-pp"
+                | "--print-progress" | "-nt" | "--no-timestamps" | "-sow" | "--split-on-word" | "-dl"
+                | "--detect-language" | "-ng" | "--no-gpu" | "-fa" | "--flash-attn" | "--vad" => ArgKind::Flag,
+                _ => return None,
+            },
+            SandboxProgram::Ffmpeg => match option {
+                "-i" => ArgKind::Input,
+                "-af" | "-filter:a" => ArgKind::AudioFilter,
+                "-f" => ArgKind::Format,
+                "-loglevel" | "-v" | "-ss" | "-t" | "-to" | "-ar" | "-ac" | "-c" | "-c:a" | "-acodec"
+                | "-c:v" | "-vcodec" | "-b:a" | "-q:a" | "-map" | "-threads" => ArgKind::Value,
+                "-version" | "-y" | "-n" | "-hide_banner" | "-nostdin" | "-stats" | "-nostats" | "-vn" | "-an" | "-sn"
+                | "-dn" => ArgKind::Flag,
+                _ => return None,
+            },
+            SandboxProgram::YtDlp => match option {
+                "-o" | "--output" => ArgKind::Output,
+                "-P" | "--paths" => ArgKind::Dir,
+                "-f" | "--format" | "--audio-format" | "--audio-quality" | "--playlist-items" | "-I"
+                | "--print" | "--sub-langs" | "--sub-format" | "--convert-subs" | "--socket-timeout"
+                | "--retries" | "-r" | "--limit-rate" | "--merge-output-format" | "--remux-video"
+                | "--recode-video" | "-N" | "--concurrent-fragments" | "--proxy" | "--user-agent"
+                | "--referer" | "--add-header" => ArgKind::Value,
+                "--version" | "-x" | "--extract-audio" | "-j" | "--dump-json" | "-J" | "--dump-single-json"
+                | "--flat-playlist" | "--no-playlist" | "--yes-playlist" | "--no-warnings" | "-q"
+                | "--quiet" | "-s" | "--simulate" | "--skip-download" | "-g" | "--get-url" | "--get-title"
+                | "--get-duration" | "--newline" | "--progress" | "--no-progress" | "--write-subs"
+                | "--write-auto-subs" | "--write-thumbnail" | "--embed-thumbnail" | "--embed-metadata"
+                | "--restrict-filenames" | "--no-mtime" | "--no-part" | "--ignore-config" | "--no-cache-dir" => {
+                    ArgKind::Flag
+                }
+                _ => return None,
+            },
+        };
+        Some(kind)
+    }
+
+    // 位置参数的类型：whisper-cli 为输入文件，ffmpeg 为输出文件，yt-dlp 为视频链接
+    fn positional_kind(&self) -> ArgKind {
+        match self {
+            SandboxProgram::WhisperCli => ArgKind::Input,
+            SandboxProgram::Ffmpeg => ArgKind::Output,
+            SandboxProgram::YtDlp => ArgKind::Url,
+        }
+    }
+
+    // 解析内置或系统中的可执行文件路径
+    fn resolve_path(&self, app: &AppHandle) -> Result<PathBuf, String> {
+        match self {
+            SandboxProgram::WhisperCli => crate::get_whisper_cli_path(app),
+            SandboxProgram::Ffmpeg => crate::get_ffmpeg_path(app),
+            SandboxProgram::YtDlp => crate::get_ytdlp_path(app),
+        }
+    }
+}
+
+// 参数类型
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArgKind {
+    // 不带值的开关
+    Flag,
+    // 普通值（不涉及文件）
+    Value,
+    // 读取的文件，必须已存在
+    Input,
+    // 写入的文件，所在目录必须已存在
+    Output,
+    // 目录
+    Dir,
+    // http(s) 链接
+    Url,
+    // ffmpeg 音频滤镜链，只允许不读写文件的滤镜
+    AudioFilter,
+    // ffmpeg 容器格式
+    Format,
+}
+
+// ffmpeg 允许使用的音频滤镜（movie、amovie、ametadata 等可以读写任意文件，不在此列）
+const ALLOWED_AUDIO_FILTERS: &[&str] = &[
+    "loudnorm", "dynaudnorm", "highpass", "lowpass", "afftdn", "anlmdn", "silenceremove", "volume",
+    "aresample", "aformat", "atempo", "atrim", "asetpts", "pan", "acompressor", "agate", "apad",
+];
+
+// ffmpeg 允许使用的容器格式（lavfi、concat 等可以读取任意文件，不在此列）
+const ALLOWED_FORMATS: &[&str] = &[
+    "wav", "mp3", "mp4", "m4a", "ipod", "flac", "ogg", "opus", "webm", "matroska", "mov", "adts", "s16le",
+    "f32le", "null",
+];
+
+// 运行中的受控命令管理器（event_id -> 取消信号），同时记录用户通过对话框选择的路径
+#[derive(Clone)]
+pub struct RunningCommands {
+    commands: Arc<Mutex<HashMap<String, Arc<Notify>>>>,
+    picked_paths: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl RunningCommands {
+    pub fn new() -> Self {
+        Self {
+            commands: Arc::new(Mutex::new(HashMap::new())),
+            picked_paths: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    // 记录用户选择的文件或目录，之后可以作为命令的输入输出路径
+    pub async fn allow_path(&self, path: &Path) {
+        // 保存对话框选择的文件可能尚不存在，只解析所在目录
+        let path = normalize_path(path)
+            .or_else(|e| match (path.parent(), path.file_name()) {
+                (Some(parent), Some(name)) => normalize_path(parent).map(|p| p.join(name)),
+                _ => Err(e),
+            })
+            .unwrap_or_else(|_| path.to_path_buf());
+        self.picked_paths.lock().await.insert(path);
+    }
+
+    async fn insert(&self, event_id: String, cancel: Arc<Notify>) -> bool {
+        let mut commands = self.commands.lock().await;
+        if commands.contains_key(&event_id) {
+            return false;
+        }
+        commands.insert(event_id, cancel);
+        true
+    }
+
+    async fn remove(&self, event_id: &str) {
+        let mut commands = self.commands.lock().await;
+        commands.remove(event_id);
+    }
+
+    pub async fn cancel(&self, event_id: &str) -> bool {
+        let commands = self.commands.lock().await;
+        match commands.get(event_id) {
+            Some(cancel) => {
+                cancel.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// 路径只能位于应用数据目录下，或是用户通过对话框选择的文件（目录）及其子路径
+struct PathPolicy {
+    app_data_dir: PathBuf,
+    picked_paths: HashSet<PathBuf>,
+    working_dir: Option<PathBuf>,
+}
+
+impl PathPolicy {
+    fn allows(&self, path: &Path) -> bool {
+        path.starts_with(&self.app_data_dir) || self.picked_paths.iter().any(|picked| path.starts_with(picked))
+    }
+
+    // 解析为绝对路径并检查是否允许，返回传给子进程的路径
+    fn check(&self, value: &str, kind: ArgKind) -> Result<String, String> {
+        let path = Path::new(value);
+        let path = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            let base = self
+                .working_dir
+                .as_ref()
+                .ok_or_else(|| format!("未指定工作目录时必须使用绝对路径: {}", value))?;
+            base.join(path)
+        };
+
+        let resolved = match kind {
+            ArgKind::Output => {
+                // 输出文件可能尚不存在，只解析所在目录
+                let file_name = path
+                    .file_name()
+                    .ok_or_else(|| format!("无效的输出路径: {}", value))?;
+                let parent = path.parent().ok_or_else(|| format!("无效的输出路径: {}", value))?;
+                if parent.to_string_lossy().contains('%') {
+                    return Err(format!("输出目录中不能包含模板: {}", value));
+                }
+                let output = normalize_path(parent)
+                    .map_err(|_| format!("输出目录不存在: {}", parent.display()))?
+                    .join(file_name);
+                // 已存在的输出文件可能是指向其它位置的符号链接
+                if output.exists() {
+                    normalize_path(&output).map_err(|_| format!("无效的输出路径: {}", value))?
+                } else {
+                    output
+                }
+            }
+            _ => normalize_path(&path).map_err(|_| format!("路径不存在: {}", value))?,
+        };
+
+        if kind == ArgKind::Input && !resolved.is_file() {
+            return Err(format!("输入文件不存在: {}", value));
+        }
+        if kind == ArgKind::Dir && !resolved.is_dir() {
+            return Err(format!("目录不存在: {}", value));
+        }
+        if !self.allows(&resolved) {
+            return Err(format!("不允许访问路径 {}，请通过对话框选择文件或使用应用数据目录", value));
+        }
+        Ok(resolved.to_string_lossy().to_string())
+    }
+}
+
+// 解析符号链接和 ..，Windows 上去掉 canonicalize 返回的 \\?\ 前缀（部分程序无法识别）
+fn normalize_path(path: &Path) -> std::io::Result<PathBuf> {
+    let canonical = std::fs::canonicalize(path)?;
+    #[cfg(windows)]
+    {
+        let text = canonical.to_string_lossy();
+        if let Some(stripped) = text.strip_prefix(r"\\?\") {
+            if !stripped.starts_with("UNC\\") {
+                return Ok(PathBuf::from(stripped));
+            }
+        }
+    }
+    Ok(canonical)
+}
+
+// 校验单个参数的值
+fn check_value(program: SandboxProgram, policy: &PathPolicy, kind: ArgKind, value: &str) -> Result<String, String> {
+    match kind {
+        ArgKind::Flag | ArgKind::Value => Ok(value.to_string()),
+        ArgKind::Input | ArgKind::Output | ArgKind::Dir => policy.check(value, kind),
+        ArgKind::Url => {
+            if value.starts_with("https://") || value.starts_with("http://") {
+                Ok(value.to_string())
+            } else {
+                Err(format!("{} 只接受 http(s) 链接: {}", program.name(), value))
+            }
+        }
+        ArgKind::AudioFilter => {
+            for filter in value.split([',', ';']) {
+                let name = filter.trim().split('=').next().unwrap_or("").trim();
+                if !ALLOWED_AUDIO_FILTERS.contains(&name) {
+                    return Err(format!("不允许使用音频滤镜 {}", name));
+                }
+            }
+            Ok(value.to_string())
+        }
+        ArgKind::Format => {
+            if ALLOWED_FORMATS.contains(&value) {
+                Ok(value.to_string())
+            } else {
+                Err(format!("不允许使用格式 {}", value))
+            }
+        }
+    }
+}
+
+// 按程序的选项白名单校验参数，返回明确的拒绝原因；路径参数会被替换为解析后的绝对路径
+fn validate_args(program: SandboxProgram, policy: &PathPolicy, args: &[String]) -> Result<Vec<String>, String> {
+    if args.len() > MAX_ARG_COUNT {
+        return Err(format!("参数数量超过限制（最多 {} 个）", MAX_ARG_COUNT));
+    }
+
+    for arg in args {
+        if arg.len() > MAX_ARG_LENGTH {
+            return Err(format!("参数长度超过限制（最多 {} 字节）", MAX_ARG_LENGTH));
+        }
+        if arg.contains('\0') {
+            return Err("参数中不能包含空字符".to_string());
+        }
+    }
+
+    let mut checked = Vec::with_capacity(args.len() + 1);
+    // yt-dlp 默认会读取用户和当前目录下的配置文件
+    if program == SandboxProgram::YtDlp {
+        checked.push("--ignore-config".to_string());
+    }
+
+    let mut options_ended = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if options_ended || !arg.starts_with('-') || arg == "-" {
+            if arg == "-" {
+                return Err(format!("不允许在 {} 中使用标准输入输出", program.name()));
+            }
+            checked.push(check_value(program, policy, program.positional_kind(), arg)?);
+            continue;
+        }
+        if arg == "--" && program == SandboxProgram::YtDlp {
+            options_ended = true;
+            checked.push(arg.clone());
+            continue;
+        }
+
+        // 长选项支持 --option=value 形式
+        let (option, inline_value) = match arg.split_once('=') {
+            Some((option, value)) if arg.starts_with("--") => (option, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        let kind = program
+            .option_kind(option)
+            .ok_or_else(|| format!("不允许在 {} 中使用参数 {}", program.name(), option))?;
+
+        if kind == ArgKind::Flag {
+            if inline_value.is_some() {
+                return Err(format!("参数 {} 不接受值", option));
+            }
+            checked.push(arg.clone());
+            continue;
+        }
+
+        let value = match inline_value {
+            Some(value) => value,
+            None => iter.next().ok_or_else(|| format!("参数 {} 缺少值", option))?,
+        };
+        checked.push(option.to_string());
+        checked.push(check_value(program, policy, kind, value)?);
+    }
+
+    Ok(checked)
+}
+
+// 读取输出流：逐行发送事件，超过大小限制时标记并唤醒等待方终止进程
+fn spawn_limited_reader(
+    stream: impl AsyncRead + Send + Unpin + 'static,
+    app: AppHandle,
+    event_name: String,
+    exceeded: Arc<AtomicBool>,
+    kill: Arc<Notify>,
+) -> JoinHandle<String> {
+    tokio::spawn(async move {
+        let mut reader = tokio::io::BufReader::new(stream);
+        let mut output = Vec::new();
+        let mut line = Vec::new();
+        let mut buf = [0u8; 8192];
+
+        loop {
+            let n = match reader.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+
+            if output.len() + n > MAX_OUTPUT_BYTES {
+                exceeded.store(true, Ordering::SeqCst);
+                kill.notify_one();
+                break;
+            }
+            output.extend_from_slice(&buf[..n]);
+
+            // 按行发送到前端
+            for &byte in &buf[..n] {
+                if byte == b'\n' {
+                    let text = String::from_utf8_lossy(&line).trim_end_matches('\r').to_string();
+                    let _ = app.emit(&event_name, &text);
+                    line.clear();
+                } else {
+                    line.push(byte);
+                }
+            }
+        }
+
+        if !line.is_empty() {
+            let _ = app.emit(&event_name, &String::from_utf8_lossy(&line).to_string());
+        }
+
+        String::from_utf8_lossy(&output).to_string()
+    })
+}
+
+// 在受控环境中执行允许的命令
+pub async fn run(
+    app: &AppHandle,
+    running: &RunningCommands,
+    command: &str,
+    args: Vec<String>,
+    event_id: &str,
+    working_dir: Option<String>,
+    timeout_secs: Option<u64>,
+) -> Result<CommandExecutionResult, String> {
+    let program = SandboxProgram::from_command(command)
+        .ok_or_else(|| format!("不允许执行命令 {}，仅支持 whisper-cli、ffmpeg 和 yt-dlp", command))?;
+
+    let mut policy = PathPolicy {
+        app_data_dir: normalize_path(&crate::get_app_data_dir(app)?)
+            .map_err(|e| format!("无法解析应用数据目录: {}", e))?,
+        picked_paths: running.picked_paths.lock().await.clone(),
+        working_dir: None,
+    };
+
+    // 工作目录同样只能位于允许的位置
+    if let Some(dir) = working_dir {
+        let dir_path = normalize_path(Path::new(&dir)).map_err(|_| format!("工作目录不存在: {}", dir))?;
+        if !dir_path.is_dir() {
+            return Err(format!("工作目录不存在: {}", dir));
+        }
+        if !policy.allows(&dir_path) {
+            return Err(format!("不允许使用工作目录 {}", dir));
+        }
+        policy.working_dir = Some(dir_path);
+    }
+
+    let args = validate_args(program, &policy, &args)?;
+
+    // 超时时间不能超过程序的默认上限
+    let max_timeout = program.default_timeout_secs();
+    let timeout_secs = timeout_secs.filter(|t| *t > 0).unwrap_or(max_timeout).min(max_timeout);
+
+    let executable = program.resolve_path(app)?;
+
+    let mut cmd = tokio::process::Command::new(&executable);
+    cmd.args(&args);
+
+    if let Some(dir) = &policy.working_dir {
+        cmd.current_dir(dir);
+    }
+
+    // 清理环境变量，只保留必要的部分
+    cmd.env_clear();
+    for key in ALLOWED_ENV_VARS {
+        if let Ok(value) = std::env::var(key) {
+            cmd.env(key, value);
+        }
+    }
+
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+    cmd.kill_on_drop(true);
+
+    let cancel = Arc::new(Notify::new());
+    if !running.insert(event_id.to_string(), cancel.clone()).await {
+        return Err(format!("事件 ID {} 对应的命令正在运行", event_id));
+    }
+
+    let result = run_child(app, cmd, program, event_id, timeout_secs, cancel).await;
+    running.remove(event_id).await;
+    result
+}
+
+async fn run_child(
+    app: &AppHandle,
+    mut cmd: tokio::process::Command,
+    program: SandboxProgram,
+    event_id: &str,
+    timeout_secs: u64,
+    cancel: Arc<Notify>,
+) -> Result<CommandExecutionResult, String> {
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("无法执行命令 {}: {}", program.name(), e))?;
+
+    let stdout = child.stdout.take().ok_or("无法获取 stdout 句柄")?;
+    let stderr = child.stderr.take().ok_or("无法获取 stderr 句柄")?;
+
+    let exceeded = Arc::new(AtomicBool::new(false));
+    let output_limit = Arc::new(Notify::new());
+    let stdout_handle = spawn_limited_reader(
+        stdout,
+        app.clone(),
+        format!("cmd-stdout-{}", event_id),
+        exceeded.clone(),
+        output_limit.clone(),
+    );
+    let stderr_handle = spawn_limited_reader(
+        stderr,
+        app.clone(),
+        format!("cmd-stderr-{}", event_id),
+        exceeded.clone(),
+        output_limit.clone(),
+    );
+
+    let timeout = tokio::time::sleep(tokio::time::Duration::from_secs(timeout_secs));
+    tokio::pin!(timeout);
+
+    let termination = tokio::select! {
+        status = child.wait() => {
+            let status = status.map_err(|e| format!("等待进程完成失败: {}", e))?;
+            Ok(status)
+        }
+        _ = &mut timeout => Err(format!("命令 {} 运行超过 {} 秒，已终止", program.name(), timeout_secs)),
+        _ = cancel.notified() => Err(format!("命令 {} 已被取消", program.name())),
+        _ = output_limit.notified() => Err(format!(
+            "命令 {} 输出超过 {} MB，已终止",
+            program.name(),
+            MAX_OUTPUT_BYTES / 1024 / 1024
+        )),
+    };
+
+    let status = match termination {
+        Ok(status) => status,
+        Err(e) => {
+            let _ = child.kill().await;
+            stdout_handle.abort();
+            stderr_handle.abort();
+            return Err(e);
+        }
+    };
+
+    let stdout_output = stdout_handle.await.map_err(|e| format!("读取 stdout 失败: {}", e))?;
+    let stderr_output = stderr_handle.await.map_err(|e| format!("读取 stderr 失败: {}", e))?;
+
+    if exceeded.load(Ordering::SeqCst) {
+        return Err(format!(
+            "命令 {} 输出超过 {} MB，已终止",
+            program.name(),
+            MAX_OUTPUT_BYTES / 1024 / 1024
+        ));
+    }
+
+    Ok(CommandExecutionResult {
+        exit_code: status.code(),
+        stdout: stdout_output,
+        stderr: stderr_output,
+        success: status.success(),
+    })
+}
@@ -0,0 +1,418 @@
+use crate::{ai, db, usage, AIConfig, ChatSummary};
+use chrono::Utc;
+use ring::digest;
+use serde::Serialize;
+use std::path::PathBuf;
+
+// 未配置上下文窗口时使用的默认值（token）
+pub const DEFAULT_CONTEXT_WINDOW: i64 = 32_000;
+// 为模型回复预留的 token 数
+const RESPONSE_RESERVE_TOKENS: usize = 4_096;
+// 每条消息的固定开销（role、分隔符等）
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+// 较早的工具结果超过该大小时会被截断
+const OLD_TOOL_RESULT_MAX_TOKENS: usize = 300;
+// 截断后保留的工具结果预览长度（字符）
+const TOOL_RESULT_PREVIEW_CHARS: usize = 600;
+// 生成摘要时每条消息最多保留的字符数
+const SUMMARY_INPUT_MAX_CHARS: usize = 2_000;
+// 生成新摘要时的目标占用比例，留出余量避免每轮都重新生成摘要
+const SUMMARY_TARGET_RATIO: f64 = 0.7;
+
+const SUMMARY_PROMPT: &str = "请将以下对话压缩为简洁的摘要，供后续对话作为上下文使用。\
+保留用户的目标和要求、已确认的事实和关键数据（如资源 ID、任务 ID、时间点）、已得出的结论以及尚未完成的事项。\
+省略寒暄和重复内容。直接输出摘要，不要添加额外说明。";
+
+// 上下文裁剪结果统计
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ContextFitReport {
+    pub budget_tokens: usize,
+    pub original_tokens: usize,
+    pub final_tokens: usize,
+    pub truncated_tool_results: usize,
+    pub summarized_messages: usize,
+    pub dropped_messages: usize,
+}
+
+impl ContextFitReport {
+    pub fn changed(&self) -> bool {
+        self.truncated_tool_results > 0 || self.summarized_messages > 0 || self.dropped_messages > 0
+    }
+}
+
+// 估算文本的 token 数
+// 不依赖具体的 tokenizer：ASCII 字符约 4 个一个 token，中文等非 ASCII 字符约 1 个一个 token
+pub fn estimate_tokens(text: &str) -> usize {
+    let mut ascii = 0usize;
+    let mut other = 0usize;
+    for c in text.chars() {
+        if c.is_ascii() {
+            ascii += 1;
+        } else {
+            other += 1;
+        }
+    }
+    ascii.div_ceil(4) + other
+}
+
+// 估算单条消息的 token 数
+pub fn estimate_message_tokens(message: &ai::ChatMessage) -> usize {
+    let mut tokens = MESSAGE_OVERHEAD_TOKENS;
+    if let Some(content) = &message.content {
+        tokens += estimate_tokens(content);
+    }
+    if let Some(tool_calls) = &message.tool_calls {
+        for tool_call in tool_calls {
+            tokens += MESSAGE_OVERHEAD_TOKENS
+                + estimate_tokens(&tool_call.function.name)
+                + estimate_tokens(&tool_call.function.arguments);
+        }
+    }
+    if let Some(name) = &message.name {
+        tokens += estimate_tokens(name);
+    }
+    tokens
+}
+
+pub fn estimate_messages_tokens(messages: &[ai::ChatMessage]) -> usize {
+    messages.iter().map(estimate_message_tokens).sum()
+}
+
+// 估算工具定义占用的 token 数
+pub fn estimate_tools_tokens(tools: Option<&[ai::ToolDefinition]>) -> usize {
+    tools
+        .map(|tools| estimate_tokens(&serde_json::to_string(tools).unwrap_or_default()))
+        .unwrap_or(0)
+}
+
+// 计算消息可用的 token 预算
+pub fn message_budget(ai_config: &AIConfig, tools_tokens: usize) -> usize {
+    let window = ai_config
+        .context_window
+        .filter(|w| *w > 0)
+        .unwrap_or(DEFAULT_CONTEXT_WINDOW) as usize;
+    let reserve = RESPONSE_RESERVE_TOKENS.min(window / 4);
+    window.saturating_sub(reserve).saturating_sub(tools_tokens)
+}
+
+// 裁剪所需的参数
+pub struct ContextFitOptions<'a> {
+    pub db_path: PathBuf,
+    pub ai_config: &'a AIConfig,
+    pub chat_id: Option<String>, // 为空时不生成/保存滚动摘要
+    pub tools: Option<&'a [ai::ToolDefinition]>,
+}
+
+// 计算消息的指纹（用于判断摘要覆盖的历史是否发生变化）
+// 指纹会保存到数据库，使用 SHA-256 保证在不同版本和平台上结果一致
+fn hash_messages(messages: &[ai::ChatMessage]) -> String {
+    // 每个字段带长度前缀写入，避免不同字段拼接后产生相同的输入
+    fn update(context: &mut digest::Context, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                context.update(&[1]);
+                context.update(&(value.len() as u64).to_le_bytes());
+                context.update(value.as_bytes());
+            }
+            None => context.update(&[0]),
+        }
+    }
+
+    let mut context = digest::Context::new(&digest::SHA256);
+    for message in messages {
+        update(&mut context, Some(&message.role));
+        update(&mut context, message.content.as_deref());
+        update(&mut context, message.tool_call_id.as_deref());
+        let tool_calls = message.tool_calls.as_deref().unwrap_or_default();
+        context.update(&(tool_calls.len() as u64).to_le_bytes());
+        for tool_call in tool_calls {
+            update(&mut context, Some(&tool_call.id));
+            update(&mut context, Some(&tool_call.function.name));
+            update(&mut context, Some(&tool_call.function.arguments));
+        }
+    }
+    context
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        text.to_string()
+    } else {
+        text.chars().take(max_chars).collect::<String>() + "…"
+    }
+}
+
+// 截断工具结果，只保留开头的预览
+fn truncate_tool_result(message: &mut ai::ChatMessage) -> bool {
+    let content = match &message.content {
+        Some(content) if estimate_tokens(content) > OLD_TOOL_RESULT_MAX_TOKENS => content,
+        _ => return false,
+    };
+    let original_tokens = estimate_tokens(content);
+    let tool_name = message.name.clone().unwrap_or_else(|| "工具".to_string());
+    message.content = Some(format!(
+        "{}\n\n[为节省上下文，较早的 {} 结果已截断（原始约 {} tokens），如需完整内容请重新调用该工具]",
+        truncate_chars(content, TOOL_RESULT_PREVIEW_CHARS),
+        tool_name,
+        original_tokens
+    ));
+    true
+}
+
+// 对话轮次的起点（每条 user 消息开始一轮）
+fn turn_starts(history: &[ai::ChatMessage]) -> Vec<usize> {
+    history
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| m.role == "user")
+        .map(|(i, _)| i)
+        .collect()
+}
+
+// 将消息渲染为用于生成摘要的文本
+fn render_for_summary(messages: &[ai::ChatMessage]) -> String {
+    let mut lines = Vec::new();
+    for message in messages {
+        let content = message.content.as_deref().unwrap_or("");
+        match message.role.as_str() {
+            "user" => lines.push(format!("用户: {}", truncate_chars(content, SUMMARY_INPUT_MAX_CHARS))),
+            "assistant" => {
+                if !content.is_empty() {
+                    lines.push(format!("助手: {}", truncate_chars(content, SUMMARY_INPUT_MAX_CHARS)));
+                }
+                if let Some(tool_calls) = &message.tool_calls {
+                    for tool_call in tool_calls {
+                        lines.push(format!(
+                            "助手调用工具 {}，参数: {}",
+                            tool_call.function.name,
+                            truncate_chars(&tool_call.function.arguments, 300)
+                        ));
+                    }
+                }
+            }
+            "tool" => lines.push(format!(
+                "工具 {} 返回: {}",
+                message.name.as_deref().unwrap_or(""),
+                truncate_chars(content, SUMMARY_INPUT_MAX_CHARS)
+            )),
+            _ => {}
+        }
+    }
+    lines.join("\n")
+}
+
+async fn generate_summary(
+    ai_config: &AIConfig,
+    previous_summary: Option<&str>,
+    messages: &[ai::ChatMessage],
+) -> Result<ai::CompletionOutput, String> {
+    let mut input = String::new();
+    if let Some(previous) = previous_summary {
+        input.push_str(&format!("此前的对话摘要：\n{}\n\n", previous));
+    }
+    input.push_str(&format!("需要压缩的后续对话：\n{}", render_for_summary(messages)));
+
+    ai::complete_once(
+        ai_config,
+        vec![
+            ai::ChatMessage {
+                role: "system".to_string(),
+                content: Some(SUMMARY_PROMPT.to_string()),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+                cache_control: None,
+            },
+            ai::ChatMessage {
+                role: "user".to_string(),
+                content: Some(input),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+                cache_control: None,
+            },
+        ],
+        Some(0.3),
+    )
+    .await
+}
+
+async fn load_summary(db_path: PathBuf, chat_id: String) -> Result<Option<ChatSummary>, String> {
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::get_chat_summary(&conn, &chat_id)
+            .map_err(|e| format!("无法读取对话摘要: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+async fn store_summary(db_path: PathBuf, summary: ChatSummary) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::save_chat_summary(&conn, &summary)
+            .map_err(|e| format!("无法保存对话摘要: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 组装最终消息：system 消息（附带摘要） + 保留的历史
+fn assemble(system: &[ai::ChatMessage], summary: Option<&str>, history: &[ai::ChatMessage]) -> Vec<ai::ChatMessage> {
+    let mut messages = system.to_vec();
+    if let Some(summary) = summary {
+        let section = format!("之前对话的摘要（较早的消息已被压缩）：\n{}", summary);
+        match messages.first_mut() {
+            Some(first) => {
+                let content = first.content.take().unwrap_or_default();
+                first.content = Some(if content.is_empty() { section } else { format!("{}\n\n{}", content, section) });
+            }
+            None => messages.push(ai::ChatMessage {
+                role: "system".to_string(),
+                content: Some(section),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+                cache_control: None,
+            }),
+        }
+    }
+    messages.extend_from_slice(history);
+    messages
+}
+
+// 在构建请求前将消息裁剪到模型的上下文预算内
+// 依次尝试：截断较早的工具结果 -> 使用/生成早期对话的滚动摘要 -> 丢弃最早的对话轮次
+// 当前轮次（最后一条 user 消息之后的内容）只在最后一步才会截断工具结果，不会被丢弃
+pub async fn fit_messages(
+    options: &ContextFitOptions<'_>,
+    messages: Vec<ai::ChatMessage>,
+) -> (Vec<ai::ChatMessage>, ContextFitReport) {
+    let budget = message_budget(options.ai_config, estimate_tools_tokens(options.tools));
+    let original_tokens = estimate_messages_tokens(&messages);
+    let mut report = ContextFitReport {
+        budget_tokens: budget,
+        original_tokens,
+        final_tokens: original_tokens,
+        ..Default::default()
+    };
+
+    // 分离开头的 system 消息和历史消息
+    let system_count = messages.iter().take_while(|m| m.role == "system").count();
+    let system: Vec<ai::ChatMessage> = messages[..system_count].to_vec();
+    let original_history: Vec<ai::ChatMessage> = messages[system_count..].to_vec();
+
+    // 读取已有摘要，仅当其覆盖的历史未被修改时才使用
+    let stored_summary = match &options.chat_id {
+        Some(chat_id) => load_summary(options.db_path.clone(), chat_id.clone())
+            .await
+            .unwrap_or_else(|e| {
+                eprintln!("[Context] {}", e);
+                None
+            })
+            .filter(|s| {
+                let covered = s.covered_count.max(0) as usize;
+                covered <= original_history.len() && hash_messages(&original_history[..covered]) == s.covered_hash
+            }),
+        None => None,
+    };
+
+    if original_tokens <= budget && stored_summary.is_none() {
+        return (messages, report);
+    }
+
+    let starts = turn_starts(&original_history);
+    let current_turn = starts.last().copied().unwrap_or(0);
+    let mut history = original_history.clone();
+
+    // 策略 1：截断当前轮次之前的大型工具结果（从最早的开始）
+    let mut summary_text = stored_summary.as_ref().map(|s| s.summary.clone());
+    let mut summary_start = stored_summary.as_ref().map(|s| s.covered_count as usize).unwrap_or(0);
+    let total = |summary: Option<&str>, start: usize, history: &[ai::ChatMessage]| {
+        estimate_messages_tokens(&assemble(&system, summary, &history[start..]))
+    };
+
+    for i in summary_start..current_turn {
+        if total(summary_text.as_deref(), summary_start, &history) <= budget {
+            break;
+        }
+        if history[i].role == "tool" && truncate_tool_result(&mut history[i]) {
+            report.truncated_tool_results += 1;
+        }
+    }
+
+    // 策略 2：用滚动摘要替换早期对话轮次
+    if total(summary_text.as_deref(), summary_start, &history) > budget {
+        if let Some(chat_id) = &options.chat_id {
+            let target = (budget as f64 * SUMMARY_TARGET_RATIO) as usize;
+            let estimated_summary_tokens = 500;
+            let cut = starts
+                .iter()
+                .copied()
+                .filter(|&s| s > summary_start && s <= current_turn)
+                .find(|&s| {
+                    estimate_messages_tokens(&assemble(&system, None, &history[s..])) + estimated_summary_tokens <= target
+                })
+                .unwrap_or(current_turn);
+
+            if cut > summary_start {
+                match generate_summary(options.ai_config, summary_text.as_deref(), &history[summary_start..cut]).await {
+                    Ok(output) => {
+                        if let Some(token_usage) = output.usage {
+                            let scope = usage::UsageScope {
+                                chat_id: Some(chat_id.clone()),
+                                ..Default::default()
+                            };
+                            usage::record(options.db_path.clone(), options.ai_config, usage::SOURCE_CONTEXT_SUMMARY, scope, token_usage).await;
+                        }
+                        let summary = output.content;
+                        let record = ChatSummary {
+                            chat_id: chat_id.clone(),
+                            summary: summary.clone(),
+                            covered_count: cut as i64,
+                            covered_hash: hash_messages(&original_history[..cut]),
+                            updated_at: Utc::now().to_rfc3339(),
+                        };
+                        if let Err(e) = store_summary(options.db_path.clone(), record).await {
+                            eprintln!("[Context] {}", e);
+                        }
+                        summary_text = Some(summary);
+                        summary_start = cut;
+                    }
+                    Err(e) => eprintln!("[Context] 生成对话摘要失败: {}", e),
+                }
+            }
+        }
+    }
+    report.summarized_messages = summary_start;
+
+    // 策略 3：丢弃最早的对话轮次
+    let mut start = summary_start;
+    for &turn in starts.iter().filter(|&&s| s > summary_start && s <= current_turn) {
+        if total(summary_text.as_deref(), start, &history) <= budget {
+            break;
+        }
+        start = turn;
+    }
+    report.dropped_messages = start - summary_start;
+
+    // 最后手段：截断当前轮次中的工具结果
+    if total(summary_text.as_deref(), start, &history) > budget {
+        for message in history[start..].iter_mut() {
+            if message.role == "tool" && truncate_tool_result(message) {
+                report.truncated_tool_results += 1;
+            }
+        }
+    }
+
+    let result = assemble(&system, summary_text.as_deref(), &history[start..]);
+    report.final_tokens = estimate_messages_tokens(&result);
+    (result, report)
+}
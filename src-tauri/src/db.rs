@@ -1,7 +1,8 @@
-use rusqlite::{Connection, Result as SqlResult, params};
+use rusqlite::{Connection, OptionalExtension, Result as SqlResult, params};
 use std::path::PathBuf;
 use serde_json;
-use crate::{TranscriptionResource, TranscriptionTask, TranscriptionParams, ResourceType, SourceType, Platform, AIConfig, Chat, Message};
+use crate::{TranscriptionResource, TranscriptionTask, TranscriptionParams, ResourceType, SourceType, Platform, AIConfig, Chat, ChatContext, ChatSummary, Message, MCPToolCallLog, UsageRecord, UsageReportRow, PromptTemplate, SubtitleTrack, WatchFolder, WatchFolderFile};
+use crate::prompt_templates;
 
 // 获取数据库路径
 pub fn get_db_path(app_data_dir: &PathBuf) -> PathBuf {
@@ -51,6 +52,39 @@ pub fn init_database(db_path: &PathBuf) -> SqlResult<Connection> {
         [],
     );
     
+    // 迁移：添加 uploader 和 duration 字段（可选，URL 资源的元数据）
+    // 迁移：添加 upload_date、description、tags 和 chapters 字段（可选，tags 和 chapters 为 JSON 格式）
+    for column in ["uploader TEXT", "duration REAL", "upload_date TEXT", "description TEXT", "tags TEXT", "chapters TEXT"] {
+        let _ = conn.execute(
+            &format!("ALTER TABLE transcription_resources ADD COLUMN {}", column),
+            [],
+        );
+    }
+    
+    // 迁移：添加 content_hash 字段（可选，文件内容哈希，用于检测重复导入）
+    let _ = conn.execute(
+        "ALTER TABLE transcription_resources ADD COLUMN content_hash TEXT",
+        [],
+    );
+    let _ = conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_transcription_resources_content_hash ON transcription_resources(content_hash)",
+        [],
+    );
+    
+    // 迁移：添加 extraction_status 和 extraction_error 字段（可选，视频资源的音频提取状态）
+    for column in ["extraction_status TEXT", "extraction_error TEXT"] {
+        let _ = conn.execute(
+            &format!("ALTER TABLE transcription_resources ADD COLUMN {}", column),
+            [],
+        );
+    }
+    
+    // 迁移：添加 media_info 字段（可选，导入时探测的媒体信息，JSON 格式）
+    let _ = conn.execute(
+        "ALTER TABLE transcription_resources ADD COLUMN media_info TEXT",
+        [],
+    );
+    
     // 迁移：添加 topics 字段（可选，JSON 格式）
     let _ = conn.execute(
         "ALTER TABLE transcription_resources ADD COLUMN topics TEXT",
@@ -127,6 +161,20 @@ pub fn init_database(db_path: &PathBuf) -> SqlResult<Connection> {
         [],
     ).ok(); // 如果字段已存在，忽略错误
     
+    // 添加 context_window 字段（模型上下文窗口大小，单位 token）
+    conn.execute(
+        "ALTER TABLE ai_configs ADD COLUMN context_window INTEGER",
+        [],
+    ).ok(); // 如果字段已存在，忽略错误
+    
+    // 添加模型价格字段（单位：每百万 token）
+    for column in ["input_price", "output_price", "cached_input_price"] {
+        conn.execute(
+            &format!("ALTER TABLE ai_configs ADD COLUMN {} REAL", column),
+            [],
+        ).ok(); // 如果字段已存在，忽略错误
+    }
+    
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_ai_configs_created_at ON ai_configs(created_at)",
         [],
@@ -171,6 +219,28 @@ pub fn init_database(db_path: &PathBuf) -> SqlResult<Connection> {
         [],
     );
     
+    // 迁移：添加 parent_message_id 字段（消息树），首次添加时按时间顺序把已有消息串成一条链
+    // 加列与回填在同一事务中，回填失败时连同新列一起回滚，下次启动重新迁移
+    let tx = conn.unchecked_transaction()?;
+    if tx.execute("ALTER TABLE messages ADD COLUMN parent_message_id TEXT", []).is_ok() {
+        tx.execute(
+            "UPDATE messages SET parent_message_id = (
+                SELECT p.prev_id FROM (
+                    SELECT id, LAG(id) OVER (PARTITION BY chat_id ORDER BY created_at, rowid) AS prev_id
+                    FROM messages
+                ) p WHERE p.id = messages.id
+            )",
+            [],
+        )?;
+    }
+    tx.commit()?;
+    
+    // 迁移：chats 表记录当前激活分支的最后一条消息
+    let _ = conn.execute(
+        "ALTER TABLE chats ADD COLUMN active_leaf_id TEXT",
+        [],
+    );
+    
     conn.execute(
         "CREATE INDEX IF NOT EXISTS idx_messages_chat_id ON messages(chat_id)",
         [],
@@ -181,6 +251,198 @@ pub fn init_database(db_path: &PathBuf) -> SqlResult<Connection> {
         [],
     )?;
     
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_messages_parent_message_id ON messages(parent_message_id)",
+        [],
+    )?;
+    
+    // 创建 chat_contexts 表（chat 关联的资源/任务）
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS chat_contexts (
+            chat_id TEXT NOT NULL,
+            context_type TEXT NOT NULL,
+            context_id TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY (chat_id, context_type, context_id),
+            FOREIGN KEY (chat_id) REFERENCES chats(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_chat_contexts_context ON chat_contexts(context_type, context_id)",
+        [],
+    )?;
+    
+    // 创建 chat_summaries 表（早期对话的滚动摘要）
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS chat_summaries (
+            chat_id TEXT PRIMARY KEY,
+            summary TEXT NOT NULL,
+            covered_count INTEGER NOT NULL,
+            covered_hash TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY (chat_id) REFERENCES chats(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+    
+    // 创建 MCP 工具调用审计日志表
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS mcp_tool_call_logs (
+            id TEXT PRIMARY KEY,
+            server_name TEXT NOT NULL,
+            tool_name TEXT NOT NULL,
+            arguments TEXT NOT NULL,
+            policy TEXT NOT NULL,
+            decision TEXT NOT NULL,
+            approved_by TEXT,
+            success INTEGER NOT NULL DEFAULT 0,
+            result_size INTEGER,
+            duration_ms INTEGER NOT NULL DEFAULT 0,
+            error TEXT,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_mcp_tool_call_logs_created_at ON mcp_tool_call_logs(created_at DESC)",
+        [],
+    )?;
+    
+    // 创建提示词模板表，并写入内置默认模板（用户修改过的不覆盖）
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS prompt_templates (
+            kind TEXT NOT NULL,
+            language TEXT NOT NULL,
+            system_prompt TEXT NOT NULL,
+            user_prompt TEXT NOT NULL,
+            is_customized INTEGER NOT NULL DEFAULT 0,
+            updated_at TEXT NOT NULL,
+            PRIMARY KEY (kind, language)
+        )",
+        [],
+    )?;
+    
+    // 写入内置模板；未被用户修改的模板随内置默认值更新
+    for template in prompt_templates::default_templates() {
+        conn.execute(
+            "INSERT INTO prompt_templates (kind, language, system_prompt, user_prompt, is_customized, updated_at)
+             VALUES (?1, ?2, ?3, ?4, 0, ?5)
+             ON CONFLICT(kind, language) DO UPDATE SET
+                system_prompt = excluded.system_prompt,
+                user_prompt = excluded.user_prompt,
+                updated_at = excluded.updated_at
+             WHERE is_customized = 0
+               AND (system_prompt != excluded.system_prompt OR user_prompt != excluded.user_prompt)",
+            params![template.kind, template.language, template.system_prompt, template.user_prompt, template.updated_at],
+        )?;
+    }
+    
+    // 创建 token 用量记录表（删除 chat / 资源后保留记录，用于费用统计）
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS usage_records (
+            id TEXT PRIMARY KEY,
+            config_id TEXT NOT NULL,
+            model TEXT NOT NULL,
+            source TEXT NOT NULL,
+            chat_id TEXT,
+            message_id TEXT,
+            task_id TEXT,
+            resource_id TEXT,
+            prompt_tokens INTEGER NOT NULL DEFAULT 0,
+            completion_tokens INTEGER NOT NULL DEFAULT 0,
+            cached_tokens INTEGER NOT NULL DEFAULT 0,
+            cost REAL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    
+    // 添加 cache_write_tokens 字段（写入 prompt 缓存的 token 数）
+    conn.execute(
+        "ALTER TABLE usage_records ADD COLUMN cache_write_tokens INTEGER NOT NULL DEFAULT 0",
+        [],
+    ).ok(); // 如果字段已存在，忽略错误
+    
+    // 添加 event_id 字段（流式对话的事件 ID，用于关联之后保存的助手消息）
+    conn.execute(
+        "ALTER TABLE usage_records ADD COLUMN event_id TEXT",
+        [],
+    ).ok(); // 如果字段已存在，忽略错误
+    
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_usage_records_event_id ON usage_records(event_id)",
+        [],
+    )?;
+    
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_usage_records_created_at ON usage_records(created_at)",
+        [],
+    )?;
+    
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_usage_records_chat_id ON usage_records(chat_id)",
+        [],
+    )?;
+    
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_usage_records_task_id ON usage_records(task_id)",
+        [],
+    )?;
+    
+    // 创建字幕轨道表（URL 资源的每条字幕单独保存，任务的 result 指向主轨道）
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS subtitle_tracks (
+            id TEXT PRIMARY KEY,
+            task_id TEXT NOT NULL,
+            language TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            file_path TEXT NOT NULL,
+            is_primary INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_subtitle_tracks_task_id ON subtitle_tracks(task_id)",
+        [],
+    )?;
+    
+    // 创建监视文件夹表
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS watch_folders (
+            id TEXT PRIMARY KEY,
+            path TEXT NOT NULL UNIQUE,
+            enabled INTEGER NOT NULL DEFAULT 1,
+            params TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    
+    // 创建监视文件夹文件处理记录表
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS watch_folder_files (
+            file_path TEXT PRIMARY KEY,
+            folder_id TEXT NOT NULL,
+            status TEXT NOT NULL,
+            resource_id TEXT,
+            task_id TEXT,
+            error TEXT,
+            processed_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_watch_folder_files_folder_id ON watch_folder_files(folder_id)",
+        [],
+    )?;
+    
     Ok(conn)
 }
 
@@ -246,12 +508,23 @@ fn string_to_topics(s: Option<String>) -> Option<Vec<crate::Topic>> {
     })
 }
 
+// 将可选值序列化为 JSON 字符串（用于 tags、chapters 等列表字段）
+fn json_to_string<T: serde::Serialize>(value: &Option<T>) -> Option<String> {
+    value.as_ref().and_then(|v| serde_json::to_string(v).ok())
+}
+
+// 从 JSON 字符串反序列化可选值
+fn string_to_json<T: serde::de::DeserializeOwned>(s: Option<String>) -> Option<T> {
+    s.and_then(|s| serde_json::from_str(&s).ok())
+}
+
 // 资源 CRUD 操作
 pub fn create_resource(conn: &Connection, resource: &TranscriptionResource) -> SqlResult<()> {
     conn.execute(
         "INSERT INTO transcription_resources 
-         (id, name, file_path, resource_type, source_type, platform, extracted_audio_path, latest_completed_task_id, cover_url, created_at, updated_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+         (id, name, file_path, resource_type, source_type, platform, extracted_audio_path, latest_completed_task_id, cover_url, created_at, updated_at, uploader, duration,
+          upload_date, description, tags, chapters, content_hash, media_info)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
         params![
             resource.id,
             resource.name,
@@ -264,6 +537,14 @@ pub fn create_resource(conn: &Connection, resource: &TranscriptionResource) -> S
             resource.cover_url,
             resource.created_at,
             resource.updated_at,
+            resource.uploader,
+            resource.duration,
+            resource.upload_date,
+            resource.description,
+            json_to_string(&resource.tags),
+            json_to_string(&resource.chapters),
+            resource.content_hash,
+            json_to_string(&resource.media_info),
         ],
     )?;
     Ok(())
@@ -272,7 +553,7 @@ pub fn create_resource(conn: &Connection, resource: &TranscriptionResource) -> S
 pub fn get_resource(conn: &Connection, resource_id: &str) -> SqlResult<Option<TranscriptionResource>> {
     // 尝试最新格式（包含 source_type, platform 和 cover_url）
     let stmt = conn.prepare(
-        "SELECT id, name, file_path, resource_type, source_type, platform, extracted_audio_path, latest_completed_task_id, cover_url, created_at, updated_at
+        "SELECT id, name, file_path, resource_type, source_type, platform, extracted_audio_path, latest_completed_task_id, cover_url, created_at, updated_at, uploader, duration, upload_date, description, tags, chapters, content_hash, extraction_status, extraction_error, media_info
          FROM transcription_resources WHERE id = ?1"
     );
     
@@ -288,6 +569,16 @@ pub fn get_resource(conn: &Connection, resource_id: &str) -> SqlResult<Option<Tr
                 extracted_audio_path: row.get(6)?,
                 latest_completed_task_id: row.get(7)?,
                 cover_url: row.get(8)?,
+                uploader: row.get(11)?,
+                duration: row.get(12)?,
+                upload_date: row.get(13)?,
+                description: row.get(14)?,
+                tags: string_to_json(row.get(15)?),
+                chapters: string_to_json(row.get(16)?),
+                content_hash: row.get(17)?,
+                extraction_status: row.get(18)?,
+                extraction_error: row.get(19)?,
+                media_info: string_to_json(row.get(20)?),
                 created_at: row.get(9)?,
                 updated_at: row.get(10)?,
             })
@@ -316,6 +607,16 @@ pub fn get_resource(conn: &Connection, resource_id: &str) -> SqlResult<Option<Tr
                 extracted_audio_path: row.get(6)?,
                 latest_completed_task_id: row.get(7)?,
                 cover_url: None, // 默认值
+                uploader: None,
+                duration: None,
+                upload_date: None,
+                description: None,
+                tags: None,
+                chapters: None,
+                content_hash: None,
+                extraction_status: None,
+                extraction_error: None,
+                media_info: None,
                 created_at: row.get(8)?,
                 updated_at: row.get(9)?,
             })
@@ -344,6 +645,16 @@ pub fn get_resource(conn: &Connection, resource_id: &str) -> SqlResult<Option<Tr
                 extracted_audio_path: row.get(4)?,
                 latest_completed_task_id: row.get(5)?,
                 cover_url: None, // 默认值
+                uploader: None,
+                duration: None,
+                upload_date: None,
+                description: None,
+                tags: None,
+                chapters: None,
+                content_hash: None,
+                extraction_status: None,
+                extraction_error: None,
+                media_info: None,
                 created_at: row.get(6)?,
                 updated_at: row.get(7)?,
             })
@@ -371,6 +682,16 @@ pub fn get_resource(conn: &Connection, resource_id: &str) -> SqlResult<Option<Tr
             extracted_audio_path: row.get(4)?,
             latest_completed_task_id: row.get(6)?, // 跳过 status (5)
             cover_url: None, // 默认值
+            uploader: None,
+            duration: None,
+            upload_date: None,
+            description: None,
+            tags: None,
+            chapters: None,
+            content_hash: None,
+            extraction_status: None,
+            extraction_error: None,
+            media_info: None,
             created_at: row.get(7)?,
             updated_at: row.get(8)?,
         })
@@ -385,7 +706,7 @@ pub fn get_resource(conn: &Connection, resource_id: &str) -> SqlResult<Option<Tr
 pub fn get_all_resources(conn: &Connection) -> SqlResult<Vec<TranscriptionResource>> {
     // 尝试最新格式（包含 source_type, platform, cover_url 和 topics）
     let stmt = conn.prepare(
-        "SELECT id, name, file_path, resource_type, source_type, platform, extracted_audio_path, latest_completed_task_id, cover_url, topics, created_at, updated_at
+        "SELECT id, name, file_path, resource_type, source_type, platform, extracted_audio_path, latest_completed_task_id, cover_url, topics, created_at, updated_at, uploader, duration, upload_date, description, tags, chapters, content_hash, extraction_status, extraction_error, media_info
          FROM transcription_resources
          ORDER BY created_at DESC"
     );
@@ -402,6 +723,16 @@ pub fn get_all_resources(conn: &Connection) -> SqlResult<Vec<TranscriptionResour
                 extracted_audio_path: row.get(6)?,
                 latest_completed_task_id: row.get(7)?,
                 cover_url: row.get(8)?,
+                uploader: row.get(12)?,
+                duration: row.get(13)?,
+                upload_date: row.get(14)?,
+                description: row.get(15)?,
+                tags: string_to_json(row.get(16)?),
+                chapters: string_to_json(row.get(17)?),
+                content_hash: row.get(18)?,
+                extraction_status: row.get(19)?,
+                extraction_error: row.get(20)?,
+                media_info: string_to_json(row.get(21)?),
                 created_at: row.get(10)?,
                 updated_at: row.get(11)?,
             })
@@ -433,6 +764,16 @@ pub fn get_all_resources(conn: &Connection) -> SqlResult<Vec<TranscriptionResour
                 extracted_audio_path: row.get(6)?,
                 latest_completed_task_id: row.get(7)?,
                 cover_url: None, // 默认值
+                uploader: None,
+                duration: None,
+                upload_date: None,
+                description: None,
+                tags: None,
+                chapters: None,
+                content_hash: None,
+                extraction_status: None,
+                extraction_error: None,
+                media_info: None,
                 created_at: row.get(8)?,
                 updated_at: row.get(9)?,
             })
@@ -464,6 +805,16 @@ pub fn get_all_resources(conn: &Connection) -> SqlResult<Vec<TranscriptionResour
                 extracted_audio_path: row.get(4)?,
                 latest_completed_task_id: row.get(5)?,
                 cover_url: None, // 默认值
+                uploader: None,
+                duration: None,
+                upload_date: None,
+                description: None,
+                tags: None,
+                chapters: None,
+                content_hash: None,
+                extraction_status: None,
+                extraction_error: None,
+                media_info: None,
                 created_at: row.get(6)?,
                 updated_at: row.get(7)?,
             })
@@ -494,6 +845,16 @@ pub fn get_all_resources(conn: &Connection) -> SqlResult<Vec<TranscriptionResour
             extracted_audio_path: row.get(4)?,
             latest_completed_task_id: row.get(6)?, // 跳过 status (5)
             cover_url: None, // 默认值
+            uploader: None,
+            duration: None,
+            upload_date: None,
+            description: None,
+            tags: None,
+            chapters: None,
+            content_hash: None,
+            extraction_status: None,
+            extraction_error: None,
+            media_info: None,
             created_at: row.get(7)?,
             updated_at: row.get(8)?,
         })
@@ -510,7 +871,7 @@ pub fn search_resources(conn: &Connection, keyword: &str) -> SqlResult<Vec<Trans
     let search_pattern = format!("%{}%", keyword);
     // 尝试最新格式（包含 source_type, platform 和 cover_url）
     let stmt = conn.prepare(
-        "SELECT id, name, file_path, resource_type, source_type, platform, extracted_audio_path, latest_completed_task_id, cover_url, created_at, updated_at
+        "SELECT id, name, file_path, resource_type, source_type, platform, extracted_audio_path, latest_completed_task_id, cover_url, created_at, updated_at, uploader, duration, upload_date, description, tags, chapters, content_hash, extraction_status, extraction_error, media_info
          FROM transcription_resources
          WHERE name LIKE ?1 OR file_path LIKE ?1
          ORDER BY created_at DESC"
@@ -528,6 +889,16 @@ pub fn search_resources(conn: &Connection, keyword: &str) -> SqlResult<Vec<Trans
                 extracted_audio_path: row.get(6)?,
                 latest_completed_task_id: row.get(7)?,
                 cover_url: row.get(8)?,
+                uploader: row.get(11)?,
+                duration: row.get(12)?,
+                upload_date: row.get(13)?,
+                description: row.get(14)?,
+                tags: string_to_json(row.get(15)?),
+                chapters: string_to_json(row.get(16)?),
+                content_hash: row.get(17)?,
+                extraction_status: row.get(18)?,
+                extraction_error: row.get(19)?,
+                media_info: string_to_json(row.get(20)?),
                 created_at: row.get(9)?,
                 updated_at: row.get(10)?,
             })
@@ -560,6 +931,16 @@ pub fn search_resources(conn: &Connection, keyword: &str) -> SqlResult<Vec<Trans
                 extracted_audio_path: row.get(6)?,
                 latest_completed_task_id: row.get(7)?,
                 cover_url: None, // 默认值
+                uploader: None,
+                duration: None,
+                upload_date: None,
+                description: None,
+                tags: None,
+                chapters: None,
+                content_hash: None,
+                extraction_status: None,
+                extraction_error: None,
+                media_info: None,
                 created_at: row.get(8)?,
                 updated_at: row.get(9)?,
             })
@@ -592,6 +973,16 @@ pub fn search_resources(conn: &Connection, keyword: &str) -> SqlResult<Vec<Trans
                 extracted_audio_path: row.get(4)?,
                 latest_completed_task_id: row.get(5)?,
                 cover_url: None, // 默认值
+                uploader: None,
+                duration: None,
+                upload_date: None,
+                description: None,
+                tags: None,
+                chapters: None,
+                content_hash: None,
+                extraction_status: None,
+                extraction_error: None,
+                media_info: None,
                 created_at: row.get(6)?,
                 updated_at: row.get(7)?,
             })
@@ -623,6 +1014,16 @@ pub fn search_resources(conn: &Connection, keyword: &str) -> SqlResult<Vec<Trans
             extracted_audio_path: row.get(4)?,
             latest_completed_task_id: row.get(6)?, // 跳过 status (5)
             cover_url: None, // 默认值
+            uploader: None,
+            duration: None,
+            upload_date: None,
+            description: None,
+            tags: None,
+            chapters: None,
+            content_hash: None,
+            extraction_status: None,
+            extraction_error: None,
+            media_info: None,
             created_at: row.get(7)?,
             updated_at: row.get(8)?,
         })
@@ -635,12 +1036,55 @@ pub fn search_resources(conn: &Connection, keyword: &str) -> SqlResult<Vec<Trans
     Ok(resources)
 }
 
+// 按内容哈希查找资源（最早导入的在前）
+pub fn get_resources_by_content_hash(conn: &Connection, content_hash: &str) -> SqlResult<Vec<TranscriptionResource>> {
+    let mut stmt = conn.prepare(
+        "SELECT id FROM transcription_resources WHERE content_hash = ?1 ORDER BY created_at ASC"
+    )?;
+    let ids = stmt
+        .query_map(params![content_hash], |row| row.get::<_, String>(0))?
+        .collect::<SqlResult<Vec<String>>>()?;
+    
+    let mut resources = Vec::new();
+    for id in ids {
+        if let Some(resource) = get_resource(conn, &id)? {
+            resources.push(resource);
+        }
+    }
+    Ok(resources)
+}
+
+// 更新音频提取状态（单独更新，避免长时间操作中持有的旧资源数据覆盖提取状态）
+pub fn update_resource_extraction_status(
+    conn: &Connection,
+    resource_id: &str,
+    status: Option<&str>,
+    error: Option<&str>,
+) -> SqlResult<()> {
+    conn.execute(
+        "UPDATE transcription_resources SET extraction_status = ?2, extraction_error = ?3 WHERE id = ?1",
+        params![resource_id, status, error],
+    )?;
+    Ok(())
+}
+
+// 应用启动时将上次未完成的音频提取标记为失败（ffmpeg 进程已随应用退出）
+pub fn fail_interrupted_extractions(conn: &Connection) -> SqlResult<usize> {
+    conn.execute(
+        "UPDATE transcription_resources SET extraction_status = 'failed', extraction_error = '应用退出，音频提取已中断'
+         WHERE extraction_status = 'extracting'",
+        [],
+    )
+}
+
 pub fn update_resource(conn: &Connection, resource: &TranscriptionResource) -> SqlResult<()> {
     // 尝试最新格式（包含 source_type, platform 和 cover_url）
     let result = conn.execute(
         "UPDATE transcription_resources
          SET name = ?2, file_path = ?3, resource_type = ?4, source_type = ?5, platform = ?6,
-             extracted_audio_path = ?7, latest_completed_task_id = ?8, cover_url = ?9, updated_at = ?10
+             extracted_audio_path = ?7, latest_completed_task_id = ?8, cover_url = ?9, updated_at = ?10,
+             uploader = ?11, duration = ?12, upload_date = ?13, description = ?14, tags = ?15, chapters = ?16, content_hash = ?17,
+             media_info = ?18
          WHERE id = ?1",
         params![
             resource.id,
@@ -653,6 +1097,14 @@ pub fn update_resource(conn: &Connection, resource: &TranscriptionResource) -> S
             resource.latest_completed_task_id,
             resource.cover_url,
             resource.updated_at,
+            resource.uploader,
+            resource.duration,
+            resource.upload_date,
+            resource.description,
+            json_to_string(&resource.tags),
+            json_to_string(&resource.chapters),
+            resource.content_hash,
+            json_to_string(&resource.media_info),
         ],
     );
     
@@ -728,6 +1180,7 @@ pub fn delete_resource(conn: &Connection, resource_id: &str) -> SqlResult<()> {
         "DELETE FROM transcription_resources WHERE id = ?1",
         params![resource_id],
     )?;
+    delete_chat_contexts_by_target(conn, "resource", resource_id)?;
     Ok(())
 }
 
@@ -1020,6 +1473,8 @@ pub fn delete_task(conn: &Connection, task_id: &str) -> SqlResult<()> {
         "DELETE FROM transcription_tasks WHERE id = ?1",
         params![task_id],
     )?;
+    delete_chat_contexts_by_target(conn, "task", task_id)?;
+    delete_subtitle_tracks_by_task(conn, task_id)?;
     Ok(())
 }
 
@@ -1034,6 +1489,11 @@ pub fn delete_tasks_by_resource(conn: &Connection, resource_id: &str) -> SqlResu
         params![resource_id],
     )?;
     
+    for task_id in &task_ids {
+        delete_chat_contexts_by_target(conn, "task", task_id)?;
+        delete_subtitle_tracks_by_task(conn, task_id)?;
+    }
+    
     Ok(task_ids)
 }
 
@@ -1041,8 +1501,9 @@ pub fn delete_tasks_by_resource(conn: &Connection, resource_id: &str) -> SqlResu
 pub fn create_ai_config(conn: &Connection, config: &AIConfig) -> SqlResult<()> {
     let is_compression = if config.is_compression_config.unwrap_or(false) { 1 } else { 0 };
     conn.execute(
-        "INSERT INTO ai_configs (id, name, base_url, api_key, model, created_at, updated_at, is_compression_config)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        "INSERT INTO ai_configs (id, name, base_url, api_key, model, created_at, updated_at, is_compression_config, context_window,
+                input_price, output_price, cached_input_price)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
         params![
             config.id,
             config.name,
@@ -1052,6 +1513,10 @@ pub fn create_ai_config(conn: &Connection, config: &AIConfig) -> SqlResult<()> {
             config.created_at,
             config.updated_at,
             is_compression,
+            config.context_window,
+            config.input_price,
+            config.output_price,
+            config.cached_input_price,
         ],
     )?;
     Ok(())
@@ -1059,7 +1524,8 @@ pub fn create_ai_config(conn: &Connection, config: &AIConfig) -> SqlResult<()> {
 
 pub fn get_ai_config(conn: &Connection, config_id: &str) -> SqlResult<Option<AIConfig>> {
     let mut stmt = conn.prepare(
-        "SELECT id, name, base_url, api_key, model, created_at, updated_at, is_compression_config
+        "SELECT id, name, base_url, api_key, model, created_at, updated_at, is_compression_config, context_window,
+                input_price, output_price, cached_input_price
          FROM ai_configs WHERE id = ?1"
     )?;
     
@@ -1074,6 +1540,10 @@ pub fn get_ai_config(conn: &Connection, config_id: &str) -> SqlResult<Option<AIC
             created_at: row.get(5)?,
             updated_at: row.get(6)?,
             is_compression_config: is_compression.map(|v| v != 0),
+            context_window: row.get(8)?,
+            input_price: row.get(9)?,
+            output_price: row.get(10)?,
+            cached_input_price: row.get(11)?,
         })
     })?;
     
@@ -1085,7 +1555,8 @@ pub fn get_ai_config(conn: &Connection, config_id: &str) -> SqlResult<Option<AIC
 
 pub fn get_all_ai_configs(conn: &Connection) -> SqlResult<Vec<AIConfig>> {
     let mut stmt = conn.prepare(
-        "SELECT id, name, base_url, api_key, model, created_at, updated_at, is_compression_config
+        "SELECT id, name, base_url, api_key, model, created_at, updated_at, is_compression_config, context_window,
+                input_price, output_price, cached_input_price
          FROM ai_configs
          ORDER BY created_at DESC"
     )?;
@@ -1101,6 +1572,10 @@ pub fn get_all_ai_configs(conn: &Connection) -> SqlResult<Vec<AIConfig>> {
             created_at: row.get(5)?,
             updated_at: row.get(6)?,
             is_compression_config: is_compression.map(|v| v != 0),
+            context_window: row.get(8)?,
+            input_price: row.get(9)?,
+            output_price: row.get(10)?,
+            cached_input_price: row.get(11)?,
         })
     })?;
     
@@ -1115,7 +1590,8 @@ pub fn update_ai_config(conn: &Connection, config: &AIConfig) -> SqlResult<()> {
     let is_compression = if config.is_compression_config.unwrap_or(false) { 1 } else { 0 };
     conn.execute(
         "UPDATE ai_configs
-         SET name = ?2, base_url = ?3, api_key = ?4, model = ?5, updated_at = ?6, is_compression_config = ?7
+         SET name = ?2, base_url = ?3, api_key = ?4, model = ?5, updated_at = ?6, is_compression_config = ?7, context_window = ?8,
+             input_price = ?9, output_price = ?10, cached_input_price = ?11
          WHERE id = ?1",
         params![
             config.id,
@@ -1125,6 +1601,10 @@ pub fn update_ai_config(conn: &Connection, config: &AIConfig) -> SqlResult<()> {
             config.model,
             config.updated_at,
             is_compression,
+            config.context_window,
+            config.input_price,
+            config.output_price,
+            config.cached_input_price,
         ],
     )?;
     Ok(())
@@ -1133,7 +1613,8 @@ pub fn update_ai_config(conn: &Connection, config: &AIConfig) -> SqlResult<()> {
 // 获取用于压缩的 AI 配置
 pub fn get_compression_config(conn: &Connection) -> SqlResult<Option<AIConfig>> {
     let mut stmt = conn.prepare(
-        "SELECT id, name, base_url, api_key, model, created_at, updated_at, is_compression_config
+        "SELECT id, name, base_url, api_key, model, created_at, updated_at, is_compression_config, context_window,
+                input_price, output_price, cached_input_price
          FROM ai_configs WHERE is_compression_config = 1 LIMIT 1"
     )?;
     
@@ -1148,6 +1629,10 @@ pub fn get_compression_config(conn: &Connection) -> SqlResult<Option<AIConfig>>
             created_at: row.get(5)?,
             updated_at: row.get(6)?,
             is_compression_config: is_compression.map(|v| v != 0),
+            context_window: row.get(8)?,
+            input_price: row.get(9)?,
+            output_price: row.get(10)?,
+            cached_input_price: row.get(11)?,
         })
     })?;
     
@@ -1260,14 +1745,165 @@ pub fn delete_chat(conn: &Connection, chat_id: &str) -> SqlResult<()> {
         "DELETE FROM chats WHERE id = ?1",
         params![chat_id],
     )?;
+    conn.execute(
+        "DELETE FROM chat_contexts WHERE chat_id = ?1",
+        params![chat_id],
+    )?;
+    conn.execute(
+        "DELETE FROM chat_summaries WHERE chat_id = ?1",
+        params![chat_id],
+    )?;
+    Ok(())
+}
+
+// Chat 滚动摘要操作
+pub fn get_chat_summary(conn: &Connection, chat_id: &str) -> SqlResult<Option<ChatSummary>> {
+    let mut stmt = conn.prepare(
+        "SELECT chat_id, summary, covered_count, covered_hash, updated_at
+         FROM chat_summaries WHERE chat_id = ?1"
+    )?;
+    
+    let summary_iter = stmt.query_map(params![chat_id], |row| {
+        Ok(ChatSummary {
+            chat_id: row.get(0)?,
+            summary: row.get(1)?,
+            covered_count: row.get(2)?,
+            covered_hash: row.get(3)?,
+            updated_at: row.get(4)?,
+        })
+    })?;
+    
+    for summary in summary_iter {
+        return Ok(Some(summary?));
+    }
+    Ok(None)
+}
+
+pub fn save_chat_summary(conn: &Connection, summary: &ChatSummary) -> SqlResult<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO chat_summaries (chat_id, summary, covered_count, covered_hash, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            summary.chat_id,
+            summary.summary,
+            summary.covered_count,
+            summary.covered_hash,
+            summary.updated_at,
+        ],
+    )?;
+    Ok(())
+}
+
+// 获取关联到资源的 chats（包括关联到该资源下任务的 chats）
+pub fn get_chats_by_resource(conn: &Connection, resource_id: &str) -> SqlResult<Vec<Chat>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, created_at, updated_at
+         FROM chats
+         WHERE id IN (
+             SELECT chat_id FROM chat_contexts
+             WHERE context_type = 'resource' AND context_id = ?1
+             UNION
+             SELECT cc.chat_id FROM chat_contexts cc
+             JOIN transcription_tasks t ON t.id = cc.context_id
+             WHERE cc.context_type = 'task' AND t.resource_id = ?1
+         )
+         ORDER BY updated_at DESC"
+    )?;
+    
+    let chat_iter = stmt.query_map(params![resource_id], |row| {
+        Ok(Chat {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            created_at: row.get(2)?,
+            updated_at: row.get(3)?,
+        })
+    })?;
+    
+    let mut chats = Vec::new();
+    for chat in chat_iter {
+        chats.push(chat?);
+    }
+    Ok(chats)
+}
+
+// Chat 上下文操作
+pub fn add_chat_context(conn: &Connection, context: &ChatContext) -> SqlResult<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO chat_contexts (chat_id, context_type, context_id, created_at)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![
+            context.chat_id,
+            context.context_type,
+            context.context_id,
+            context.created_at,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn remove_chat_context(conn: &Connection, chat_id: &str, context_type: &str, context_id: &str) -> SqlResult<()> {
+    conn.execute(
+        "DELETE FROM chat_contexts WHERE chat_id = ?1 AND context_type = ?2 AND context_id = ?3",
+        params![chat_id, context_type, context_id],
+    )?;
+    Ok(())
+}
+
+pub fn get_chat_contexts(conn: &Connection, chat_id: &str) -> SqlResult<Vec<ChatContext>> {
+    let mut stmt = conn.prepare(
+        "SELECT chat_id, context_type, context_id, created_at
+         FROM chat_contexts
+         WHERE chat_id = ?1
+         ORDER BY created_at ASC"
+    )?;
+    
+    let context_iter = stmt.query_map(params![chat_id], |row| {
+        Ok(ChatContext {
+            chat_id: row.get(0)?,
+            context_type: row.get(1)?,
+            context_id: row.get(2)?,
+            created_at: row.get(3)?,
+        })
+    })?;
+    
+    let mut contexts = Vec::new();
+    for context in context_iter {
+        contexts.push(context?);
+    }
+    Ok(contexts)
+}
+
+// 删除指向某个资源/任务的所有上下文
+fn delete_chat_contexts_by_target(conn: &Connection, context_type: &str, context_id: &str) -> SqlResult<()> {
+    conn.execute(
+        "DELETE FROM chat_contexts WHERE context_type = ?1 AND context_id = ?2",
+        params![context_type, context_id],
+    )?;
     Ok(())
 }
 
 // Message CRUD 操作
+const MESSAGE_COLUMNS: &str = "id, chat_id, role, content, tool_calls, tool_call_id, name, reasoning, created_at, parent_message_id";
+
+fn row_to_message(row: &rusqlite::Row) -> SqlResult<Message> {
+    Ok(Message {
+        id: row.get(0)?,
+        chat_id: row.get(1)?,
+        role: row.get(2)?,
+        content: row.get(3)?,
+        tool_calls: row.get(4)?,
+        tool_call_id: row.get(5)?,
+        name: row.get(6)?,
+        reasoning: row.get(7)?,
+        created_at: row.get(8)?,
+        parent_message_id: row.get(9)?,
+    })
+}
+
 pub fn create_message(conn: &Connection, message: &Message) -> SqlResult<()> {
     conn.execute(
-        "INSERT INTO messages (id, chat_id, role, content, tool_calls, tool_call_id, name, reasoning, created_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        "INSERT INTO messages (id, chat_id, role, content, tool_calls, tool_call_id, name, reasoning, created_at, parent_message_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
         params![
             message.id,
             message.chat_id,
@@ -1278,32 +1914,85 @@ pub fn create_message(conn: &Connection, message: &Message) -> SqlResult<()> {
             message.name,
             message.reasoning,
             message.created_at,
+            message.parent_message_id,
         ],
     )?;
     Ok(())
 }
 
+// 追加消息到当前激活分支：未指定父消息时挂到激活分支的末尾，并把新消息设为激活分支末尾
+pub fn append_message(conn: &Connection, message: &mut Message) -> SqlResult<()> {
+    if message.parent_message_id.is_none() {
+        message.parent_message_id = get_active_leaf_id(conn, &message.chat_id)?;
+    }
+    create_message(conn, message)?;
+    set_active_leaf_id(conn, &message.chat_id, Some(&message.id))
+}
+
+pub fn get_message(conn: &Connection, message_id: &str) -> SqlResult<Option<Message>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM messages WHERE id = ?1", MESSAGE_COLUMNS))?;
+    let message_iter = stmt.query_map(params![message_id], row_to_message)?;
+    for message in message_iter {
+        return Ok(Some(message?));
+    }
+    Ok(None)
+}
+
+// 获取 chat 的所有消息（包含所有分支）
+pub fn get_all_messages_by_chat(conn: &Connection, chat_id: &str) -> SqlResult<Vec<Message>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM messages WHERE chat_id = ?1 ORDER BY created_at ASC, rowid ASC",
+        MESSAGE_COLUMNS
+    ))?;
+    
+    let message_iter = stmt.query_map(params![chat_id], row_to_message)?;
+    
+    let mut messages = Vec::new();
+    for message in message_iter {
+        messages.push(message?);
+    }
+    Ok(messages)
+}
+
+// 获取 chat 当前激活分支上的消息（从根到激活分支末尾）
 pub fn get_messages_by_chat(conn: &Connection, chat_id: &str) -> SqlResult<Vec<Message>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, chat_id, role, content, tool_calls, tool_call_id, name, reasoning, created_at
-         FROM messages
-         WHERE chat_id = ?1
-         ORDER BY created_at ASC"
-    )?;
+    let all_messages = get_all_messages_by_chat(conn, chat_id)?;
+    let leaf_id = match get_active_leaf_id(conn, chat_id)? {
+        Some(leaf_id) => leaf_id,
+        None => return Ok(Vec::new()),
+    };
+    
+    let by_id: std::collections::HashMap<&str, &Message> =
+        all_messages.iter().map(|m| (m.id.as_str(), m)).collect();
+    
+    let mut path = Vec::new();
+    let mut visited = std::collections::HashSet::new();
+    let mut current = by_id.get(leaf_id.as_str()).copied();
+    while let Some(message) = current {
+        // 防止数据异常导致死循环
+        if !visited.insert(message.id.as_str()) {
+            break;
+        }
+        path.push(message.clone());
+        current = message
+            .parent_message_id
+            .as_deref()
+            .and_then(|parent_id| by_id.get(parent_id).copied());
+    }
+    path.reverse();
+    Ok(path)
+}
+
+// 获取同一父消息下的所有消息（分支），按创建时间排序
+pub fn get_sibling_messages(conn: &Connection, message: &Message) -> SqlResult<Vec<Message>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM messages
+         WHERE chat_id = ?1 AND parent_message_id IS ?2
+         ORDER BY created_at ASC, rowid ASC",
+        MESSAGE_COLUMNS
+    ))?;
     
-    let message_iter = stmt.query_map(params![chat_id], |row| {
-        Ok(Message {
-            id: row.get(0)?,
-            chat_id: row.get(1)?,
-            role: row.get(2)?,
-            content: row.get(3)?,
-            tool_calls: row.get(4)?,
-            tool_call_id: row.get(5)?,
-            name: row.get(6)?,
-            reasoning: row.get(7)?,
-            created_at: row.get(8)?,
-        })
-    })?;
+    let message_iter = stmt.query_map(params![message.chat_id, message.parent_message_id], row_to_message)?;
     
     let mut messages = Vec::new();
     for message in message_iter {
@@ -1312,28 +2001,67 @@ pub fn get_messages_by_chat(conn: &Connection, chat_id: &str) -> SqlResult<Vec<M
     Ok(messages)
 }
 
-pub fn get_last_message_by_chat(conn: &Connection, chat_id: &str) -> SqlResult<Option<Message>> {
+// 从指定消息向下找到分支末尾（每一层都选择最新的子消息）
+pub fn get_branch_leaf_id(conn: &Connection, message_id: &str) -> SqlResult<String> {
     let mut stmt = conn.prepare(
-        "SELECT id, chat_id, role, content, tool_calls, tool_call_id, name, reasoning, created_at
-         FROM messages
-         WHERE chat_id = ?1
-         ORDER BY created_at DESC
-         LIMIT 1"
+        "SELECT id FROM messages WHERE parent_message_id = ?1 ORDER BY created_at DESC, rowid DESC LIMIT 1"
     )?;
     
-    let message_iter = stmt.query_map(params![chat_id], |row| {
-        Ok(Message {
-            id: row.get(0)?,
-            chat_id: row.get(1)?,
-            role: row.get(2)?,
-            content: row.get(3)?,
-            tool_calls: row.get(4)?,
-            tool_call_id: row.get(5)?,
-            name: row.get(6)?,
-            reasoning: row.get(7)?,
-            created_at: row.get(8)?,
-        })
-    })?;
+    let mut current = message_id.to_string();
+    let mut visited = std::collections::HashSet::new();
+    while visited.insert(current.clone()) {
+        let child: Option<String> = stmt
+            .query_map(params![current], |row| row.get(0))?
+            .next()
+            .transpose()?;
+        match child {
+            Some(child) => current = child,
+            None => break,
+        }
+    }
+    Ok(current)
+}
+
+// 获取当前激活分支的末尾消息 ID（未记录时使用最新的一条消息）
+pub fn get_active_leaf_id(conn: &Connection, chat_id: &str) -> SqlResult<Option<String>> {
+    let active: Option<String> = conn
+        .query_row(
+            "SELECT active_leaf_id FROM chats WHERE id = ?1",
+            params![chat_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+    
+    if let Some(active) = active {
+        if get_message(conn, &active)?.is_some() {
+            return Ok(Some(active));
+        }
+    }
+    
+    conn.query_row(
+        "SELECT id FROM messages WHERE chat_id = ?1 ORDER BY created_at DESC, rowid DESC LIMIT 1",
+        params![chat_id],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+pub fn set_active_leaf_id(conn: &Connection, chat_id: &str, message_id: Option<&str>) -> SqlResult<()> {
+    conn.execute(
+        "UPDATE chats SET active_leaf_id = ?2 WHERE id = ?1",
+        params![chat_id, message_id],
+    )?;
+    Ok(())
+}
+
+pub fn get_last_message_by_chat(conn: &Connection, chat_id: &str) -> SqlResult<Option<Message>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM messages WHERE chat_id = ?1 ORDER BY created_at DESC LIMIT 1",
+        MESSAGE_COLUMNS
+    ))?;
+    
+    let message_iter = stmt.query_map(params![chat_id], row_to_message)?;
     
     for message in message_iter {
         return Ok(Some(message?));
@@ -1353,3 +2081,460 @@ pub fn get_message_count_by_chat(conn: &Connection, chat_id: &str) -> SqlResult<
     Ok(count)
 }
 
+
+// MCP 工具调用审计日志相关操作
+pub fn create_mcp_tool_call_log(conn: &Connection, log: &MCPToolCallLog) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO mcp_tool_call_logs (id, server_name, tool_name, arguments, policy, decision, approved_by, success, result_size, duration_ms, error, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            log.id,
+            log.server_name,
+            log.tool_name,
+            log.arguments,
+            log.policy,
+            log.decision,
+            log.approved_by,
+            if log.success { 1 } else { 0 },
+            log.result_size,
+            log.duration_ms,
+            log.error,
+            log.created_at,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn get_mcp_tool_call_logs(conn: &Connection, server_name: Option<&str>, limit: i64) -> SqlResult<Vec<MCPToolCallLog>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, server_name, tool_name, arguments, policy, decision, approved_by, success, result_size, duration_ms, error, created_at
+         FROM mcp_tool_call_logs
+         WHERE ?1 IS NULL OR server_name = ?1
+         ORDER BY created_at DESC
+         LIMIT ?2"
+    )?;
+    
+    let log_iter = stmt.query_map(params![server_name, limit], |row| {
+        Ok(MCPToolCallLog {
+            id: row.get(0)?,
+            server_name: row.get(1)?,
+            tool_name: row.get(2)?,
+            arguments: row.get(3)?,
+            policy: row.get(4)?,
+            decision: row.get(5)?,
+            approved_by: row.get(6)?,
+            success: row.get::<_, i32>(7)? != 0,
+            result_size: row.get(8)?,
+            duration_ms: row.get(9)?,
+            error: row.get(10)?,
+            created_at: row.get(11)?,
+        })
+    })?;
+    
+    let mut logs = Vec::new();
+    for log in log_iter {
+        logs.push(log?);
+    }
+    Ok(logs)
+}
+
+pub fn clear_mcp_tool_call_logs(conn: &Connection) -> SqlResult<()> {
+    conn.execute("DELETE FROM mcp_tool_call_logs", [])?;
+    Ok(())
+}
+
+// Token 用量记录操作
+pub fn create_usage_record(conn: &Connection, record: &UsageRecord, event_id: Option<&str>) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO usage_records (id, config_id, model, source, chat_id, message_id, task_id, resource_id,
+                                    prompt_tokens, completion_tokens, cached_tokens, cost, created_at, cache_write_tokens, event_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+        params![
+            record.id,
+            record.config_id,
+            record.model,
+            record.source,
+            record.chat_id,
+            record.message_id,
+            record.task_id,
+            record.resource_id,
+            record.prompt_tokens,
+            record.completion_tokens,
+            record.cached_tokens,
+            record.cost,
+            record.created_at,
+            record.cache_write_tokens,
+            event_id,
+        ],
+    )?;
+    Ok(())
+}
+
+// 把流式对话的用量关联到助手消息，返回更新的记录数
+pub fn set_usage_message_id(conn: &Connection, event_id: &str, message_id: &str) -> SqlResult<usize> {
+    conn.execute(
+        "UPDATE usage_records SET message_id = ?1 WHERE event_id = ?2 AND message_id IS NULL",
+        params![message_id, event_id],
+    )
+}
+
+pub fn get_usage_records(conn: &Connection, chat_id: Option<&str>, task_id: Option<&str>, limit: i64) -> SqlResult<Vec<UsageRecord>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, config_id, model, source, chat_id, message_id, task_id, resource_id,
+                prompt_tokens, completion_tokens, cached_tokens, cost, created_at, cache_write_tokens
+         FROM usage_records
+         WHERE (?1 IS NULL OR chat_id = ?1) AND (?2 IS NULL OR task_id = ?2)
+         ORDER BY created_at DESC
+         LIMIT ?3"
+    )?;
+    
+    let record_iter = stmt.query_map(params![chat_id, task_id, limit], |row| {
+        Ok(UsageRecord {
+            id: row.get(0)?,
+            config_id: row.get(1)?,
+            model: row.get(2)?,
+            source: row.get(3)?,
+            chat_id: row.get(4)?,
+            message_id: row.get(5)?,
+            task_id: row.get(6)?,
+            resource_id: row.get(7)?,
+            prompt_tokens: row.get(8)?,
+            completion_tokens: row.get(9)?,
+            cached_tokens: row.get(10)?,
+            cost: row.get(11)?,
+            created_at: row.get(12)?,
+            cache_write_tokens: row.get(13)?,
+        })
+    })?;
+    
+    let mut records = Vec::new();
+    for record in record_iter {
+        records.push(record?);
+    }
+    Ok(records)
+}
+
+// 聚合用量报表，group_by: "day" | "config" | "chat" | "resource"
+// 按本地日期统计和过滤：created_at 保存的是 UTC 时间，转换为本地时区后取日期，start / end 为 YYYY-MM-DD（包含两端）
+pub fn get_usage_report(conn: &Connection, group_by: &str, start: Option<&str>, end: Option<&str>) -> SqlResult<Vec<UsageReportRow>> {
+    let (key_expr, label_expr, join) = match group_by {
+        "config" => ("u.config_id", "MAX(c.name)", "LEFT JOIN ai_configs c ON c.id = u.config_id"),
+        "chat" => ("u.chat_id", "MAX(c.title)", "LEFT JOIN chats c ON c.id = u.chat_id"),
+        "resource" => ("u.resource_id", "MAX(r.name)", "LEFT JOIN transcription_resources r ON r.id = u.resource_id"),
+        _ => ("date(u.created_at, 'localtime')", "NULL", ""),
+    };
+    
+    // 只比较日期部分
+    let start = start.map(|s| s.chars().take(10).collect::<String>());
+    let end = end.map(|e| e.chars().take(10).collect::<String>());
+    
+    let sql = format!(
+        "SELECT {key} AS key, {label}, COUNT(*), SUM(u.prompt_tokens), SUM(u.completion_tokens), SUM(u.cached_tokens),
+                SUM(MAX(u.prompt_tokens - u.cached_tokens, 0)), SUM(u.cache_write_tokens), SUM(u.cost)
+         FROM usage_records u {join}
+         WHERE {key} IS NOT NULL
+           AND (?1 IS NULL OR date(u.created_at, 'localtime') >= ?1)
+           AND (?2 IS NULL OR date(u.created_at, 'localtime') <= ?2)
+         GROUP BY {key}
+         ORDER BY {order}",
+        key = key_expr,
+        label = label_expr,
+        join = join,
+        order = if label_expr == "NULL" { "key DESC" } else { "SUM(u.cost) DESC, COUNT(*) DESC" },
+    );
+    
+    let mut stmt = conn.prepare(&sql)?;
+    let row_iter = stmt.query_map(params![start, end], |row| {
+        Ok(UsageReportRow {
+            key: row.get(0)?,
+            label: row.get(1)?,
+            call_count: row.get(2)?,
+            prompt_tokens: row.get(3)?,
+            completion_tokens: row.get(4)?,
+            cached_tokens: row.get(5)?,
+            cache_miss_tokens: row.get(6)?,
+            cache_write_tokens: row.get(7)?,
+            cost: row.get(8)?,
+        })
+    })?;
+    
+    let mut rows = Vec::new();
+    for row in row_iter {
+        rows.push(row?);
+    }
+    Ok(rows)
+}
+
+// 提示词模板操作
+fn row_to_prompt_template(row: &rusqlite::Row) -> SqlResult<PromptTemplate> {
+    Ok(PromptTemplate {
+        kind: row.get(0)?,
+        language: row.get(1)?,
+        system_prompt: row.get(2)?,
+        user_prompt: row.get(3)?,
+        is_customized: row.get::<_, i32>(4)? != 0,
+        updated_at: row.get(5)?,
+    })
+}
+
+pub fn get_prompt_templates(conn: &Connection) -> SqlResult<Vec<PromptTemplate>> {
+    let mut stmt = conn.prepare(
+        "SELECT kind, language, system_prompt, user_prompt, is_customized, updated_at
+         FROM prompt_templates
+         ORDER BY kind, language"
+    )?;
+    
+    let template_iter = stmt.query_map([], row_to_prompt_template)?;
+    
+    let mut templates = Vec::new();
+    for template in template_iter {
+        templates.push(template?);
+    }
+    Ok(templates)
+}
+
+pub fn get_prompt_template(conn: &Connection, kind: &str, language: &str) -> SqlResult<Option<PromptTemplate>> {
+    conn.query_row(
+        "SELECT kind, language, system_prompt, user_prompt, is_customized, updated_at
+         FROM prompt_templates WHERE kind = ?1 AND language = ?2",
+        params![kind, language],
+        row_to_prompt_template,
+    )
+    .optional()
+}
+
+pub fn save_prompt_template(conn: &Connection, template: &PromptTemplate) -> SqlResult<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO prompt_templates (kind, language, system_prompt, user_prompt, is_customized, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            template.kind,
+            template.language,
+            template.system_prompt,
+            template.user_prompt,
+            if template.is_customized { 1 } else { 0 },
+            template.updated_at,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn delete_prompt_template(conn: &Connection, kind: &str, language: &str) -> SqlResult<()> {
+    conn.execute(
+        "DELETE FROM prompt_templates WHERE kind = ?1 AND language = ?2",
+        params![kind, language],
+    )?;
+    Ok(())
+}
+
+// 字幕轨道操作
+fn row_to_subtitle_track(row: &rusqlite::Row) -> SqlResult<SubtitleTrack> {
+    Ok(SubtitleTrack {
+        id: row.get(0)?,
+        task_id: row.get(1)?,
+        language: row.get(2)?,
+        kind: row.get(3)?,
+        file_path: row.get(4)?,
+        is_primary: row.get::<_, i64>(5)? != 0,
+        created_at: row.get(6)?,
+    })
+}
+
+pub fn create_subtitle_track(conn: &Connection, track: &SubtitleTrack) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO subtitle_tracks (id, task_id, language, kind, file_path, is_primary, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            track.id,
+            track.task_id,
+            track.language,
+            track.kind,
+            track.file_path,
+            if track.is_primary { 1 } else { 0 },
+            track.created_at,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn get_subtitle_tracks(conn: &Connection, task_id: &str) -> SqlResult<Vec<SubtitleTrack>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, task_id, language, kind, file_path, is_primary, created_at
+         FROM subtitle_tracks WHERE task_id = ?1
+         ORDER BY is_primary DESC, created_at ASC, rowid ASC"
+    )?;
+    
+    let track_iter = stmt.query_map(params![task_id], row_to_subtitle_track)?;
+    
+    let mut tracks = Vec::new();
+    for track in track_iter {
+        tracks.push(track?);
+    }
+    Ok(tracks)
+}
+
+// 将指定轨道设为任务的主轨道（同一任务只有一个主轨道）
+pub fn set_primary_subtitle_track(conn: &Connection, task_id: &str, track_id: &str) -> SqlResult<()> {
+    conn.execute(
+        "UPDATE subtitle_tracks SET is_primary = CASE WHEN id = ?2 THEN 1 ELSE 0 END WHERE task_id = ?1",
+        params![task_id, track_id],
+    )?;
+    Ok(())
+}
+
+pub fn delete_subtitle_tracks_by_task(conn: &Connection, task_id: &str) -> SqlResult<()> {
+    conn.execute(
+        "DELETE FROM subtitle_tracks WHERE task_id = ?1",
+        params![task_id],
+    )?;
+    Ok(())
+}
+
+// 监视文件夹操作
+fn row_to_watch_folder(row: &rusqlite::Row) -> SqlResult<WatchFolder> {
+    let params_json: String = row.get(3)?;
+    let params: TranscriptionParams = serde_json::from_str(&params_json)
+        .map_err(|_e| rusqlite::Error::InvalidColumnType(3, "params".to_string(), rusqlite::types::Type::Text))?;
+    Ok(WatchFolder {
+        id: row.get(0)?,
+        path: row.get(1)?,
+        enabled: row.get::<_, i64>(2)? != 0,
+        params,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+    })
+}
+
+pub fn create_watch_folder(conn: &Connection, folder: &WatchFolder) -> SqlResult<()> {
+    let params_json = serde_json::to_string(&folder.params)
+        .map_err(|_e| rusqlite::Error::InvalidColumnType(0, "params".to_string(), rusqlite::types::Type::Text))?;
+    conn.execute(
+        "INSERT INTO watch_folders (id, path, enabled, params, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            folder.id,
+            folder.path,
+            if folder.enabled { 1 } else { 0 },
+            params_json,
+            folder.created_at,
+            folder.updated_at,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn update_watch_folder(conn: &Connection, folder: &WatchFolder) -> SqlResult<()> {
+    let params_json = serde_json::to_string(&folder.params)
+        .map_err(|_e| rusqlite::Error::InvalidColumnType(0, "params".to_string(), rusqlite::types::Type::Text))?;
+    conn.execute(
+        "UPDATE watch_folders SET path = ?2, enabled = ?3, params = ?4, updated_at = ?5 WHERE id = ?1",
+        params![
+            folder.id,
+            folder.path,
+            if folder.enabled { 1 } else { 0 },
+            params_json,
+            folder.updated_at,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn get_watch_folder(conn: &Connection, id: &str) -> SqlResult<Option<WatchFolder>> {
+    conn.query_row(
+        "SELECT id, path, enabled, params, created_at, updated_at FROM watch_folders WHERE id = ?1",
+        params![id],
+        row_to_watch_folder,
+    )
+    .optional()
+}
+
+pub fn get_watch_folders(conn: &Connection) -> SqlResult<Vec<WatchFolder>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, path, enabled, params, created_at, updated_at
+         FROM watch_folders
+         ORDER BY created_at ASC"
+    )?;
+    
+    let folder_iter = stmt.query_map([], row_to_watch_folder)?;
+    
+    let mut folders = Vec::new();
+    for folder in folder_iter {
+        folders.push(folder?);
+    }
+    Ok(folders)
+}
+
+// 删除监视文件夹（保留文件处理记录，重新添加同一文件夹时不会重复处理）
+pub fn delete_watch_folder(conn: &Connection, id: &str) -> SqlResult<()> {
+    conn.execute(
+        "DELETE FROM watch_folders WHERE id = ?1",
+        params![id],
+    )?;
+    Ok(())
+}
+
+fn row_to_watch_folder_file(row: &rusqlite::Row) -> SqlResult<WatchFolderFile> {
+    Ok(WatchFolderFile {
+        file_path: row.get(0)?,
+        folder_id: row.get(1)?,
+        status: row.get(2)?,
+        resource_id: row.get(3)?,
+        task_id: row.get(4)?,
+        error: row.get(5)?,
+        processed_at: row.get(6)?,
+    })
+}
+
+// 写入或更新文件处理记录
+pub fn upsert_watch_folder_file(conn: &Connection, file: &WatchFolderFile) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO watch_folder_files (file_path, folder_id, status, resource_id, task_id, error, processed_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT(file_path) DO UPDATE SET
+            folder_id = excluded.folder_id,
+            status = excluded.status,
+            resource_id = excluded.resource_id,
+            task_id = excluded.task_id,
+            error = excluded.error,
+            processed_at = excluded.processed_at",
+        params![
+            file.file_path,
+            file.folder_id,
+            file.status,
+            file.resource_id,
+            file.task_id,
+            file.error,
+            file.processed_at,
+        ],
+    )?;
+    Ok(())
+}
+
+// 所有已记录的文件路径
+pub fn get_watch_folder_file_paths(conn: &Connection) -> SqlResult<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT file_path FROM watch_folder_files")?;
+    let path_iter = stmt.query_map([], |row| row.get::<_, String>(0))?;
+    
+    let mut paths = Vec::new();
+    for path in path_iter {
+        paths.push(path?);
+    }
+    Ok(paths)
+}
+
+pub fn get_watch_folder_files(conn: &Connection, folder_id: &str, limit: i64) -> SqlResult<Vec<WatchFolderFile>> {
+    let mut stmt = conn.prepare(
+        "SELECT file_path, folder_id, status, resource_id, task_id, error, processed_at
+         FROM watch_folder_files WHERE folder_id = ?1
+         ORDER BY processed_at DESC
+         LIMIT ?2"
+    )?;
+    
+    let file_iter = stmt.query_map(params![folder_id, limit], row_to_watch_folder_file)?;
+    
+    let mut files = Vec::new();
+    for file in file_iter {
+        files.push(file?);
+    }
+    Ok(files)
+}
@@ -18,6 +18,7 @@ pub fn get_default_server_config() -> MCPServerConfig {
         args: None,
         env: None,
         url: None,
+        timeout: None,
     }
 }
 
@@ -120,9 +121,17 @@ pub fn get_default_server_info() -> MCPServerInfo {
         tools: Some(get_default_tools()),
         error: None,
         is_default: Some(true),
+        latency_ms: None,
+        last_error: None,
+        last_checked_at: None,
     }
 }
 
+// 检查服务器名称是否指向默认服务（支持配置键名和显示名称）
+pub fn is_default_server(server_name: &str) -> bool {
+    server_name == DEFAULT_MCP_SERVER_NAME || server_name == get_default_server_info().name
+}
+
 // 检查是否是默认工具
 // 此函数可用于验证工具名是否为默认工具，目前未使用但保留以备将来扩展
 #[allow(dead_code)]
@@ -2,6 +2,25 @@ mod db;
 mod mcp;
 mod ai;
 mod default_mcp;
+mod mcp_permission;
+mod agent;
+mod mcp_schema;
+mod mcp_import;
+mod mcp_secrets;
+mod mcp_monitor;
+mod command_sandbox;
+mod chat_context;
+mod context_window;
+mod chat_export;
+mod usage;
+mod prompt_cache;
+mod prompt_templates;
+mod bilibili;
+mod url_metadata;
+mod playlist;
+mod watch_folders;
+mod audio_preprocess;
+mod media_probe;
 
 // 压缩优化相关常量
 const COMPRESSION_SHORT_CONTENT_THRESHOLD: usize = 30000; // 短内容阈值（小于此值不压缩）
@@ -11,7 +30,7 @@ const COMPRESSION_TRUNCATE_TAIL_LENGTH: usize = 50000; // 超长内容截断时
 
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tauri::{Emitter, Manager, State};
 use uuid::Uuid;
@@ -70,6 +89,27 @@ pub struct Topic {
     pub time_ranges: Vec<TopicTimeRange>, // 时间范围列表
 }
 
+// 章节标记（视频作者提供的章节）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Chapter {
+    pub title: String,
+    pub start_time: f64,       // 开始时间（秒）
+    pub end_time: Option<f64>, // 结束时间（秒）
+}
+
+// 导入时探测的媒体信息
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MediaInfo {
+    pub container: Option<String>, // 容器格式，如 "mov,mp4,m4a,3gp,3g2,mj2"
+    pub duration: Option<f64>, // 时长（秒）
+    pub has_audio: bool, // 是否包含音频流
+    pub has_video: bool, // 是否包含视频流（不含音频文件的封面图片）
+    pub audio_codec: Option<String>,
+    pub video_codec: Option<String>,
+    pub sample_rate: Option<u32>, // 音频采样率（Hz）
+    pub channels: Option<u32>, // 音频声道数
+}
+
 // 转写资源模型
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TranscriptionResource {
@@ -88,6 +128,26 @@ pub struct TranscriptionResource {
     pub latest_completed_task_id: Option<String>, // 最新一条转写成功的任务 ID
     #[serde(default)]
     pub cover_url: Option<String>, // 封面 URL（仅URL资源有）
+    #[serde(default)]
+    pub uploader: Option<String>, // 上传者（仅URL资源有）
+    #[serde(default)]
+    pub duration: Option<f64>, // 时长（秒）
+    #[serde(default)]
+    pub upload_date: Option<String>, // 上传日期（YYYY-MM-DD，仅URL资源有）
+    #[serde(default)]
+    pub description: Option<String>, // 视频简介（仅URL资源有）
+    #[serde(default)]
+    pub tags: Option<Vec<String>>, // 标签（仅URL资源有）
+    #[serde(default)]
+    pub chapters: Option<Vec<Chapter>>, // 章节标记（仅URL资源有）
+    #[serde(default)]
+    pub content_hash: Option<String>, // 文件内容的 SHA-256（仅文件资源有，用于检测重复导入）
+    #[serde(default)]
+    pub extraction_status: Option<String>, // 音频提取状态："extracting" | "completed" | "failed"（仅视频文件资源有）
+    #[serde(default)]
+    pub extraction_error: Option<String>, // 音频提取失败的原因
+    #[serde(default)]
+    pub media_info: Option<MediaInfo>, // 导入时探测的媒体信息（仅文件资源有）
     pub created_at: String,
     pub updated_at: String,
 }
@@ -137,6 +197,93 @@ pub struct TranscriptionParams {
     pub log_prob_threshold: Option<f32>,
     pub no_speech_threshold: Option<f32>,
     pub translate: Option<bool>,
+    #[serde(default)]
+    pub subtitle_languages: Option<Vec<String>>, // URL 资源的字幕语言偏好（按优先级排列）
+    #[serde(default)]
+    pub allow_auto_subtitles: Option<bool>, // 是否接受自动生成的字幕（默认接受）
+    #[serde(default)]
+    pub preprocess: Option<AudioPreprocessOptions>, // 转写前的音频预处理（为空时不预处理）
+}
+
+// 转写前的音频预处理选项（输出始终为 16kHz 单声道）
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AudioPreprocessOptions {
+    #[serde(default)]
+    pub loudness_normalization: bool, // 响度标准化（loudnorm）
+    #[serde(default)]
+    pub highpass_hz: Option<u32>, // 高通滤波截止频率，去除低频噪声
+    #[serde(default)]
+    pub noise_reduction: bool, // FFT 降噪（afftdn）
+    #[serde(default)]
+    pub trim_silence: bool, // 裁剪开头和结尾的静音
+    #[serde(default)]
+    pub start_time: Option<f64>, // 裁剪开始时间（秒）
+    #[serde(default)]
+    pub end_time: Option<f64>, // 裁剪结束时间（秒）
+    #[serde(default)]
+    pub filter_chain: Option<String>, // 实际使用的 ffmpeg 滤镜链（执行后写入，仅用于展示）
+    #[serde(default)]
+    pub time_offset: Option<f64>, // 预处理音频相对原始音频的起始偏移（秒），用于还原时间戳
+    #[serde(default)]
+    pub resolved_end_time: Option<f64>, // 实际使用的裁剪结束时间（秒，执行后写入，已包含静音裁剪）
+}
+
+impl AudioPreprocessOptions {
+    // 清除执行后才写入的字段，前端传入的参数不能指定滤镜链和时间偏移
+    pub fn clear_resolved(&mut self) {
+        self.filter_chain = None;
+        self.time_offset = None;
+        self.resolved_end_time = None;
+    }
+}
+
+impl TranscriptionParams {
+    // 前端传入的转写参数在保存前调用
+    pub fn without_resolved(mut self) -> Self {
+        if let Some(preprocess) = self.preprocess.as_mut() {
+            preprocess.clear_resolved();
+        }
+        self
+    }
+}
+
+// 字幕轨道类型
+pub const SUBTITLE_KIND_MANUAL: &str = "manual";
+pub const SUBTITLE_KIND_AUTO: &str = "auto";
+
+// 字幕轨道（URL 资源下载的每条字幕单独保存为一个转写结果）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubtitleTrack {
+    pub id: String,
+    pub task_id: String,
+    pub language: String, // 字幕语言代码，如 "zh-Hans"、"en"
+    pub kind: String, // "manual" | "auto"
+    pub file_path: String, // 转写结果 JSON 文件路径
+    pub is_primary: bool, // 是否为任务的主转写结果
+    pub created_at: String,
+}
+
+// 监视文件夹：新出现的音视频文件自动创建资源并开始转写
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WatchFolder {
+    pub id: String,
+    pub path: String,
+    pub enabled: bool,
+    pub params: TranscriptionParams, // 自动创建的转写任务使用的参数
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+// 监视文件夹的文件处理记录（按文件路径去重，重启后不会重复处理）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WatchFolderFile {
+    pub file_path: String,
+    pub folder_id: String,
+    pub status: String, // "processing" | "completed" | "failed" | "skipped"
+    pub resource_id: Option<String>,
+    pub task_id: Option<String>,
+    pub error: Option<String>,
+    pub processed_at: String,
 }
 
 // AI 配置模型（OpenAI 兼容）
@@ -150,6 +297,14 @@ pub struct AIConfig {
     pub created_at: String,
     pub updated_at: String,
     pub is_compression_config: Option<bool>,
+    #[serde(default)]
+    pub context_window: Option<i64>, // 模型上下文窗口大小（token），为空时使用默认值
+    #[serde(default)]
+    pub input_price: Option<f64>, // 输入价格（每百万 token）
+    #[serde(default)]
+    pub output_price: Option<f64>, // 输出价格（每百万 token）
+    #[serde(default)]
+    pub cached_input_price: Option<f64>, // 缓存命中的输入价格（每百万 token），为空时按输入价格计算
 }
 
 // Chat 模型
@@ -161,6 +316,70 @@ pub struct Chat {
     pub updated_at: String,
 }
 
+// Chat 早期对话的滚动摘要
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatSummary {
+    pub chat_id: String,
+    pub summary: String,
+    pub covered_count: i64, // 摘要覆盖的历史消息数量（从第一条非 system 消息开始计算）
+    pub covered_hash: String, // 被覆盖消息的指纹，用于判断历史是否被修改
+    pub updated_at: String,
+}
+
+// Token 用量记录（每次 AI 调用一条）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageRecord {
+    pub id: String,
+    pub config_id: String,
+    pub model: String,
+    pub source: String, // "chat" | "agent" | "compression" | "topics" | "title" | "context_summary"
+    pub chat_id: Option<String>,
+    pub message_id: Option<String>,
+    pub task_id: Option<String>,
+    pub resource_id: Option<String>,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cached_tokens: i64, // 缓存命中
+    #[serde(default)]
+    pub cache_write_tokens: i64, // 缓存写入
+    pub cost: Option<f64>, // 未配置价格时为空
+    pub created_at: String,
+}
+
+// 用量报表的一行（按天 / 配置 / chat / 资源聚合）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageReportRow {
+    pub key: String,
+    pub label: Option<String>,
+    pub call_count: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cached_tokens: i64, // 缓存命中
+    pub cache_miss_tokens: i64, // 缓存未命中（prompt_tokens - cached_tokens）
+    pub cache_write_tokens: i64,
+    pub cost: Option<f64>,
+}
+
+// 提示词模板（压缩、topics、标题），按类型和语言区分
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PromptTemplate {
+    pub kind: String, // "compression" | "topics" | "title"
+    pub language: String, // 语言代码，如 "zh"、"en"
+    pub system_prompt: String,
+    pub user_prompt: String, // 支持 {{transcript}}、{{duration}}、{{segment_count}}、{{language}} 变量
+    pub is_customized: bool,
+    pub updated_at: String,
+}
+
+// Chat 关联的上下文（资源或任务）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatContext {
+    pub chat_id: String,
+    pub context_type: String, // "resource" | "task"
+    pub context_id: String,
+    pub created_at: String,
+}
+
 // Message 模型
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Message {
@@ -173,6 +392,8 @@ pub struct Message {
     pub name: Option<String>, // tool name
     pub reasoning: Option<String>, // thinking/reasoning 内容
     pub created_at: String,
+    #[serde(default)]
+    pub parent_message_id: Option<String>, // 父消息 ID（消息树，用于编辑和重新生成时保留分支）
 }
 
 // Chat 列表项（包含最后消息时间）
@@ -184,6 +405,8 @@ pub struct ChatListItem {
     pub updated_at: String,
     pub last_message_at: Option<String>,
     pub message_count: i32,
+    #[serde(default)]
+    pub contexts: Vec<ChatContext>,
 }
 
 // MCP HTTP 传输配置
@@ -248,6 +471,10 @@ pub struct MCPServerConfig {
     // 旧格式的 HTTP 传输配置（向后兼容）
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub url: Option<String>,
+    
+    // 连接/健康检查超时时间（秒），未设置时使用默认值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
 }
 
 // MCP 配置（整个配置文件格式）
@@ -258,6 +485,55 @@ pub struct MCPServerConfig {
 pub struct MCPConfig {
     #[serde(default, rename = "mcpServers")]
     pub mcp_servers: IndexMap<String, MCPServerConfig>,
+    // 工具调用权限规则：服务器键名 -> 权限配置（系统默认服务使用 __system_default__）
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub permissions: IndexMap<String, MCPServerPermission>,
+}
+
+// MCP 工具调用权限策略
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MCPPermissionPolicy {
+    Allow, // 始终允许
+    Ask,   // 每次调用前询问用户
+    Deny,  // 始终拒绝
+}
+
+// 单个工具的权限规则
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MCPToolPermission {
+    pub policy: MCPPermissionPolicy,
+    // 参数约束：参数名 -> 正则表达式，参数值（字符串化后）必须完整匹配，否则拒绝调用
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "argumentPatterns")]
+    pub argument_patterns: Option<HashMap<String, String>>,
+}
+
+// 单个服务器的权限配置
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MCPServerPermission {
+    // 未单独配置的工具使用的默认策略（未设置时为 allow）
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "defaultPolicy")]
+    pub default_policy: Option<MCPPermissionPolicy>,
+    // 工具名 -> 权限规则（"*" 匹配所有未单独配置的工具）
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub tools: IndexMap<String, MCPToolPermission>,
+}
+
+// MCP 工具调用审计日志
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MCPToolCallLog {
+    pub id: String,
+    pub server_name: String,
+    pub tool_name: String,
+    pub arguments: String, // JSON 字符串
+    pub policy: String, // "allow" | "ask" | "deny"
+    pub decision: String, // "allowed" | "denied" | "rejected" | "timeout"
+    pub approved_by: Option<String>, // "policy" | "user"
+    pub success: bool,
+    pub result_size: Option<i64>, // 结果 JSON 字节数
+    pub duration_ms: i64,
+    pub error: Option<String>,
+    pub created_at: String,
 }
 
 // MCP 工具定义
@@ -284,6 +560,12 @@ pub struct MCPServerInfo {
     pub error: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub is_default: Option<bool>, // 是否为系统默认服务
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>, // 最近一次健康检查的延迟
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>, // 最近一次出现的错误（恢复连接后仍保留）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_checked_at: Option<String>, // 最近一次健康检查时间
 }
 
 // 运行中的任务进程管理器
@@ -391,6 +673,69 @@ impl RunningStreams {
     }
 }
 
+// 任务的压缩 / topics 提取流式请求管理器（用于停止任务时中断 HTTP 流）
+#[derive(Clone)]
+pub struct RunningAIRequests {
+    // 存储 task_id -> 取消通知
+    requests: Arc<Mutex<HashMap<String, Arc<tokio::sync::Notify>>>>,
+}
+
+impl RunningAIRequests {
+    pub fn new() -> Self {
+        Self {
+            requests: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub async fn register(&self, task_id: String) -> Arc<tokio::sync::Notify> {
+        let cancel = Arc::new(tokio::sync::Notify::new());
+        let mut requests = self.requests.lock().await;
+        requests.insert(task_id, cancel.clone());
+        cancel
+    }
+
+    pub async fn remove(&self, task_id: &str) {
+        let mut requests = self.requests.lock().await;
+        requests.remove(task_id);
+    }
+
+    pub async fn cancel(&self, task_id: &str) -> bool {
+        let requests = self.requests.lock().await;
+        match requests.get(task_id) {
+            Some(cancel) => {
+                cancel.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// 等待用户批准的工具调用管理器
+#[derive(Clone)]
+pub struct PendingApprovals {
+    // 存储 request_id -> 批准结果发送端
+    approvals: Arc<Mutex<HashMap<String, tokio::sync::oneshot::Sender<bool>>>>,
+}
+
+impl PendingApprovals {
+    pub fn new() -> Self {
+        Self {
+            approvals: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub async fn insert(&self, request_id: String, sender: tokio::sync::oneshot::Sender<bool>) {
+        let mut approvals = self.approvals.lock().await;
+        approvals.insert(request_id, sender);
+    }
+
+    pub async fn remove(&self, request_id: &str) -> Option<tokio::sync::oneshot::Sender<bool>> {
+        let mut approvals = self.approvals.lock().await;
+        approvals.remove(request_id)
+    }
+}
+
 // 获取应用数据目录
 fn get_app_data_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
     let app_data_dir = app
@@ -625,24 +970,80 @@ fn spawn_stream_reader(
     })
 }
 
-// 辅助函数：读取 ffmpeg 输出并发送日志事件
+// 音频提取进度（extraction-progress-{resource_id} 事件）
+#[derive(Debug, Serialize, Clone)]
+pub struct ExtractionProgress {
+    pub resource_id: String,
+    pub status: String, // "extracting" | "completed" | "failed"
+    pub processed_seconds: f64, // 已处理的媒体时长（秒）
+    pub duration: Option<f64>, // 媒体总时长（秒），无法探测时为空
+    pub percent: Option<f64>, // 0-100，总时长未知时为空
+    pub speed: Option<f64>, // 处理速度（相对实时播放的倍数）
+    pub eta_seconds: Option<f64>, // 预计剩余时间（秒）
+    pub error: Option<String>,
+}
+
+// 辅助函数：读取 ffmpeg -progress pipe:1 的输出，换算为百分比和剩余时间并发送进度事件
+// 每个进度块由若干 key=value 行组成，以 progress=continue / progress=end 结束
 fn spawn_ffmpeg_progress_reader(
     stream: impl AsyncRead + Send + Unpin + 'static,
     app: tauri::AppHandle,
-    log_event_name: String,
+    progress_event_name: String,
+    resource_id: String,
+    duration: Option<f64>,
 ) -> JoinHandle<String> {
     tokio::spawn(async move {
         use tokio::io::AsyncBufReadExt;
         let reader = tokio::io::BufReader::new(stream);
         let mut lines = reader.lines();
         let mut output = String::new();
+        let started_at = std::time::Instant::now();
+        let mut processed_seconds = 0.0;
+        let mut speed: Option<f64> = None;
         
         while let Ok(Some(line)) = lines.next_line().await {
-            let line_with_newline = format!("{}\n", line);
-            output.push_str(&line_with_newline);
+            output.push_str(&line);
+            output.push('\n');
             
-            // 发送日志事件
-            let _ = app.emit(&log_event_name, &line);
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            match key.trim() {
+                // out_time_us 和 out_time_ms 的单位都是微秒
+                "out_time_us" | "out_time_ms" => {
+                    if let Ok(us) = value.trim().parse::<f64>() {
+                        processed_seconds = (us / 1_000_000.0).max(0.0);
+                    }
+                }
+                "speed" => {
+                    speed = value.trim().trim_end_matches('x').parse::<f64>().ok().filter(|s| *s > 0.0);
+                }
+                "progress" => {
+                    let percent = duration.map(|d| (processed_seconds / d * 100.0).clamp(0.0, 100.0));
+                    // 优先按处理速度估算，速度未知时按已用时间和进度估算
+                    let eta_seconds = duration.and_then(|d| {
+                        let remaining = (d - processed_seconds).max(0.0);
+                        match speed {
+                            Some(speed) => Some(remaining / speed),
+                            None if processed_seconds > 0.0 => {
+                                Some(started_at.elapsed().as_secs_f64() * remaining / processed_seconds)
+                            }
+                            None => None,
+                        }
+                    });
+                    let _ = app.emit(&progress_event_name, &ExtractionProgress {
+                        resource_id: resource_id.clone(),
+                        status: "extracting".to_string(),
+                        processed_seconds,
+                        duration,
+                        percent,
+                        speed,
+                        eta_seconds,
+                        error: None,
+                    });
+                }
+                _ => {}
+            }
         }
         output
     })
@@ -789,6 +1190,32 @@ fn extract_bilibili_video_id(url: &str) -> Option<(Option<String>, Option<String
     }
 }
 
+// 视频的去重键：YouTube 使用视频 ID，Bilibili 使用 BV 号（或 AV 号）和分 P 序号，其它 URL 原样使用
+// 同一视频的不同 URL 形式（短链接、附加参数等）得到相同的键
+fn video_dedup_key(url: &str) -> String {
+    match detect_url_platform(url) {
+        Some(Platform::Youtube) => {
+            if let Some(video_id) = extract_youtube_video_id(url) {
+                return format!("youtube:{}", video_id);
+            }
+        }
+        Some(Platform::Bilibili) => {
+            if let Some((bvid, aid)) = extract_bilibili_video_id(url) {
+                let page = url
+                    .split(['?', '&'])
+                    .skip(1)
+                    .find_map(|param| param.strip_prefix("p="))
+                    .and_then(|p| p.parse::<u32>().ok())
+                    .unwrap_or(1);
+                let id = bvid.or_else(|| aid.map(|aid| format!("av{}", aid))).unwrap_or_default();
+                return format!("bilibili:{}:{}", id, page);
+            }
+        }
+        _ => {}
+    }
+    url.trim().to_string()
+}
+
 // 获取 Bilibili 视频封面 URL（通过 API）
 async fn get_bilibili_thumbnail_url(url: &str) -> Option<String> {
     let (bvid, aid) = extract_bilibili_video_id(url)?;
@@ -828,18 +1255,146 @@ async fn get_bilibili_thumbnail_url(url: &str) -> Option<String> {
     None
 }
 
+// 探测媒体文件的容器、编码、时长、采样率、声道数以及是否包含音频
+async fn probe_media(app: &tauri::AppHandle, file_path: &str) -> Result<MediaInfo, String> {
+    let ffmpeg_path = get_ffmpeg_path(app)?;
+    media_probe::probe(&ffmpeg_path, Path::new(file_path)).await
+}
+
+// 导入前探测媒体文件（前端据此提示没有音频流的文件）
+#[tauri::command]
+async fn probe_media_file(
+    file_path: String,
+    app: tauri::AppHandle,
+) -> Result<MediaInfo, String> {
+    probe_media(&app, &file_path).await
+}
+
+// 重复导入时的处理方式
+pub(crate) const DUPLICATE_ACTION_LINK: &str = "link"; // 不创建新资源，直接使用已有资源
+const DUPLICATE_ACTION_REUSE: &str = "reuse"; // 创建新资源并复制已有资源的已完成转写结果
+const DUPLICATE_ACTION_IMPORT: &str = "import"; // 仍然作为新资源导入
+
+// 计算文件内容的 SHA-256（分块读取，大文件不会一次性载入内存）
+fn compute_file_hash(path: &Path) -> Result<String, String> {
+    use std::io::Read;
+    
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| format!("无法打开文件: {}", e))?;
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)
+            .map_err(|e| format!("无法读取文件: {}", e))?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buffer[..read]);
+    }
+    Ok(context
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+async fn compute_file_hash_async(file_path: &str) -> Result<String, String> {
+    let path = PathBuf::from(file_path);
+    tokio::task::spawn_blocking(move || compute_file_hash(&path))
+        .await
+        .map_err(|e| format!("计算文件哈希失败: {}", e))?
+}
+
+// 与导入文件内容相同的已有资源
+#[derive(Debug, Serialize)]
+pub struct DuplicateResourceCheck {
+    pub content_hash: String,
+    pub existing: Option<TranscriptionResource>, // 最早导入的相同内容资源
+    pub completed_task_count: usize, // 已有资源中已完成的转写任务数
+}
+
+// 导入前检查文件内容是否已导入过
+#[tauri::command]
+async fn check_duplicate_resource(
+    file_path: String,
+    app: tauri::AppHandle,
+) -> Result<DuplicateResourceCheck, String> {
+    let content_hash = compute_file_hash_async(&file_path).await?;
+    
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        let existing = db::get_resources_by_content_hash(&conn, &content_hash)
+            .map_err(|e| format!("无法查询资源: {}", e))?
+            .into_iter()
+            .next();
+        let completed_task_count = match &existing {
+            Some(resource) => db::get_tasks_by_resource(&conn, &resource.id)
+                .map_err(|e| format!("无法查询关联任务: {}", e))?
+                .iter()
+                .filter(|t| t.status == "completed")
+                .count(),
+            None => 0,
+        };
+        Ok(DuplicateResourceCheck {
+            content_hash,
+            existing,
+            completed_task_count,
+        })
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
 // 创建转写资源
+// 会计算文件内容哈希并保存（可传入 check_duplicate_resource 已计算的哈希），
+// 内容重复时按 duplicate_action 处理：link / reuse / import（默认）
 #[tauri::command]
 async fn create_transcription_resource(
     name: String,
     file_path: String,
+    content_hash: Option<String>,
+    duplicate_action: Option<String>,
     app: tauri::AppHandle,
 ) -> Result<TranscriptionResource, String> {
     let id = Uuid::new_v4().to_string();
     let now = Utc::now().to_rfc3339();
     
-    // 检测资源类型
-    let resource_type = detect_resource_type(&file_path);
+    // 探测媒体信息（失败时不影响导入），没有音频流的文件无法转写
+    let media_info = match probe_media(&app, &file_path).await {
+        Ok(info) => Some(info),
+        Err(e) => {
+            eprintln!("探测媒体信息失败: {}", e);
+            None
+        }
+    };
+    if media_info.as_ref().is_some_and(|info| !info.has_audio) {
+        return Err("文件中没有音频流，无法转写".to_string());
+    }
+    
+    // 检测资源类型：优先使用探测结果，探测失败时按扩展名判断
+    let resource_type = match &media_info {
+        Some(info) if info.has_video => ResourceType::Video,
+        Some(_) => ResourceType::Audio,
+        None => detect_resource_type(&file_path),
+    };
+    
+    // 内容哈希始终在后端计算（失败时不影响导入），不直接信任调用方传入的哈希
+    // 传入的哈希来自重复检测，与文件当前内容不一致时说明文件已变化，不能按检测结果关联或复用
+    let content_hash = match (content_hash, compute_file_hash_async(&file_path).await) {
+        (Some(expected), Ok(actual)) if expected != actual => {
+            return Err("文件内容在检测重复后发生了变化，请重新导入".to_string());
+        }
+        (_, Ok(actual)) => Some(actual),
+        (_, Err(e)) => {
+            eprintln!("计算文件哈希失败: {}", e);
+            None
+        }
+    };
     
     let resource = TranscriptionResource {
         id: id.clone(),
@@ -851,6 +1406,16 @@ async fn create_transcription_resource(
         extracted_audio_path: None,
         latest_completed_task_id: None,
         cover_url: None, // 文件资源没有封面
+        uploader: None,
+        duration: media_info.as_ref().and_then(|info| info.duration),
+        upload_date: None,
+        description: None,
+        tags: None,
+        chapters: None,
+        content_hash: content_hash.clone(),
+        extraction_status: None,
+        extraction_error: None,
+        media_info,
         created_at: now.clone(),
         updated_at: now,
     };
@@ -858,18 +1423,140 @@ async fn create_transcription_resource(
     // 保存到数据库
     let app_data_dir = get_app_data_dir(&app)?;
     let db_path = db::get_db_path(&app_data_dir);
+    let results_dir = app_data_dir.join("transcription_results");
+    let action = duplicate_action.unwrap_or_else(|| DUPLICATE_ACTION_IMPORT.to_string());
     
     tokio::task::spawn_blocking(move || {
-        let conn = db::init_database(&db_path)
+        let mut conn = db::init_database(&db_path)
             .map_err(|e| format!("无法初始化数据库: {}", e))?;
-        db::create_resource(&conn, &resource)
-            .map_err(|e| format!("无法保存资源到数据库: {}", e))?;
-        Ok(resource)
+        
+        let existing = match &content_hash {
+            Some(hash) if action != DUPLICATE_ACTION_IMPORT => db::get_resources_by_content_hash(&conn, hash)
+                .map_err(|e| format!("无法查询资源: {}", e))?
+                .into_iter()
+                .next(),
+            _ => None,
+        };
+        
+        match existing {
+            Some(existing) if action == DUPLICATE_ACTION_LINK => Ok(existing),
+            Some(existing) if action == DUPLICATE_ACTION_REUSE => {
+                let mut resource = resource;
+                let mut copied_files = Vec::new();
+                let result = (|| {
+                    let tx = conn.transaction()
+                        .map_err(|e| format!("无法开始事务: {}", e))?;
+                    db::create_resource(&tx, &resource)
+                        .map_err(|e| format!("无法保存资源到数据库: {}", e))?;
+                    resource.latest_completed_task_id = copy_completed_tasks(&tx, &existing.id, &resource.id, &results_dir, &mut copied_files)?;
+                    if resource.latest_completed_task_id.is_some() {
+                        db::update_resource(&tx, &resource)
+                            .map_err(|e| format!("无法更新资源: {}", e))?;
+                    }
+                    tx.commit()
+                        .map_err(|e| format!("无法提交事务: {}", e))
+                })();
+                // 事务失败时删除已复制的结果文件
+                if let Err(e) = result {
+                    for file in &copied_files {
+                        let _ = std::fs::remove_file(file);
+                    }
+                    return Err(e);
+                }
+                Ok(resource)
+            }
+            _ => {
+                db::create_resource(&conn, &resource)
+                    .map_err(|e| format!("无法保存资源到数据库: {}", e))?;
+                Ok(resource)
+            }
+        }
     })
     .await
     .map_err(|e| format!("数据库操作失败: {}", e))?
 }
 
+// 将已有资源的已完成任务复制到新资源（结果文件和字幕轨道一并复制，删除任一资源互不影响）
+// 已复制的文件记录到 copied_files，调用方在事务失败时删除；返回最新复制的任务 ID
+fn copy_completed_tasks(
+    conn: &rusqlite::Connection,
+    from_resource_id: &str,
+    to_resource_id: &str,
+    results_dir: &Path,
+    copied_files: &mut Vec<PathBuf>,
+) -> Result<Option<String>, String> {
+    let mut tasks: Vec<TranscriptionTask> = db::get_tasks_by_resource(conn, from_resource_id)
+        .map_err(|e| format!("无法查询关联任务: {}", e))?
+        .into_iter()
+        .filter(|t| t.status == "completed")
+        .collect();
+    tasks.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    
+    std::fs::create_dir_all(results_dir)
+        .map_err(|e| format!("无法创建输出目录: {}", e))?;
+    
+    let mut copy_file = |source: &str, file_name: String| -> Result<String, String> {
+        let target = results_dir.join(file_name);
+        std::fs::copy(source, &target)
+            .map_err(|e| format!("无法复制转写结果: {}", e))?;
+        copied_files.push(target.clone());
+        Ok(target.to_string_lossy().to_string())
+    };
+    
+    let mut latest_task_id = None;
+    for task in tasks {
+        let Some(result_path) = task.result.clone().filter(|p| PathBuf::from(p).exists()) else {
+            continue;
+        };
+        let new_id = Uuid::new_v4().to_string();
+        let tracks = db::get_subtitle_tracks(conn, &task.id)
+            .map_err(|e| format!("无法查询字幕轨道: {}", e))?;
+        
+        // 字幕轨道文件名以任务 ID 开头（{task_id}.{kind}.{language}.json），替换为新任务 ID
+        let mut new_result = None;
+        let mut copied_tracks = Vec::with_capacity(tracks.len());
+        for track in tracks {
+            if !PathBuf::from(&track.file_path).exists() {
+                continue;
+            }
+            let file_name = PathBuf::from(&track.file_path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .and_then(|name| name.strip_prefix(task.id.as_str()).map(|rest| format!("{}{}", new_id, rest)))
+                .unwrap_or_else(|| format!("{}.{}.json", new_id, track.id));
+            let file_path = copy_file(&track.file_path, file_name)?;
+            if track.file_path == result_path {
+                new_result = Some(file_path.clone());
+            }
+            copied_tracks.push(SubtitleTrack {
+                id: Uuid::new_v4().to_string(),
+                task_id: new_id.clone(),
+                file_path,
+                ..track
+            });
+        }
+        let new_result = match new_result {
+            Some(path) => path,
+            None => copy_file(&result_path, format!("{}.json", new_id))?,
+        };
+        
+        let copied = TranscriptionTask {
+            id: new_id.clone(),
+            resource_id: to_resource_id.to_string(),
+            result: Some(new_result),
+            ..task
+        };
+        db::create_task(conn, &copied)
+            .map_err(|e| format!("无法保存任务到数据库: {}", e))?;
+        for track in &copied_tracks {
+            db::create_subtitle_track(conn, track)
+                .map_err(|e| format!("无法保存字幕轨道: {}", e))?;
+        }
+        latest_task_id = Some(new_id);
+    }
+    Ok(latest_task_id)
+}
+
 // 从URL创建转写资源
 #[tauri::command]
 async fn create_transcription_resource_from_url(
@@ -885,9 +1572,29 @@ async fn create_transcription_resource_from_url(
     // 检测平台类型
     let platform = detect_url_platform(&url);
     
+    // Bilibili 通过 API 获取标题、UP 主、时长和封面，分P视频对应 ?p= 指定的分P（默认第一个）
+    if let Some(Platform::Bilibili) = platform {
+        match bilibili::fetch_video_info(&url).await {
+            Ok(info) => {
+                let page_number = bilibili::page_number(&url);
+                if let Some(page) = info.page(page_number) {
+                    let custom_name = (!name.is_empty() && !name.starts_with("Bilibili视频-")).then_some(name);
+                    let resource = new_bilibili_resource(&info, page, custom_name);
+                    return save_new_resource(resource, &app).await;
+                }
+            }
+            Err(e) => {
+                eprintln!("获取 Bilibili 视频信息失败: {}，使用通用方式创建资源", e);
+            }
+        }
+    }
+    
     // 检测资源类型（URL资源默认是视频）
     let resource_type = ResourceType::Video;
     
+    // 运行一次 yt-dlp --dump-json 获取标题、频道、上传日期、时长、简介、标签和章节
+    let metadata = fetch_url_metadata(&url, &app).await;
+    
     // 尝试获取视频标题
     // 如果 name 看起来是默认名称（以 "YouTube视频-"、"Bilibili视频-" 或 "外部视频-" 开头），
     // 或者 name 为空，则尝试获取真实标题
@@ -895,8 +1602,10 @@ async fn create_transcription_resource_from_url(
         || name.starts_with("YouTube视频-") 
         || name.starts_with("Bilibili视频-") 
         || name.starts_with("外部视频-") {
-        // 对于 YouTube，优先使用快速的 oEmbed API
-        if let Some(Platform::Youtube) = platform {
+        if let Some(title) = metadata.as_ref().and_then(|m| m.title.clone()) {
+            title
+        // 对于 YouTube，使用快速的 oEmbed API
+        } else if let Some(Platform::Youtube) = platform {
             match get_youtube_title_from_oembed(&url).await {
                 Ok(title) => {
                     eprintln!("成功通过 oEmbed API 获取 YouTube 视频标题: {}", title);
@@ -959,7 +1668,7 @@ async fn create_transcription_resource_from_url(
     let id = Uuid::new_v4().to_string();
     let now = Utc::now().to_rfc3339();
     
-    let resource = TranscriptionResource {
+    let mut resource = TranscriptionResource {
         id: id.clone(),
         name: final_name,
         file_path: url, // URL存储在file_path字段中
@@ -969,13 +1678,100 @@ async fn create_transcription_resource_from_url(
         extracted_audio_path: None,
         latest_completed_task_id: None,
         cover_url,
+        uploader: None,
+        duration: None,
+        upload_date: None,
+        description: None,
+        tags: None,
+        chapters: None,
+        content_hash: None,
+        extraction_status: None,
+        extraction_error: None,
+        media_info: None,
         created_at: now.clone(),
         updated_at: now,
     };
     
-    // 保存到数据库
-    let app_data_dir = get_app_data_dir(&app)?;
-    let db_path = db::get_db_path(&app_data_dir);
+    if let Some(metadata) = &metadata {
+        url_metadata::apply(&mut resource, metadata);
+    }
+    
+    save_new_resource(resource, &app).await
+}
+
+// 使用 yt-dlp --dump-json 获取 URL 资源的元数据，失败时返回 None
+async fn fetch_url_metadata(url: &str, app: &tauri::AppHandle) -> Option<url_metadata::UrlMetadata> {
+    let ytdlp_path = get_ytdlp_path(app).ok()?;
+    match url_metadata::fetch(&ytdlp_path, url).await {
+        Ok(metadata) => Some(metadata),
+        Err(e) => {
+            eprintln!("获取视频元数据失败: {}", e);
+            None
+        }
+    }
+}
+
+// 重新获取 URL 资源的元数据（频道、上传日期、时长、简介、标签、章节）
+#[tauri::command]
+async fn refresh_resource_metadata(
+    resource_id: String,
+    app: tauri::AppHandle,
+) -> Result<TranscriptionResource, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    let mut resource = tokio::task::spawn_blocking({
+        let db_path = db_path.clone();
+        let resource_id = resource_id.clone();
+        move || {
+            let conn = db::init_database(&db_path)
+                .map_err(|e| format!("无法初始化数据库: {}", e))?;
+            db::get_resource(&conn, &resource_id)
+                .map_err(|e| format!("无法读取资源: {}", e))?
+                .ok_or_else(|| format!("转写资源不存在: {}", resource_id))
+        }
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))??;
+    
+    if !matches!(resource.source_type, SourceType::Url) {
+        return Err("只有 URL 资源可以获取视频元数据".to_string());
+    }
+    
+    if let Some(Platform::Bilibili) = resource.platform {
+        let info = bilibili::fetch_video_info(&resource.file_path).await?;
+        if let Some(page) = info.page(bilibili::page_number(&resource.file_path)) {
+            resource.uploader = info.uploader.clone();
+            resource.duration = Some(page.duration).filter(|d| *d > 0.0);
+            resource.upload_date = info.upload_date.clone();
+            resource.description = info.description.clone();
+            resource.cover_url = resource.cover_url.take().or(info.cover_url.clone());
+        }
+    } else {
+        let ytdlp_path = get_ytdlp_path(&app)?;
+        let metadata = url_metadata::fetch(&ytdlp_path, &resource.file_path).await?;
+        url_metadata::apply(&mut resource, &metadata);
+    }
+    resource.updated_at = Utc::now().to_rfc3339();
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::update_resource(&conn, &resource)
+            .map_err(|e| format!("无法更新资源: {}", e))?;
+        Ok(resource)
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 保存新建的资源到数据库
+async fn save_new_resource(
+    resource: TranscriptionResource,
+    app: &tauri::AppHandle,
+) -> Result<TranscriptionResource, String> {
+    let app_data_dir = get_app_data_dir(app)?;
+    let db_path = db::get_db_path(&app_data_dir);
     
     tokio::task::spawn_blocking(move || {
         let conn = db::init_database(&db_path)
@@ -988,6 +1784,353 @@ async fn create_transcription_resource_from_url(
     .map_err(|e| format!("数据库操作失败: {}", e))?
 }
 
+// 根据 Bilibili 视频信息创建分P对应的资源（不保存）
+fn new_bilibili_resource(
+    info: &bilibili::BilibiliVideoInfo,
+    page: &bilibili::BilibiliPage,
+    name: Option<String>,
+) -> TranscriptionResource {
+    let now = Utc::now().to_rfc3339();
+    TranscriptionResource {
+        id: Uuid::new_v4().to_string(),
+        name: name.unwrap_or_else(|| info.page_title(page)),
+        file_path: info.page_url(page),
+        resource_type: ResourceType::Video,
+        source_type: SourceType::Url,
+        platform: Some(Platform::Bilibili),
+        extracted_audio_path: None,
+        latest_completed_task_id: None,
+        cover_url: info.cover_url.clone(),
+        uploader: info.uploader.clone(),
+        duration: Some(page.duration).filter(|d| *d > 0.0),
+        upload_date: info.upload_date.clone(),
+        description: info.description.clone(),
+        tags: None,
+        chapters: None,
+        content_hash: None,
+        extraction_status: None,
+        extraction_error: None,
+        media_info: None,
+        created_at: now.clone(),
+        updated_at: now,
+    }
+}
+
+// 获取 Bilibili 视频信息（标题、UP 主、时长、分P列表）
+#[tauri::command]
+async fn get_bilibili_video_info(url: String) -> Result<bilibili::BilibiliVideoInfo, String> {
+    bilibili::fetch_video_info(&url).await
+}
+
+// 为 Bilibili 多P视频的每个分P创建单独的资源，pages 为空时创建所有分P
+#[tauri::command]
+async fn create_bilibili_part_resources(
+    url: String,
+    pages: Option<Vec<u32>>,
+    app: tauri::AppHandle,
+) -> Result<Vec<TranscriptionResource>, String> {
+    let info = bilibili::fetch_video_info(&url).await?;
+    
+    let selected: Vec<&bilibili::BilibiliPage> = match &pages {
+        Some(pages) if !pages.is_empty() => info.pages.iter().filter(|p| pages.contains(&p.page)).collect(),
+        _ => info.pages.iter().collect(),
+    };
+    if selected.is_empty() {
+        return Err("没有匹配的分P".to_string());
+    }
+    
+    let mut resources = Vec::with_capacity(selected.len());
+    for page in selected {
+        resources.push(save_new_resource(new_bilibili_resource(&info, page, None), &app).await?);
+    }
+    Ok(resources)
+}
+
+// 批量导入 YouTube / Bilibili 播放列表或频道：每个视频创建一个资源，跳过已存在的视频（按视频 ID 判断）
+// 传入 params 时为每个新资源创建转写任务，并在后台按顺序执行
+#[tauri::command]
+async fn import_playlist(
+    url: String,
+    params: Option<TranscriptionParams>,
+    app: tauri::AppHandle,
+) -> Result<playlist::PlaylistImportResult, String> {
+    if !is_url(&url) {
+        return Err("无效的URL格式。请提供以 http:// 或 https:// 开头的URL。".to_string());
+    }
+    
+    let ytdlp_path = get_ytdlp_path(&app)?;
+    let playlist = playlist::fetch(&ytdlp_path, &url).await?;
+    let total = playlist.entries.len();
+    
+    // 已存在的 URL 资源（按视频去重键比较）
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    let mut existing: std::collections::HashSet<String> = tokio::task::spawn_blocking({
+        let db_path = db_path.clone();
+        move || -> Result<std::collections::HashSet<String>, String> {
+            let conn = db::init_database(&db_path)
+                .map_err(|e| format!("无法初始化数据库: {}", e))?;
+            let resources = db::get_all_resources(&conn)
+                .map_err(|e| format!("无法读取资源: {}", e))?;
+            Ok(resources.into_iter().map(|r| video_dedup_key(&r.file_path)).collect())
+        }
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))??;
+    
+    let mut resources = Vec::new();
+    let mut skipped = Vec::new();
+    for entry in playlist.entries {
+        // 同一播放列表中重复的视频也只导入一次
+        if !existing.insert(video_dedup_key(&entry.url)) {
+            skipped.push(entry.url);
+            continue;
+        }
+        
+        let platform = detect_url_platform(&entry.url);
+        let cover_url = match (&entry.thumbnail, &platform) {
+            (Some(thumbnail), _) => Some(thumbnail.clone()),
+            (None, Some(Platform::Youtube)) => get_youtube_thumbnail_url(&entry.url),
+            (None, Some(Platform::Bilibili)) => get_bilibili_thumbnail_url(&entry.url).await,
+            _ => None,
+        };
+        let now = Utc::now().to_rfc3339();
+        let resource = TranscriptionResource {
+            id: Uuid::new_v4().to_string(),
+            name: entry.title.unwrap_or_else(|| entry.url.clone()),
+            file_path: entry.url,
+            resource_type: ResourceType::Video,
+            source_type: SourceType::Url,
+            platform,
+            extracted_audio_path: None,
+            latest_completed_task_id: None,
+            cover_url,
+            uploader: entry.uploader,
+            duration: entry.duration,
+            upload_date: None,
+            description: None,
+            tags: None,
+            chapters: None,
+            content_hash: None,
+            extraction_status: None,
+            extraction_error: None,
+            media_info: None,
+            created_at: now.clone(),
+            updated_at: now,
+        };
+        resources.push(save_new_resource(resource, &app).await?);
+    }
+    
+    let mut tasks = Vec::new();
+    if let Some(params) = params {
+        for resource in &resources {
+            tasks.push(create_transcription_task(resource.id.clone(), params.clone(), app.clone()).await?);
+        }
+        if !tasks.is_empty() {
+            let queue: Vec<(String, String)> = tasks
+                .iter()
+                .map(|t| (t.id.clone(), t.resource_id.clone()))
+                .collect();
+            let app = app.clone();
+            tokio::spawn(async move {
+                run_task_queue(queue, app).await;
+            });
+        }
+    }
+    
+    Ok(playlist::PlaylistImportResult {
+        playlist_title: playlist.title,
+        total,
+        resources,
+        skipped,
+        tasks,
+    })
+}
+
+// 按顺序执行转写任务，单个任务失败不影响后续任务
+async fn run_task_queue(queue: Vec<(String, String)>, app: tauri::AppHandle) {
+    let total = queue.len();
+    let db_path = match get_app_data_dir(&app) {
+        Ok(app_data_dir) => db::get_db_path(&app_data_dir),
+        Err(e) => {
+            eprintln!("无法执行任务队列: {}", e);
+            return;
+        }
+    };
+    for (index, (task_id, resource_id)) in queue.into_iter().enumerate() {
+        // 排队期间任务可能已被手动执行、停止或删除，只执行仍在等待的任务
+        let status = tokio::task::spawn_blocking({
+            let db_path = db_path.clone();
+            let task_id = task_id.clone();
+            move || -> Result<Option<String>, String> {
+                let conn = db::init_database(&db_path)
+                    .map_err(|e| format!("无法初始化数据库: {}", e))?;
+                let task = db::get_task(&conn, &task_id)
+                    .map_err(|e| format!("无法读取任务: {}", e))?;
+                Ok(task.map(|t| t.status))
+            }
+        })
+        .await
+        .map_err(|e| format!("数据库操作失败: {}", e))
+        .and_then(|r| r);
+        match status {
+            Ok(Some(status)) if status == "pending" => {}
+            Ok(status) => {
+                eprintln!("跳过队列任务 {}（状态: {}）", task_id, status.as_deref().unwrap_or("已删除"));
+                continue;
+            }
+            Err(e) => {
+                eprintln!("跳过队列任务 {}: {}", task_id, e);
+                continue;
+            }
+        }
+        
+        eprintln!("执行队列任务 {}/{}: {}", index + 1, total, task_id);
+        if let Err(e) = execute_transcription_task(task_id.clone(), resource_id, app.clone()).await {
+            eprintln!("队列任务 {} 执行失败: {}", task_id, e);
+        }
+    }
+}
+
+// 获取所有监视文件夹
+#[tauri::command]
+async fn get_watch_folders(app: tauri::AppHandle) -> Result<Vec<WatchFolder>, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::get_watch_folders(&conn)
+            .map_err(|e| format!("无法读取监视文件夹: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 添加监视文件夹，include_existing 为 false 时文件夹中已有的文件不会被处理
+#[tauri::command]
+async fn add_watch_folder(
+    path: String,
+    params: Option<TranscriptionParams>,
+    include_existing: Option<bool>,
+    app: tauri::AppHandle,
+) -> Result<WatchFolder, String> {
+    if !PathBuf::from(&path).is_dir() {
+        return Err(format!("文件夹不存在: {}", path));
+    }
+    
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    let now = Utc::now().to_rfc3339();
+    let folder = WatchFolder {
+        id: Uuid::new_v4().to_string(),
+        path,
+        enabled: true,
+        params: params.map(TranscriptionParams::without_resolved).unwrap_or_else(watch_folders::default_params),
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    
+    let folder = tokio::task::spawn_blocking(move || -> Result<WatchFolder, String> {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        if db::get_watch_folders(&conn)
+            .map_err(|e| format!("无法读取监视文件夹: {}", e))?
+            .iter()
+            .any(|f| f.path == folder.path)
+        {
+            return Err("该文件夹已在监视列表中".to_string());
+        }
+        if !include_existing.unwrap_or(false) {
+            watch_folders::mark_existing_files(&conn, &folder)
+                .map_err(|e| format!("无法记录已有文件: {}", e))?;
+        }
+        db::create_watch_folder(&conn, &folder)
+            .map_err(|e| format!("无法保存监视文件夹: {}", e))?;
+        Ok(folder)
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))??;
+    
+    app.state::<watch_folders::WatchFolderMonitor>().wake();
+    Ok(folder)
+}
+
+// 更新监视文件夹（启用状态、转写参数）
+#[tauri::command]
+async fn update_watch_folder(
+    folder_id: String,
+    enabled: Option<bool>,
+    params: Option<TranscriptionParams>,
+    app: tauri::AppHandle,
+) -> Result<WatchFolder, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    let folder = tokio::task::spawn_blocking(move || -> Result<WatchFolder, String> {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        let mut folder = db::get_watch_folder(&conn, &folder_id)
+            .map_err(|e| format!("无法读取监视文件夹: {}", e))?
+            .ok_or_else(|| format!("监视文件夹不存在: {}", folder_id))?;
+        if let Some(enabled) = enabled {
+            folder.enabled = enabled;
+        }
+        if let Some(params) = params {
+            folder.params = params.without_resolved();
+        }
+        folder.updated_at = Utc::now().to_rfc3339();
+        db::update_watch_folder(&conn, &folder)
+            .map_err(|e| format!("无法更新监视文件夹: {}", e))?;
+        Ok(folder)
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))??;
+    
+    app.state::<watch_folders::WatchFolderMonitor>().wake();
+    Ok(folder)
+}
+
+// 移除监视文件夹
+#[tauri::command]
+async fn remove_watch_folder(
+    folder_id: String,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::delete_watch_folder(&conn, &folder_id)
+            .map_err(|e| format!("无法删除监视文件夹: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 获取监视文件夹的文件处理记录（最新的在前）
+#[tauri::command]
+async fn get_watch_folder_files(
+    folder_id: String,
+    limit: Option<i64>,
+    app: tauri::AppHandle,
+) -> Result<Vec<WatchFolderFile>, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::get_watch_folder_files(&conn, &folder_id, limit.unwrap_or(100))
+            .map_err(|e| format!("无法读取文件处理记录: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
 // 创建转写任务
 #[tauri::command]
 async fn create_transcription_task(
@@ -1007,7 +2150,7 @@ async fn create_transcription_task(
         result: None,
         error: None,
         log: None,
-        params,
+        params: params.without_resolved(),
         compressed_content: None,
         topics: None,
     };
@@ -1027,6 +2170,104 @@ async fn create_transcription_task(
     .map_err(|e| format!("数据库操作失败: {}", e))?
 }
 
+// 从平台下载的一条字幕轨道（已转换为转写结果 JSON）
+struct DownloadedSubtitle {
+    language: String,
+    kind: &'static str,
+    json_content: String,
+}
+
+// 将转写结果 JSON 中的 result.language 设置为字幕语言（"zh-Hans" -> "zh"）
+fn with_result_language(json_content: String, language: &str) -> String {
+    let primary = language.trim_start_matches("ai-").split(['-', '_']).next().unwrap_or(language).to_lowercase();
+    match serde_json::from_str::<serde_json::Value>(&json_content) {
+        Ok(mut value) => {
+            value["result"] = json!({ "language": primary });
+            serde_json::to_string_pretty(&value).unwrap_or(json_content)
+        }
+        Err(_) => json_content,
+    }
+}
+
+// 从视频平台获取所有符合偏好的字幕并转换为转写结果 JSON，返回 (字幕轨道, 任务日志)
+// 字幕轨道按偏好排序，第一条作为任务的主转写结果
+async fn fetch_platform_subtitle(
+    url: &str,
+    platform: Option<&Platform>,
+    task: &TranscriptionTask,
+    db_path: &PathBuf,
+    app: &tauri::AppHandle,
+) -> Result<(Vec<DownloadedSubtitle>, String), String> {
+    let stdout_event_name = format!("transcription-stdout-{}", task.id);
+    let (preferences, user_specified) = subtitle_language_preferences(&task.params);
+    let allow_auto = task.params.allow_auto_subtitles.unwrap_or(true);
+    
+    let subtitles = match platform {
+        Some(Platform::Youtube) => {
+            eprintln!("检测到 YouTube URL 资源，使用 yt-dlp 获取字幕...");
+            let _ = app.emit(&stdout_event_name, "检测到 YouTube URL 资源，使用 yt-dlp 获取字幕...\n");
+            
+            let tracks = download_subtitle_tracks_from_url(url, &preferences, user_specified, allow_auto, app, &task.id).await?;
+            
+            // 将SRT转换为JSON
+            let _ = app.emit(&stdout_event_name, "正在将字幕转换为转写结果格式...\n");
+            let mut subtitles = Vec::with_capacity(tracks.len());
+            for (language, kind, srt_path) in tracks {
+                match convert_srt_to_transcription_json(&srt_path) {
+                    Ok(json_content) => subtitles.push(DownloadedSubtitle {
+                        json_content: with_result_language(json_content, &language),
+                        language,
+                        kind,
+                    }),
+                    Err(e) => {
+                        let _ = app.emit(&format!("transcription-stderr-{}", task.id), &format!("SRT转JSON失败（{}）: {}\n", language, e));
+                    }
+                }
+            }
+            subtitles
+        }
+        Some(Platform::Bilibili) => {
+            eprintln!("检测到 Bilibili URL 资源，获取 CC 字幕...");
+            let _ = app.emit(&stdout_event_name, "检测到 Bilibili URL 资源，获取 CC 字幕...\n");
+            
+            let tracks = bilibili::fetch_transcription_tracks(url, &preferences, user_specified, allow_auto).await?;
+            tracks
+                .into_iter()
+                .map(|(subtitle, json_content)| {
+                    let kind = if subtitle.is_auto { SUBTITLE_KIND_AUTO } else { SUBTITLE_KIND_MANUAL };
+                    let _ = app.emit(&stdout_event_name, &format!("字幕下载成功: {} ({})\n", subtitle.language_name, kind));
+                    DownloadedSubtitle {
+                        language: subtitle.language,
+                        kind,
+                        json_content,
+                    }
+                })
+                .collect()
+        }
+        _ => return Err("该平台暂不支持获取字幕".to_string()),
+    };
+    
+    // 请求期间任务可能已被停止
+    if check_task_stopped(&task.id, db_path).await? {
+        return Err("任务已被用户停止".to_string());
+    }
+    
+    if subtitles.is_empty() {
+        return Err("没有可用的字幕".to_string());
+    }
+    
+    let summary = subtitles
+        .iter()
+        .map(|s| format!("{} ({})", s.language, s.kind))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let log = format!(
+        "从URL成功获取字幕并转换为转写结果\n字幕轨道: {}\n主轨道: {} ({})\n",
+        summary, subtitles[0].language, subtitles[0].kind
+    );
+    Ok((subtitles, log))
+}
+
 // 执行转写任务（调用 faster-whisper）
 #[tauri::command]
 async fn execute_transcription_task(
@@ -1095,187 +2336,194 @@ async fn execute_transcription_task(
     .await
     .map_err(|e| format!("数据库操作失败: {}", e))??;
     
-    // 如果是URL资源（YouTube），尝试从URL获取字幕（不使用 whisper）
+    // 如果是URL资源（YouTube、Bilibili），优先尝试从平台获取字幕（不使用 whisper）
     if matches!(resource.source_type, SourceType::Url) {
-        // 只处理 YouTube 资源，暂时不考虑 Bilibili
-        let is_youtube = resource.platform.as_ref()
-            .map(|p| matches!(p, Platform::Youtube))
-            .unwrap_or_else(|| {
-                // 如果没有 platform 信息，通过 URL 检测
-                let url_lower = resource.file_path.to_lowercase();
-                url_lower.contains("youtube.com") || url_lower.contains("youtu.be")
-            });
+        // 如果没有 platform 信息，通过 URL 检测
+        let platform = resource.platform.clone()
+            .or_else(|| detect_url_platform(&resource.file_path));
         
-        if is_youtube {
-            eprintln!("检测到 YouTube URL 资源，使用 yt-dlp 获取字幕...");
-            
+        if matches!(platform, Some(Platform::Youtube) | Some(Platform::Bilibili)) {
             let output_dir = app_data_dir.join("transcription_results");
             std::fs::create_dir_all(&output_dir)
                 .map_err(|e| format!("无法创建结果目录: {}", e))?;
             
-            let output_file = output_dir.join(format!("{}.json", task_id));
-            
             // 创建事件名称用于发送实时日志
             let stdout_event_name = format!("transcription-stdout-{}", task_id);
             let stderr_event_name = format!("transcription-stderr-{}", task_id);
             
-            // 发送开始消息
-            let _ = app.emit(&stdout_event_name, "检测到 YouTube URL 资源，使用 yt-dlp 获取字幕...\n");
-            
-            // 尝试下载字幕（传入 task_id 以实时发送日志）
-            match download_subtitle_from_url_internal(resource.file_path.clone(), app.clone(), Some(task_id.clone())).await {
-            Ok(srt_path) => {
-                eprintln!("字幕下载成功: {}", srt_path);
-                let _ = app.emit(&stdout_event_name, &format!("字幕下载成功: {}\n", srt_path));
-                
-                // 将SRT转换为JSON
-                let _ = app.emit(&stdout_event_name, "正在将字幕转换为转写结果格式...\n");
-                match convert_srt_to_transcription_json(&PathBuf::from(&srt_path)) {
-                    Ok(json_content) => {
-                        // 保存JSON结果
-                        let _ = app.emit(&stdout_event_name, "正在保存转写结果...\n");
-                        std::fs::write(&output_file, &json_content)
-                            .map_err(|e| format!("无法保存转写结果: {}", e))?;
-                        
-                        // 更新任务状态为完成
-                        task.status = "completed".to_string();
-                        task.result = Some(output_file.to_string_lossy().to_string());
-                        task.completed_at = Some(Utc::now().to_rfc3339());
-                        task.log = Some(format!("从URL成功获取字幕并转换为转写结果\nSRT文件: {}\n", srt_path));
-                        
-                        let _ = app.emit(&stdout_event_name, "转写任务完成！\n");
-                        
-                        let db_path_clone = db_path.clone();
-                        let task_clone = task.clone();
-                        tokio::task::spawn_blocking(move || {
-                            let conn = db::init_database(&db_path_clone)
-                                .map_err(|e| format!("无法初始化数据库: {}", e))?;
-                            db::update_task(&conn, &task_clone)
-                                .map_err(|e| format!("无法更新任务: {}", e))
-                        })
-                        .await
-                        .map_err(|e| format!("数据库操作失败: {}", e))??;
-                        
-                        // 更新资源的最新完成任务ID
-                        resource.latest_completed_task_id = Some(task_id.clone());
-                        resource.updated_at = Utc::now().to_rfc3339();
-                        let db_path_clone = db_path.clone();
-                        let resource_clone = resource.clone();
-                        tokio::task::spawn_blocking(move || {
-                            let conn = db::init_database(&db_path_clone)
-                                .map_err(|e| format!("无法初始化数据库: {}", e))?;
-                            db::update_resource(&conn, &resource_clone)
-                                .map_err(|e| format!("无法更新资源: {}", e))
-                        })
-                        .await
-                        .map_err(|e| format!("数据库操作失败: {}", e))??;
-                        
-                        // 字幕下载完成后，自动压缩转写内容，然后提取 topics
-                        // 注意：task 和 resource 已经在上面的 await 中保存完成，这里可以安全地调用压缩
-                        // 添加一个小的延迟，确保数据库写入完全完成
-                        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                        
-                        // 先压缩（等待完成）
-                        if let Err(e) = compress_transcription_after_completion(
-                            output_file.clone(),
-                            task_id.clone(),
-                            db_path.clone(),
-                            Some(app.clone()),
-                        ).await {
-                            let _ = app.emit(&stderr_event_name, &format!("压缩转写内容失败: {}\n", e));
-                            eprintln!("压缩转写内容失败: {}", e);
-                        } else {
-                            // 压缩完成后，提取 topics（等待完成）
-                            tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
-                            if let Err(e) = extract_topics_after_compression(
-                                task_id.clone(),
-                                db_path.clone(),
-                                Some(app.clone()),
-                            ).await {
-                                let _ = app.emit(&stderr_event_name, &format!("提取 topics 失败: {}\n", e));
-                                eprintln!("提取 topics 失败: {}", e);
-                            } else {
-                                let _ = app.emit(&stdout_event_name, "转写、压缩和 topics 提取全部完成！\n");
-                            }
-                        }
-                        
-                        return Ok("从URL成功获取字幕并转换为转写结果".to_string());
-                    }
-                    Err(e) => {
-                        eprintln!("SRT转JSON失败: {}", e);
-                        let _ = app.emit(&stderr_event_name, &format!("SRT转JSON失败: {}\n", e));
-                        task.status = "failed".to_string();
-                        task.error = Some(format!("SRT转JSON失败: {}", e));
-                        task.completed_at = Some(Utc::now().to_rfc3339());
-                        
-                        let db_path_clone = db_path.clone();
-                        let task_clone = task.clone();
-                        tokio::task::spawn_blocking(move || {
-                            let conn = db::init_database(&db_path_clone)
-                                .map_err(|e| format!("无法初始化数据库: {}", e))?;
-                            db::update_task(&conn, &task_clone)
-                                .map_err(|e| format!("无法更新任务: {}", e))
-                        })
-                        .await
-                        .map_err(|e| format!("数据库操作失败: {}", e))??;
-                        
-                        return Err(format!("SRT转JSON失败: {}", e));
-                    }
-                }
-            }
-            Err(e) => {
-                eprintln!("字幕下载失败: {}", e);
-                let _ = app.emit(&stderr_event_name, &format!("字幕下载失败: {}\n", e));
-                
-                // 如果错误是"任务已被用户停止"，说明 stop_transcription_task 已经更新了任务状态
-                // 不需要再次更新，直接返回
-                if e == "任务已被用户停止" {
-                    return Err(e);
+            match fetch_platform_subtitle(&resource.file_path, platform.as_ref(), &task, &db_path, &app).await {
+            Ok((subtitles, subtitle_log)) => {
+                // 每条字幕轨道单独保存为一个转写结果，第一条为主轨道
+                let _ = app.emit(&stdout_event_name, "正在保存转写结果...\n");
+                let now = Utc::now().to_rfc3339();
+                let mut tracks = Vec::with_capacity(subtitles.len());
+                for (index, subtitle) in subtitles.iter().enumerate() {
+                    let safe_language: String = subtitle.language
+                        .chars()
+                        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+                        .collect();
+                    let track_file = output_dir.join(format!("{}.{}.{}.json", task_id, subtitle.kind, safe_language));
+                    std::fs::write(&track_file, &subtitle.json_content)
+                        .map_err(|e| format!("无法保存转写结果: {}", e))?;
+                    tracks.push(SubtitleTrack {
+                        id: Uuid::new_v4().to_string(),
+                        task_id: task_id.clone(),
+                        language: subtitle.language.clone(),
+                        kind: subtitle.kind.to_string(),
+                        file_path: track_file.to_string_lossy().to_string(),
+                        is_primary: index == 0,
+                        created_at: now.clone(),
+                    });
                 }
+                let output_file = PathBuf::from(&tracks[0].file_path);
                 
-                // 对于URL资源，如果字幕获取失败，返回错误（不尝试whisper转写）
-                task.status = "failed".to_string();
-                task.error = Some(format!("无法从URL获取字幕: {}。请确保视频有字幕且yt-dlp已正确安装。", e));
+                // 更新任务状态为完成
+                task.status = "completed".to_string();
+                task.result = Some(output_file.to_string_lossy().to_string());
                 task.completed_at = Some(Utc::now().to_rfc3339());
+                task.log = Some(subtitle_log);
+                
+                let _ = app.emit(&stdout_event_name, "转写任务完成！\n");
                 
                 let db_path_clone = db_path.clone();
                 let task_clone = task.clone();
                 tokio::task::spawn_blocking(move || {
                     let conn = db::init_database(&db_path_clone)
                         .map_err(|e| format!("无法初始化数据库: {}", e))?;
+                    // 重新执行任务时替换旧的字幕轨道
+                    db::delete_subtitle_tracks_by_task(&conn, &task_clone.id)
+                        .map_err(|e| format!("无法删除字幕轨道: {}", e))?;
+                    for track in &tracks {
+                        db::create_subtitle_track(&conn, track)
+                            .map_err(|e| format!("无法保存字幕轨道: {}", e))?;
+                    }
                     db::update_task(&conn, &task_clone)
                         .map_err(|e| format!("无法更新任务: {}", e))
                 })
                 .await
                 .map_err(|e| format!("数据库操作失败: {}", e))??;
                 
-                return Err(format!("无法从URL获取字幕: {}。请确保视频有字幕且yt-dlp已正确安装。", e));
+                // 更新资源的最新完成任务ID
+                resource.latest_completed_task_id = Some(task_id.clone());
+                resource.updated_at = Utc::now().to_rfc3339();
+                let db_path_clone = db_path.clone();
+                let resource_clone = resource.clone();
+                tokio::task::spawn_blocking(move || {
+                    let conn = db::init_database(&db_path_clone)
+                        .map_err(|e| format!("无法初始化数据库: {}", e))?;
+                    db::update_resource(&conn, &resource_clone)
+                        .map_err(|e| format!("无法更新资源: {}", e))
+                })
+                .await
+                .map_err(|e| format!("数据库操作失败: {}", e))??;
+                
+                // 字幕下载完成后，自动压缩转写内容，然后提取 topics
+                // 注意：task 和 resource 已经在上面的 await 中保存完成，这里可以安全地调用压缩
+                // 添加一个小的延迟，确保数据库写入完全完成
+                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                
+                // 先压缩（等待完成）
+                if let Err(e) = compress_transcription_after_completion(
+                    output_file.clone(),
+                    task_id.clone(),
+                    db_path.clone(),
+                    Some(app.clone()),
+                ).await {
+                    let _ = app.emit(&stderr_event_name, &format!("压缩转写内容失败: {}\n", e));
+                    eprintln!("压缩转写内容失败: {}", e);
+                } else {
+                    // 压缩完成后，提取 topics（等待完成）
+                    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+                    if let Err(e) = extract_topics_after_compression(
+                        task_id.clone(),
+                        db_path.clone(),
+                        Some(app.clone()),
+                    ).await {
+                        let _ = app.emit(&stderr_event_name, &format!("提取 topics 失败: {}\n", e));
+                        eprintln!("提取 topics 失败: {}", e);
+                    } else {
+                        let _ = app.emit(&stdout_event_name, "转写、压缩和 topics 提取全部完成！\n");
+                    }
+                }
+                
+                return Ok("从URL成功获取字幕并转换为转写结果".to_string());
+            }
+            Err(e) => {
+                eprintln!("字幕获取失败: {}", e);
+                let _ = app.emit(&stderr_event_name, &format!("字幕获取失败: {}\n", e));
+                
+                // 如果错误是"任务已被用户停止"，说明 stop_transcription_task 已经更新了任务状态
+                // 不需要再次更新，直接返回
+                if e == "任务已被用户停止" {
+                    return Err(e);
+                }
+                
+                // 没有可用字幕时，改为下载音频并使用 whisper 转写
+                let _ = app.emit(&stdout_event_name, "未能获取字幕，改为下载音频并使用 whisper 转写...\n");
             }
             } // 闭合 match 语句
+        }
+        
+        // 其他平台的 URL 或没有字幕的视频：下载音频后走 whisper 转写流程
+        let existing_audio = resource.extracted_audio_path.as_ref()
+            .filter(|p| std::path::Path::new(p).exists())
+            .cloned();
+        if let Some(path) = existing_audio {
+            let _ = app.emit(&format!("transcription-stdout-{}", task_id), &format!("使用已下载的音频: {}\n", path));
         } else {
-            // 非 YouTube URL 资源，暂时不支持
-            task.status = "failed".to_string();
-            task.error = Some("暂不支持此类型的 URL 资源。目前仅支持 YouTube 视频。".to_string());
-            task.completed_at = Some(Utc::now().to_rfc3339());
-            
-            let db_path_clone = db_path.clone();
-            let task_clone = task.clone();
-            tokio::task::spawn_blocking(move || {
-                let conn = db::init_database(&db_path_clone)
-                    .map_err(|e| format!("无法初始化数据库: {}", e))?;
-                db::update_task(&conn, &task_clone)
-                    .map_err(|e| format!("无法更新任务: {}", e))
-            })
-            .await
-            .map_err(|e| format!("数据库操作失败: {}", e))??;
-            
-            return Err("暂不支持此类型的 URL 资源。目前仅支持 YouTube 视频。".to_string());
+            match download_audio_from_url_internal(&resource.file_path, &resource_id, &task_id, &app).await {
+                Ok(path) => {
+                    let _ = app.emit(&format!("transcription-stdout-{}", task_id), &format!("音频下载完成: {}\n", path.display()));
+                    resource.extracted_audio_path = Some(path.to_string_lossy().to_string());
+                    resource.updated_at = Utc::now().to_rfc3339();
+                    
+                    let db_path_clone = db_path.clone();
+                    let resource_clone = resource.clone();
+                    tokio::task::spawn_blocking(move || {
+                        let conn = db::init_database(&db_path_clone)
+                            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+                        db::update_resource(&conn, &resource_clone)
+                            .map_err(|e| format!("无法更新资源: {}", e))
+                    })
+                    .await
+                    .map_err(|e| format!("数据库操作失败: {}", e))??;
+                }
+                Err(e) => {
+                    eprintln!("音频下载失败: {}", e);
+                    let _ = app.emit(&format!("transcription-stderr-{}", task_id), &format!("音频下载失败: {}\n", e));
+                    
+                    // stop_transcription_task 已经更新了任务状态
+                    if e == "任务已被用户停止" {
+                        return Err(e);
+                    }
+                    
+                    task.status = "failed".to_string();
+                    task.error = Some(format!("无法从URL下载音频: {}", e));
+                    task.completed_at = Some(Utc::now().to_rfc3339());
+                    
+                    let db_path_clone = db_path.clone();
+                    let task_clone = task.clone();
+                    tokio::task::spawn_blocking(move || {
+                        let conn = db::init_database(&db_path_clone)
+                            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+                        db::update_task(&conn, &task_clone)
+                            .map_err(|e| format!("无法更新任务: {}", e))
+                    })
+                    .await
+                    .map_err(|e| format!("数据库操作失败: {}", e))??;
+                    
+                    return Err(format!("无法从URL下载音频: {}", e));
+                }
+            }
         }
     }
     
     // 调用 whisper-cli 进行转写（文件资源）
     // 如果是视频资源，使用提取的音频路径；否则使用原始文件路径
     let audio_path = match resource.resource_type {
+        _ if matches!(resource.source_type, SourceType::Url) => {
+            PathBuf::from(resource.extracted_audio_path.as_deref().ok_or("URL 资源尚未下载音频")?)
+        }
         ResourceType::Video => {
             if let Some(extracted_path) = &resource.extracted_audio_path {
                 PathBuf::from(extracted_path)
@@ -1327,6 +2575,51 @@ async fn execute_transcription_task(
         return Err(err_msg);
     }
     
+    // 音频预处理：按任务参数生成 16kHz 单声道的临时音频，实际使用的滤镜链写回任务参数
+    let mut preprocessed_audio_path: Option<PathBuf> = None;
+    if let Some(options) = task.params.preprocess.clone() {
+        let preprocessed_dir = app_data_dir.join("extracted_audio");
+        std::fs::create_dir_all(&preprocessed_dir)
+            .map_err(|e| format!("无法创建音频目录: {}", e))?;
+        let preprocessed_path = preprocessed_dir.join(format!("{}.preprocessed.wav", task_id));
+        
+        let result = match get_ffmpeg_path(&app) {
+            Ok(ffmpeg_path) => {
+                audio_preprocess::run(&ffmpeg_path, &audio_path, &preprocessed_path, &options, &task_id, &app).await
+            }
+            Err(e) => Err(e),
+        };
+        
+        match result {
+            Ok(resolved) => {
+                task.params.preprocess = Some(resolved);
+                preprocessed_audio_path = Some(preprocessed_path);
+            }
+            Err(err_msg) => {
+                eprintln!("音频预处理失败: {}", err_msg);
+                let _ = std::fs::remove_file(&preprocessed_path);
+                
+                task.status = "failed".to_string();
+                task.error = Some(err_msg.clone());
+                task.completed_at = Some(Utc::now().to_rfc3339());
+                
+                let db_path_clone = db_path.clone();
+                let task_clone = task.clone();
+                tokio::task::spawn_blocking(move || {
+                    let conn = db::init_database(&db_path_clone)
+                        .map_err(|e| format!("无法初始化数据库: {}", e))?;
+                    db::update_task(&conn, &task_clone)
+                        .map_err(|e| format!("无法更新任务: {}", e))
+                })
+                .await
+                .map_err(|e| format!("数据库操作失败: {}", e))??;
+                
+                return Err(err_msg);
+            }
+        }
+    }
+    let audio_path = preprocessed_audio_path.clone().unwrap_or(audio_path);
+    
     // 获取 whisper-cli 路径
     let whisper_cli = get_whisper_cli_path(&app)?;
     
@@ -1462,6 +2755,11 @@ async fn execute_transcription_task(
     // 保存日志到任务
     task.log = Some(log_buffer);
     
+    // 预处理生成的临时音频不再需要
+    if let Some(path) = &preprocessed_audio_path {
+        let _ = std::fs::remove_file(path);
+    }
+    
     eprintln!("whisper-cli stdout: {}", stdout_output);
     eprintln!("whisper-cli stderr: {}", stderr_output);
     eprintln!("whisper-cli 退出码: {:?}", status.code());
@@ -1520,6 +2818,19 @@ async fn execute_transcription_task(
     
     eprintln!("转写成功，输出文件: {}", output_file.display());
     
+    // 裁剪过开头的音频，将时间戳还原为原始音频的时间
+    if let Some(offset) = task.params.preprocess.as_ref().and_then(|p| p.time_offset).filter(|o| *o > 0.0) {
+        let shifted = std::fs::read_to_string(&output_file)
+            .map_err(|e| format!("无法读取转写结果: {}", e))
+            .and_then(|content| audio_preprocess::shift_transcription_json(&content, offset))
+            .and_then(|content| {
+                std::fs::write(&output_file, content).map_err(|e| format!("无法写入转写结果: {}", e))
+            });
+        if let Err(e) = shifted {
+            eprintln!("还原时间戳失败: {}", e);
+        }
+    }
+    
     // 更新任务状态为 completed
     task.status = "completed".to_string();
     task.completed_at = Some(Utc::now().to_rfc3339());
@@ -1649,7 +2960,93 @@ async fn check_task_stopped(
     .await
     .map_err(|e| format!("数据库操作失败: {}", e))??;
     
-    Ok(task_status == "failed")
+    Ok(task_status == "failed")
+}
+
+// 获取任务所属资源的章节标记
+async fn get_task_chapters(
+    task_id: &str,
+    db_path: &PathBuf,
+) -> Result<Vec<Chapter>, String> {
+    tokio::task::spawn_blocking({
+        let db_path = db_path.clone();
+        let task_id = task_id.to_string();
+        move || -> Result<Vec<Chapter>, String> {
+            let conn = db::init_database(&db_path)
+                .map_err(|e| format!("无法初始化数据库: {}", e))?;
+            let task = db::get_task(&conn, &task_id)
+                .map_err(|e| format!("无法获取任务: {}", e))?
+                .ok_or_else(|| "任务不存在".to_string())?;
+            let resource = db::get_resource(&conn, &task.resource_id)
+                .map_err(|e| format!("无法读取资源: {}", e))?;
+            Ok(resource.and_then(|r| r.chapters).unwrap_or_default())
+        }
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 获取任务设置的转写语言（用于选择提示词模板）
+async fn get_task_language(
+    task_id: &str,
+    db_path: &PathBuf,
+) -> Result<Option<String>, String> {
+    tokio::task::spawn_blocking({
+        let db_path = db_path.clone();
+        let task_id = task_id.to_string();
+        move || -> Result<Option<String>, String> {
+            let conn = db::init_database(&db_path)
+                .map_err(|e| format!("无法初始化数据库: {}", e))?;
+            let task = db::get_task(&conn, &task_id)
+                .map_err(|e| format!("无法获取任务: {}", e))?;
+            Ok(task.and_then(|t| t.params.language))
+        }
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 以流式请求运行任务的 AI 阶段（压缩 / topics），部分结果通过 transcription-ai-stream-{task_id} 事件发送
+// 请求期间注册到 RunningAIRequests，停止任务时可以直接中断 HTTP 流
+async fn stream_task_ai_stage(
+    app: Option<&tauri::AppHandle>,
+    task_id: &str,
+    stage: &str,
+    ai_config: &AIConfig,
+    messages: Vec<ai::ChatMessage>,
+    temperature: Option<f64>,
+) -> Result<ai::CompletionOutput, String> {
+    let event_name = format!("transcription-ai-stream-{}", task_id);
+    let emit = |payload: serde_json::Value| {
+        if let Some(app) = app {
+            let _ = app.emit(&event_name, payload);
+        }
+    };
+    
+    let requests = app.map(|app| app.state::<RunningAIRequests>().inner().clone());
+    let cancel = match &requests {
+        Some(requests) => Some(requests.register(task_id.to_string()).await),
+        None => None,
+    };
+    
+    emit(json!({ "stage": stage, "type": "start" }));
+    let result = ai::stream_once(ai_config, messages, temperature, cancel, |delta| {
+        emit(json!({ "stage": stage, "type": "delta", "content": delta }));
+    })
+    .await;
+    
+    if let Some(requests) = &requests {
+        requests.remove(task_id).await;
+    }
+    
+    let end_type = match &result {
+        Ok(_) => "done",
+        Err(e) if e == ai::STREAM_CANCELLED => "cancelled",
+        Err(_) => "error",
+    };
+    emit(json!({ "stage": stage, "type": end_type }));
+    
+    result
 }
 
 // 压缩转写内容（在转写完成后自动调用）
@@ -1835,22 +3232,6 @@ async fn compress_transcription_after_completion(
         return Err("任务已被停止".to_string());
     }
     
-    let compression_model = compression_config.model;
-    let base_url = compression_config.base_url;
-    let api_key = compression_config.api_key;
-    
-    // 构建压缩提示词
-    let system_message = ai::ChatMessage {
-        role: "system".to_string(),
-        content: Some(
-            "你是一个专业的视频转写内容压缩助手。你的任务是将长视频的转写内容压缩成简洁的摘要，同时保留关键信息。\n\n要求：\n1. 保留所有时间戳信息（格式：[HH:MM:SS]）\n2. 保留每个时间段的主要内容要点\n3. 对于相似或重复的内容，进行合并\n4. 保持时间顺序\n5. 压缩后的内容应该保留原始内容的 20-30% 左右\n6. 输出格式：每行一个时间段，格式为 [时间戳] 内容摘要\n7. 如果某个时间段内容不重要，可以省略，但重要内容必须保留".to_string(),
-        ),
-        tool_calls: None,
-        tool_call_id: None,
-        name: None,
-        cache_control: None,
-    };
-    
     // 保存原始内容长度（在移动 full_text 之前）
     let full_text_len = full_text.len();
     
@@ -1871,37 +3252,47 @@ async fn compress_transcription_after_completion(
         full_text
     };
     
-    let user_message = ai::ChatMessage {
-        role: "user".to_string(),
-        content: Some(format!(
-            "请压缩以下转写内容（总时长: {:.1} 秒，共 {} 个片段）：\n\n{}",
-            duration,
-            segments.len(),
-            input_text
-        )),
+    // 按任务语言选择提示词模板（自动识别时使用 whisper 检测到的语言）
+    let detected_language = json_value
+        .get("result")
+        .and_then(|r| r.get("language"))
+        .and_then(|l| l.as_str())
+        .map(|l| l.to_string());
+    let language = get_task_language(&task_id, &db_path)
+        .await?
+        .filter(|l| prompt_templates::normalize_language(Some(l)).is_some())
+        .or(detected_language);
+    let prompt = prompt_templates::resolve(db_path.clone(), prompt_templates::KIND_COMPRESSION, language.as_deref(), &input_text).await?;
+    emit_log(&format!("使用提示词模板: {}（{}）\n", prompt.template.kind, prompt.template.language));
+    
+    let duration_text = format!("{:.1}", duration);
+    let segment_count = segments.len().to_string();
+    let language_name = prompt.language_name();
+    let vars = [
+        ("transcript", input_text.as_str()),
+        ("duration", duration_text.as_str()),
+        ("segment_count", segment_count.as_str()),
+        ("language", language_name.as_str()),
+    ];
+    
+    let system_message = ai::ChatMessage {
+        role: "system".to_string(),
+        content: Some(prompt_templates::render(&prompt.template.system_prompt, &vars)),
         tool_calls: None,
         tool_call_id: None,
         name: None,
         cache_control: None,
     };
     
-    // 构建非流式请求
-    let request = ai::ChatCompletionRequest {
-        model: compression_model.to_string(),
-        messages: vec![system_message, user_message],
-        tools: None,
-        tool_choice: None,
-        stream: false,
-        temperature: Some(0.3), // 使用较低温度以获得更稳定的压缩结果
+    let user_message = ai::ChatMessage {
+        role: "user".to_string(),
+        content: Some(prompt_templates::render(&prompt.template.user_prompt, &vars)),
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
+        cache_control: None,
     };
     
-    // 构建 URL
-    let url = ai::build_chat_url(&base_url);
-    
-    // 创建 HTTP 客户端
-    let client = reqwest::Client::new();
-    
-    // 发送请求
     emit_log("正在调用 AI 模型进行压缩...\n");
     
     // 在发送请求前再次检查任务状态
@@ -1910,43 +3301,41 @@ async fn compress_transcription_after_completion(
         return Err("任务已被停止".to_string());
     }
     
-    let response = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-        .map_err(|e| {
-            let msg = format!("发送压缩请求失败: {}", e);
+    // 使用流式请求，部分结果实时发送到前端（使用较低温度以获得更稳定的压缩结果）
+    let output = match stream_task_ai_stage(
+        app.as_ref(),
+        &task_id,
+        "compression",
+        &compression_config,
+        vec![system_message, user_message],
+        Some(0.3),
+    )
+    .await
+    {
+        Ok(output) => output,
+        Err(e) if e == ai::STREAM_CANCELLED => {
+            emit_error("任务已被停止，已中断压缩请求\n");
+            return Err("任务已被停止".to_string());
+        }
+        Err(e) => {
+            let msg = format!("压缩失败: {}", e);
             emit_error(&format!("{}\n", msg));
-            msg
-        })?;
-    
-    // 检查响应状态
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_default();
-        let msg = format!("压缩失败: {} - {}", status, error_text);
-        emit_error(&format!("{}\n", msg));
-        return Err(msg);
-    }
+            return Err(msg);
+        }
+    };
     
-    emit_log("AI 模型响应成功，正在解析结果...\n");
+    emit_log("AI 模型响应完成，正在保存结果...\n");
     
-    // 解析响应
-    let completion_response: ai::ChatCompletionResponse = response
-        .json()
-        .await
-        .map_err(|e| format!("解析压缩响应失败: {}", e))?;
+    // 记录 token 用量
+    if let Some(token_usage) = output.usage {
+        let scope = usage::UsageScope {
+            task_id: Some(task_id.clone()),
+            ..Default::default()
+        };
+        usage::record(db_path.clone(), &compression_config, usage::SOURCE_COMPRESSION, scope, token_usage).await;
+    }
     
-    // 提取压缩后的内容
-    let compressed = completion_response
-        .choices
-        .first()
-        .and_then(|choice| choice.message.content.as_ref())
-        .map(|content| content.trim().to_string())
-        .ok_or_else(|| "AI 响应中没有内容".to_string())?;
+    let compressed = output.content;
     
     // 添加元信息
     let final_compressed = format!(
@@ -2230,11 +3619,7 @@ async fn extract_topics_internal(
         msg
     })?;
     
-    let compression_model = compression_config.model;
-    let base_url = compression_config.base_url;
-    let api_key = compression_config.api_key;
-    
-    emit_log(&format!("使用模型: {} 提取 topics\n", compression_model));
+    emit_log(&format!("使用模型: {} 提取 topics\n", compression_config.model));
     
     // 再次检查任务是否被停止
     if check_task_stopped(&task_id, &db_path).await? {
@@ -2242,47 +3627,48 @@ async fn extract_topics_internal(
         return Err("任务已被停止".to_string());
     }
     
-    // 构建提取 topics 的提示词
+    // 按任务语言选择提示词模板
+    let language = get_task_language(&task_id, &db_path).await?;
+    let prompt = prompt_templates::resolve(db_path.clone(), prompt_templates::KIND_TOPICS, language.as_deref(), &compressed_content).await?;
+    emit_log(&format!("使用提示词模板: {}（{}）\n", prompt.template.kind, prompt.template.language));
+    
+    let language_name = prompt.language_name();
+    let vars = [
+        ("transcript", compressed_content.as_str()),
+        ("language", language_name.as_str()),
+    ];
+    
     let system_message = ai::ChatMessage {
         role: "system".to_string(),
-        content: Some(
-            "你是一个专业的视频内容分析助手。你的任务是从视频转写内容中提取相关的 topics（主题），并为每个 topic 标注时间范围。\n\n要求：\n1. 分析转写内容，识别出几个主要的 topics（主题）\n2. 为每个 topic 从以下10种预定义颜色中选择一个唯一的颜色（按顺序分配，第一个 topic 用第一种颜色，第二个 topic 用第二种颜色，以此类推）：\n   - #3B82F6 (蓝色)\n   - #10B981 (绿色)\n   - #F59E0B (橙色)\n   - #EF4444 (红色)\n   - #8B5CF6 (紫色)\n   - #EC4899 (粉色)\n   - #06B6D4 (青色)\n   - #84CC16 (黄绿色)\n   - #F97316 (橙红色)\n   - #6366F1 (靛蓝色)\n3. 每个 topic 的透明度设置为 0.6（用于叠加显示）\n4. 为每个 topic 提取对应的时间范围（可能有多个时间范围）\n5. 时间范围使用秒数（浮点数），与转写结果中的 offsets 字段格式一致\n6. 如果同一个 topic 的多个时间范围是连续的或接近的（间隔小于 5 秒），应该将这些时间范围合并为一个连续的时间范围\n7. 输出格式必须是有效的 JSON，格式如下：\n{\n  \"topics\": [\n    {\n      \"name\": \"topic 名称\",\n      \"color\": \"#3B82F6\",\n      \"opacity\": 0.6,\n      \"time_ranges\": [\n        {\"start\": 10.5, \"end\": 45.2},\n        {\"start\": 120.3, \"end\": 180.7}\n      ]\n    }\n  ]\n}\n8. 确保严格按照颜色列表的顺序为 topics 分配颜色，每个 topic 都有唯一的颜色".to_string(),
-        ),
+        content: Some(prompt_templates::render(&prompt.template.system_prompt, &vars)),
         tool_calls: None,
         tool_call_id: None,
         name: None,
         cache_control: None,
     };
     
+    // 视频作者提供的章节作为划分 topics 的基准大纲
+    let mut user_prompt = prompt_templates::render(&prompt.template.user_prompt, &vars);
+    let chapters = get_task_chapters(&task_id, &db_path).await?;
+    if !chapters.is_empty() {
+        emit_log(&format!("使用视频章节作为参考大纲（{} 个章节）\n", chapters.len()));
+        let header = if prompt.template.language == "zh" {
+            "视频作者提供的章节（可作为划分 topics 的基准大纲，时间范围应与章节边界保持一致）："
+        } else {
+            "Chapters provided by the video author (use them as a baseline outline for the topics, keeping time ranges aligned with chapter boundaries):"
+        };
+        user_prompt.push_str(&format!("\n\n{}\n{}", header, url_metadata::format_outline(&chapters)));
+    }
+    
     let user_message = ai::ChatMessage {
         role: "user".to_string(),
-        content: Some(format!(
-            "请从以下转写内容中提取 topics：\n\n{}",
-            compressed_content
-        )),
+        content: Some(user_prompt),
         tool_calls: None,
         tool_call_id: None,
         name: None,
         cache_control: None,
     };
     
-    // 构建非流式请求
-    let request = ai::ChatCompletionRequest {
-        model: compression_model.to_string(),
-        messages: vec![system_message, user_message],
-        tools: None,
-        tool_choice: None,
-        stream: false,
-        temperature: Some(0.7),
-    };
-    
-    // 构建 URL
-    let url = ai::build_chat_url(&base_url);
-    
-    // 创建 HTTP 客户端
-    let client = reqwest::Client::new();
-    
-    // 发送请求
     emit_log("正在调用 AI 模型提取 topics...\n");
     
     // 在发送请求前再次检查任务状态
@@ -2291,43 +3677,41 @@ async fn extract_topics_internal(
         return Err("任务已被停止".to_string());
     }
     
-    let response = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-        .map_err(|e| {
-            let msg = format!("发送 topics 提取请求失败: {}", e);
+    // 使用流式请求，部分结果实时发送到前端
+    let output = match stream_task_ai_stage(
+        app.as_ref(),
+        &task_id,
+        "topics",
+        &compression_config,
+        vec![system_message, user_message],
+        Some(0.7),
+    )
+    .await
+    {
+        Ok(output) => output,
+        Err(e) if e == ai::STREAM_CANCELLED => {
+            emit_error("任务已被停止，已中断 topics 提取请求\n");
+            return Err("任务已被停止".to_string());
+        }
+        Err(e) => {
+            let msg = format!("提取 topics 失败: {}", e);
             emit_error(&format!("{}\n", msg));
-            msg
-        })?;
-    
-    // 检查响应状态
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_default();
-        let msg = format!("提取 topics 失败: {} - {}", status, error_text);
-        emit_error(&format!("{}\n", msg));
-        return Err(msg);
-    }
+            return Err(msg);
+        }
+    };
     
-    emit_log("AI 模型响应成功，正在解析 topics...\n");
+    emit_log("AI 模型响应完成，正在解析 topics...\n");
     
-    // 解析响应
-    let completion_response: ai::ChatCompletionResponse = response
-        .json()
-        .await
-        .map_err(|e| format!("解析 topics 提取响应失败: {}", e))?;
+    // 记录 token 用量
+    if let Some(token_usage) = output.usage {
+        let scope = usage::UsageScope {
+            task_id: Some(task_id.clone()),
+            ..Default::default()
+        };
+        usage::record(db_path.clone(), &compression_config, usage::SOURCE_TOPICS, scope, token_usage).await;
+    }
     
-    // 提取响应内容
-    let content = completion_response
-        .choices
-        .first()
-        .and_then(|choice| choice.message.content.as_ref())
-        .map(|content| content.trim().to_string())
-        .ok_or_else(|| "AI 响应中没有内容".to_string())?;
+    let content = output.content;
     
     // 尝试从响应中提取 JSON（可能包含 markdown 代码块）
     let json_content = if content.starts_with("```") {
@@ -2521,6 +3905,12 @@ async fn stop_transcription_task(
         eprintln!("任务 {} 状态为 RUNNING，但不在运行列表中，直接标记为失败", task_id);
     }
     
+    // 中断正在进行的压缩 / topics 提取流式请求
+    let ai_requests: State<'_, RunningAIRequests> = app.state();
+    if ai_requests.cancel(&task_id).await {
+        eprintln!("已中断任务 {} 的 AI 请求", task_id);
+    }
+    
     // 更新任务状态为 failed（因为是被用户停止的）
     task.status = "failed".to_string();
     task.error = Some("任务已被用户停止".to_string());
@@ -2626,6 +4016,12 @@ async fn delete_transcription_resource(
         // 先获取所有关联的任务，以便删除它们的结果文件
         let tasks = db::get_tasks_by_resource(&conn, &resource_id)
             .map_err(|e| format!("无法查询关联任务: {}", e))?;
+        let mut track_files = Vec::new();
+        for task in &tasks {
+            let tracks = db::get_subtitle_tracks(&conn, &task.id)
+                .map_err(|e| format!("无法查询字幕轨道: {}", e))?;
+            track_files.extend(tracks.into_iter().map(|t| t.file_path));
+        }
         
         // 使用事务保护数据库操作
         let tx = conn.transaction()
@@ -2652,6 +4048,9 @@ async fn delete_transcription_resource(
                 }
             }
         }
+        for track_file in &track_files {
+            let _ = std::fs::remove_file(track_file);
+        }
         
         Ok(())
     })
@@ -2718,6 +4117,13 @@ async fn delete_transcription_task(
             }
         }
         
+        // 删除所有字幕轨道文件
+        let tracks = db::get_subtitle_tracks(&conn, &task_id)
+            .map_err(|e| format!("无法查询字幕轨道: {}", e))?;
+        for track in &tracks {
+            let _ = std::fs::remove_file(&track.file_path);
+        }
+        
         db::delete_task(&conn, &task_id)
             .map_err(|e| format!("无法删除任务: {}", e))?;
         
@@ -2761,6 +4167,75 @@ async fn read_transcription_result(
     .map_err(|e| format!("数据库操作失败: {}", e))?
 }
 
+// 获取任务下载的所有字幕轨道（主轨道在前）
+#[tauri::command]
+async fn get_task_subtitle_tracks(
+    task_id: String,
+    app: tauri::AppHandle,
+) -> Result<Vec<SubtitleTrack>, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::get_subtitle_tracks(&conn, &task_id)
+            .map_err(|e| format!("无法查询字幕轨道: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 切换任务的主字幕轨道，任务结果指向该轨道的文件
+// 压缩内容和主题基于旧轨道生成，切换后清空
+#[tauri::command]
+async fn set_primary_subtitle_track(
+    task_id: String,
+    track_id: String,
+    app: tauri::AppHandle,
+) -> Result<TranscriptionTask, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let mut conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        
+        let mut task = db::get_task(&conn, &task_id)
+            .map_err(|e| format!("无法查询任务: {}", e))?
+            .ok_or_else(|| format!("转写任务不存在: {}", task_id))?;
+        let track = db::get_subtitle_tracks(&conn, &task_id)
+            .map_err(|e| format!("无法查询字幕轨道: {}", e))?
+            .into_iter()
+            .find(|t| t.id == track_id)
+            .ok_or_else(|| format!("字幕轨道不存在: {}", track_id))?;
+        
+        if !PathBuf::from(&track.file_path).exists() {
+            return Err("字幕轨道文件不存在".to_string());
+        }
+        
+        if task.result.as_deref() != Some(track.file_path.as_str()) {
+            task.result = Some(track.file_path.clone());
+            task.compressed_content = None;
+            task.topics = None;
+        }
+        
+        // 主轨道标记与任务结果在同一事务中更新，保持一致
+        let tx = conn.transaction()
+            .map_err(|e| format!("无法开始事务: {}", e))?;
+        db::set_primary_subtitle_track(&tx, &task_id, &track_id)
+            .map_err(|e| format!("无法设置主字幕轨道: {}", e))?;
+        db::update_task(&tx, &task)
+            .map_err(|e| format!("无法更新任务: {}", e))?;
+        tx.commit()
+            .map_err(|e| format!("无法提交事务: {}", e))?;
+        
+        Ok(task)
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
 // whisper-cli 环境检测结果
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FastWhisperStatus {
@@ -3036,82 +4511,58 @@ pub struct CommandExecutionResult {
 }
 
 // 执行命令（通用）
+// 仅允许运行 whisper-cli、ffmpeg 和 yt-dlp，并限制运行时间和输出大小
 #[tauri::command]
 async fn execute_command(
     command: String,
     args: Vec<String>,
     event_id: String,
     working_dir: Option<String>,
+    timeout_secs: Option<u64>,
     app: tauri::AppHandle,
+    commands: State<'_, command_sandbox::RunningCommands>,
 ) -> Result<CommandExecutionResult, String> {
-    // 构建命令
-    let mut cmd = tokio::process::Command::new(&command);
-    
-    // 添加参数
-    for arg in args {
-        cmd.arg(arg);
-    }
-    
-    // 设置工作目录（如果提供）
-    if let Some(dir) = working_dir {
-        cmd.current_dir(dir);
-    }
-    
-    // 设置 stdout 和 stderr 为管道，以便实时读取
-    cmd.stdout(std::process::Stdio::piped());
-    cmd.stderr(std::process::Stdio::piped());
-    
-    // 启动进程
-    let mut child = cmd.spawn()
-        .map_err(|e| format!("无法执行命令 {}: {}", command, e))?;
-    
-    // 获取 stdout 和 stderr 的句柄
-    let stdout = child.stdout.take()
-        .ok_or("无法获取 stdout 句柄")?;
-    let stderr = child.stderr.take()
-        .ok_or("无法获取 stderr 句柄")?;
-    
-    // 创建事件名称
-    let stdout_event_name = format!("cmd-stdout-{}", event_id);
-    let stderr_event_name = format!("cmd-stderr-{}", event_id);
-    
-    // 使用辅助函数并发读取 stdout 和 stderr，实时发送事件
-    let stdout_handle = spawn_stream_reader(
-        stdout,
-        app.clone(),
-        stdout_event_name,
-        "stdout",
-        false, // 启用调试日志
-    );
-    
-    let stderr_handle = spawn_stream_reader(
-        stderr,
-        app.clone(),
-        stderr_event_name,
-        "stderr",
-        false, // 启用调试日志
-    );
-    
-    // 等待进程完成
-    let status = child.wait().await
-        .map_err(|e| format!("等待进程完成失败: {}", e))?;
-    
-    // 获取 stdout 和 stderr 的输出
-    let stdout_output = stdout_handle.await
-        .map_err(|e| format!("读取 stdout 失败: {}", e))?;
-    let stderr_output = stderr_handle.await
-        .map_err(|e| format!("读取 stderr 失败: {}", e))?;
-    
-    // 获取退出码
-    let exit_code = status.code();
-    let success = status.success();
-    
-    Ok(CommandExecutionResult {
-        exit_code,
-        stdout: stdout_output,
-        stderr: stderr_output,
-        success,
+    command_sandbox::run(&app, &commands, &command, args, &event_id, working_dir, timeout_secs).await
+}
+
+// 通过对话框选择命令使用的文件或目录，选择结果会加入受控命令允许访问的路径
+// mode: "open" 选择文件，"save" 选择保存位置，"folder" 选择目录
+#[tauri::command]
+async fn pick_command_path(
+    mode: String,
+    app: tauri::AppHandle,
+    commands: State<'_, command_sandbox::RunningCommands>,
+) -> Result<Option<String>, String> {
+    use tauri_plugin_dialog::DialogExt;
+    
+    let dialog_app = app.clone();
+    let picked = tokio::task::spawn_blocking(move || {
+        let dialog = dialog_app.dialog().file();
+        match mode.as_str() {
+            "open" => Ok(dialog.blocking_pick_file()),
+            "save" => Ok(dialog.blocking_save_file()),
+            "folder" => Ok(dialog.blocking_pick_folder()),
+            _ => Err(format!("未知的选择模式: {}", mode)),
+        }
     })
+    .await
+    .map_err(|e| format!("打开对话框失败: {}", e))??;
+    
+    let path = match picked {
+        Some(picked) => picked.into_path().map_err(|e| format!("无效的路径: {}", e))?,
+        None => return Ok(None),
+    };
+    commands.allow_path(&path).await;
+    Ok(Some(path.to_string_lossy().to_string()))
+}
+
+// 取消正在执行的命令
+#[tauri::command]
+async fn cancel_command(
+    event_id: String,
+    commands: State<'_, command_sandbox::RunningCommands>,
+) -> Result<bool, String> {
+    Ok(commands.cancel(&event_id).await)
 }
 
 // 从视频中提取音频
@@ -3148,9 +4599,9 @@ async fn extract_audio_from_video(
         }
     }
     
-    // 检查是否是 URL 资源，URL 资源不需要提取音频（会直接使用 yt-dlp 获取字幕）
+    // 检查是否是 URL 资源，URL 资源不需要提取音频（转写任务会使用 yt-dlp 获取字幕或下载音频）
     if matches!(resource.source_type, SourceType::Url) || is_url(&resource.file_path) {
-        return Err("URL资源无需提取音频，转写任务会直接使用 yt-dlp 获取字幕".to_string());
+        return Err("URL资源无需提取音频，转写任务会使用 yt-dlp 获取字幕或下载音频".to_string());
     }
     
     // 检查是否已经有提取的音频
@@ -3175,19 +4626,112 @@ async fn extract_audio_from_video(
     std::fs::create_dir_all(&extracted_audio_dir)
         .map_err(|e| format!("无法创建提取音频目录: {}", e))?;
     
-    let video_path = PathBuf::from(&resource.file_path);
-    let output_file_name = format!("{}.wav", resource_id);
-    let output_path = extracted_audio_dir.join(&output_file_name);
+    let video_path = PathBuf::from(&resource.file_path);
+    let output_file_name = format!("{}.wav", resource_id);
+    let output_path = extracted_audio_dir.join(&output_file_name);
+    
+    // 记录提取状态，页面重新加载后也能知道提取正在进行
+    save_extraction_status(&db_path, &resource_id, Some("extracting"), None).await?;
+    
+    let progress_event_name = format!("extraction-progress-{}", resource_id);
+    // 时长用于计算百分比和剩余时间：优先使用导入时探测的时长，没有时重新探测
+    let duration = match resource.media_info.as_ref().and_then(|info| info.duration).or(resource.duration) {
+        Some(duration) => Some(duration),
+        None => media_probe::probe(&ffmpeg_path, &video_path).await.ok().and_then(|info| info.duration),
+    };
+    
+    if let Err(err_msg) = run_audio_extraction(&app, &resource_id, &ffmpeg_path, &video_path, &output_path, duration).await {
+        eprintln!("{}", err_msg);
+        save_extraction_status(&db_path, &resource_id, Some("failed"), Some(&err_msg)).await?;
+        let _ = app.emit(&progress_event_name, &ExtractionProgress {
+            resource_id: resource_id.clone(),
+            status: "failed".to_string(),
+            processed_seconds: 0.0,
+            duration: None,
+            percent: None,
+            speed: None,
+            eta_seconds: None,
+            error: Some(err_msg.clone()),
+        });
+        return Err(err_msg);
+    }
+    
+    eprintln!("音频提取成功，输出文件: {}", output_path.display());
+    
+    // 保存提取的音频路径
+    resource.extracted_audio_path = Some(output_path.to_string_lossy().to_string());
+    resource.updated_at = Utc::now().to_rfc3339();
+    
+    let db_path_clone = db_path.clone();
+    let resource_clone = resource.clone();
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path_clone)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::update_resource(&conn, &resource_clone)
+            .map_err(|e| format!("无法更新资源: {}", e))?;
+        db::update_resource_extraction_status(&conn, &resource_clone.id, Some("completed"), None)
+            .map_err(|e| format!("无法更新提取状态: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))??;
+    
+    let _ = app.emit(&progress_event_name, &ExtractionProgress {
+        resource_id: resource_id.clone(),
+        status: "completed".to_string(),
+        processed_seconds: 0.0,
+        duration: None,
+        percent: Some(100.0),
+        speed: None,
+        eta_seconds: Some(0.0),
+        error: None,
+    });
     
+    Ok(output_path.to_string_lossy().to_string())
+}
+
+async fn save_extraction_status(
+    db_path: &Path,
+    resource_id: &str,
+    status: Option<&str>,
+    error: Option<&str>,
+) -> Result<(), String> {
+    let db_path = db_path.to_path_buf();
+    let resource_id = resource_id.to_string();
+    let status = status.map(|s| s.to_string());
+    let error = error.map(|e| e.to_string());
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::update_resource_extraction_status(&conn, &resource_id, status.as_deref(), error.as_deref())
+            .map_err(|e| format!("无法更新提取状态: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 运行 ffmpeg 提取音频，进度通过 extraction-progress-{resource_id} 事件发送
+async fn run_audio_extraction(
+    app: &tauri::AppHandle,
+    resource_id: &str,
+    ffmpeg_path: &Path,
+    video_path: &Path,
+    output_path: &Path,
+    duration: Option<f64>,
+) -> Result<(), String> {
     // 构建 ffmpeg 命令
     // ffmpeg -i input.mp4 -vn -acodec pcm_s16le -ar 16000 -ac 1 output.wav
     // -vn: 不包含视频
     // -acodec pcm_s16le: 音频编码为 PCM 16-bit little-endian
     // -ar 16000: 采样率为 16000 Hz（适合 whisper）
     // -ac 1: 单声道
-    let mut cmd = tokio::process::Command::new(&ffmpeg_path);
-    cmd.arg("-i")
-        .arg(&video_path)
+    // -progress pipe:1: 将机器可读的进度信息输出到 stdout
+    let mut cmd = tokio::process::Command::new(ffmpeg_path);
+    cmd.arg("-hide_banner")
+        .arg("-nostats")
+        .arg("-progress")
+        .arg("pipe:1")
+        .arg("-i")
+        .arg(video_path)
         .arg("-vn")  // 不包含视频
         .arg("-acodec")
         .arg("pcm_s16le")  // PCM 16-bit little-endian
@@ -3196,7 +4740,7 @@ async fn extract_audio_from_video(
         .arg("-ac")
         .arg("1")  // 单声道
         .arg("-y")  // 覆盖输出文件
-        .arg(&output_path);
+        .arg(output_path);
     
     // 设置 stdout 和 stderr 为管道
     cmd.stdout(std::process::Stdio::piped());
@@ -3204,11 +4748,7 @@ async fn extract_audio_from_video(
     
     // 启动进程
     let mut child = cmd.spawn()
-        .map_err(|e| {
-            let err_msg = format!("无法执行 ffmpeg: {}。请确保工具已正确安装。", e);
-            eprintln!("{}", err_msg);
-            err_msg
-        })?;
+        .map_err(|e| format!("无法执行 ffmpeg: {}。请确保工具已正确安装。", e))?;
     
     // 获取 stdout 和 stderr 的句柄
     let stdout = child.stdout.take()
@@ -3217,34 +4757,33 @@ async fn extract_audio_from_video(
         .ok_or("无法获取 stderr 句柄")?;
     
     // 将进程句柄存储到 RunningExtractions 中
-    running_extractions.insert(resource_id.clone(), child).await;
-    
-    // 创建事件名称
-    let log_event_name = format!("extraction-log-{}", resource_id);
+    let running_extractions: State<'_, RunningExtractions> = app.state();
+    running_extractions.insert(resource_id.to_string(), child).await;
     
-    // 读取 stdout（通常为空，但保留以防万一）
-    let stdout_handle = spawn_stream_reader(
+    // stdout 为进度信息，换算后发送进度事件
+    let stdout_handle = spawn_ffmpeg_progress_reader(
         stdout,
         app.clone(),
-        log_event_name.clone(),
-        "stdout",
-        false,
+        format!("extraction-progress-{}", resource_id),
+        resource_id.to_string(),
+        duration,
     );
     
-    // 读取 stderr 并发送日志事件（ffmpeg 的进度信息在 stderr 中）
-    let stderr_handle = spawn_ffmpeg_progress_reader(
+    // stderr 为 ffmpeg 日志
+    let stderr_handle = spawn_stream_reader(
         stderr,
         app.clone(),
-        log_event_name,
+        format!("extraction-log-{}", resource_id),
+        "stderr",
+        false,
     );
     
     // 等待进程完成
-    let running_extractions_clone: State<'_, RunningExtractions> = app.state();
     let status = loop {
-        if let Some(child_arc) = running_extractions_clone.get(&resource_id).await {
+        if let Some(child_arc) = running_extractions.get(resource_id).await {
             let mut child_guard = child_arc.lock().await;
             if let Ok(Some(exit_status)) = child_guard.try_wait() {
-                let _ = running_extractions_clone.remove(&resource_id).await;
+                let _ = running_extractions.remove(resource_id).await;
                 break exit_status;
             }
             drop(child_guard);
@@ -3255,12 +4794,10 @@ async fn extract_audio_from_video(
     };
     
     // 获取输出
-    let _stdout_output = stdout_handle.await
-        .map_err(|e| format!("读取 stdout 失败: {}", e))?;
+    let _ = stdout_handle.await;
     let stderr_output = stderr_handle.await
         .map_err(|e| format!("读取 stderr 失败: {}", e))?;
     
-    eprintln!("ffmpeg stdout: {}", _stdout_output);
     eprintln!("ffmpeg stderr: {}", stderr_output);
     eprintln!("ffmpeg 退出码: {:?}", status.code());
     
@@ -3270,38 +4807,15 @@ async fn extract_audio_from_video(
         } else {
             format!("ffmpeg 执行失败，退出码: {:?}", status.code())
         };
-        
-        eprintln!("音频提取失败: {}", error_msg);
-        
         return Err(format!("音频提取失败: {}", error_msg));
     }
     
     // 检查输出文件是否存在
     if !output_path.exists() {
-        let err_msg = format!("提取完成但未生成输出文件: {}", output_path.display());
-        eprintln!("{}", err_msg);
-        
-        return Err(err_msg);
+        return Err(format!("提取完成但未生成输出文件: {}", output_path.display()));
     }
     
-    eprintln!("音频提取成功，输出文件: {}", output_path.display());
-    
-    // 保存提取的音频路径
-    resource.extracted_audio_path = Some(output_path.to_string_lossy().to_string());
-    resource.updated_at = Utc::now().to_rfc3339();
-    
-    let db_path_clone = db_path.clone();
-    let resource_clone = resource.clone();
-    tokio::task::spawn_blocking(move || {
-        let conn = db::init_database(&db_path_clone)
-            .map_err(|e| format!("无法初始化数据库: {}", e))?;
-        db::update_resource(&conn, &resource_clone)
-            .map_err(|e| format!("无法更新资源: {}", e))
-    })
-    .await
-    .map_err(|e| format!("数据库操作失败: {}", e))??;
-    
-    Ok(output_path.to_string_lossy().to_string())
+    Ok(())
 }
 
 // 检查文件是否存在
@@ -3355,6 +4869,10 @@ async fn create_ai_config(
     base_url: String,
     api_key: String,
     model: String,
+    context_window: Option<i64>,
+    input_price: Option<f64>,
+    output_price: Option<f64>,
+    cached_input_price: Option<f64>,
     app: tauri::AppHandle,
 ) -> Result<AIConfig, String> {
     let id = Uuid::new_v4().to_string();
@@ -3369,6 +4887,10 @@ async fn create_ai_config(
         created_at: now.clone(),
         updated_at: now,
         is_compression_config: None,
+        context_window,
+        input_price,
+        output_price,
+        cached_input_price,
     };
     
     let app_data_dir = get_app_data_dir(&app)?;
@@ -3396,6 +4918,10 @@ async fn update_ai_config(
     base_url: String,
     api_key: String,
     model: String,
+    context_window: Option<i64>,
+    input_price: Option<f64>,
+    output_price: Option<f64>,
+    cached_input_price: Option<f64>,
     app: tauri::AppHandle,
 ) -> Result<AIConfig, String> {
     let app_data_dir = get_app_data_dir(&app)?;
@@ -3426,6 +4952,10 @@ async fn update_ai_config(
         created_at: existing_config.created_at,
         updated_at: Utc::now().to_rfc3339(),
         is_compression_config: existing_config.is_compression_config,
+        context_window,
+        input_price,
+        output_price,
+        cached_input_price,
     };
     
     let config_clone = updated_config.clone();
@@ -3528,6 +5058,99 @@ async fn delete_ai_config(
     Ok(())
 }
 
+// 获取所有提示词模板
+#[tauri::command]
+async fn get_prompt_templates(
+    app: tauri::AppHandle,
+) -> Result<Vec<PromptTemplate>, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::get_prompt_templates(&conn)
+            .map_err(|e| format!("无法获取提示词模板: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 保存提示词模板（不存在时按该语言新建）
+#[tauri::command]
+async fn update_prompt_template(
+    kind: String,
+    language: String,
+    system_prompt: String,
+    user_prompt: String,
+    app: tauri::AppHandle,
+) -> Result<PromptTemplate, String> {
+    prompt_templates::validate_kind(&kind)?;
+    let language = prompt_templates::normalize_language(Some(&language))
+        .ok_or_else(|| "请指定模板语言".to_string())?;
+    if system_prompt.trim().is_empty() {
+        return Err("系统提示词不能为空".to_string());
+    }
+    if kind != prompt_templates::KIND_TITLE && !user_prompt.contains("{{transcript}}") {
+        return Err("用户提示词必须包含 {{transcript}} 变量".to_string());
+    }
+    
+    let template = PromptTemplate {
+        kind,
+        language,
+        system_prompt,
+        user_prompt,
+        is_customized: true,
+        updated_at: Utc::now().to_rfc3339(),
+    };
+    
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    let template_clone = template.clone();
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::save_prompt_template(&conn, &template_clone)
+            .map_err(|e| format!("无法保存提示词模板: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))??;
+    
+    Ok(template)
+}
+
+// 重置提示词模板为内置默认值（没有内置默认值的语言会被删除，返回 None）
+#[tauri::command]
+async fn reset_prompt_template(
+    kind: String,
+    language: String,
+    app: tauri::AppHandle,
+) -> Result<Option<PromptTemplate>, String> {
+    prompt_templates::validate_kind(&kind)?;
+    let language = prompt_templates::normalize_language(Some(&language))
+        .ok_or_else(|| "请指定模板语言".to_string())?;
+    let default_template = prompt_templates::default_template(&kind, &language);
+    
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    let template_clone = default_template.clone();
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        match template_clone {
+            Some(template) => db::save_prompt_template(&conn, &template),
+            None => db::delete_prompt_template(&conn, &kind, &language),
+        }
+        .map_err(|e| format!("无法重置提示词模板: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))??;
+    
+    Ok(default_template)
+}
+
 // 获取所有 MCP 配置
 #[tauri::command]
 async fn get_mcp_configs(
@@ -3543,6 +5166,7 @@ async fn get_mcp_configs(
     .map_err(|e| format!("任务执行失败: {}", e))?
     .map_err(|e| e)?;
     
+    let monitor = app.state::<mcp_monitor::MCPMonitor>().inner().clone();
     let mut servers = Vec::new();
     
     // 首先添加默认服务（固定在第一行）
@@ -3566,31 +5190,11 @@ async fn get_mcp_configs(
         // 优先使用配置中的 name 字段，如果没有则使用配置键名
         let display_name = server_config.name.as_ref().unwrap_or(&name).clone();
         
-        // 测试连接并获取工具列表
-        match mcp::test_mcp_connection(&name, &server_config).await {
-            Ok(tools) => {
-                servers.push(MCPServerInfo {
-                    name: display_name,
-                    key: Some(name.clone()), // 保存原始键名
-                    config: server_config.clone(),
-                    status: "connected".to_string(),
-                    tools: Some(tools),
-                    error: None,
-                    is_default: Some(false),
-                });
-            }
-            Err(e) => {
-                servers.push(MCPServerInfo {
-                    name: display_name,
-                    key: Some(name.clone()), // 保存原始键名
-                    config: server_config.clone(),
-                    status: "error".to_string(),
-                    tools: None,
-                    error: Some(e),
-                    is_default: Some(false),
-                });
-            }
-        }
+        // 从健康监控器获取状态和缓存的工具列表（没有缓存时立即检查一次）
+        let mut info = monitor.get_or_check(&app, &app_data_dir, &name, &server_config).await;
+        info.name = display_name;
+        info.config = server_config;
+        servers.push(info);
     }
     
     Ok(servers)
@@ -3634,6 +5238,9 @@ async fn save_mcp_config(
     .map_err(|e| format!("任务执行失败: {}", e))?
     .map_err(|e| e)?;
     
+    // 通知健康监控器重新检查
+    app.state::<mcp_monitor::MCPMonitor>().wake();
+    
     Ok(())
 }
 
@@ -3648,7 +5255,12 @@ async fn save_mcp_config_full(
     
     tokio::task::spawn_blocking({
         let config_path_clone = config_path.clone();
+        let mut config = config;
         move || {
+            // 前端编辑服务器列表时不会携带权限规则，保留已有的规则
+            if config.permissions.is_empty() {
+                config.permissions = mcp::load_mcp_config(&config_path_clone)?.permissions;
+            }
             mcp::save_mcp_config(&config_path_clone, &config)
         }
     })
@@ -3656,6 +5268,9 @@ async fn save_mcp_config_full(
     .map_err(|e| format!("任务执行失败: {}", e))?
     .map_err(|e| e)?;
     
+    // 通知健康监控器重新检查
+    app.state::<mcp_monitor::MCPMonitor>().wake();
+    
     Ok(())
 }
 
@@ -3694,6 +5309,9 @@ async fn delete_mcp_config(
     .map_err(|e| format!("任务执行失败: {}", e))?
     .map_err(|e| e)?;
     
+    // 通知健康监控器重新检查
+    app.state::<mcp_monitor::MCPMonitor>().wake();
+    
     Ok(())
 }
 
@@ -3734,51 +5352,295 @@ async fn update_mcp_enabled(
         }
     })
     .await
-    .map_err(|e| format!("任务执行失败: {}", e))?
-    .map_err(|e| e)?;
-    
-    Ok(())
+    .map_err(|e| format!("任务执行失败: {}", e))?
+    .map_err(|e| e)?;
+    
+    // 通知健康监控器重新检查
+    app.state::<mcp_monitor::MCPMonitor>().wake();
+    
+    Ok(())
+}
+
+// 测试 MCP 连接并获取工具列表
+#[tauri::command]
+async fn test_mcp_connection(
+    server_name: String,
+    server_config: MCPServerConfig,
+    app: tauri::AppHandle,
+) -> Result<MCPServerInfo, String> {
+    // 优先使用配置中的 name 字段，如果没有则使用 server_name
+    let display_name = server_config.name.as_ref().unwrap_or(&server_name).clone();
+    
+    let app_data_dir = get_app_data_dir(&app)?;
+    let started_at = std::time::Instant::now();
+    let connection_result = match mcp_secrets::resolve_server_config(&app_data_dir, &server_config).await {
+        Ok(resolved_config) => mcp::test_mcp_connection(&server_name, &resolved_config).await,
+        Err(e) => Err(e),
+    };
+    
+    match connection_result {
+        Ok(tools) => Ok(MCPServerInfo {
+            name: display_name,
+            key: Some(server_name.clone()), // 保存原始键名
+            config: server_config,
+            status: "connected".to_string(),
+            tools: Some(tools),
+            error: None,
+            is_default: Some(false),
+            latency_ms: Some(started_at.elapsed().as_millis() as u64),
+            last_error: None,
+            last_checked_at: Some(Utc::now().to_rfc3339()),
+        }),
+        Err(e) => Ok(MCPServerInfo {
+            name: display_name,
+            key: Some(server_name.clone()), // 保存原始键名
+            config: server_config,
+            status: "error".to_string(),
+            tools: None,
+            error: Some(e.clone()),
+            is_default: Some(false),
+            latency_ms: None,
+            last_error: Some(e),
+            last_checked_at: Some(Utc::now().to_rfc3339()),
+        }),
+    }
+}
+
+// 立即检查 MCP 服务器并刷新缓存的工具列表
+#[tauri::command]
+async fn refresh_mcp_server(
+    server_name: String,
+    app: tauri::AppHandle,
+) -> Result<MCPServerInfo, String> {
+    if default_mcp::is_default_server(&server_name) {
+        return Ok(default_mcp::get_default_server_info());
+    }
+    
+    let config = get_mcp_config_full(app.clone()).await?;
+    let server_config = config.mcp_servers.get(&server_name)
+        .ok_or_else(|| format!("MCP 服务器 {} 不存在", server_name))?;
+    
+    let app_data_dir = get_app_data_dir(&app)?;
+    let monitor = app.state::<mcp_monitor::MCPMonitor>().inner().clone();
+    Ok(monitor.refresh(&app, &app_data_dir, &server_name, server_config).await)
+}
+
+// 从 Claude Desktop、Cursor 或 VS Code 的配置文件导入 MCP 服务器
+// format 为空时自动识别；dry_run 为 true 时只返回诊断信息，不保存
+#[tauri::command]
+async fn import_mcp_config(
+    file_path: String,
+    format: Option<String>,
+    dry_run: Option<bool>,
+    app: tauri::AppHandle,
+) -> Result<mcp_import::MCPImportReport, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let config_path = mcp::get_mcp_config_path(&app_data_dir);
+    
+    let report = tokio::task::spawn_blocking(move || -> Result<mcp_import::MCPImportReport, String> {
+        let content = std::fs::read_to_string(&file_path)
+            .map_err(|e| format!("无法读取配置文件: {}", e))?;
+        
+        let mut config = mcp::load_mcp_config(&config_path)?;
+        let report = mcp_import::import_config(&content, format.as_deref(), &mut config)?;
+        
+        if !dry_run.unwrap_or(false) && !report.imported.is_empty() {
+            mcp::save_mcp_config(&config_path, &config)?;
+        }
+        
+        Ok(report)
+    })
+    .await
+    .map_err(|e| format!("任务执行失败: {}", e))??;
+    
+    if !report.imported.is_empty() {
+        app.state::<mcp_monitor::MCPMonitor>().wake();
+    }
+    
+    Ok(report)
+}
+
+// 导出 MCP 配置为 Claude Desktop、Cursor 或 VS Code 格式（提供 file_path 时同时写入文件）
+#[tauri::command]
+async fn export_mcp_config(
+    format: String,
+    file_path: Option<String>,
+    app: tauri::AppHandle,
+) -> Result<mcp_import::MCPExportResult, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let config_path = mcp::get_mcp_config_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let config = mcp::load_mcp_config(&config_path)?;
+        let result = mcp_import::export_config(&config, &format)?;
+        
+        if let Some(file_path) = file_path {
+            std::fs::write(&file_path, &result.content)
+                .map_err(|e| format!("无法写入配置文件: {}", e))?;
+        }
+        
+        Ok(result)
+    })
+    .await
+    .map_err(|e| format!("任务执行失败: {}", e))?
+}
+
+// 获取 MCP 工具调用权限规则
+#[tauri::command]
+async fn get_mcp_permissions(
+    app: tauri::AppHandle,
+) -> Result<IndexMap<String, MCPServerPermission>, String> {
+    let config = get_mcp_config_full(app).await?;
+    Ok(config.permissions)
+}
+
+// 保存单个服务器的权限规则（permission 为空时删除该服务器的规则）
+#[tauri::command]
+async fn save_mcp_permission(
+    server_name: String,
+    permission: Option<MCPServerPermission>,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    // 校验参数约束中的正则表达式
+    if let Some(permission) = &permission {
+        for (tool_name, rule) in &permission.tools {
+            if let Some(patterns) = &rule.argument_patterns {
+                for (arg_name, pattern) in patterns {
+                    regex::Regex::new(pattern).map_err(|e| {
+                        format!("工具 {} 的参数 {} 约束规则无效: {}", tool_name, arg_name, e)
+                    })?;
+                }
+            }
+        }
+    }
+    
+    // 默认服务的规则统一保存在 DEFAULT_MCP_SERVER_NAME 下
+    let server_key = if default_mcp::is_default_server(&server_name) {
+        default_mcp::DEFAULT_MCP_SERVER_NAME.to_string()
+    } else {
+        server_name
+    };
+    
+    let app_data_dir = get_app_data_dir(&app)?;
+    let config_path = mcp::get_mcp_config_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let mut config = mcp::load_mcp_config(&config_path)?;
+        match permission {
+            Some(permission) => {
+                config.permissions.insert(server_key, permission);
+            }
+            None => {
+                config.permissions.shift_remove(&server_key);
+            }
+        }
+        mcp::save_mcp_config(&config_path, &config)
+    })
+    .await
+    .map_err(|e| format!("任务执行失败: {}", e))?
+}
+
+// 响应工具调用批准请求
+#[tauri::command]
+async fn respond_mcp_tool_approval(
+    request_id: String,
+    approved: bool,
+    approvals: State<'_, PendingApprovals>,
+) -> Result<(), String> {
+    let sender = approvals
+        .remove(&request_id)
+        .await
+        .ok_or_else(|| "批准请求不存在或已过期".to_string())?;
+    
+    sender
+        .send(approved)
+        .map_err(|_| "批准请求已失效".to_string())
+}
+
+// 获取 MCP 工具调用审计日志
+#[tauri::command]
+async fn get_mcp_tool_call_logs(
+    server_name: Option<String>,
+    limit: Option<i64>,
+    app: tauri::AppHandle,
+) -> Result<Vec<MCPToolCallLog>, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::get_mcp_tool_call_logs(&conn, server_name.as_deref(), limit.unwrap_or(200))
+            .map_err(|e| format!("无法获取工具调用日志: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 清空 MCP 工具调用审计日志
+#[tauri::command]
+async fn clear_mcp_tool_call_logs(
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::clear_mcp_tool_call_logs(&conn)
+            .map_err(|e| format!("无法清空工具调用日志: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
 }
 
-// 测试 MCP 连接并获取工具列表
+// 获取 MCP 密钥名称列表（不返回密钥值）
 #[tauri::command]
-async fn test_mcp_connection(
-    server_name: String,
-    server_config: MCPServerConfig,
-) -> Result<MCPServerInfo, String> {
-    // 优先使用配置中的 name 字段，如果没有则使用 server_name
-    let display_name = server_config.name.as_ref().unwrap_or(&server_name).clone();
-    
-    match mcp::test_mcp_connection(&server_name, &server_config).await {
-        Ok(tools) => Ok(MCPServerInfo {
-            name: display_name,
-            key: Some(server_name.clone()), // 保存原始键名
-            config: server_config,
-            status: "connected".to_string(),
-            tools: Some(tools),
-            error: None,
-            is_default: Some(false),
-        }),
-        Err(e) => Ok(MCPServerInfo {
-            name: display_name,
-            key: Some(server_name.clone()), // 保存原始键名
-            config: server_config,
-            status: "error".to_string(),
-            tools: None,
-            error: Some(e),
-            is_default: Some(false),
-        }),
-    }
+async fn list_mcp_secrets(
+    app: tauri::AppHandle,
+) -> Result<Vec<String>, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    tokio::task::spawn_blocking(move || mcp_secrets::list_secret_names(&app_data_dir))
+        .await
+        .map_err(|e| format!("任务执行失败: {}", e))?
+}
+
+// 保存 MCP 密钥（加密存储，在配置中通过 ${secret:name} 引用）
+#[tauri::command]
+async fn set_mcp_secret(
+    name: String,
+    value: String,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    tokio::task::spawn_blocking(move || mcp_secrets::set_secret(&app_data_dir, &name, &value))
+        .await
+        .map_err(|e| format!("任务执行失败: {}", e))?
+}
+
+// 删除 MCP 密钥
+#[tauri::command]
+async fn delete_mcp_secret(
+    name: String,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    tokio::task::spawn_blocking(move || mcp_secrets::delete_secret(&app_data_dir, &name))
+        .await
+        .map_err(|e| format!("任务执行失败: {}", e))?
 }
 
 // AI 流式对话
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn chat_completion(
     config_id: String,
     messages: Vec<ai::ChatMessage>,
     tools: Option<Vec<MCPTool>>,
     system_message: Option<String>,
     event_id: Option<String>,
+    chat_id: Option<String>,
     app: tauri::AppHandle,
     streams: State<'_, RunningStreams>,
 ) -> Result<String, String> {
@@ -3801,6 +5663,10 @@ async fn chat_completion(
     
     let ai_config = ai_config.ok_or("AI 配置不存在")?;
     
+    // 追加 chat 关联的上下文说明
+    let (_, _, context_prompt) = chat_context::load_for_chat(db_path.clone(), chat_id.clone(), None, None).await?;
+    let system_message = chat_context::append_to_system_message(system_message, context_prompt);
+    
     // 构建消息列表（添加 system message）
     let mut chat_messages = Vec::new();
     if let Some(system_msg) = system_message {
@@ -3815,13 +5681,8 @@ async fn chat_completion(
     }
     chat_messages.extend(messages);
     
-    // 应用 cache control 优化
-    // 为工具调用结果（如 "get task info"）添加 ephemeral 缓存标记
-    // 这样可以优化 OpenRouter 等支持 prompt caching 的提供商的缓存使用
-    ai::apply_cache_control(&mut chat_messages);
-    
     // 转换 MCP 工具为 OpenAI 工具
-    let openai_tools = if let Some(mcp_tools) = tools {
+    let openai_tools: Option<Vec<ai::ToolDefinition>> = if let Some(mcp_tools) = tools {
         Some(
             mcp_tools
                 .iter()
@@ -3832,6 +5693,29 @@ async fn chat_completion(
         None
     };
     
+    // 将消息裁剪到模型的上下文预算内（截断旧工具结果、滚动摘要、丢弃最早的轮次）
+    let (mut chat_messages, fit_report) = context_window::fit_messages(
+        &context_window::ContextFitOptions {
+            db_path: db_path.clone(),
+            ai_config: &ai_config,
+            chat_id: chat_id.clone(),
+            tools: openai_tools.as_deref(),
+        },
+        chat_messages,
+    )
+    .await;
+    if fit_report.changed() {
+        eprintln!("[AI Stream] 上下文已裁剪: {:?}", fit_report);
+    }
+    
+    // 按提供商放置 prompt 缓存断点（system prompt、工具定义、大负载消息、最近轮次，最多 4 个）
+    let mut openai_tools = openai_tools;
+    prompt_cache::apply_breakpoints(
+        &ai_config,
+        &mut chat_messages,
+        openai_tools.as_deref_mut().unwrap_or_default(),
+    );
+    
     // 构建请求
     let request = ai::ChatCompletionRequest {
         model: ai_config.model.clone(),
@@ -3839,6 +5723,7 @@ async fn chat_completion(
         tools: openai_tools.clone(),
         tool_choice: Some("auto".to_string()),
         stream: true,
+        stream_options: ai::StreamOptions::with_usage(),
         temperature: Some(0.7),
     };
     
@@ -3893,7 +5778,7 @@ async fn chat_completion(
     
     // 读取流式响应
     let mut stream = response.bytes_stream();
-    let mut buffer = String::new();
+    let mut sse = ai::SseLineBuffer::default();
     let mut current_tool_calls: HashMap<u32, ai::ToolCallChunk> = HashMap::new();
     
     use futures_util::StreamExt;
@@ -3902,20 +5787,21 @@ async fn chat_completion(
     let app_clone = app.clone();
     let event_name_clone = event_name.clone();
     let streams_clone = streams.inner().clone();
+    let usage_config = ai_config.clone();
+    let usage_scope = usage::UsageScope {
+        chat_id,
+        event_id: Some(event_id.clone()),
+        ..Default::default()
+    };
     let handle = tokio::spawn(async move {
         eprintln!("[AI Stream] 开始接收流式响应，事件 ID: {}", event_id_clone);
         while let Some(chunk_result) = stream.next().await {
             match chunk_result {
                 Ok(chunk) => {
-                    let text = String::from_utf8_lossy(chunk.as_ref());
-                    eprintln!("[AI Stream] 收到原始数据块: {}", text);
-                    buffer.push_str(&text);
+                    sse.push(chunk.as_ref());
                     
                     // 按行处理 SSE 数据
-                    while let Some(newline_pos) = buffer.find('\n') {
-                        let line = buffer[..newline_pos].trim().to_string();
-                        buffer = buffer[newline_pos + 1..].to_string();
-                        
+                    while let Some(line) = sse.next_line() {
                         eprintln!("[AI Stream] 处理行: {}", line);
                         
                         if line.starts_with("data: ") {
@@ -4070,6 +5956,16 @@ async fn chat_completion(
                                             }
                                         }
                                     }
+                                    
+                                    // 最后一个块携带本次调用的 token 用量（在 finish_reason 之后、[DONE] 之前）
+                                    if let Some(token_usage) = chunk_data.usage.as_ref().and_then(usage::parse_usage) {
+                                        let _ = app_clone.emit(&event_name_clone, &json!({
+                                            "type": "usage",
+                                            "usage": token_usage,
+                                            "event_id": event_id_clone
+                                        }));
+                                        usage::record(db_path.clone(), &usage_config, usage::SOURCE_CHAT, usage_scope.clone(), token_usage).await;
+                                    }
                                 }
                                 Err(e) => {
                                     eprintln!("[AI Stream] JSON 解析失败: {}, 原始数据: {}", e, data);
@@ -4087,159 +5983,73 @@ async fn chat_completion(
         // 流正常结束（没有更多数据）
         eprintln!("[AI Stream] 流处理结束（没有更多数据）");
         
-        // 处理剩余的 buffer 内容（如果有）
-        if !buffer.trim().is_empty() {
-            eprintln!("[AI Stream] 处理剩余的 buffer 内容: {}", buffer);
-            // 尝试处理剩余的 buffer（包括没有换行符的最后一行）
-            loop {
-                if buffer.trim().is_empty() {
+        // 处理剩余的 buffer 内容（没有换行符的最后一行）
+        sse.finish();
+        while let Some(line) = sse.next_line() {
+            if let Some(data) = line.strip_prefix("data: ") {
+                if data == "[DONE]" {
+                    eprintln!("[AI Stream] 在 buffer 中发现 [DONE]");
                     break;
                 }
                 
-                if let Some(newline_pos) = buffer.find('\n') {
-                    let line = buffer[..newline_pos].trim().to_string();
-                    buffer = buffer[newline_pos + 1..].to_string();
-                    
-                    if line.starts_with("data: ") {
-                        let data = &line[6..];
-                        if data == "[DONE]" {
-                            eprintln!("[AI Stream] 在 buffer 中发现 [DONE]");
-                            break;
-                        }
+                // 尝试解析 JSON
+                if let Ok(chunk_data) = serde_json::from_str::<ai::ChatCompletionChunk>(data) {
+                    if let Some(choice) = chunk_data.choices.first() {
+                        let delta = &choice.delta;
                         
-                        // 尝试解析 JSON
-                        if let Ok(chunk_data) = serde_json::from_str::<ai::ChatCompletionChunk>(data) {
-                            if let Some(choice) = chunk_data.choices.first() {
-                                let delta = &choice.delta;
-                                
-                                // 处理内容增量
-                                if let Some(content) = &delta.content {
-                                    if !content.is_empty() {
-                                        let _ = app_clone.emit(&event_name_clone, &json!({
-                                            "type": "content",
-                                            "content": content,
-                                            "event_id": event_id_clone
-                                        }));
-                                    }
-                                }
-                                
-                                // 处理 reasoning
-                                if let Some(reasoning) = &delta.reasoning {
-                                    if !reasoning.is_empty() {
-                                        let _ = app_clone.emit(&event_name_clone, &json!({
-                                            "type": "reasoning",
-                                            "content": reasoning,
-                                            "event_id": event_id_clone
-                                        }));
-                                    }
-                                }
-                                
-                                // 检查 finish_reason
-                                if let Some(finish_reason) = &choice.finish_reason {
-                                    eprintln!("[AI Stream] 在 buffer 中发现 finish_reason: {}", finish_reason);
-                                    if finish_reason == "tool_calls" && !current_tool_calls.is_empty() {
-                                        let tool_calls: Vec<serde_json::Value> = current_tool_calls
-                                            .values()
-                                            .map(|tc| {
-                                                json!({
-                                                    "id": tc.id.as_ref().unwrap_or(&"".to_string()),
-                                                    "type": tc.call_type.as_ref().unwrap_or(&"function".to_string()),
-                                                    "function": {
-                                                        "name": tc.function.as_ref()
-                                                            .and_then(|f| f.name.as_ref())
-                                                            .unwrap_or(&"".to_string()),
-                                                        "arguments": tc.function.as_ref()
-                                                            .and_then(|f| f.arguments.as_ref())
-                                                            .unwrap_or(&"".to_string()),
-                                                    }
-                                                })
-                                            })
-                                            .collect();
-                                        
-                                        let _ = app_clone.emit(&event_name_clone, &json!({
-                                            "type": "tool_calls",
-                                            "tool_calls": tool_calls,
-                                            "event_id": event_id_clone
-                                        }));
-                                        current_tool_calls.clear();
-                                    }
-                                }
+                        // 处理内容增量
+                        if let Some(content) = &delta.content {
+                            if !content.is_empty() {
+                                let _ = app_clone.emit(&event_name_clone, &json!({
+                                    "type": "content",
+                                    "content": content,
+                                    "event_id": event_id_clone
+                                }));
                             }
                         }
-                    }
-                } else {
-                    // 处理最后一行（没有换行符）
-                    let line = buffer.trim().to_string();
-                    buffer.clear();
-                    
-                    if line.starts_with("data: ") {
-                        let data = &line[6..];
-                        if data == "[DONE]" {
-                            eprintln!("[AI Stream] 在 buffer 中发现 [DONE]");
-                            break;
+                        
+                        // 处理 reasoning
+                        if let Some(reasoning) = &delta.reasoning {
+                            if !reasoning.is_empty() {
+                                let _ = app_clone.emit(&event_name_clone, &json!({
+                                    "type": "reasoning",
+                                    "content": reasoning,
+                                    "event_id": event_id_clone
+                                }));
+                            }
                         }
                         
-                        // 尝试解析 JSON
-                        if let Ok(chunk_data) = serde_json::from_str::<ai::ChatCompletionChunk>(data) {
-                            if let Some(choice) = chunk_data.choices.first() {
-                                let delta = &choice.delta;
-                                
-                                // 处理内容增量
-                                if let Some(content) = &delta.content {
-                                    if !content.is_empty() {
-                                        let _ = app_clone.emit(&event_name_clone, &json!({
-                                            "type": "content",
-                                            "content": content,
-                                            "event_id": event_id_clone
-                                        }));
-                                    }
-                                }
-                                
-                                // 处理 reasoning
-                                if let Some(reasoning) = &delta.reasoning {
-                                    if !reasoning.is_empty() {
-                                        let _ = app_clone.emit(&event_name_clone, &json!({
-                                            "type": "reasoning",
-                                            "content": reasoning,
-                                            "event_id": event_id_clone
-                                        }));
-                                    }
-                                }
+                        // 检查 finish_reason
+                        if let Some(finish_reason) = &choice.finish_reason {
+                            eprintln!("[AI Stream] 在 buffer 中发现 finish_reason: {}", finish_reason);
+                            if finish_reason == "tool_calls" && !current_tool_calls.is_empty() {
+                                let tool_calls: Vec<serde_json::Value> = current_tool_calls
+                                    .values()
+                                    .map(|tc| {
+                                        json!({
+                                            "id": tc.id.as_ref().unwrap_or(&"".to_string()),
+                                            "type": tc.call_type.as_ref().unwrap_or(&"function".to_string()),
+                                            "function": {
+                                                "name": tc.function.as_ref()
+                                                    .and_then(|f| f.name.as_ref())
+                                                    .unwrap_or(&"".to_string()),
+                                                "arguments": tc.function.as_ref()
+                                                    .and_then(|f| f.arguments.as_ref())
+                                                    .unwrap_or(&"".to_string()),
+                                            }
+                                        })
+                                    })
+                                    .collect();
                                 
-                                // 检查 finish_reason
-                                if let Some(finish_reason) = &choice.finish_reason {
-                                    eprintln!("[AI Stream] 在 buffer 中发现 finish_reason: {}", finish_reason);
-                                    if finish_reason == "tool_calls" && !current_tool_calls.is_empty() {
-                                        let tool_calls: Vec<serde_json::Value> = current_tool_calls
-                                            .values()
-                                            .map(|tc| {
-                                                json!({
-                                                    "id": tc.id.as_ref().unwrap_or(&"".to_string()),
-                                                    "type": tc.call_type.as_ref().unwrap_or(&"function".to_string()),
-                                                    "function": {
-                                                        "name": tc.function.as_ref()
-                                                            .and_then(|f| f.name.as_ref())
-                                                            .unwrap_or(&"".to_string()),
-                                                        "arguments": tc.function.as_ref()
-                                                            .and_then(|f| f.arguments.as_ref())
-                                                            .unwrap_or(&"".to_string()),
-                                                    }
-                                                })
-                                            })
-                                            .collect();
-                                        
-                                        let _ = app_clone.emit(&event_name_clone, &json!({
-                                            "type": "tool_calls",
-                                            "tool_calls": tool_calls,
-                                            "event_id": event_id_clone
-                                        }));
-                                        current_tool_calls.clear();
-                                    }
-                                }
+                                let _ = app_clone.emit(&event_name_clone, &json!({
+                                    "type": "tool_calls",
+                                    "tool_calls": tool_calls,
+                                    "event_id": event_id_clone
+                                }));
+                                current_tool_calls.clear();
                             }
                         }
                     }
-                    break;
                 }
             }
         }
@@ -4271,56 +6081,264 @@ async fn chat_completion(
                 "event_id": event_id_clone
             }));
         }
-        
-        // 无论什么情况，流结束时都要发送 done 事件
-        eprintln!("[AI Stream] 发送完成事件（流结束），事件名称: {}", event_name_clone);
-        let emit_result = app_clone.emit(&event_name_clone, &json!({
-            "type": "done",
-            "event_id": event_id_clone
-        }));
-        if let Err(e) = emit_result {
-            eprintln!("[AI Stream] 发送完成事件失败: {}", e);
-        } else {
-            eprintln!("[AI Stream] 完成事件发送成功");
-        }
-        
+        
+        // 无论什么情况，流结束时都要发送 done 事件
+        eprintln!("[AI Stream] 发送完成事件（流结束），事件名称: {}", event_name_clone);
+        let emit_result = app_clone.emit(&event_name_clone, &json!({
+            "type": "done",
+            "event_id": event_id_clone
+        }));
+        if let Err(e) = emit_result {
+            eprintln!("[AI Stream] 发送完成事件失败: {}", e);
+        } else {
+            eprintln!("[AI Stream] 完成事件发送成功");
+        }
+        
+        // 清理流式任务
+        streams_clone.remove(&event_id_clone).await;
+    });
+    
+    // 存储 AbortHandle 以便可以停止任务
+    streams.insert(event_id.clone(), handle.abort_handle()).await;
+    
+    Ok(event_id)
+}
+
+// 停止 AI 流式对话
+#[tauri::command]
+async fn stop_chat_completion(
+    event_id: String,
+    streams: State<'_, RunningStreams>,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    eprintln!("[AI Stream] 收到停止请求，事件 ID: {}", event_id);
+    
+    // 停止流式任务
+    if streams.abort(&event_id).await {
+        eprintln!("[AI Stream] 已停止流式任务: {}", event_id);
+        
+        // 发送停止完成事件
+        let event_name = format!("ai-chat-stream-{}", event_id);
+        let _ = app.emit(&event_name, &json!({
+            "type": "stopped",
+            "event_id": event_id
+        }));
+        
+        Ok(())
+    } else {
+        eprintln!("[AI Stream] 未找到流式任务: {}", event_id);
+        Err("未找到指定的流式任务".to_string())
+    }
+}
+
+// 获取 token 用量报表（group_by: "day" | "config" | "chat" | "resource"，按天统计时使用本地日期）
+#[tauri::command]
+async fn get_usage_report(
+    group_by: String,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    app: tauri::AppHandle,
+) -> Result<Vec<UsageReportRow>, String> {
+    if !matches!(group_by.as_str(), "day" | "config" | "chat" | "resource") {
+        return Err(format!("不支持的分组方式: {}", group_by));
+    }
+    
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::get_usage_report(&conn, &group_by, start_date.as_deref(), end_date.as_deref())
+            .map_err(|e| format!("无法获取用量报表: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 获取 token 用量明细（可按 chat 或任务过滤）
+#[tauri::command]
+async fn get_usage_records(
+    chat_id: Option<String>,
+    task_id: Option<String>,
+    limit: Option<i64>,
+    app: tauri::AppHandle,
+) -> Result<Vec<UsageRecord>, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::get_usage_records(&conn, chat_id.as_deref(), task_id.as_deref(), limit.unwrap_or(200))
+            .map_err(|e| format!("无法获取用量记录: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 在后端运行多步 Agent 循环
+// 流式补全 -> 并行执行工具调用 -> 追加工具消息 -> 重复，所有消息都会保存到数据库
+// 事件通过 ai-chat-stream-{event_id} 发送，可使用 stop_chat_completion 取消
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn agent_run(
+    chat_id: String,
+    config_id: String,
+    messages: Vec<ai::ChatMessage>,
+    servers: Vec<MCPServerInfo>,
+    system_message: Option<String>,
+    event_id: Option<String>,
+    max_steps: Option<u32>,
+    current_resource_id: Option<String>,
+    current_task_id: Option<String>,
+    app: tauri::AppHandle,
+    streams: State<'_, RunningStreams>,
+) -> Result<String, String> {
+    start_agent_run(
+        app,
+        streams.inner(),
+        chat_id,
+        config_id,
+        messages,
+        servers,
+        system_message,
+        event_id,
+        max_steps,
+        current_resource_id,
+        current_task_id,
+        None,
+    )
+    .await
+}
+
+// 重新生成时切换前的激活分支，在新回复保存之前出错时恢复
+#[derive(Clone)]
+struct ActiveLeafRestore {
+    chat_id: String,
+    parent_id: String,
+    previous_leaf: Option<String>,
+}
+
+// 激活分支仍停留在父消息（还没有保存新回复）时恢复为切换前的分支
+async fn restore_active_leaf(db_path: PathBuf, restore: ActiveLeafRestore) {
+    let result = tokio::task::spawn_blocking(move || -> Result<(), String> {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        let current = db::get_active_leaf_id(&conn, &restore.chat_id)
+            .map_err(|e| format!("无法获取激活分支: {}", e))?;
+        if current.as_deref() == Some(restore.parent_id.as_str()) {
+            db::set_active_leaf_id(&conn, &restore.chat_id, restore.previous_leaf.as_deref())
+                .map_err(|e| format!("无法恢复激活分支: {}", e))?;
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))
+    .and_then(|r| r);
+    if let Err(e) = result {
+        eprintln!("[Agent] {}", e);
+    }
+}
+
+// 启动后台 Agent 循环，返回事件 ID
+// restore_leaf 不为空时，循环出错会恢复重新生成前的激活分支
+#[allow(clippy::too_many_arguments)]
+async fn start_agent_run(
+    app: tauri::AppHandle,
+    streams: &RunningStreams,
+    chat_id: String,
+    config_id: String,
+    messages: Vec<ai::ChatMessage>,
+    servers: Vec<MCPServerInfo>,
+    system_message: Option<String>,
+    event_id: Option<String>,
+    max_steps: Option<u32>,
+    current_resource_id: Option<String>,
+    current_task_id: Option<String>,
+    restore_leaf: Option<ActiveLeafRestore>,
+) -> Result<String, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    let ai_config = tokio::task::spawn_blocking({
+        let db_path_clone = db_path.clone();
+        move || {
+            let conn = db::init_database(&db_path_clone)
+                .map_err(|e| format!("无法初始化数据库: {}", e))?;
+            db::get_ai_config(&conn, &config_id)
+                .map_err(|e| format!("无法从数据库读取 AI 配置: {}", e))
+        }
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))??;
+    
+    let ai_config = ai_config.ok_or("AI 配置不存在")?;
+    
+    // 使用 chat 关联的上下文补全当前资源/任务，并追加到 system message
+    let (current_resource_id, current_task_id, context_prompt) = chat_context::load_for_chat(
+        db_path.clone(),
+        Some(chat_id.clone()),
+        current_resource_id,
+        current_task_id,
+    )
+    .await?;
+    let system_message = chat_context::append_to_system_message(system_message, context_prompt);
+    
+    // 构建消息列表（添加 system message）
+    let mut chat_messages = Vec::new();
+    if let Some(system_msg) = system_message {
+        chat_messages.push(ai::ChatMessage {
+            role: "system".to_string(),
+            content: Some(system_msg),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            cache_control: None,
+        });
+    }
+    chat_messages.extend(messages);
+    
+    // 生成事件 ID（如果未提供，则生成新的）
+    let event_id = event_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+    
+    let ctx = agent::AgentRunContext {
+        app: app.clone(),
+        db_path: db_path.clone(),
+        ai_config,
+        chat_id,
+        event_id: event_id.clone(),
+        servers,
+        max_steps: max_steps.unwrap_or(agent::DEFAULT_AGENT_MAX_STEPS).max(1),
+        current_resource_id,
+        current_task_id,
+    };
+    
+    // 在后台任务中运行循环
+    let streams_clone = streams.clone();
+    let event_id_clone = event_id.clone();
+    let handle = tokio::spawn(async move {
+        if let Err(e) = agent::run_agent_loop(ctx, chat_messages).await {
+            eprintln!("[Agent] 运行失败: {}", e);
+            if let Some(restore) = restore_leaf {
+                restore_active_leaf(db_path, restore).await;
+            }
+            let _ = app.emit(&format!("ai-chat-stream-{}", event_id_clone), &json!({
+                "type": "error",
+                "error": e,
+                "event_id": event_id_clone
+            }));
+        }
         // 清理流式任务
         streams_clone.remove(&event_id_clone).await;
     });
     
-    // 存储 AbortHandle 以便可以停止任务
+    // 保存任务句柄，以便可以取消
     streams.insert(event_id.clone(), handle.abort_handle()).await;
     
     Ok(event_id)
 }
 
-// 停止 AI 流式对话
-#[tauri::command]
-async fn stop_chat_completion(
-    event_id: String,
-    streams: State<'_, RunningStreams>,
-    app: tauri::AppHandle,
-) -> Result<(), String> {
-    eprintln!("[AI Stream] 收到停止请求，事件 ID: {}", event_id);
-    
-    // 停止流式任务
-    if streams.abort(&event_id).await {
-        eprintln!("[AI Stream] 已停止流式任务: {}", event_id);
-        
-        // 发送停止完成事件
-        let event_name = format!("ai-chat-stream-{}", event_id);
-        let _ = app.emit(&event_name, &json!({
-            "type": "stopped",
-            "event_id": event_id
-        }));
-        
-        Ok(())
-    } else {
-        eprintln!("[AI Stream] 未找到流式任务: {}", event_id);
-        Err("未找到指定的流式任务".to_string())
-    }
-}
-
 // 执行 MCP 工具调用
 #[tauri::command]
 async fn execute_mcp_tool_call(
@@ -4330,44 +6348,253 @@ async fn execute_mcp_tool_call(
     app: tauri::AppHandle,
     current_resource_id: Option<String>,
     current_task_id: Option<String>,
+    chat_id: Option<String>,
 ) -> Result<serde_json::Value, String> {
-    // 获取所有服务器信息，检查是否是默认服务
-    let servers = get_mcp_configs(app.clone()).await?;
+    // 未传入当前资源/任务时，使用 chat 关联的上下文
+    let app_data_dir = get_app_data_dir(&app)?;
+    let (current_resource_id, current_task_id, _) = chat_context::load_for_chat(
+        db::get_db_path(&app_data_dir),
+        chat_id,
+        current_resource_id,
+        current_task_id,
+    )
+    .await?;
+    
+    call_mcp_tool_with_policy(
+        &app,
+        &server_name,
+        &tool_name,
+        arguments,
+        None,
+        current_resource_id,
+        current_task_id,
+    )
+    .await
+}
+
+// 查找工具的 input_schema（默认服务使用内置定义，外部服务器使用健康监控器缓存的工具列表）
+async fn resolve_tool_schema(
+    app: &tauri::AppHandle,
+    app_data_dir: &std::path::Path,
+    config: &MCPConfig,
+    is_default: bool,
+    server_name: &str,
+    tool_name: &str,
+) -> Option<serde_json::Value> {
+    let tools = if is_default {
+        default_mcp::get_default_tools()
+    } else {
+        let server_config = config.mcp_servers.get(server_name)?;
+        let monitor = app.state::<mcp_monitor::MCPMonitor>().inner().clone();
+        monitor.get_or_check(app, app_data_dir, server_name, server_config).await.tools?
+    };
     
-    // 查找对应的服务器
-    let server_info = servers.iter().find(|s| {
-        s.key.as_ref().map(|k| k == &server_name).unwrap_or(false)
-            || s.name == server_name
-    });
+    tools
+        .into_iter()
+        .find(|tool| tool.name == tool_name)
+        .map(|tool| tool.input_schema)
+}
+
+// 按权限策略执行 MCP 工具调用，并记录审计日志
+// input_schema 为空时会自动查找工具定义，参数校验失败时返回带 isError 的结果供模型自行修正
+#[allow(clippy::too_many_arguments)]
+async fn call_mcp_tool_with_policy(
+    app: &tauri::AppHandle,
+    server_name: &str,
+    tool_name: &str,
+    mut arguments: serde_json::Value,
+    input_schema: Option<serde_json::Value>,
+    current_resource_id: Option<String>,
+    current_task_id: Option<String>,
+) -> Result<serde_json::Value, String> {
+    let started_at = std::time::Instant::now();
     
-    // 如果是默认服务，直接调用默认工具
-    if let Some(server) = server_info {
-        if server.is_default == Some(true) {
-            return default_mcp::call_default_tool(
-                &tool_name,
+    // 获取 MCP 配置
+    let app_data_dir = get_app_data_dir(app)?;
+    let config_path = mcp::get_mcp_config_path(&app_data_dir);
+    
+    let config = tokio::task::spawn_blocking(move || {
+        mcp::load_mcp_config(&config_path)
+    })
+    .await
+    .map_err(|e| format!("任务执行失败: {}", e))??;
+    
+    // 权限规则按配置键名查找，默认服务统一使用 DEFAULT_MCP_SERVER_NAME
+    let is_default = default_mcp::is_default_server(server_name);
+    let server_key = if is_default {
+        default_mcp::DEFAULT_MCP_SERVER_NAME
+    } else {
+        server_name
+    };
+    
+    let policy = mcp_permission::resolve_policy(&config, server_key, tool_name);
+    let mut log = MCPToolCallLog {
+        id: Uuid::new_v4().to_string(),
+        server_name: server_key.to_string(),
+        tool_name: tool_name.to_string(),
+        arguments: arguments.to_string(),
+        policy: mcp_permission::policy_to_string(policy),
+        decision: "allowed".to_string(),
+        approved_by: Some("policy".to_string()),
+        success: false,
+        result_size: None,
+        duration_ms: 0,
+        error: None,
+        created_at: Utc::now().to_rfc3339(),
+    };
+    
+    // 先按原始参数做权限检查，被拒绝的工具不会为了获取 schema 而启动服务器
+    let mut rejection = check_tool_permission(app, &config, server_key, tool_name, &arguments, &mut log).await?;
+    
+    if rejection.is_none() {
+        // 按 input_schema 校验并修正参数
+        let input_schema = match input_schema {
+            Some(schema) => Some(schema),
+            None => resolve_tool_schema(app, &app_data_dir, &config, is_default, server_name, tool_name).await,
+        };
+        if let Some(schema) = &input_schema {
+            match mcp_schema::validate_and_coerce(schema, arguments.clone()) {
+                Ok(coerced) if coerced != arguments => {
+                    // 修正后的参数可能命中不同的规则，重新检查（用户已批准过的不再重复询问）
+                    log.arguments = coerced.to_string();
+                    let approved_by_user = log.approved_by.as_deref() == Some("user");
+                    rejection = match mcp_permission::check_permission(&config, server_key, tool_name, &coerced) {
+                        mcp_permission::PermissionCheck::Deny(reason) => Some(("denied", reason)),
+                        mcp_permission::PermissionCheck::Ask if !approved_by_user => {
+                            check_tool_permission(app, &config, server_key, tool_name, &coerced, &mut log).await?
+                        }
+                        _ => None,
+                    };
+                    arguments = coerced;
+                }
+                Ok(_) => {}
+                // 参数校验失败时不调用工具，把结构化错误作为工具结果返回
+                Err(errors) => {
+                    log.decision = "invalid".to_string();
+                    log.approved_by = None;
+                    log.error = Some(format!("参数校验失败: {}", serde_json::to_string(&errors).unwrap_or_default()));
+                    log.duration_ms = started_at.elapsed().as_millis() as i64;
+                    save_mcp_tool_call_log(&app_data_dir, log).await;
+                    return Ok(mcp_schema::validation_error_result(tool_name, schema, &errors));
+                }
+            }
+        }
+    }
+    
+    let result = match rejection {
+        Some((decision, reason)) => {
+            log.decision = decision.to_string();
+            log.approved_by = None;
+            Err(reason)
+        }
+        None => {
+            dispatch_mcp_tool_call(
+                app.clone(),
+                &config,
+                is_default,
+                server_name,
+                tool_name,
                 arguments,
-                app,
                 current_resource_id,
                 current_task_id,
             )
-            .await;
+            .await
+        }
+    };
+    
+    log.duration_ms = started_at.elapsed().as_millis() as i64;
+    match &result {
+        Ok(value) => {
+            log.success = true;
+            log.result_size = serde_json::to_string(value).ok().map(|s| s.len() as i64);
+        }
+        Err(e) => {
+            log.error = Some(e.clone());
         }
     }
     
-    // 获取 MCP 配置
-    let app_data_dir = get_app_data_dir(&app)?;
-    let config_path = mcp::get_mcp_config_path(&app_data_dir);
+    save_mcp_tool_call_log(&app_data_dir, log).await;
     
-    let config = tokio::task::spawn_blocking(move || {
-        mcp::load_mcp_config(&config_path)
+    result
+}
+
+// 检查工具调用权限，需要确认时请求用户批准；返回拒绝原因（None 表示允许）
+async fn check_tool_permission(
+    app: &tauri::AppHandle,
+    config: &MCPConfig,
+    server_key: &str,
+    tool_name: &str,
+    arguments: &serde_json::Value,
+    log: &mut MCPToolCallLog,
+) -> Result<Option<(&'static str, String)>, String> {
+    Ok(match mcp_permission::check_permission(config, server_key, tool_name, arguments) {
+        mcp_permission::PermissionCheck::Allow => None,
+        mcp_permission::PermissionCheck::Ask => {
+            match mcp_permission::request_approval(app, server_key, tool_name, arguments).await? {
+                mcp_permission::ApprovalResult::Approved => {
+                    log.approved_by = Some("user".to_string());
+                    None
+                }
+                mcp_permission::ApprovalResult::Rejected => {
+                    Some(("rejected", format!("用户拒绝了工具 {} 的调用", tool_name)))
+                }
+                mcp_permission::ApprovalResult::Timeout => {
+                    Some(("timeout", format!("等待用户批准工具 {} 超时", tool_name)))
+                }
+            }
+        }
+        mcp_permission::PermissionCheck::Deny(reason) => Some(("denied", reason)),
+    })
+}
+
+// 写入工具调用审计日志（失败时只打印错误，不影响工具调用结果）
+async fn save_mcp_tool_call_log(app_data_dir: &std::path::Path, log: MCPToolCallLog) {
+    let db_path = db::get_db_path(&app_data_dir.to_path_buf());
+    let log_result = tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::create_mcp_tool_call_log(&conn, &log)
+            .map_err(|e| format!("无法保存工具调用日志: {}", e))
     })
     .await
-    .map_err(|e| format!("任务执行失败: {}", e))?
-    .map_err(|e| e)?;
+    .map_err(|e| format!("数据库操作失败: {}", e))
+    .and_then(|r| r);
+    if let Err(e) = log_result {
+        eprintln!("{}", e);
+    }
+}
+
+// 将工具调用分发到默认服务或外部 MCP 服务器
+#[allow(clippy::too_many_arguments)]
+async fn dispatch_mcp_tool_call(
+    app: tauri::AppHandle,
+    config: &MCPConfig,
+    is_default: bool,
+    server_name: &str,
+    tool_name: &str,
+    arguments: serde_json::Value,
+    current_resource_id: Option<String>,
+    current_task_id: Option<String>,
+) -> Result<serde_json::Value, String> {
+    // 如果是默认服务，直接调用默认工具
+    if is_default {
+        return default_mcp::call_default_tool(
+            tool_name,
+            arguments,
+            app,
+            current_resource_id,
+            current_task_id,
+        )
+        .await;
+    }
     
-    let server_config = config.mcp_servers.get(&server_name)
+    let original_config = config.mcp_servers.get(server_name)
         .ok_or_else(|| format!("MCP 服务器 {} 不存在", server_name))?;
     
+    // 启动前展开 ${VAR} / ${secret:name} 占位符
+    let app_data_dir = get_app_data_dir(&app)?;
+    let server_config = &mcp_secrets::resolve_server_config(&app_data_dir, original_config).await?;
+    
     // 检查是否是 HTTP 传输（支持两种格式）
     let http_url = if let Some(transport_value) = &server_config.transport {
         if let Some(transport_obj) = transport_value.as_object() {
@@ -4430,122 +6657,26 @@ async fn execute_mcp_tool_call(
         return Ok(result);
     }
     
-    // stdio 传输：启动 MCP 服务器并调用工具
-    let command = server_config.command.as_ref()
-        .ok_or_else(|| format!("stdio 传输需要 command 字段，或 HTTP 传输需要 transport 或 url 字段"))?;
-    
-    let mut cmd = tokio::process::Command::new(command);
-    
-    if let Some(args) = &server_config.args {
-        for arg in args {
-            cmd.arg(arg);
-        }
-    }
-    
-    if let Some(env) = &server_config.env {
-        for (key, value) in env {
-            cmd.env(key, value);
-        }
-    }
-    
-    cmd.stdin(std::process::Stdio::piped());
-    cmd.stdout(std::process::Stdio::piped());
-    cmd.stderr(std::process::Stdio::piped());
-    
-    let mut child = cmd.spawn()
-        .map_err(|e| format!("无法启动 MCP 服务器: {}", e))?;
-    
-    let mut stdin = child.stdin.take().ok_or("无法获取 stdin")?;
-    let stdout = child.stdout.take().ok_or("无法获取 stdout")?;
-    
-    // 发送 initialize 请求
-    let init_request = json!({
-        "jsonrpc": "2.0",
-        "id": 1,
-        "method": "initialize",
-        "params": {
-            "protocolVersion": "2024-11-05",
-            "capabilities": {},
-            "clientInfo": {
-                "name": "qqh-tauri",
-                "version": "0.1.0"
-            }
-        }
-    });
-    
-    use tokio::io::{AsyncWriteExt, BufReader, AsyncBufReadExt};
-    let mut writer = tokio::io::BufWriter::new(&mut stdin);
-    writer.write_all(format!("{}\n", serde_json::to_string(&init_request).unwrap()).as_bytes()).await
-        .map_err(|e| format!("无法发送初始化请求: {}", e))?;
-    writer.flush().await.map_err(|e| format!("无法刷新 stdin: {}", e))?;
-    
-    // 读取初始化响应
-    let mut reader = BufReader::new(stdout);
-    let mut line = String::new();
-    let timeout = tokio::time::Duration::from_secs(5);
-    let _ = tokio::time::timeout(timeout, reader.read_line(&mut line)).await;
-    
-    // 发送 initialized 通知
-    let initialized_notification = json!({
-        "jsonrpc": "2.0",
-        "method": "notifications/initialized"
-    });
-    writer.write_all(format!("{}\n", serde_json::to_string(&initialized_notification).unwrap()).as_bytes()).await
-        .map_err(|e| format!("无法发送 initialized 通知: {}", e))?;
-    writer.flush().await.map_err(|e| format!("无法刷新 stdin: {}", e))?;
-    
-    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-    
-    // 调用工具
-    let call_request = json!({
-        "jsonrpc": "2.0",
-        "id": 2,
-        "method": "tools/call",
-        "params": {
-            "name": tool_name,
-            "arguments": arguments
-        }
-    });
-    
-    writer.write_all(format!("{}\n", serde_json::to_string(&call_request).unwrap()).as_bytes()).await
-        .map_err(|e| format!("无法发送工具调用请求: {}", e))?;
-    writer.flush().await.map_err(|e| format!("无法刷新 stdin: {}", e))?;
-    
-    // 读取响应
-    let mut response_line = String::new();
-    let response_result = tokio::time::timeout(timeout, reader.read_line(&mut response_line)).await
-        .map_err(|_| "读取工具调用响应超时")?;
-    
-    response_result.map_err(|e| format!("读取响应失败: {}", e))?;
-    
-    drop(writer);
-    
-    // 解析响应
-    let response: serde_json::Value = serde_json::from_str(&response_line)
-        .map_err(|e| format!("无法解析响应: {}", e))?;
-    
-    if let Some(error) = response.get("error") {
-        return Err(format!("工具调用失败: {}", error));
-    }
-    
-    if let Some(result) = response.get("result") {
-        Ok(result.clone())
-    } else {
-        Err("响应中没有结果".to_string())
-    }
+    // stdio 传输：复用监控模块维护的长连接调用工具，不再每次调用都启动新进程
+    let monitor = app.state::<mcp_monitor::MCPMonitor>().inner().clone();
+    monitor
+        .call_stdio_tool(server_name, original_config, server_config, tool_name, arguments)
+        .await
 }
 
 // 创建新 chat
 #[tauri::command]
 async fn create_chat(
     title: String,
+    resource_id: Option<String>,
+    task_id: Option<String>,
     app: tauri::AppHandle,
 ) -> Result<Chat, String> {
     let app_data_dir = get_app_data_dir(&app)?;
     let db_path = db::get_db_path(&app_data_dir);
     
     let chat = tokio::task::spawn_blocking(move || {
-        let conn = db::init_database(&db_path)
+        let mut conn = db::init_database(&db_path)
             .map_err(|e| format!("无法初始化数据库: {}", e))?;
         
         let chat_id = Uuid::new_v4().to_string();
@@ -4558,9 +6689,34 @@ async fn create_chat(
             updated_at: now.clone(),
         };
         
-        db::create_chat(&conn, &chat)
+        // chat 与上下文在同一事务中写入，任一关联目标不存在时不留下孤立的 chat
+        let tx = conn.transaction()
+            .map_err(|e| format!("无法开始事务: {}", e))?;
+        
+        db::create_chat(&tx, &chat)
             .map_err(|e| format!("无法创建 chat: {}", e))?;
         
+        // 创建时关联资源/任务
+        let targets = [
+            (chat_context::CONTEXT_TYPE_RESOURCE, resource_id),
+            (chat_context::CONTEXT_TYPE_TASK, task_id),
+        ];
+        for (context_type, context_id) in targets {
+            if let Some(context_id) = context_id {
+                chat_context::ensure_target_exists(&tx, context_type, &context_id)?;
+                db::add_chat_context(&tx, &ChatContext {
+                    chat_id: chat_id.clone(),
+                    context_type: context_type.to_string(),
+                    context_id,
+                    created_at: now.clone(),
+                })
+                .map_err(|e| format!("无法关联 chat 上下文: {}", e))?;
+            }
+        }
+        
+        tx.commit()
+            .map_err(|e| format!("无法提交事务: {}", e))?;
+        
         Ok(chat)
     })
     .await
@@ -4570,8 +6726,10 @@ async fn create_chat(
 }
 
 // 获取所有 chats（包含最后消息时间）
+// 指定 resource_id 时只返回关联到该资源（或其任务）的 chats
 #[tauri::command]
 async fn get_all_chats(
+    resource_id: Option<String>,
     app: tauri::AppHandle,
 ) -> Result<Vec<ChatListItem>, String> {
     let app_data_dir = get_app_data_dir(&app)?;
@@ -4581,8 +6739,11 @@ async fn get_all_chats(
         let conn = db::init_database(&db_path)
             .map_err(|e| format!("无法初始化数据库: {}", e))?;
         
-        let all_chats = db::get_all_chats(&conn)
-            .map_err(|e| format!("无法获取 chats: {}", e))?;
+        let all_chats = match &resource_id {
+            Some(resource_id) => db::get_chats_by_resource(&conn, resource_id),
+            None => db::get_all_chats(&conn),
+        }
+        .map_err(|e| format!("无法获取 chats: {}", e))?;
         
         let mut chat_items = Vec::new();
         for chat in all_chats {
@@ -4592,6 +6753,9 @@ async fn get_all_chats(
             let message_count = db::get_message_count_by_chat(&conn, &chat.id)
                 .map_err(|e| format!("无法获取消息数量: {}", e))?;
             
+            let contexts = db::get_chat_contexts(&conn, &chat.id)
+                .map_err(|e| format!("无法获取 chat 上下文: {}", e))?;
+            
             chat_items.push(ChatListItem {
                 id: chat.id,
                 title: chat.title,
@@ -4599,36 +6763,116 @@ async fn get_all_chats(
                 updated_at: chat.updated_at,
                 last_message_at: last_message.map(|m| m.created_at),
                 message_count,
+                contexts,
             });
         }
         
-        Ok(chat_items)
+        Ok(chat_items)
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?;
+    
+    chats
+}
+
+// 获取单个 chat
+#[tauri::command]
+async fn get_chat(
+    chat_id: String,
+    app: tauri::AppHandle,
+) -> Result<Option<Chat>, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    let chat = tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::get_chat(&conn, &chat_id)
+            .map_err(|e| format!("无法获取 chat: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?;
+    
+    chat
+}
+
+// 获取 chat 关联的上下文
+#[tauri::command]
+async fn get_chat_contexts(
+    chat_id: String,
+    app: tauri::AppHandle,
+) -> Result<Vec<ChatContext>, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::get_chat_contexts(&conn, &chat_id)
+            .map_err(|e| format!("无法获取 chat 上下文: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 将资源或任务关联到 chat
+#[tauri::command]
+async fn add_chat_context(
+    chat_id: String,
+    context_type: String,
+    context_id: String,
+    app: tauri::AppHandle,
+) -> Result<Vec<ChatContext>, String> {
+    chat_context::validate_context_type(&context_type)?;
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        
+        db::get_chat(&conn, &chat_id)
+            .map_err(|e| format!("无法获取 chat: {}", e))?
+            .ok_or_else(|| "Chat 不存在".to_string())?;
+        chat_context::ensure_target_exists(&conn, &context_type, &context_id)?;
+        
+        db::add_chat_context(&conn, &ChatContext {
+            chat_id: chat_id.clone(),
+            context_type,
+            context_id,
+            created_at: Utc::now().to_rfc3339(),
+        })
+        .map_err(|e| format!("无法关联 chat 上下文: {}", e))?;
+        
+        db::get_chat_contexts(&conn, &chat_id)
+            .map_err(|e| format!("无法获取 chat 上下文: {}", e))
     })
     .await
-    .map_err(|e| format!("数据库操作失败: {}", e))?;
-    
-    chats
+    .map_err(|e| format!("数据库操作失败: {}", e))?
 }
 
-// 获取单个 chat
+// 取消 chat 与资源或任务的关联
 #[tauri::command]
-async fn get_chat(
+async fn remove_chat_context(
     chat_id: String,
+    context_type: String,
+    context_id: String,
     app: tauri::AppHandle,
-) -> Result<Option<Chat>, String> {
+) -> Result<Vec<ChatContext>, String> {
+    chat_context::validate_context_type(&context_type)?;
     let app_data_dir = get_app_data_dir(&app)?;
     let db_path = db::get_db_path(&app_data_dir);
     
-    let chat = tokio::task::spawn_blocking(move || {
+    tokio::task::spawn_blocking(move || {
         let conn = db::init_database(&db_path)
             .map_err(|e| format!("无法初始化数据库: {}", e))?;
-        db::get_chat(&conn, &chat_id)
-            .map_err(|e| format!("无法获取 chat: {}", e))
+        db::remove_chat_context(&conn, &chat_id, &context_type, &context_id)
+            .map_err(|e| format!("无法取消关联 chat 上下文: {}", e))?;
+        db::get_chat_contexts(&conn, &chat_id)
+            .map_err(|e| format!("无法获取 chat 上下文: {}", e))
     })
     .await
-    .map_err(|e| format!("数据库操作失败: {}", e))?;
-    
-    chat
+    .map_err(|e| format!("数据库操作失败: {}", e))?
 }
 
 // 更新 chat 标题
@@ -4739,10 +6983,20 @@ async fn summarize_chat_title(
         chat_messages = chat_messages[..10].to_vec();
     }
     
+    // 按对话语言选择标题模板
+    let sample_text = chat_messages
+        .iter()
+        .filter_map(|m| m.content.as_deref())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let prompt = prompt_templates::resolve(db_path.clone(), prompt_templates::KIND_TITLE, None, &sample_text).await?;
+    let language_name = prompt.language_name();
+    let vars = [("language", language_name.as_str())];
+    
     // 添加 system message，要求生成简短标题
     let system_message = ai::ChatMessage {
         role: "system".to_string(),
-        content: Some("请根据以下对话内容，生成一个简洁的标题，纯文本，不超过 20 个字符。只返回标题，不要包含其他内容，不包含emoji。".to_string()),
+        content: Some(prompt_templates::render(&prompt.template.system_prompt, &vars)),
         tool_calls: None,
         tool_call_id: None,
         name: None,
@@ -4752,6 +7006,19 @@ async fn summarize_chat_title(
     let mut all_messages = vec![system_message];
     all_messages.extend(chat_messages);
     
+    // user_prompt 不为空时作为最后一条用户消息追加
+    let user_prompt = prompt_templates::render(&prompt.template.user_prompt, &vars);
+    if !user_prompt.trim().is_empty() {
+        all_messages.push(ai::ChatMessage {
+            role: "user".to_string(),
+            content: Some(user_prompt),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            cache_control: None,
+        });
+    }
+    
     // 构建非流式请求
     let request = ai::ChatCompletionRequest {
         model: ai_config.model.clone(),
@@ -4759,6 +7026,7 @@ async fn summarize_chat_title(
         tools: None,
         tool_choice: None,
         stream: false,
+        stream_options: None,
         temperature: Some(0.3), // 使用较低的温度以获得更稳定的标题
     };
     
@@ -4794,6 +7062,15 @@ async fn summarize_chat_title(
         .await
         .map_err(|e| format!("解析响应失败: {}", e))?;
     
+    // 记录 token 用量
+    if let Some(token_usage) = completion_response.usage.as_ref().and_then(usage::parse_usage) {
+        let scope = usage::UsageScope {
+            chat_id: Some(chat_id.clone()),
+            ..Default::default()
+        };
+        usage::record(db_path.clone(), &ai_config, usage::SOURCE_TITLE, scope, token_usage).await;
+    }
+    
     // 提取标题
     let title = completion_response
         .choices
@@ -4859,6 +7136,59 @@ async fn delete_chat(
     Ok(())
 }
 
+// 导出 chat 为 Markdown 或 JSON（提供 file_path 时同时写入文件）
+#[tauri::command]
+async fn export_chat(
+    chat_id: String,
+    format: String,
+    file_path: Option<String>,
+    app: tauri::AppHandle,
+) -> Result<chat_export::ChatExportResult, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        let result = chat_export::export_chat(&conn, &chat_id, &format)?;
+        
+        if let Some(file_path) = file_path {
+            std::fs::write(&file_path, &result.content)
+                .map_err(|e| format!("无法写入导出文件: {}", e))?;
+        }
+        
+        Ok(result)
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 从 JSON 导出文件（或 JSON 内容）导入 chat
+#[tauri::command]
+async fn import_chat(
+    file_path: Option<String>,
+    content: Option<String>,
+    app: tauri::AppHandle,
+) -> Result<chat_export::ChatImportResult, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let content = match (content, file_path) {
+            (Some(content), _) => content,
+            (None, Some(file_path)) => std::fs::read_to_string(&file_path)
+                .map_err(|e| format!("无法读取导入文件: {}", e))?,
+            (None, None) => return Err("请提供导入文件路径或内容".to_string()),
+        };
+        
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        chat_export::import_chat(&conn, &content)
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
 // 获取 chat 的所有 messages
 #[tauri::command]
 async fn get_messages_by_chat(
@@ -4880,6 +7210,203 @@ async fn get_messages_by_chat(
     messages
 }
 
+// 获取与指定消息同级的所有分支（包含自身），用于切换分支
+#[tauri::command]
+async fn get_message_branches(
+    message_id: String,
+    app: tauri::AppHandle,
+) -> Result<Vec<Message>, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        let message = db::get_message(&conn, &message_id)
+            .map_err(|e| format!("无法获取 message: {}", e))?
+            .ok_or_else(|| "Message 不存在".to_string())?;
+        db::get_sibling_messages(&conn, &message)
+            .map_err(|e| format!("无法获取分支: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 切换到指定消息所在的分支（激活该消息下最新的一条路径），返回新的激活路径
+#[tauri::command]
+async fn switch_message_branch(
+    message_id: String,
+    app: tauri::AppHandle,
+) -> Result<Vec<Message>, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        let message = db::get_message(&conn, &message_id)
+            .map_err(|e| format!("无法获取 message: {}", e))?
+            .ok_or_else(|| "Message 不存在".to_string())?;
+        let leaf_id = db::get_branch_leaf_id(&conn, &message.id)
+            .map_err(|e| format!("无法获取分支末尾: {}", e))?;
+        db::set_active_leaf_id(&conn, &message.chat_id, Some(&leaf_id))
+            .map_err(|e| format!("无法切换分支: {}", e))?;
+        db::get_messages_by_chat(&conn, &message.chat_id)
+            .map_err(|e| format!("无法获取 messages: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 从指定消息处分叉：把该消息设为激活分支末尾，之后保存的消息会成为新的分支
+#[tauri::command]
+async fn fork_from_message(
+    message_id: String,
+    app: tauri::AppHandle,
+) -> Result<Vec<Message>, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        let message = db::get_message(&conn, &message_id)
+            .map_err(|e| format!("无法获取 message: {}", e))?
+            .ok_or_else(|| "Message 不存在".to_string())?;
+        db::set_active_leaf_id(&conn, &message.chat_id, Some(&message.id))
+            .map_err(|e| format!("无法切换分支: {}", e))?;
+        db::get_messages_by_chat(&conn, &message.chat_id)
+            .map_err(|e| format!("无法获取 messages: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 编辑消息：以新内容创建同级分支（原消息及其后续对话保留），返回新的激活路径
+#[tauri::command]
+async fn edit_message(
+    message_id: String,
+    content: String,
+    app: tauri::AppHandle,
+) -> Result<Vec<Message>, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        let original = db::get_message(&conn, &message_id)
+            .map_err(|e| format!("无法获取 message: {}", e))?
+            .ok_or_else(|| "Message 不存在".to_string())?;
+        if original.role != "user" && original.role != "assistant" {
+            return Err("只能编辑用户消息或助手消息".to_string());
+        }
+        
+        let now = Utc::now().to_rfc3339();
+        let edited = Message {
+            id: Uuid::new_v4().to_string(),
+            chat_id: original.chat_id.clone(),
+            role: original.role.clone(),
+            content,
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+            reasoning: None,
+            created_at: now.clone(),
+            parent_message_id: original.parent_message_id.clone(),
+        };
+        
+        // 根消息没有父消息，不能依赖 append_message 自动挂载
+        db::create_message(&conn, &edited)
+            .map_err(|e| format!("无法保存 message: {}", e))?;
+        db::set_active_leaf_id(&conn, &edited.chat_id, Some(&edited.id))
+            .map_err(|e| format!("无法切换分支: {}", e))?;
+        
+        if let Ok(Some(mut chat)) = db::get_chat(&conn, &edited.chat_id) {
+            chat.updated_at = now;
+            let _ = db::update_chat(&conn, &chat);
+        }
+        
+        db::get_messages_by_chat(&conn, &edited.chat_id)
+            .map_err(|e| format!("无法获取 messages: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 重新生成助手回复：在原回复的父消息下运行 Agent，新回复作为同级分支保存
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn regenerate_message(
+    message_id: String,
+    config_id: String,
+    servers: Vec<MCPServerInfo>,
+    system_message: Option<String>,
+    event_id: Option<String>,
+    max_steps: Option<u32>,
+    current_resource_id: Option<String>,
+    current_task_id: Option<String>,
+    app: tauri::AppHandle,
+    streams: State<'_, RunningStreams>,
+) -> Result<String, String> {
+    let app_data_dir = get_app_data_dir(&app)?;
+    let db_path = db::get_db_path(&app_data_dir);
+    
+    let db_path_for_load = db_path.clone();
+    let (chat_id, history, restore) = tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path_for_load)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        let message = db::get_message(&conn, &message_id)
+            .map_err(|e| format!("无法获取 message: {}", e))?
+            .ok_or_else(|| "Message 不存在".to_string())?;
+        if message.role != "assistant" {
+            return Err("只能重新生成助手消息".to_string());
+        }
+        let parent_id = message
+            .parent_message_id
+            .clone()
+            .ok_or_else(|| "该消息没有上文，无法重新生成".to_string())?;
+        
+        // 回到父消息，新的回复会挂在父消息下；记录切换前的分支，出错时恢复
+        let previous_leaf = db::get_active_leaf_id(&conn, &message.chat_id)
+            .map_err(|e| format!("无法获取激活分支: {}", e))?;
+        db::set_active_leaf_id(&conn, &message.chat_id, Some(&parent_id))
+            .map_err(|e| format!("无法切换分支: {}", e))?;
+        let history = db::get_messages_by_chat(&conn, &message.chat_id)
+            .map_err(|e| format!("无法获取 messages: {}", e))?;
+        let restore = ActiveLeafRestore {
+            chat_id: message.chat_id.clone(),
+            parent_id,
+            previous_leaf,
+        };
+        Ok::<_, String>((message.chat_id, history, restore))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))??;
+    
+    // 启动失败时（如 AI 配置不存在）立即恢复；循环中出错由 start_agent_run 恢复
+    let restore_fallback = restore.clone();
+    let result = start_agent_run(
+        app,
+        streams.inner(),
+        chat_id,
+        config_id,
+        agent::history_to_chat_messages(&history),
+        servers,
+        system_message,
+        event_id,
+        max_steps,
+        current_resource_id,
+        current_task_id,
+        Some(restore),
+    )
+    .await;
+    if result.is_err() {
+        restore_active_leaf(db_path, restore_fallback).await;
+    }
+    result
+}
+
 // 保存 message
 #[tauri::command]
 async fn save_message(
@@ -4890,6 +7417,8 @@ async fn save_message(
     tool_call_id: Option<String>,
     name: Option<String>,
     reasoning: Option<String>,
+    parent_message_id: Option<String>,
+    event_id: Option<String>, // 生成该助手消息的流式对话事件 ID，用于关联 token 用量
     app: tauri::AppHandle,
 ) -> Result<Message, String> {
     let app_data_dir = get_app_data_dir(&app)?;
@@ -4902,7 +7431,7 @@ async fn save_message(
         let message_id = Uuid::new_v4().to_string();
         let now = Utc::now().to_rfc3339();
         
-        let message = Message {
+        let mut message = Message {
             id: message_id.clone(),
             chat_id: chat_id.clone(),
             role,
@@ -4912,11 +7441,19 @@ async fn save_message(
             name,
             reasoning,
             created_at: now.clone(),
+            parent_message_id,
         };
         
-        db::create_message(&conn, &message)
+        // 未指定父消息时追加到当前激活分支末尾
+        db::append_message(&conn, &mut message)
             .map_err(|e| format!("无法保存 message: {}", e))?;
         
+        if let Some(event_id) = event_id.as_deref().filter(|_| message.role == "assistant") {
+            if let Err(e) = usage::link_message(&conn, event_id, &message.id) {
+                eprintln!("[Usage] 无法关联消息用量: {}", e);
+            }
+        }
+        
         // 更新 chat 的 updated_at
         if let Ok(Some(mut chat)) = db::get_chat(&conn, &chat_id) {
             chat.updated_at = now;
@@ -5167,6 +7704,231 @@ async fn download_subtitle_from_url_internal(
     Err("无法下载字幕。可能该视频没有字幕，或 yt-dlp 无法访问该视频。".to_string())
 }
 
+// 未指定字幕语言偏好时使用的默认语言（与原先的下载顺序一致：中文优先，其次英文）
+const DEFAULT_SUBTITLE_LANGUAGES: &[&str] = &["zh", "zh-Hans", "zh-Hant", "zh-CN", "zh-TW", "en"];
+
+// 任务的字幕语言偏好，返回 (语言列表, 是否为用户指定)
+fn subtitle_language_preferences(params: &TranscriptionParams) -> (Vec<String>, bool) {
+    match params.subtitle_languages.as_ref().filter(|l| !l.is_empty()) {
+        Some(languages) => (languages.clone(), true),
+        None => (DEFAULT_SUBTITLE_LANGUAGES.iter().map(|l| l.to_string()).collect(), false),
+    }
+}
+
+// 字幕语言在偏好列表中的位置（越小越优先），不匹配时返回列表长度
+// "zh" 可以匹配 "zh-Hans"，自动字幕的 "ai-" 前缀会被忽略
+fn subtitle_language_rank(language: &str, preferences: &[String]) -> usize {
+    let language = language.trim_start_matches("ai-").to_lowercase();
+    preferences
+        .iter()
+        .position(|p| {
+            let p = p.to_lowercase();
+            language == p || language.starts_with(&format!("{}-", p))
+        })
+        .unwrap_or(preferences.len())
+}
+
+// 按语言偏好下载 YouTube 字幕，人工字幕和自动字幕分开下载以区分类型
+// 返回 (语言, 类型, SRT 文件路径)，按偏好排序（同一语言人工字幕优先）
+async fn download_subtitle_tracks_from_url(
+    url: &str,
+    preferences: &[String],
+    user_specified: bool,
+    allow_auto: bool,
+    app: &tauri::AppHandle,
+    task_id: &str,
+) -> Result<Vec<(String, &'static str, PathBuf)>, String> {
+    let ytdlp_path = get_ytdlp_path(app)?;
+    let app_data_dir = get_app_data_dir(app)?;
+    let subtitles_dir = app_data_dir.join("subtitles");
+    std::fs::create_dir_all(&subtitles_dir)
+        .map_err(|e| format!("无法创建字幕目录: {}", e))?;
+
+    let subtitle_id = Uuid::new_v4().to_string();
+    let stdout_event_name = format!("transcription-stdout-{}", task_id);
+    let stderr_event_name = format!("transcription-stderr-{}", task_id);
+
+    let mut attempts = vec![(SUBTITLE_KIND_MANUAL, "--write-subs", preferences.join(","))];
+    if allow_auto {
+        attempts.push((SUBTITLE_KIND_AUTO, "--write-auto-subs", preferences.join(",")));
+    }
+
+    let mut tracks: Vec<(String, &'static str, PathBuf)> = Vec::new();
+    let mut errors = Vec::new();
+    let mut index = 0;
+    while index < attempts.len() {
+        let (kind, flag, sub_langs) = attempts[index].clone();
+        index += 1;
+
+        let kind_name = if kind == SUBTITLE_KIND_MANUAL { "人工字幕" } else { "自动字幕" };
+        let _ = app.emit(&stdout_event_name, &format!("尝试下载{}（{}）...\n", kind_name, sub_langs));
+
+        let prefix = format!("{}.{}", subtitle_id, kind);
+        let output = tokio::process::Command::new(&ytdlp_path)
+            .arg("--skip-download")
+            .arg(flag)
+            .arg("--sub-langs")
+            .arg(&sub_langs)
+            .arg("--convert-subs")
+            .arg("srt")
+            .arg("--no-playlist")
+            .arg("-o")
+            .arg(subtitles_dir.join(format!("{}.%(ext)s", prefix)))
+            .arg(url)
+            .output()
+            .await
+            .map_err(|e| format!("无法执行 yt-dlp: {}。请确保 yt-dlp 已正确安装。", e))?;
+
+        // 等待文件写入完成
+        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+        // 字幕文件名格式：{prefix}.{语言}.srt（转换失败时可能保留 vtt）
+        let mut found = 0;
+        if let Ok(entries) = std::fs::read_dir(&subtitles_dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let file_name = path.file_name().and_then(|s| s.to_str()).unwrap_or("").to_string();
+                let Some(rest) = file_name.strip_prefix(&format!("{}.", prefix)) else {
+                    continue;
+                };
+                let (language, srt_path) = if let Some(language) = rest.strip_suffix(".srt") {
+                    (language.to_string(), path.clone())
+                } else if let Some(language) = rest.strip_suffix(".vtt") {
+                    match convert_vtt_to_srt(&path) {
+                        Ok(srt_path) => (language.to_string(), PathBuf::from(srt_path)),
+                        Err(_) => continue,
+                    }
+                } else {
+                    continue;
+                };
+                if !tracks.iter().any(|(l, k, _)| *l == language && *k == kind) {
+                    let _ = app.emit(&stdout_event_name, &format!("{}下载成功: {}\n", kind_name, language));
+                    tracks.push((language, kind, srt_path));
+                    found += 1;
+                }
+            }
+        }
+
+        // 部分语言下载失败（如 429）时只要有文件就继续
+        if found == 0 && !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            let _ = app.emit(&stderr_event_name, &format!("{}下载失败: {}\n", kind_name, stderr));
+            errors.push(stderr);
+        }
+
+        // 未指定语言偏好且默认语言都没有字幕时，下载所有人工字幕
+        if index == attempts.len() && tracks.is_empty() && !user_specified && sub_langs != "all" {
+            attempts.push((SUBTITLE_KIND_MANUAL, "--write-subs", "all".to_string()));
+        }
+    }
+
+    if tracks.is_empty() {
+        return Err(if errors.is_empty() {
+            "没有找到符合语言偏好的字幕".to_string()
+        } else {
+            format!("yt-dlp 执行失败且未找到字幕文件: {}", errors.join("\n"))
+        });
+    }
+
+    tracks.sort_by_key(|(language, kind, _)| (subtitle_language_rank(language, preferences), *kind != SUBTITLE_KIND_MANUAL));
+    Ok(tracks)
+}
+
+// 从URL下载音频（使用yt-dlp，仅音频），转换为 whisper 使用的 16kHz 单声道 wav
+// 进程句柄存储在 RunningTasks 中，stop_transcription_task 可以停止下载
+async fn download_audio_from_url_internal(
+    url: &str,
+    resource_id: &str,
+    task_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<PathBuf, String> {
+    let ytdlp_path = get_ytdlp_path(app)?;
+    let ffmpeg_path = get_ffmpeg_path(app)?;
+
+    // 与视频提取的音频放在同一目录
+    let app_data_dir = get_app_data_dir(app)?;
+    let audio_dir = app_data_dir.join("extracted_audio");
+    std::fs::create_dir_all(&audio_dir)
+        .map_err(|e| format!("无法创建音频目录: {}", e))?;
+    let audio_path = audio_dir.join(format!("{}.wav", resource_id));
+
+    let stdout_event_name = format!("transcription-stdout-{}", task_id);
+    let stderr_event_name = format!("transcription-stderr-{}", task_id);
+    let _ = app.emit(&stdout_event_name, "正在使用 yt-dlp 下载音频...\n");
+
+    let mut cmd = tokio::process::Command::new(&ytdlp_path);
+    cmd.arg("-f")
+        .arg("bestaudio/best")
+        .arg("-x")
+        .arg("--audio-format")
+        .arg("wav")
+        .arg("--postprocessor-args")
+        .arg("ExtractAudio:-ar 16000 -ac 1")
+        .arg("--ffmpeg-location")
+        .arg(&ffmpeg_path)
+        .arg("--no-playlist")
+        .arg("--newline") // 每行输出一次进度，便于实时显示
+        .arg("--force-overwrites")
+        .arg("-o")
+        .arg(audio_dir.join(format!("{}.%(ext)s", resource_id)))
+        .arg(url);
+
+    cmd.stdout(std::process::Stdio::piped());
+    cmd.stderr(std::process::Stdio::piped());
+
+    let mut child = cmd.spawn()
+        .map_err(|e| format!("无法执行 yt-dlp: {}。请确保 yt-dlp 已正确安装。", e))?;
+
+    let stdout = child.stdout.take()
+        .ok_or("无法获取 stdout 句柄")?;
+    let stderr = child.stderr.take()
+        .ok_or("无法获取 stderr 句柄")?;
+
+    // 将进程句柄存储到 RunningTasks 中，以便可以停止
+    let running_tasks: State<'_, RunningTasks> = app.state();
+    running_tasks.insert(task_id.to_string(), child).await;
+
+    let stdout_handle = spawn_stream_reader(stdout, app.clone(), stdout_event_name, "stdout", false);
+    let stderr_handle = spawn_stream_reader(stderr, app.clone(), stderr_event_name, "stderr", false);
+
+    // 定期检查进程状态，child 不在 RunningTasks 中说明任务已被停止
+    let status = loop {
+        if let Some(child_arc) = running_tasks.get(task_id).await {
+            let mut child_guard = child_arc.lock().await;
+            if let Ok(Some(exit_status)) = child_guard.try_wait() {
+                let _ = running_tasks.remove(task_id).await;
+                break Some(exit_status);
+            }
+            drop(child_guard);
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        } else {
+            break None;
+        }
+    };
+
+    let _ = stdout_handle.await;
+    let stderr_output = stderr_handle.await.unwrap_or_default();
+
+    let status = match status {
+        Some(status) => status,
+        None => {
+            let _ = std::fs::remove_file(&audio_path);
+            return Err("任务已被用户停止".to_string());
+        }
+    };
+
+    if !status.success() || !audio_path.exists() {
+        let detail = stderr_output.trim();
+        return Err(if detail.is_empty() {
+            format!("yt-dlp 下载音频失败，退出码: {:?}", status.code())
+        } else {
+            format!("yt-dlp 下载音频失败: {}", detail)
+        });
+    }
+
+    Ok(audio_path)
+}
+
 // 从URL下载字幕（使用yt-dlp）- 公开的 Tauri 命令
 #[tauri::command]
 async fn download_subtitle_from_url(
@@ -5552,9 +8314,29 @@ pub fn run() {
         .manage(RunningTasks::new())
         .manage(RunningExtractions::new())
         .manage(RunningStreams::new())
+        .manage(RunningAIRequests::new())
+        .manage(command_sandbox::RunningCommands::new())
+        .manage(PendingApprovals::new())
+        .manage(mcp_monitor::MCPMonitor::new())
+        .manage(watch_folders::WatchFolderMonitor::new())
+        .setup(|app| {
+            // 启动 MCP 服务器健康监控
+            mcp_monitor::start(app.handle().clone());
+            // 启动监视文件夹扫描
+            watch_folders::start(app.handle().clone());
+            // 上次退出时未完成的音频提取已中断，标记为失败
+            if let Ok(app_data_dir) = app.path().app_data_dir() {
+                if let Ok(conn) = db::init_database(&db::get_db_path(&app_data_dir)) {
+                    let _ = db::fail_interrupted_extractions(&conn);
+                }
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             compress_transcription_content_manual,
             extract_topics_manual,
+            check_duplicate_resource,
+            probe_media_file,
             create_transcription_resource,
             create_transcription_resource_from_url,
             create_transcription_task,
@@ -5566,6 +8348,8 @@ pub fn run() {
             delete_transcription_resource,
             update_resource_name,
             delete_transcription_task,
+            get_task_subtitle_tracks,
+            set_primary_subtitle_track,
             read_transcription_result,
             check_fast_whisper_status,
             install_faster_whisper,
@@ -5574,11 +8358,22 @@ pub fn run() {
             download_model,
             delete_model,
             execute_command,
+            pick_command_path,
+            cancel_command,
             check_file_exists,
             extract_audio_from_video,
             create_temp_subtitle_file,
             download_subtitle_from_url,
             get_video_title_from_url_command,
+            get_bilibili_video_info,
+            create_bilibili_part_resources,
+            import_playlist,
+            get_watch_folders,
+            add_watch_folder,
+            update_watch_folder,
+            remove_watch_folder,
+            get_watch_folder_files,
+            refresh_resource_metadata,
             convert_srt_to_transcription_json_command,
             get_ai_configs,
             create_ai_config,
@@ -5586,6 +8381,9 @@ pub fn run() {
             delete_ai_config,
             set_compression_config,
             get_compression_config,
+            get_prompt_templates,
+            update_prompt_template,
+            reset_prompt_template,
             get_mcp_configs,
             get_mcp_config_full,
             save_mcp_config,
@@ -5593,17 +8391,41 @@ pub fn run() {
             delete_mcp_config,
             update_mcp_enabled,
             test_mcp_connection,
+            refresh_mcp_server,
+            import_mcp_config,
+            export_mcp_config,
+            list_mcp_secrets,
+            set_mcp_secret,
+            delete_mcp_secret,
             chat_completion,
             stop_chat_completion,
+            get_usage_report,
+            get_usage_records,
+            agent_run,
             execute_mcp_tool_call,
+            get_mcp_permissions,
+            save_mcp_permission,
+            respond_mcp_tool_approval,
+            get_mcp_tool_call_logs,
+            clear_mcp_tool_call_logs,
             create_chat,
             get_all_chats,
+            get_chat_contexts,
+            add_chat_context,
+            remove_chat_context,
             get_chat,
             update_chat_title,
             summarize_chat_title,
             delete_chat,
             get_messages_by_chat,
             save_message,
+            export_chat,
+            import_chat,
+            get_message_branches,
+            switch_message_branch,
+            fork_from_message,
+            edit_message,
+            regenerate_message,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,4 +1,4 @@
-use crate::{MCPConfig, MCPServerConfig, MCPTool, MCPHTTPTransport};
+use crate::{MCPConfig, MCPServerConfig, MCPServerPermission, MCPTool, MCPHTTPTransport};
 use serde_json;
 use std::collections::HashMap;
 use indexmap::IndexMap;
@@ -8,6 +8,59 @@ use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command;
 use reqwest;
 
+// 默认连接超时时间（秒），可通过服务器配置的 timeout 字段覆盖
+pub const DEFAULT_MCP_TIMEOUT_SECS: u64 = 5;
+
+// 获取服务器的连接超时时间
+pub fn get_timeout(config: &MCPServerConfig) -> tokio::time::Duration {
+    tokio::time::Duration::from_secs(config.timeout.filter(|t| *t > 0).unwrap_or(DEFAULT_MCP_TIMEOUT_SECS))
+}
+
+// 从 tools/list 响应中提取工具列表
+pub fn parse_tools_list(response: &serde_json::Value) -> Vec<MCPTool> {
+    response
+        .get("result")
+        .and_then(|result| result.get("tools"))
+        .and_then(|tools| tools.as_array())
+        .map(|tools| {
+            tools
+                .iter()
+                .filter_map(|tool| serde_json::from_value::<MCPTool>(tool.clone()).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// 通过 HTTP 发送 ping 请求（服务器返回 JSON-RPC 错误也视为在线）
+pub async fn ping_http(url: &str, timeout: tokio::time::Duration) -> Result<(), String> {
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| format!("无法创建 HTTP 客户端: {}", e))?;
+    
+    let ping_request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "ping"
+    });
+    
+    let response = client
+        .post(url)
+        .json(&ping_request)
+        .send()
+        .await
+        .map_err(|e| format!("HTTP 请求失败: {}", e))?;
+    
+    if response.status().is_server_error() {
+        return Err(format!("服务器返回错误: {}", response.status()));
+    }
+    
+    response.json::<serde_json::Value>().await
+        .map_err(|e| format!("解析 ping 响应失败: {}", e))?;
+    
+    Ok(())
+}
+
 // 获取 MCP 配置文件路径
 pub fn get_mcp_config_path(app_data_dir: &PathBuf) -> PathBuf {
     app_data_dir.join("mcp_configs.json")
@@ -67,6 +120,7 @@ pub fn load_mcp_config(config_path: &PathBuf) -> Result<MCPConfig, String> {
     if !config_path.exists() {
         return Ok(MCPConfig {
             mcp_servers: IndexMap::new(),
+            permissions: IndexMap::new(),
         });
     }
 
@@ -92,8 +146,8 @@ pub fn load_mcp_config(config_path: &PathBuf) -> Result<MCPConfig, String> {
         // 新格式：{ "server-name": { name: "...", transport: { ... } } }
         // 检查是否有 mcpServers 字段，如果没有，则认为是新格式
         for (key, value) in root_obj {
-            // 跳过 mcpServers 字段（如果存在）
-            if key == "mcpServers" {
+            // 跳过 mcpServers 和 permissions 字段（如果存在）
+            if key == "mcpServers" || key == "permissions" {
                 continue;
             }
             
@@ -108,10 +162,21 @@ pub fn load_mcp_config(config_path: &PathBuf) -> Result<MCPConfig, String> {
         }
     }
 
-    Ok(MCPConfig { mcp_servers })
+    // 读取工具调用权限规则（两种格式都放在根级 permissions 字段中）
+    let mut permissions = IndexMap::new();
+    if let Some(permissions_obj) = json_value.get("permissions").and_then(|v| v.as_object()) {
+        for (name, permission_value) in permissions_obj {
+            if let Ok(permission) = serde_json::from_value::<MCPServerPermission>(permission_value.clone()) {
+                permissions.insert(name.clone(), permission);
+            }
+        }
+    }
+
+    Ok(MCPConfig { mcp_servers, permissions })
 }
 
 // 保存 MCP 配置
+// 注意：只能保存原始配置，${VAR} / ${secret:name} 占位符仅在启动服务器时由 mcp_secrets 展开
 pub fn save_mcp_config(config_path: &PathBuf, config: &MCPConfig) -> Result<(), String> {
     let content = serde_json::to_string_pretty(config)
         .map_err(|e| format!("无法序列化 MCP 配置: {}", e))?;
@@ -125,8 +190,12 @@ pub fn save_mcp_config(config_path: &PathBuf, config: &MCPConfig) -> Result<(),
 // 通过 HTTP 测试 MCP 连接并获取工具列表
 async fn test_mcp_connection_http(
     transport: &MCPHTTPTransport,
+    timeout: tokio::time::Duration,
 ) -> Result<Vec<MCPTool>, String> {
-    let client = reqwest::Client::new();
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| format!("无法创建 HTTP 客户端: {}", e))?;
     
     // 发送 initialize 请求
     let init_request = serde_json::json!({
@@ -208,6 +277,8 @@ pub async fn test_mcp_connection(
     _server_name: &str,
     config: &MCPServerConfig,
 ) -> Result<Vec<MCPTool>, String> {
+    let timeout = get_timeout(config);
+    
     // 检查新格式的 transport 字段
     if let Some(transport_value) = &config.transport {
         if let Some(transport_obj) = transport_value.as_object() {
@@ -220,7 +291,7 @@ pub async fn test_mcp_connection(
                                 transport_type: "http".to_string(),
                                 url: url.to_string(),
                             };
-                            return test_mcp_connection_http(&transport).await;
+                            return test_mcp_connection_http(&transport, timeout).await;
                         }
                     }
                     "stdio" => {
@@ -245,6 +316,7 @@ pub async fn test_mcp_connection(
                                 transport_obj.get("workingDir").or_else(|| transport_obj.get("working_dir"))
                                     .and_then(|v| v.as_str())
                                     .map(|s| s.to_string()),
+                                timeout,
                             ).await;
                         }
                     }
@@ -260,7 +332,7 @@ pub async fn test_mcp_connection(
             transport_type: "http".to_string(),
             url: url.clone(),
         };
-        return test_mcp_connection_http(&transport).await;
+        return test_mcp_connection_http(&transport, timeout).await;
     }
     
     // 默认使用 stdio 传输（旧格式）
@@ -272,6 +344,7 @@ pub async fn test_mcp_connection(
         config.args.clone(),
         config.env.clone(),
         None, // 旧格式不支持 workingDir
+        timeout,
     ).await;
 }
 
@@ -281,6 +354,7 @@ async fn test_mcp_connection_stdio(
     args: Option<Vec<String>>,
     env: Option<HashMap<String, String>>,
     working_dir: Option<String>,
+    timeout: tokio::time::Duration,
 ) -> Result<Vec<MCPTool>, String> {
     // 启动 MCP 服务器进程
     let mut cmd = Command::new(command);
@@ -344,8 +418,6 @@ async fn test_mcp_connection_stdio(
     let mut reader = BufReader::new(stdout);
     let mut line = String::new();
     
-    // 设置超时（5秒）
-    let timeout = tokio::time::Duration::from_secs(5);
     let read_result = tokio::time::timeout(timeout, reader.read_line(&mut line)).await;
     
     match read_result {
@@ -0,0 +1,438 @@
+use crate::{MCPConfig, MCPServerConfig};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+// 支持导入/导出的外部配置格式
+pub const FORMAT_CLAUDE_DESKTOP: &str = "claude_desktop"; // { "mcpServers": { name: { command, args, env } } }
+pub const FORMAT_CURSOR: &str = "cursor"; // { "mcpServers": { name: { command, args, env } | { url } } }
+pub const FORMAT_VSCODE: &str = "vscode"; // { "servers": { name: { type, command, args, env } | { type, url } } }
+
+// 单个服务器的诊断信息
+#[derive(Debug, Serialize, Clone)]
+pub struct MCPImportDiagnostic {
+    pub server_name: String,
+    pub level: String, // "error" | "warning" | "info"
+    pub message: String,
+}
+
+// 导入结果报告
+#[derive(Debug, Serialize, Clone)]
+pub struct MCPImportReport {
+    pub format: String,
+    pub imported: Vec<String>, // 已导入（或 dry_run 时将导入）的服务器键名
+    pub skipped: Vec<String>, // 因错误或同名冲突而跳过的服务器
+    pub diagnostics: Vec<MCPImportDiagnostic>,
+}
+
+// 导出结果
+#[derive(Debug, Serialize, Clone)]
+pub struct MCPExportResult {
+    pub format: String,
+    pub content: String, // 导出的 JSON 字符串
+    pub skipped: Vec<String>, // 目标格式不支持而未导出的服务器
+    pub diagnostics: Vec<MCPImportDiagnostic>, // 未导出或导出时丢失设置的原因
+}
+
+// 去除 JSONC 中的注释和尾随逗号（VS Code 的 mcp.json 允许这些写法）
+fn strip_jsonc(content: &str) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let mut output = String::with_capacity(content.len());
+    let mut i = 0;
+    let mut in_string = false;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if in_string {
+            output.push(c);
+            if c == '\\' && i + 1 < chars.len() {
+                output.push(chars[i + 1]);
+                i += 2;
+                continue;
+            }
+            if c == '"' {
+                in_string = false;
+            }
+            i += 1;
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                output.push(c);
+                i += 1;
+            }
+            '/' if i + 1 < chars.len() && chars[i + 1] == '/' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if i + 1 < chars.len() && chars[i + 1] == '*' => {
+                i += 2;
+                while i + 1 < chars.len() && !(chars[i] == '*' && chars[i + 1] == '/') {
+                    i += 1;
+                }
+                i += 2;
+            }
+            ',' => {
+                // 跳过 } 或 ] 之前的尾随逗号
+                let next = chars[i + 1..].iter().find(|ch| !ch.is_whitespace());
+                if !matches!(next, Some('}') | Some(']')) {
+                    output.push(c);
+                }
+                i += 1;
+            }
+            _ => {
+                output.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    output
+}
+
+// 根据文件内容识别配置格式，返回 (格式, 服务器列表)
+fn detect_format(root: &Value) -> Result<(String, Map<String, Value>), String> {
+    // VS Code mcp.json：{ "servers": { ... } }
+    if let Some(servers) = root.get("servers").and_then(|v| v.as_object()) {
+        return Ok((FORMAT_VSCODE.to_string(), servers.clone()));
+    }
+    // VS Code settings.json：{ "mcp": { "servers": { ... } } }
+    if let Some(servers) = root.get("mcp").and_then(|v| v.get("servers")).and_then(|v| v.as_object()) {
+        return Ok((FORMAT_VSCODE.to_string(), servers.clone()));
+    }
+    // Claude Desktop / Cursor：{ "mcpServers": { ... } }，包含 url 的服务器只有 Cursor 支持
+    if let Some(servers) = root.get("mcpServers").and_then(|v| v.as_object()) {
+        let has_url = servers.values().any(|s| s.get("url").is_some());
+        let format = if has_url { FORMAT_CURSOR } else { FORMAT_CLAUDE_DESKTOP };
+        return Ok((format.to_string(), servers.clone()));
+    }
+    Err("无法识别的 MCP 配置格式，需要包含 mcpServers 或 servers 字段".to_string())
+}
+
+// 在 PATH 中查找命令
+fn find_in_path(command: &str) -> bool {
+    let path = Path::new(command);
+    if path.is_absolute() || command.contains('/') {
+        return path.exists();
+    }
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(command).exists()))
+        .unwrap_or(false)
+}
+
+fn is_valid_env_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// 校验并转换单个外部服务器配置，诊断信息追加到 diagnostics，出现错误时返回 None
+fn convert_server(
+    name: &str,
+    value: &Value,
+    diagnostics: &mut Vec<MCPImportDiagnostic>,
+) -> Option<MCPServerConfig> {
+    let mut push = |level: &str, message: String| {
+        diagnostics.push(MCPImportDiagnostic {
+            server_name: name.to_string(),
+            level: level.to_string(),
+            message,
+        });
+    };
+
+    let obj = match value.as_object() {
+        Some(obj) => obj,
+        None => {
+            push("error", "服务器配置不是 JSON 对象".to_string());
+            return None;
+        }
+    };
+
+    let declared_type = obj.get("type").and_then(|v| v.as_str()).map(|s| s.to_lowercase());
+    let command = obj.get("command").and_then(|v| v.as_str()).map(|s| s.trim().to_string());
+    let url = obj.get("url").and_then(|v| v.as_str()).map(|s| s.trim().to_string());
+
+    let mut config = MCPServerConfig {
+        name: Some(name.to_string()),
+        description: obj.get("description").and_then(|v| v.as_str()).map(|s| s.to_string()),
+        server_type: None,
+        enabled: Some(!obj.get("disabled").and_then(|v| v.as_bool()).unwrap_or(false)),
+        transport: None,
+        command: None,
+        args: None,
+        env: None,
+        url: None,
+        timeout: None,
+    };
+
+    let is_http = match declared_type.as_deref() {
+        Some("http") | Some("streamable-http") | Some("streamablehttp") => true,
+        Some("sse") => {
+            push("warning", "SSE 传输将按 HTTP JSON-RPC 方式连接，服务器可能不兼容".to_string());
+            true
+        }
+        Some("stdio") => false,
+        Some(other) => {
+            push("error", format!("不支持的传输类型: {}", other));
+            return None;
+        }
+        None => command.is_none() && url.is_some(),
+    };
+
+    if is_http {
+        let url = match url.filter(|u| !u.is_empty()) {
+            Some(url) => url,
+            None => {
+                push("error", "HTTP 传输缺少 url 字段".to_string());
+                return None;
+            }
+        };
+        match reqwest::Url::parse(&url) {
+            Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => {}
+            Ok(parsed) => {
+                push("error", format!("URL 协议 {} 不受支持，只支持 http/https", parsed.scheme()));
+                return None;
+            }
+            Err(e) => {
+                push("error", format!("URL 无效: {}", e));
+                return None;
+            }
+        }
+        if obj.get("headers").map(|h| h.as_object().map(|o| !o.is_empty()).unwrap_or(false)).unwrap_or(false) {
+            push("warning", "暂不支持自定义 headers，已忽略".to_string());
+        }
+        config.server_type = Some("http".to_string());
+        config.url = Some(url);
+        return Some(config);
+    }
+
+    // stdio 传输
+    let command = match command.filter(|c| !c.is_empty()) {
+        Some(command) => command,
+        None => {
+            push("error", "stdio 传输缺少 command 字段".to_string());
+            return None;
+        }
+    };
+    if command.contains("${") {
+        push("warning", format!("command 中包含占位符，需要手动确认: {}", command));
+    } else if !find_in_path(&command) {
+        push("warning", format!("在 PATH 中找不到命令 {}，应用启动时的 PATH 可能与终端不同", command));
+    }
+
+    if let Some(args_value) = obj.get("args") {
+        match args_value.as_array() {
+            Some(args) => {
+                let mut converted = Vec::new();
+                for arg in args {
+                    match arg {
+                        Value::String(s) => converted.push(s.clone()),
+                        Value::Number(_) | Value::Bool(_) => {
+                            push("info", format!("参数 {} 已转换为字符串", arg));
+                            converted.push(arg.to_string());
+                        }
+                        _ => {
+                            push("error", format!("参数 {} 不是字符串", arg));
+                            return None;
+                        }
+                    }
+                }
+                if !converted.is_empty() {
+                    config.args = Some(converted);
+                }
+            }
+            None => {
+                push("error", "args 必须是数组".to_string());
+                return None;
+            }
+        }
+    }
+
+    if let Some(env_value) = obj.get("env") {
+        match env_value.as_object() {
+            Some(env) => {
+                let mut converted = HashMap::new();
+                for (key, value) in env {
+                    if !is_valid_env_key(key) {
+                        push("error", format!("环境变量名无效: {}", key));
+                        return None;
+                    }
+                    let value = match value {
+                        Value::String(s) => s.clone(),
+                        Value::Number(_) | Value::Bool(_) => value.to_string(),
+                        _ => {
+                            push("error", format!("环境变量 {} 的值不是字符串", key));
+                            return None;
+                        }
+                    };
+                    if value.is_empty() {
+                        push("warning", format!("环境变量 {} 的值为空", key));
+                    }
+                    if value.contains("${input:") {
+                        push("warning", format!("环境变量 {} 使用了 VS Code 的 input 占位符，需要手动填写", key));
+                    }
+                    converted.insert(key.clone(), value);
+                }
+                if !converted.is_empty() {
+                    config.env = Some(converted);
+                }
+            }
+            None => {
+                push("error", "env 必须是对象".to_string());
+                return None;
+            }
+        }
+    }
+
+    if obj.contains_key("envFile") {
+        push("warning", "暂不支持 envFile，已忽略".to_string());
+    }
+    if obj.contains_key("cwd") {
+        push("warning", "暂不支持 cwd，已忽略".to_string());
+    }
+
+    config.server_type = Some("stdio".to_string());
+    Some(config)
+}
+
+// 解析外部配置文件内容并合并到现有配置中（同名服务器保留现有配置，不会覆盖）
+// format 为空时自动识别
+pub fn import_config(
+    content: &str,
+    format: Option<&str>,
+    config: &mut MCPConfig,
+) -> Result<MCPImportReport, String> {
+    let root: Value = serde_json::from_str(&strip_jsonc(content))
+        .map_err(|e| format!("无法解析 MCP 配置文件 JSON: {}", e))?;
+
+    let (detected_format, servers) = detect_format(&root)?;
+    let format = match format {
+        Some(f) if f == FORMAT_CLAUDE_DESKTOP || f == FORMAT_CURSOR || f == FORMAT_VSCODE => f.to_string(),
+        Some(f) => return Err(format!("不支持的配置格式: {}", f)),
+        None => detected_format,
+    };
+
+    let mut report = MCPImportReport {
+        format,
+        imported: Vec::new(),
+        skipped: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+
+    for (name, value) in &servers {
+        if name == crate::default_mcp::DEFAULT_MCP_SERVER_NAME {
+            report.skipped.push(name.clone());
+            report.diagnostics.push(MCPImportDiagnostic {
+                server_name: name.clone(),
+                level: "error".to_string(),
+                message: "服务器名称与系统保留名称冲突".to_string(),
+            });
+            continue;
+        }
+
+        let server_config = match convert_server(name, value, &mut report.diagnostics) {
+            Some(server_config) => server_config,
+            None => {
+                report.skipped.push(name.clone());
+                continue;
+            }
+        };
+
+        if config.mcp_servers.contains_key(name) {
+            report.skipped.push(name.clone());
+            report.diagnostics.push(MCPImportDiagnostic {
+                server_name: name.clone(),
+                level: "warning".to_string(),
+                message: "已存在同名服务器，保留现有配置".to_string(),
+            });
+            continue;
+        }
+
+        config.mcp_servers.insert(name.clone(), server_config);
+        report.imported.push(name.clone());
+    }
+
+    Ok(report)
+}
+
+// 将配置导出为外部格式
+pub fn export_config(config: &MCPConfig, format: &str) -> Result<MCPExportResult, String> {
+    let mut servers = Map::new();
+    let mut skipped = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut push = |name: &str, level: &str, message: &str| {
+        diagnostics.push(MCPImportDiagnostic {
+            server_name: name.to_string(),
+            level: level.to_string(),
+            message: message.to_string(),
+        });
+    };
+
+    for (name, server_config) in &config.mcp_servers {
+        if name == crate::default_mcp::DEFAULT_MCP_SERVER_NAME {
+            continue;
+        }
+
+        let mut entry = Map::new();
+        if let Some(url) = &server_config.url {
+            // Claude Desktop 的配置文件只支持 stdio
+            if format == FORMAT_CLAUDE_DESKTOP {
+                skipped.push(name.clone());
+                push(name, "warning", "Claude Desktop 配置只支持 stdio 服务器，未导出");
+                continue;
+            }
+            if format == FORMAT_VSCODE {
+                entry.insert("type".to_string(), json!("http"));
+            }
+            entry.insert("url".to_string(), json!(url));
+        } else if let Some(command) = &server_config.command {
+            if format == FORMAT_VSCODE {
+                entry.insert("type".to_string(), json!("stdio"));
+            }
+            entry.insert("command".to_string(), json!(command));
+            entry.insert("args".to_string(), json!(server_config.args.clone().unwrap_or_default()));
+            if let Some(env) = server_config.env.as_ref().filter(|e| !e.is_empty()) {
+                // 按键名排序，保证导出结果稳定
+                let env: std::collections::BTreeMap<_, _> = env.iter().collect();
+                entry.insert("env".to_string(), json!(env));
+            }
+        } else {
+            skipped.push(name.clone());
+            push(name, "warning", "服务器只在 transport 中配置了连接方式，无法导出");
+            continue;
+        }
+
+        if server_config.enabled == Some(false) {
+            if format == FORMAT_CURSOR {
+                entry.insert("disabled".to_string(), json!(true));
+            } else {
+                push(name, "info", "目标格式不支持禁用状态，服务器将以启用状态导出");
+            }
+        }
+
+        servers.insert(name.clone(), Value::Object(entry));
+    }
+
+    let root = match format {
+        FORMAT_CLAUDE_DESKTOP | FORMAT_CURSOR => json!({ "mcpServers": servers }),
+        FORMAT_VSCODE => json!({ "servers": servers }),
+        other => return Err(format!("不支持的配置格式: {}", other)),
+    };
+
+    let content = serde_json::to_string_pretty(&root)
+        .map_err(|e| format!("无法序列化 MCP 配置: {}", e))?;
+
+    Ok(MCPExportResult {
+        format: format.to_string(),
+        content,
+        skipped,
+        diagnostics,
+    })
+}
@@ -0,0 +1,546 @@
+use crate::{mcp, mcp_secrets, MCPServerConfig, MCPServerInfo};
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::{oneshot, Mutex, Notify};
+use tokio::time::{Duration, Instant};
+
+// 健康检查间隔（秒）
+const HEALTH_CHECK_INTERVAL_SECS: u64 = 30;
+// HTTP 服务器无法推送 list_changed 通知，工具列表缓存按时间过期（秒）
+const HTTP_TOOLS_CACHE_TTL_SECS: u64 = 300;
+
+type PendingRequests = Arc<Mutex<HashMap<u64, oneshot::Sender<Value>>>>;
+
+// stdio 服务器的启动参数（兼容 transport 对象和旧格式的顶层字段）
+struct StdioLaunch {
+    command: String,
+    args: Vec<String>,
+    env: HashMap<String, String>,
+    working_dir: Option<String>,
+}
+
+fn stdio_launch(config: &MCPServerConfig) -> Result<StdioLaunch, String> {
+    if let Some(transport) = config.transport.as_ref().and_then(|t| t.as_object()) {
+        if transport.get("type").and_then(|t| t.as_str()) == Some("stdio") {
+            let command = transport
+                .get("command")
+                .and_then(|c| c.as_str())
+                .ok_or("stdio 传输需要 command 字段")?;
+            return Ok(StdioLaunch {
+                command: command.to_string(),
+                args: transport
+                    .get("args")
+                    .and_then(|a| a.as_array())
+                    .map(|a| a.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
+                    .unwrap_or_default(),
+                env: transport
+                    .get("env")
+                    .and_then(|e| e.as_object())
+                    .map(|e| {
+                        e.iter()
+                            .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                working_dir: transport
+                    .get("workingDir")
+                    .or_else(|| transport.get("working_dir"))
+                    .and_then(|w| w.as_str())
+                    .map(|w| w.to_string()),
+            });
+        }
+    }
+
+    let command = config.command.as_ref().ok_or("stdio 传输需要 command 字段")?;
+    Ok(StdioLaunch {
+        command: command.clone(),
+        args: config.args.clone().unwrap_or_default(),
+        env: config.env.clone().unwrap_or_default(),
+        working_dir: None,
+    })
+}
+
+// 与 stdio MCP 服务器保持的长连接，用于 ping、接收 notifications/tools/list_changed 和调用工具
+// 工具调用复用这个连接，每个 stdio 服务器只运行一个进程
+struct StdioSession {
+    _child: Child, // kill_on_drop，会话释放时结束进程
+    stdin: Mutex<ChildStdin>,
+    pending: PendingRequests,
+    next_id: AtomicU64,
+    alive: Arc<AtomicBool>,
+    tools_changed: Arc<AtomicBool>,
+}
+
+impl StdioSession {
+    async fn start(config: &MCPServerConfig, wake: Arc<Notify>) -> Result<Self, String> {
+        let launch = stdio_launch(config)?;
+
+        let mut cmd = Command::new(&launch.command);
+        cmd.args(&launch.args);
+        cmd.envs(&launch.env);
+        if let Some(working_dir) = &launch.working_dir {
+            cmd.current_dir(working_dir);
+        }
+        cmd.stdin(Stdio::piped());
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::null());
+        cmd.kill_on_drop(true);
+
+        let mut child = cmd.spawn().map_err(|e| format!("无法启动 MCP 服务器: {}", e))?;
+        let stdin = child.stdin.take().ok_or("无法获取 stdin")?;
+        let stdout = child.stdout.take().ok_or("无法获取 stdout")?;
+
+        let pending: PendingRequests = Arc::new(Mutex::new(HashMap::new()));
+        let alive = Arc::new(AtomicBool::new(true));
+        let tools_changed = Arc::new(AtomicBool::new(false));
+
+        // 读取服务器输出：响应按 id 分发，list_changed 通知标记缓存失效并唤醒监控循环
+        {
+            let pending = pending.clone();
+            let alive = alive.clone();
+            let tools_changed = tools_changed.clone();
+            tokio::spawn(async move {
+                let mut reader = BufReader::new(stdout);
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    match reader.read_line(&mut line).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(_) => {}
+                    }
+                    let message: Value = match serde_json::from_str(line.trim()) {
+                        Ok(message) => message,
+                        Err(_) => continue,
+                    };
+                    if message.get("method").and_then(|m| m.as_str()) == Some("notifications/tools/list_changed") {
+                        tools_changed.store(true, Ordering::SeqCst);
+                        wake.notify_one();
+                        continue;
+                    }
+                    if let Some(id) = message.get("id").and_then(|id| id.as_u64()) {
+                        if let Some(sender) = pending.lock().await.remove(&id) {
+                            let _ = sender.send(message);
+                        }
+                    }
+                }
+                alive.store(false, Ordering::SeqCst);
+                // 进程退出后立即让所有等待中的请求失败（丢弃 sender，调用方收到“服务器已退出”），不必等到超时
+                pending.lock().await.clear();
+                wake.notify_one();
+            });
+        }
+
+        Ok(Self {
+            _child: child,
+            stdin: Mutex::new(stdin),
+            pending,
+            next_id: AtomicU64::new(1),
+            alive,
+            tools_changed,
+        })
+    }
+
+    fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+
+    async fn write(&self, message: &Value) -> Result<(), String> {
+        let line = format!("{}\n", message);
+        let mut stdin = self.stdin.lock().await;
+        stdin.write_all(line.as_bytes()).await.map_err(|e| format!("无法写入 stdin: {}", e))?;
+        stdin.flush().await.map_err(|e| format!("无法刷新 stdin: {}", e))
+    }
+
+    async fn request(&self, method: &str, params: Option<Value>, timeout: Duration) -> Result<Value, String> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+
+        let (sender, receiver) = oneshot::channel();
+        {
+            // 在锁内检查进程状态，避免在读取循环清理等待项之后才登记的请求一直等到超时
+            let mut pending = self.pending.lock().await;
+            if !self.is_alive() {
+                return Err("MCP 服务器已退出".to_string());
+            }
+            pending.insert(id, sender);
+        }
+
+        let mut message = json!({ "jsonrpc": "2.0", "id": id, "method": method });
+        if let Some(params) = params {
+            message["params"] = params;
+        }
+        if let Err(e) = self.write(&message).await {
+            self.pending.lock().await.remove(&id);
+            return Err(e);
+        }
+
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => Err("MCP 服务器已退出".to_string()),
+            Err(_) => {
+                self.pending.lock().await.remove(&id);
+                Err(format!("{} 请求超时", method))
+            }
+        }
+    }
+
+    // 完成初始化握手并返回工具列表
+    async fn initialize(&self, timeout: Duration) -> Result<Vec<crate::MCPTool>, String> {
+        let init_response = self
+            .request(
+                "initialize",
+                Some(json!({
+                    "protocolVersion": "2024-11-05",
+                    "capabilities": {},
+                    "clientInfo": {
+                        "name": "qqh-tauri",
+                        "version": "0.1.0"
+                    }
+                })),
+                timeout,
+            )
+            .await?;
+        if let Some(error) = init_response.get("error") {
+            return Err(format!("初始化失败: {}", error));
+        }
+
+        self.write(&json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await?;
+        self.list_tools(timeout).await
+    }
+
+    async fn list_tools(&self, timeout: Duration) -> Result<Vec<crate::MCPTool>, String> {
+        self.tools_changed.store(false, Ordering::SeqCst);
+        let response = self.request("tools/list", None, timeout).await?;
+        if let Some(error) = response.get("error") {
+            return Err(format!("获取工具列表失败: {}", error));
+        }
+        Ok(mcp::parse_tools_list(&response))
+    }
+}
+
+// 计算配置指纹（忽略 enabled，启用/禁用不需要重建缓存）
+fn fingerprint(config: &MCPServerConfig) -> String {
+    let mut config = config.clone();
+    config.enabled = None;
+    serde_json::to_string(&config).unwrap_or_default()
+}
+
+// 单个服务器的监控状态
+struct ServerEntry {
+    fingerprint: String, // 原始配置的序列化结果，配置变化时重建连接和缓存
+    info: Option<MCPServerInfo>,
+    tools_fetched_at: Option<Instant>,
+    session: Option<Arc<StdioSession>>,
+}
+
+// MCP 服务器健康监控器
+#[derive(Clone)]
+pub struct MCPMonitor {
+    servers: Arc<Mutex<HashMap<String, Arc<Mutex<ServerEntry>>>>>,
+    wake: Arc<Notify>,
+}
+
+impl MCPMonitor {
+    pub fn new() -> Self {
+        Self {
+            servers: Arc::new(Mutex::new(HashMap::new())),
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    async fn entry(&self, key: &str, fingerprint: &str) -> Arc<Mutex<ServerEntry>> {
+        let mut servers = self.servers.lock().await;
+        let entry = servers
+            .entry(key.to_string())
+            .or_insert_with(|| {
+                Arc::new(Mutex::new(ServerEntry {
+                    fingerprint: fingerprint.to_string(),
+                    info: None,
+                    tools_fetched_at: None,
+                    session: None,
+                }))
+            })
+            .clone();
+        drop(servers);
+
+        // 配置变化时清空缓存
+        {
+            let mut guard = entry.lock().await;
+            if guard.fingerprint != fingerprint {
+                guard.fingerprint = fingerprint.to_string();
+                guard.info = None;
+                guard.tools_fetched_at = None;
+                guard.session = None;
+            }
+        }
+        entry
+    }
+
+    // 移除已不在配置中的服务器（释放其连接）
+    async fn retain(&self, keys: &[String]) {
+        let mut servers = self.servers.lock().await;
+        servers.retain(|key, _| keys.contains(key));
+    }
+
+    // 唤醒监控循环立即执行一次检查
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    // 获取服务器信息：有缓存时直接返回，否则立即检查一次
+    pub async fn get_or_check(
+        &self,
+        app: &AppHandle,
+        app_data_dir: &Path,
+        key: &str,
+        config: &MCPServerConfig,
+    ) -> MCPServerInfo {
+        let fingerprint = fingerprint(config);
+        let entry = self.entry(key, &fingerprint).await;
+        let mut guard = entry.lock().await;
+
+        if config.enabled == Some(false) {
+            // 已禁用的服务器不保持连接
+            guard.session = None;
+        } else if let Some(info) = guard.info.as_ref().filter(|info| info.status != "disconnected") {
+            return info.clone();
+        }
+
+        check_entry(app, app_data_dir, key, config, &mut guard, &self.wake).await
+    }
+
+    // 强制检查服务器并刷新工具缓存
+    pub async fn refresh(
+        &self,
+        app: &AppHandle,
+        app_data_dir: &Path,
+        key: &str,
+        config: &MCPServerConfig,
+    ) -> MCPServerInfo {
+        let fingerprint = fingerprint(config);
+        let entry = self.entry(key, &fingerprint).await;
+        let mut guard = entry.lock().await;
+        guard.tools_fetched_at = None;
+        if let Some(session) = guard.session.as_ref() {
+            session.tools_changed.store(true, Ordering::SeqCst);
+        }
+        check_entry(app, app_data_dir, key, config, &mut guard, &self.wake).await
+    }
+
+    // 通过 stdio 长连接调用工具，连接不存在或已退出时先启动并完成初始化
+    // 调用期间不持有服务器状态锁，健康检查和其它调用可以并发进行
+    pub async fn call_stdio_tool(
+        &self,
+        key: &str,
+        original_config: &MCPServerConfig,
+        resolved_config: &MCPServerConfig,
+        tool_name: &str,
+        arguments: Value,
+    ) -> Result<Value, String> {
+        let timeout = mcp::get_timeout(resolved_config);
+        let entry = self.entry(key, &fingerprint(original_config)).await;
+        let session = {
+            let mut guard = entry.lock().await;
+            match guard.session.clone().filter(|s| s.is_alive()) {
+                Some(session) => session,
+                None => {
+                    let session = Arc::new(StdioSession::start(resolved_config, self.wake.clone()).await?);
+                    session.initialize(timeout).await?;
+                    guard.session = Some(session.clone());
+                    session
+                }
+            }
+        };
+
+        let response = session
+            .request("tools/call", Some(json!({ "name": tool_name, "arguments": arguments })), timeout)
+            .await?;
+        if let Some(error) = response.get("error") {
+            return Err(format!("工具调用失败: {}", error));
+        }
+        response.get("result").cloned().ok_or_else(|| "响应中没有结果".to_string())
+    }
+}
+
+// 构建服务器信息（保留原始配置，不包含展开后的占位符）
+fn build_info(key: &str, config: &MCPServerConfig, previous: Option<&MCPServerInfo>) -> MCPServerInfo {
+    MCPServerInfo {
+        name: config.name.clone().unwrap_or_else(|| key.to_string()),
+        key: Some(key.to_string()),
+        config: config.clone(),
+        status: "disconnected".to_string(),
+        tools: previous.and_then(|p| p.tools.clone()),
+        error: None,
+        is_default: Some(false),
+        latency_ms: None,
+        last_error: previous.and_then(|p| p.last_error.clone()),
+        last_checked_at: Some(Utc::now().to_rfc3339()),
+    }
+}
+
+// 检查单个服务器的连接状态，必要时刷新工具列表
+async fn check_entry(
+    app: &AppHandle,
+    app_data_dir: &Path,
+    key: &str,
+    config: &MCPServerConfig,
+    entry: &mut ServerEntry,
+    wake: &Arc<Notify>,
+) -> MCPServerInfo {
+    let previous = entry.info.clone();
+    let mut info = build_info(key, config, previous.as_ref());
+
+    if config.enabled.unwrap_or(true) {
+        let timeout = mcp::get_timeout(config);
+        let result = match mcp_secrets::resolve_server_config(app_data_dir, config).await {
+            Ok(resolved) => check_server(key, &resolved, timeout, entry, &mut info, wake).await,
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(()) => {
+                info.status = "connected".to_string();
+            }
+            Err(e) => {
+                info.status = "error".to_string();
+                info.error = Some(e.clone());
+                info.last_error = Some(e);
+                info.tools = None;
+                entry.session = None;
+                entry.tools_fetched_at = None;
+            }
+        }
+    }
+
+    // 状态、错误或工具列表变化时通知前端
+    let changed = match &previous {
+        Some(previous) => {
+            previous.status != info.status
+                || previous.error != info.error
+                || serde_json::to_string(&previous.tools).ok() != serde_json::to_string(&info.tools).ok()
+        }
+        None => true,
+    };
+    entry.info = Some(info.clone());
+    if changed {
+        let _ = app.emit("mcp-status-changed", &info);
+    }
+
+    info
+}
+
+// 对服务器进行 ping，并在缓存失效时刷新工具列表
+async fn check_server(
+    key: &str,
+    config: &MCPServerConfig,
+    timeout: Duration,
+    entry: &mut ServerEntry,
+    info: &mut MCPServerInfo,
+    wake: &Arc<Notify>,
+) -> Result<(), String> {
+    if let Some(url) = &config.url {
+        let started_at = Instant::now();
+        mcp::ping_http(url, timeout).await?;
+        info.latency_ms = Some(started_at.elapsed().as_millis() as u64);
+
+        let expired = entry
+            .tools_fetched_at
+            .map(|t| t.elapsed() >= Duration::from_secs(HTTP_TOOLS_CACHE_TTL_SECS))
+            .unwrap_or(true);
+        if expired || info.tools.is_none() {
+            info.tools = Some(mcp::test_mcp_connection(key, config).await?);
+            entry.tools_fetched_at = Some(Instant::now());
+        }
+        return Ok(());
+    }
+
+    // stdio：保持长连接，进程退出后重新启动
+    let needs_start = entry.session.as_ref().map(|s| !s.is_alive()).unwrap_or(true);
+    if needs_start {
+        entry.session = None;
+        let started_at = Instant::now();
+        let session = StdioSession::start(config, wake.clone()).await?;
+        let tools = session.initialize(timeout).await?;
+        info.latency_ms = Some(started_at.elapsed().as_millis() as u64);
+        info.tools = Some(tools);
+        entry.tools_fetched_at = Some(Instant::now());
+        entry.session = Some(Arc::new(session));
+        return Ok(());
+    }
+
+    let session = entry.session.clone().ok_or("MCP 会话不存在")?;
+    let started_at = Instant::now();
+    // 旧版本服务器可能不支持 ping，返回 JSON-RPC 错误也说明进程仍在响应
+    session.request("ping", None, timeout).await?;
+    info.latency_ms = Some(started_at.elapsed().as_millis() as u64);
+
+    if session.tools_changed.load(Ordering::SeqCst) || info.tools.is_none() {
+        info.tools = Some(session.list_tools(timeout).await?);
+        entry.tools_fetched_at = Some(Instant::now());
+    }
+
+    Ok(())
+}
+
+// 执行一轮健康检查
+async fn run_health_check(app: &AppHandle, monitor: &MCPMonitor, app_data_dir: &PathBuf) {
+    let config_path = mcp::get_mcp_config_path(app_data_dir);
+    let config = match tokio::task::spawn_blocking(move || mcp::load_mcp_config(&config_path))
+        .await
+        .map_err(|e| format!("读取配置任务失败: {}", e))
+        .and_then(|result| result)
+    {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("[MCP Monitor] 无法读取 MCP 配置: {}", e);
+            return;
+        }
+    };
+
+    let keys: Vec<String> = config.mcp_servers.keys().cloned().collect();
+    monitor.retain(&keys).await;
+
+    let checks = config
+        .mcp_servers
+        .iter()
+        .filter(|(key, _)| key.as_str() != crate::default_mcp::DEFAULT_MCP_SERVER_NAME)
+        .filter(|(_, server_config)| server_config.enabled.unwrap_or(true))
+        .map(|(key, server_config)| async move {
+            let fingerprint = fingerprint(server_config);
+            let entry = monitor.entry(key, &fingerprint).await;
+            let mut guard = entry.lock().await;
+            check_entry(app, app_data_dir, key, server_config, &mut guard, &monitor.wake).await;
+        });
+
+    futures_util::future::join_all(checks).await;
+}
+
+// 启动后台健康监控循环
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let monitor = app.state::<MCPMonitor>().inner().clone();
+        let app_data_dir = match app.path().app_data_dir() {
+            Ok(dir) => dir,
+            Err(e) => {
+                eprintln!("[MCP Monitor] 无法获取应用数据目录: {}", e);
+                return;
+            }
+        };
+
+        loop {
+            run_health_check(&app, &monitor, &app_data_dir).await;
+
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(HEALTH_CHECK_INTERVAL_SECS)) => {}
+                _ = monitor.wake.notified() => {}
+            }
+        }
+    });
+}
@@ -0,0 +1,149 @@
+use crate::{MCPConfig, MCPPermissionPolicy, PendingApprovals};
+use regex::Regex;
+use serde_json::{json, Value};
+use tauri::{AppHandle, Emitter, Manager};
+use uuid::Uuid;
+
+// 等待用户批准的超时时间（秒）
+const APPROVAL_TIMEOUT_SECS: u64 = 300;
+
+// 权限检查结果
+pub enum PermissionCheck {
+    Allow,
+    Ask,
+    Deny(String), // 拒绝原因
+}
+
+// 用户批准结果
+pub enum ApprovalResult {
+    Approved,
+    Rejected,
+    Timeout,
+}
+
+// 将策略转换为字符串（用于审计日志）
+pub fn policy_to_string(policy: MCPPermissionPolicy) -> String {
+    match policy {
+        MCPPermissionPolicy::Allow => "allow".to_string(),
+        MCPPermissionPolicy::Ask => "ask".to_string(),
+        MCPPermissionPolicy::Deny => "deny".to_string(),
+    }
+}
+
+// 将参数值转换为用于匹配的字符串（字符串直接使用，其它类型使用 JSON 表示）
+fn argument_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+// 解析工具调用适用的策略
+// 查找顺序：工具规则 -> "*" 规则 -> 服务器默认策略 -> allow
+pub fn resolve_policy(config: &MCPConfig, server_key: &str, tool_name: &str) -> MCPPermissionPolicy {
+    let server_permission = match config.permissions.get(server_key) {
+        Some(permission) => permission,
+        None => return MCPPermissionPolicy::Allow,
+    };
+
+    server_permission
+        .tools
+        .get(tool_name)
+        .or_else(|| server_permission.tools.get("*"))
+        .map(|rule| rule.policy)
+        .or(server_permission.default_policy)
+        .unwrap_or(MCPPermissionPolicy::Allow)
+}
+
+// 检查工具调用是否被允许
+pub fn check_permission(
+    config: &MCPConfig,
+    server_key: &str,
+    tool_name: &str,
+    arguments: &Value,
+) -> PermissionCheck {
+    let policy = resolve_policy(config, server_key, tool_name);
+
+    if policy == MCPPermissionPolicy::Deny {
+        return PermissionCheck::Deny(format!("工具 {} 已被权限策略禁止调用", tool_name));
+    }
+
+    // 检查参数约束（仅对调用中实际提供的参数生效）
+    let rule = config.permissions.get(server_key).and_then(|permission| {
+        permission
+            .tools
+            .get(tool_name)
+            .or_else(|| permission.tools.get("*"))
+    });
+
+    if let Some(patterns) = rule.and_then(|r| r.argument_patterns.as_ref()) {
+        for (arg_name, pattern) in patterns {
+            let value = match arguments.get(arg_name) {
+                Some(v) if !v.is_null() => argument_to_string(v),
+                _ => continue,
+            };
+
+            // 要求完整匹配
+            let regex = match Regex::new(&format!("^(?:{})$", pattern)) {
+                Ok(r) => r,
+                Err(e) => {
+                    return PermissionCheck::Deny(format!(
+                        "参数 {} 的约束规则无效: {}",
+                        arg_name, e
+                    ));
+                }
+            };
+
+            if !regex.is_match(&value) {
+                return PermissionCheck::Deny(format!(
+                    "参数 {} 的值不符合权限约束: {}",
+                    arg_name, pattern
+                ));
+            }
+        }
+    }
+
+    match policy {
+        MCPPermissionPolicy::Ask => PermissionCheck::Ask,
+        _ => PermissionCheck::Allow,
+    }
+}
+
+// 请求用户批准工具调用
+// 发送 mcp-tool-approval-request 事件，等待前端调用 respond_mcp_tool_approval 返回结果
+// 批准、拒绝或超时后发送 mcp-tool-approval-resolved 事件
+pub async fn request_approval(
+    app: &AppHandle,
+    server_name: &str,
+    tool_name: &str,
+    arguments: &Value,
+) -> Result<ApprovalResult, String> {
+    let pending = app.state::<PendingApprovals>().inner().clone();
+    let request_id = Uuid::new_v4().to_string();
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    pending.insert(request_id.clone(), sender).await;
+
+    app.emit(
+        "mcp-tool-approval-request",
+        json!({
+            "request_id": request_id,
+            "server_name": server_name,
+            "tool_name": tool_name,
+            "arguments": arguments,
+        }),
+    )
+    .map_err(|e| format!("无法发送批准请求: {}", e))?;
+
+    let timeout = tokio::time::Duration::from_secs(APPROVAL_TIMEOUT_SECS);
+    let result = match tokio::time::timeout(timeout, receiver).await {
+        Ok(Ok(true)) => ApprovalResult::Approved,
+        Ok(Ok(false)) | Ok(Err(_)) => ApprovalResult::Rejected,
+        Err(_) => ApprovalResult::Timeout,
+    };
+
+    // 超时或通道关闭时清理等待项，并通知前端从批准队列中移除该请求
+    pending.remove(&request_id).await;
+    let _ = app.emit("mcp-tool-approval-resolved", json!({ "request_id": request_id }));
+
+    Ok(result)
+}
@@ -0,0 +1,320 @@
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+// 参数校验错误
+#[derive(Debug, Serialize, Clone)]
+pub struct ValidationError {
+    pub path: String, // 参数路径，例如 "start_time" 或 "items[0].name"
+    pub message: String,
+}
+
+// 校验并修正工具参数
+// 支持 JSON Schema 的常用子集：type、enum、const、required、properties、additionalProperties、
+// items、minimum/maximum、minLength/maxLength、minItems/maxItems、default
+// 会自动修正模型常见的错误：数字/布尔值以字符串形式传入、对象/数组以 JSON 字符串传入、缺少有默认值的参数
+pub fn validate_and_coerce(schema: &Value, arguments: Value) -> Result<Value, Vec<ValidationError>> {
+    // 模型有时会传 null 或空字符串表示没有参数
+    let arguments = match arguments {
+        Value::Null => Value::Object(Map::new()),
+        Value::String(s) if s.trim().is_empty() => Value::Object(Map::new()),
+        other => other,
+    };
+
+    let mut errors = Vec::new();
+    let value = validate_value(schema, arguments, "", &mut errors);
+    if errors.is_empty() {
+        Ok(value)
+    } else {
+        Err(errors)
+    }
+}
+
+// 构建返回给模型的校验失败结果，便于模型根据错误信息自行修正参数
+pub fn validation_error_result(tool_name: &str, schema: &Value, errors: &[ValidationError]) -> Value {
+    let summary = errors
+        .iter()
+        .map(|e| format!("{}: {}", if e.path.is_empty() { "(root)" } else { &e.path }, e.message))
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    json!({
+        "isError": true,
+        "content": [{
+            "type": "text",
+            "text": format!("工具 {} 的参数校验失败：{}。请根据 input_schema 修正参数后重试。", tool_name, summary)
+        }],
+        "validation_errors": errors,
+        "input_schema": schema,
+    })
+}
+
+fn join_path(parent: &str, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn matches_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => match value {
+            Value::Number(n) => n.is_i64() || n.is_u64() || n.as_f64().map(|f| f.fract() == 0.0).unwrap_or(false),
+            _ => false,
+        },
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+// 尝试把值转换为期望的类型，无法转换时返回 None
+fn coerce(value: &Value, expected: &str) -> Option<Value> {
+    match (expected, value) {
+        ("integer", Value::String(s)) => {
+            let s = s.trim();
+            if let Ok(i) = s.parse::<i64>() {
+                return Some(json!(i));
+            }
+            s.parse::<f64>().ok().filter(|f| f.fract() == 0.0).map(|f| json!(f as i64))
+        }
+        ("integer", Value::Number(n)) => n.as_f64().filter(|f| f.fract() == 0.0).map(|f| json!(f as i64)),
+        ("number", Value::String(s)) => s.trim().parse::<f64>().ok().filter(|f| f.is_finite()).map(|f| json!(f)),
+        ("boolean", Value::String(s)) => match s.trim().to_lowercase().as_str() {
+            "true" | "1" | "yes" => Some(json!(true)),
+            "false" | "0" | "no" => Some(json!(false)),
+            _ => None,
+        },
+        ("boolean", Value::Number(n)) => match n.as_i64() {
+            Some(1) => Some(json!(true)),
+            Some(0) => Some(json!(false)),
+            _ => None,
+        },
+        ("string", Value::Number(_)) | ("string", Value::Bool(_)) => Some(json!(value.to_string())),
+        ("object", Value::String(s)) => serde_json::from_str::<Value>(s).ok().filter(|v| v.is_object()),
+        ("array", Value::String(s)) => serde_json::from_str::<Value>(s).ok().filter(|v| v.is_array()),
+        ("null", Value::String(s)) if s.trim().is_empty() || s.trim() == "null" => Some(Value::Null),
+        _ => None,
+    }
+}
+
+// 获取 schema 中声明的类型列表（type 可以是字符串或字符串数组）
+fn expected_types(schema: &Value) -> Vec<String> {
+    match schema.get("type") {
+        Some(Value::String(t)) => vec![t.clone()],
+        Some(Value::Array(types)) => types
+            .iter()
+            .filter_map(|t| t.as_str().map(|s| s.to_string()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn validate_value(schema: &Value, value: Value, path: &str, errors: &mut Vec<ValidationError>) -> Value {
+    let schema_obj = match schema.as_object() {
+        Some(obj) => obj,
+        None => return value, // true / 空 schema 不做约束
+    };
+
+    // 类型检查与修正
+    let types = expected_types(schema);
+    let mut value = value;
+    if !types.is_empty() && !types.iter().any(|t| matches_type(&value, t)) {
+        match types.iter().find_map(|t| coerce(&value, t)) {
+            Some(coerced) => value = coerced,
+            None => {
+                errors.push(ValidationError {
+                    path: path.to_string(),
+                    message: format!("类型应为 {}，实际为 {}", types.join(" | "), type_name(&value)),
+                });
+                return value;
+            }
+        }
+    } else if types.iter().any(|t| t == "integer") && !types.iter().any(|t| t == "number") {
+        // 1.0 这样的浮点数转换为整数
+        if let Some(coerced) = coerce(&value, "integer") {
+            value = coerced;
+        }
+    }
+
+    if let Some(Value::Array(options)) = schema_obj.get("enum") {
+        if !options.contains(&value) {
+            // 枚举值为字符串时，尝试忽略大小写匹配
+            let matched = value.as_str().and_then(|s| {
+                options
+                    .iter()
+                    .find(|o| o.as_str().map(|o| o.eq_ignore_ascii_case(s)).unwrap_or(false))
+                    .cloned()
+            });
+            match matched {
+                Some(option) => value = option,
+                None => errors.push(ValidationError {
+                    path: path.to_string(),
+                    message: format!("取值应为 {} 之一", Value::Array(options.clone())),
+                }),
+            }
+        }
+    }
+
+    if let Some(expected) = schema_obj.get("const") {
+        if &value != expected {
+            errors.push(ValidationError {
+                path: path.to_string(),
+                message: format!("取值应为 {}", expected),
+            });
+        }
+    }
+
+    match value {
+        Value::Number(ref n) => {
+            let number = n.as_f64().unwrap_or(0.0);
+            if let Some(minimum) = schema_obj.get("minimum").and_then(|v| v.as_f64()) {
+                if number < minimum {
+                    errors.push(ValidationError {
+                        path: path.to_string(),
+                        message: format!("不能小于 {}", minimum),
+                    });
+                }
+            }
+            if let Some(maximum) = schema_obj.get("maximum").and_then(|v| v.as_f64()) {
+                if number > maximum {
+                    errors.push(ValidationError {
+                        path: path.to_string(),
+                        message: format!("不能大于 {}", maximum),
+                    });
+                }
+            }
+            value
+        }
+        Value::String(ref s) => {
+            let length = s.chars().count() as u64;
+            if let Some(min_length) = schema_obj.get("minLength").and_then(|v| v.as_u64()) {
+                if length < min_length {
+                    errors.push(ValidationError {
+                        path: path.to_string(),
+                        message: format!("长度不能小于 {}", min_length),
+                    });
+                }
+            }
+            if let Some(max_length) = schema_obj.get("maxLength").and_then(|v| v.as_u64()) {
+                if length > max_length {
+                    errors.push(ValidationError {
+                        path: path.to_string(),
+                        message: format!("长度不能大于 {}", max_length),
+                    });
+                }
+            }
+            value
+        }
+        Value::Array(items) => {
+            let count = items.len() as u64;
+            if let Some(min_items) = schema_obj.get("minItems").and_then(|v| v.as_u64()) {
+                if count < min_items {
+                    errors.push(ValidationError {
+                        path: path.to_string(),
+                        message: format!("元素数量不能少于 {}", min_items),
+                    });
+                }
+            }
+            if let Some(max_items) = schema_obj.get("maxItems").and_then(|v| v.as_u64()) {
+                if count > max_items {
+                    errors.push(ValidationError {
+                        path: path.to_string(),
+                        message: format!("元素数量不能多于 {}", max_items),
+                    });
+                }
+            }
+            match schema_obj.get("items") {
+                Some(item_schema) if item_schema.is_object() => Value::Array(
+                    items
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, item)| validate_value(item_schema, item, &format!("{}[{}]", path, i), errors))
+                        .collect(),
+                ),
+                _ => Value::Array(items),
+            }
+        }
+        Value::Object(obj) => Value::Object(validate_object(schema_obj, obj, path, errors)),
+        other => other,
+    }
+}
+
+fn validate_object(
+    schema_obj: &Map<String, Value>,
+    mut obj: Map<String, Value>,
+    path: &str,
+    errors: &mut Vec<ValidationError>,
+) -> Map<String, Value> {
+    let properties = schema_obj.get("properties").and_then(|v| v.as_object());
+
+    // 为缺失的参数填充默认值
+    if let Some(properties) = properties {
+        for (key, property_schema) in properties {
+            if !obj.contains_key(key) {
+                if let Some(default) = property_schema.get("default") {
+                    obj.insert(key.clone(), default.clone());
+                }
+            }
+        }
+    }
+
+    // 检查必填参数（null 视为缺失）
+    if let Some(Value::Array(required)) = schema_obj.get("required") {
+        for key in required.iter().filter_map(|k| k.as_str()) {
+            if obj.get(key).map(|v| v.is_null()).unwrap_or(true) {
+                errors.push(ValidationError {
+                    path: join_path(path, key),
+                    message: "缺少必填参数".to_string(),
+                });
+            }
+        }
+    }
+
+    let additional = schema_obj.get("additionalProperties");
+    let mut result = Map::new();
+    for (key, value) in obj {
+        let child_path = join_path(path, &key);
+        match properties.and_then(|p| p.get(&key)) {
+            Some(property_schema) => {
+                // 可选参数传 null 时直接忽略
+                if value.is_null() && !expected_types(property_schema).iter().any(|t| t == "null") {
+                    continue;
+                }
+                let value = validate_value(property_schema, value, &child_path, errors);
+                result.insert(key, value);
+            }
+            None => match additional {
+                Some(Value::Bool(false)) => errors.push(ValidationError {
+                    path: child_path,
+                    message: "不支持此参数".to_string(),
+                }),
+                Some(additional_schema) if additional_schema.is_object() => {
+                    let value = validate_value(additional_schema, value, &child_path, errors);
+                    result.insert(key, value);
+                }
+                _ => {
+                    result.insert(key, value);
+                }
+            },
+        }
+    }
+
+    result
+}
@@ -0,0 +1,265 @@
+use crate::MCPServerConfig;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+// 系统钥匙串中保存加密密钥的条目（macOS 钥匙串 / Windows 凭据管理器 / Linux Secret Service）
+const KEYCHAIN_SERVICE: &str = "qqh-tauri";
+const KEYCHAIN_USER: &str = "mcp-secrets-key";
+
+// 进程内缓存的加密密钥：钥匙串访问会阻塞（可能等待系统授权弹窗），只在首次使用时读取一次
+static SECRET_KEY: OnceLock<LessSafeKey> = OnceLock::new();
+static SECRET_KEY_INIT: Mutex<()> = Mutex::new(());
+
+// 旧版本保存在应用数据目录中的密钥文件，读取后迁移到系统钥匙串并删除
+fn get_legacy_key_path(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("mcp_secrets.key")
+}
+
+// 加密后的密钥存储文件路径：{ name: base64(nonce + ciphertext) }
+fn get_secret_store_path(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("mcp_secrets.json")
+}
+
+// 读取或生成加密密钥
+// 密钥只保存在系统钥匙串中，不与加密数据放在同一目录
+fn load_or_create_key(app_data_dir: &Path) -> Result<LessSafeKey, String> {
+    let entry = keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_USER)
+        .map_err(|e| format!("无法访问系统钥匙串: {}", e))?;
+
+    let key_bytes = match entry.get_password() {
+        Ok(encoded) => BASE64.decode(encoded.trim()).map_err(|_| "钥匙串中的密钥已损坏".to_string())?,
+        Err(keyring::Error::NoEntry) => {
+            let legacy_path = get_legacy_key_path(app_data_dir);
+            let bytes = if legacy_path.exists() {
+                std::fs::read(&legacy_path).map_err(|e| format!("无法读取密钥文件: {}", e))?
+            } else {
+                let mut bytes = vec![0u8; 32];
+                SystemRandom::new()
+                    .fill(&mut bytes)
+                    .map_err(|_| "无法生成随机密钥".to_string())?;
+                bytes
+            };
+            entry
+                .set_password(&BASE64.encode(&bytes))
+                .map_err(|e| format!("无法将密钥保存到系统钥匙串: {}", e))?;
+            if legacy_path.exists() {
+                let _ = std::fs::remove_file(&legacy_path);
+            }
+            bytes
+        }
+        Err(e) => return Err(format!("无法从系统钥匙串读取密钥: {}", e)),
+    };
+
+    let unbound = UnboundKey::new(&AES_256_GCM, &key_bytes).map_err(|_| "钥匙串中的密钥已损坏".to_string())?;
+    Ok(LessSafeKey::new(unbound))
+}
+
+// 获取缓存的密钥，首次调用时从钥匙串读取（阻塞操作，需在 spawn_blocking 中调用）
+fn cached_key(app_data_dir: &Path) -> Result<&'static LessSafeKey, String> {
+    if let Some(key) = SECRET_KEY.get() {
+        return Ok(key);
+    }
+    // 串行化首次加载，避免并发时生成两个不同的密钥
+    let _guard = SECRET_KEY_INIT.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(key) = SECRET_KEY.get() {
+        return Ok(key);
+    }
+    let key = load_or_create_key(app_data_dir)?;
+    Ok(SECRET_KEY.get_or_init(|| key))
+}
+
+fn load_store(app_data_dir: &Path) -> Result<BTreeMap<String, String>, String> {
+    let store_path = get_secret_store_path(app_data_dir);
+    if !store_path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let content = std::fs::read_to_string(&store_path).map_err(|e| format!("无法读取密钥存储: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("无法解析密钥存储: {}", e))
+}
+
+fn save_store(app_data_dir: &Path, store: &BTreeMap<String, String>) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(store).map_err(|e| format!("无法序列化密钥存储: {}", e))?;
+    std::fs::write(get_secret_store_path(app_data_dir), content).map_err(|e| format!("无法保存密钥存储: {}", e))
+}
+
+fn encrypt(key: &LessSafeKey, name: &str, value: &str) -> Result<String, String> {
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce_bytes)
+        .map_err(|_| "无法生成随机数".to_string())?;
+
+    // 使用密钥名作为附加数据，防止密文被挪用到其它名称下
+    let mut in_out = value.as_bytes().to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce_bytes), Aad::from(name.as_bytes()), &mut in_out)
+        .map_err(|_| "加密失败".to_string())?;
+
+    let mut output = nonce_bytes.to_vec();
+    output.extend_from_slice(&in_out);
+    Ok(BASE64.encode(output))
+}
+
+fn decrypt(key: &LessSafeKey, name: &str, encoded: &str) -> Result<String, String> {
+    let data = BASE64.decode(encoded).map_err(|_| format!("密钥 {} 的数据已损坏", name))?;
+    if data.len() < NONCE_LEN {
+        return Err(format!("密钥 {} 的数据已损坏", name));
+    }
+    let (nonce_bytes, ciphertext) = data.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce_bytes).map_err(|_| format!("密钥 {} 的数据已损坏", name))?;
+
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = key
+        .open_in_place(nonce, Aad::from(name.as_bytes()), &mut in_out)
+        .map_err(|_| format!("无法解密密钥 {}", name))?;
+    String::from_utf8(plaintext.to_vec()).map_err(|_| format!("密钥 {} 不是合法的 UTF-8 文本", name))
+}
+
+fn validate_secret_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.') {
+        return Err("密钥名称只能包含字母、数字、下划线、中划线和点".to_string());
+    }
+    Ok(())
+}
+
+// 列出所有密钥名称（不返回值）
+pub fn list_secret_names(app_data_dir: &Path) -> Result<Vec<String>, String> {
+    Ok(load_store(app_data_dir)?.into_keys().collect())
+}
+
+// 保存（或更新）密钥
+pub fn set_secret(app_data_dir: &Path, name: &str, value: &str) -> Result<(), String> {
+    validate_secret_name(name)?;
+    let key = cached_key(app_data_dir)?;
+    let mut store = load_store(app_data_dir)?;
+    store.insert(name.to_string(), encrypt(key, name, value)?);
+    save_store(app_data_dir, &store)
+}
+
+// 删除密钥
+pub fn delete_secret(app_data_dir: &Path, name: &str) -> Result<(), String> {
+    let mut store = load_store(app_data_dir)?;
+    if store.remove(name).is_none() {
+        return Err(format!("密钥 {} 不存在", name));
+    }
+    save_store(app_data_dir, &store)
+}
+
+// 占位符解析器（按需解密，同一次解析中复用密钥存储）
+struct PlaceholderResolver<'a> {
+    app_data_dir: &'a Path,
+    store: Option<BTreeMap<String, String>>,
+}
+
+impl<'a> PlaceholderResolver<'a> {
+    fn new(app_data_dir: &'a Path) -> Self {
+        Self {
+            app_data_dir,
+            store: None,
+        }
+    }
+
+    fn secret(&mut self, name: &str) -> Result<String, String> {
+        if self.store.is_none() {
+            self.store = Some(load_store(self.app_data_dir)?);
+        }
+        let encoded = self
+            .store
+            .as_ref()
+            .and_then(|store| store.get(name))
+            .ok_or_else(|| format!("未找到密钥 {}", name))?
+            .clone();
+        decrypt(cached_key(self.app_data_dir)?, name, &encoded)
+    }
+
+    // 展开字符串中的 ${VAR}、${env:VAR} 和 ${secret:name} 占位符，$${ 表示字面量 ${
+    fn resolve(&mut self, value: &str) -> Result<String, String> {
+        let mut output = String::with_capacity(value.len());
+        let mut rest = value;
+
+        while let Some(start) = rest.find("${") {
+            // $${ 转义
+            if start > 0 && rest[..start].ends_with('$') {
+                output.push_str(&rest[..start - 1]);
+                output.push_str("${");
+                rest = &rest[start + 2..];
+                continue;
+            }
+
+            output.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("占位符缺少右括号: {}", value))?;
+            let placeholder = rest[start + 2..start + end].trim();
+
+            let resolved = if let Some(name) = placeholder.strip_prefix("secret:") {
+                self.secret(name.trim())?
+            } else {
+                let var = placeholder.strip_prefix("env:").unwrap_or(placeholder).trim();
+                std::env::var(var).map_err(|_| format!("未找到环境变量 {}", var))?
+            };
+            output.push_str(&resolved);
+            rest = &rest[start + end + 1..];
+        }
+
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    fn resolve_json(&mut self, value: &serde_json::Value) -> Result<serde_json::Value, String> {
+        Ok(match value {
+            serde_json::Value::String(s) => serde_json::Value::String(self.resolve(s)?),
+            serde_json::Value::Array(items) => serde_json::Value::Array(
+                items.iter().map(|item| self.resolve_json(item)).collect::<Result<_, _>>()?,
+            ),
+            serde_json::Value::Object(obj) => {
+                let mut resolved = serde_json::Map::new();
+                for (key, item) in obj {
+                    resolved.insert(key.clone(), self.resolve_json(item)?);
+                }
+                serde_json::Value::Object(resolved)
+            }
+            other => other.clone(),
+        })
+    }
+}
+
+// 返回展开占位符后的服务器配置副本，仅用于启动/连接服务器，不能写回配置文件
+// 读取钥匙串和密钥文件都会阻塞，在 spawn_blocking 中执行
+pub async fn resolve_server_config(app_data_dir: &Path, config: &MCPServerConfig) -> Result<MCPServerConfig, String> {
+    let app_data_dir = app_data_dir.to_path_buf();
+    let config = config.clone();
+    tokio::task::spawn_blocking(move || resolve_server_config_blocking(&app_data_dir, &config))
+        .await
+        .map_err(|e| format!("任务执行失败: {}", e))?
+}
+
+fn resolve_server_config_blocking(app_data_dir: &Path, config: &MCPServerConfig) -> Result<MCPServerConfig, String> {
+    let mut resolver = PlaceholderResolver::new(app_data_dir);
+    let mut resolved = config.clone();
+
+    if let Some(command) = &config.command {
+        resolved.command = Some(resolver.resolve(command)?);
+    }
+    if let Some(args) = &config.args {
+        resolved.args = Some(args.iter().map(|arg| resolver.resolve(arg)).collect::<Result<_, _>>()?);
+    }
+    if let Some(env) = &config.env {
+        let mut resolved_env = std::collections::HashMap::new();
+        for (key, value) in env {
+            resolved_env.insert(key.clone(), resolver.resolve(value)?);
+        }
+        resolved.env = Some(resolved_env);
+    }
+    if let Some(url) = &config.url {
+        resolved.url = Some(resolver.resolve(url)?);
+    }
+    if let Some(transport) = &config.transport {
+        resolved.transport = Some(resolver.resolve_json(transport)?);
+    }
+
+    Ok(resolved)
+}
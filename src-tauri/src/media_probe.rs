@@ -0,0 +1,158 @@
+use crate::{url_metadata::get_str, MediaInfo};
+use serde_json::Value;
+use std::path::Path;
+
+// 探测媒体文件：优先使用与 ffmpeg 同目录的 ffprobe，没有时解析 ffmpeg -i 的输出
+pub async fn probe(ffmpeg_path: &Path, input: &Path) -> Result<MediaInfo, String> {
+    let ffprobe_path = ffmpeg_path.with_file_name("ffprobe");
+    if ffprobe_path.is_file() {
+        match probe_with_ffprobe(&ffprobe_path, input).await {
+            Ok(info) => return Ok(info),
+            Err(e) => eprintln!("ffprobe 探测失败，改用 ffmpeg: {}", e),
+        }
+    }
+    probe_with_ffmpeg(ffmpeg_path, input).await
+}
+
+// ffprobe 的数值字段是字符串（如 "44100"、"12.345000"）
+fn get_number<T: std::str::FromStr>(value: &Value, key: &str) -> Option<T> {
+    match value.get(key)? {
+        Value::String(s) => s.trim().parse().ok(),
+        v => v.to_string().parse().ok(),
+    }
+}
+
+async fn probe_with_ffprobe(ffprobe_path: &Path, input: &Path) -> Result<MediaInfo, String> {
+    let output = tokio::process::Command::new(ffprobe_path)
+        .arg("-v")
+        .arg("error")
+        .arg("-print_format")
+        .arg("json")
+        .arg("-show_format")
+        .arg("-show_streams")
+        .arg(input)
+        .output()
+        .await
+        .map_err(|e| format!("无法执行 ffprobe: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("无法识别媒体文件: {}", stderr.trim()));
+    }
+
+    let value: Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("无法解析 ffprobe 输出: {}", e))?;
+    let format = value.get("format").cloned().unwrap_or(Value::Null);
+    let streams = value.get("streams").and_then(|s| s.as_array()).cloned().unwrap_or_default();
+
+    let audio = streams.iter().find(|s| get_str(s, "codec_type").as_deref() == Some("audio"));
+    // 音频文件中的封面图片（attached_pic）不算视频流
+    let video = streams.iter().find(|s| {
+        get_str(s, "codec_type").as_deref() == Some("video")
+            && s.pointer("/disposition/attached_pic").and_then(|v| v.as_i64()) != Some(1)
+    });
+
+    Ok(MediaInfo {
+        container: get_str(&format, "format_name"),
+        duration: get_number::<f64>(&format, "duration")
+            .or_else(|| audio.and_then(|a| get_number(a, "duration")))
+            .filter(|d| *d > 0.0),
+        has_audio: audio.is_some(),
+        has_video: video.is_some(),
+        audio_codec: audio.and_then(|a| get_str(a, "codec_name")),
+        video_codec: video.and_then(|v| get_str(v, "codec_name")),
+        sample_rate: audio.and_then(|a| get_number(a, "sample_rate")),
+        channels: audio.and_then(|a| get_number(a, "channels")),
+    })
+}
+
+// 解析 "HH:MM:SS.xx" 格式的时长
+fn parse_duration(value: &str) -> Option<f64> {
+    let parts: Vec<f64> = value.trim().split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    match parts.as_slice() {
+        [h, m, s] => Some(h * 3600.0 + m * 60.0 + s),
+        _ => None,
+    }
+}
+
+// 流的编码名称："Stream #0:1(und): Audio: aac (LC) (mp4a / 0x6134706D), ..." 中的 "aac"
+fn stream_codec(description: &str) -> Option<String> {
+    description
+        .split([',', ' ', '('])
+        .map(|s| s.trim())
+        .find(|s| !s.is_empty())
+        .map(|s| s.to_string())
+}
+
+// 声道数：ffmpeg 输出的是声道布局名称
+fn parse_channels(layout: &str) -> Option<u32> {
+    let layout = layout.trim();
+    match layout {
+        "mono" => Some(1),
+        "stereo" => Some(2),
+        _ => {
+            if let Some(count) = layout.strip_suffix(" channels") {
+                return count.trim().parse().ok();
+            }
+            // 如 "5.1(side)"、"7.1"
+            let base = layout.split('(').next()?;
+            let parts: Vec<u32> = base.split('.').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+            Some(parts.iter().sum())
+        }
+    }
+}
+
+// ffmpeg -i 不指定输出时会以错误退出，但 stderr 中包含输入文件的信息
+async fn probe_with_ffmpeg(ffmpeg_path: &Path, input: &Path) -> Result<MediaInfo, String> {
+    let output = tokio::process::Command::new(ffmpeg_path)
+        .arg("-hide_banner")
+        .arg("-i")
+        .arg(input)
+        .output()
+        .await
+        .map_err(|e| format!("无法执行 ffmpeg: {}。请确保工具已正确安装。", e))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let mut info = MediaInfo::default();
+    let mut found_input = false;
+    for line in stderr.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Input #0, ") {
+            found_input = true;
+            info.container = rest.split(", from ").next().map(|s| s.trim().to_string());
+        } else if let Some(rest) = line.strip_prefix("Duration: ") {
+            info.duration = rest.split(',').next().and_then(parse_duration).filter(|d| *d > 0.0);
+        } else if line.starts_with("Stream #0:") {
+            if let Some((_, description)) = line.split_once(": Audio: ") {
+                if info.has_audio {
+                    continue;
+                }
+                info.has_audio = true;
+                info.audio_codec = stream_codec(description);
+                let fields: Vec<&str> = description.split(", ").collect();
+                info.sample_rate = fields
+                    .iter()
+                    .find_map(|f| f.trim().strip_suffix(" Hz").and_then(|hz| hz.trim().parse().ok()));
+                // 声道布局紧跟在采样率之后
+                info.channels = fields
+                    .iter()
+                    .position(|f| f.trim().ends_with(" Hz"))
+                    .and_then(|i| fields.get(i + 1))
+                    .and_then(|layout| parse_channels(layout));
+            } else if let Some((_, description)) = line.split_once(": Video: ") {
+                // 音频文件中的封面图片（attached pic）不算视频流
+                if info.has_video || description.contains("(attached pic)") {
+                    continue;
+                }
+                info.has_video = true;
+                info.video_codec = stream_codec(description);
+            }
+        }
+    }
+
+    if !found_input {
+        let detail = stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+        return Err(format!("无法识别媒体文件: {}", detail));
+    }
+    Ok(info)
+}
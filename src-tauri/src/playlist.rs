@@ -0,0 +1,125 @@
+use crate::url_metadata::get_str;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+
+// 播放列表 / 频道中的一个视频（yt-dlp --flat-playlist 输出）
+#[derive(Debug, Clone)]
+pub struct PlaylistEntry {
+    pub url: String,
+    pub title: Option<String>,
+    pub uploader: Option<String>,
+    pub duration: Option<f64>,
+    pub thumbnail: Option<String>,
+}
+
+// 展开后的播放列表
+#[derive(Debug, Clone)]
+pub struct Playlist {
+    pub title: Option<String>,
+    pub entries: Vec<PlaylistEntry>,
+}
+
+// 批量导入结果
+#[derive(Debug, Serialize)]
+pub struct PlaylistImportResult {
+    pub playlist_title: Option<String>,
+    pub total: usize, // 播放列表中的视频数
+    pub resources: Vec<crate::TranscriptionResource>, // 新建的资源
+    pub skipped: Vec<String>, // 已存在而跳过的 URL
+    pub tasks: Vec<crate::TranscriptionTask>, // 加入队列的转写任务
+}
+
+// 条目的视频 URL：优先使用 url 字段，YouTube 条目可能只有视频 ID
+fn entry_url(entry: &Value) -> Option<String> {
+    let url = get_str(entry, "url").or_else(|| get_str(entry, "webpage_url"));
+    match url {
+        Some(url) if url.starts_with("http://") || url.starts_with("https://") => Some(url),
+        _ => {
+            let id = get_str(entry, "id")?;
+            match entry.get("ie_key").and_then(|v| v.as_str()) {
+                Some("Youtube") => Some(format!("https://www.youtube.com/watch?v={}", id)),
+                Some(key) if key.starts_with("BiliBili") && id.starts_with("BV") => {
+                    Some(format!("https://www.bilibili.com/video/{}", id))
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+// 条目缩略图：取列表中最后一个（通常分辨率最高）
+fn entry_thumbnail(entry: &Value) -> Option<String> {
+    get_str(entry, "thumbnail").or_else(|| {
+        entry
+            .get("thumbnails")
+            .and_then(|t| t.as_array())
+            .and_then(|t| t.iter().rev().find_map(|t| get_str(t, "url")))
+    })
+}
+
+// 递归收集条目（频道可能包含嵌套的播放列表，如"视频"、"直播"标签页）
+fn collect_entries(value: &Value, uploader: Option<&String>, entries: &mut Vec<PlaylistEntry>) {
+    let Some(items) = value.get("entries").and_then(|e| e.as_array()) else {
+        return;
+    };
+    for item in items {
+        if item.get("entries").is_some() {
+            collect_entries(item, uploader, entries);
+            continue;
+        }
+        // 未展开的标签页 / 子播放列表无法直接转写，跳过
+        let ie_key = item.get("ie_key").and_then(|v| v.as_str()).unwrap_or_default();
+        if ie_key == "YoutubeTab" || item.get("_type").and_then(|v| v.as_str()) == Some("playlist") {
+            continue;
+        }
+        let Some(url) = entry_url(item) else {
+            continue;
+        };
+        entries.push(PlaylistEntry {
+            url,
+            title: get_str(item, "title"),
+            uploader: get_str(item, "channel")
+                .or_else(|| get_str(item, "uploader"))
+                .or_else(|| uploader.cloned()),
+            duration: item.get("duration").and_then(|v| v.as_f64()).filter(|d| *d > 0.0),
+            thumbnail: entry_thumbnail(item),
+        });
+    }
+}
+
+// 解析 --flat-playlist --dump-single-json 输出
+pub fn parse(value: &Value) -> Playlist {
+    let uploader = get_str(value, "channel").or_else(|| get_str(value, "uploader"));
+    let mut entries = Vec::new();
+    collect_entries(value, uploader.as_ref(), &mut entries);
+    Playlist {
+        title: get_str(value, "title"),
+        entries,
+    }
+}
+
+// 运行 yt-dlp --flat-playlist 展开播放列表或频道（只获取条目，不解析每个视频）
+pub async fn fetch(ytdlp_path: &Path, url: &str) -> Result<Playlist, String> {
+    let output = tokio::process::Command::new(ytdlp_path)
+        .arg("--flat-playlist")
+        .arg("--dump-single-json")
+        .arg("--no-warnings")
+        .arg(url)
+        .output()
+        .await
+        .map_err(|e| format!("无法执行 yt-dlp: {}。请确保 yt-dlp 已正确安装。", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("yt-dlp 展开播放列表失败: {}", stderr.trim()));
+    }
+
+    let value: Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("无法解析 yt-dlp 输出: {}", e))?;
+    let playlist = parse(&value);
+    if playlist.entries.is_empty() {
+        return Err("播放列表中没有可导入的视频".to_string());
+    }
+    Ok(playlist)
+}
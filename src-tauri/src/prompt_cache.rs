@@ -0,0 +1,155 @@
+use crate::{ai, context_window, AIConfig};
+use serde::Serialize;
+
+// 单个请求允许的最大缓存断点数（Anthropic 限制）
+const MAX_CACHE_BREAKPOINTS: usize = 4;
+// 低于该长度的前缀不会被缓存（Anthropic 最小可缓存长度为 1024 token）
+const MIN_CACHEABLE_PREFIX_TOKENS: usize = 1024;
+// 单条消息超过该长度视为大负载（如转写内容、任务详情等工具结果）
+const LARGE_PAYLOAD_TOKENS: usize = 2048;
+// 最近轮次的断点数量（当前轮次 + 上一轮，后者用于命中上一次请求写入的缓存）
+const RECENT_TURN_BREAKPOINTS: usize = 2;
+
+// 提供商的 prompt caching 方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheStrategy {
+    Explicit,    // 需要显式 cache_control 断点（Anthropic API、OpenRouter 上的 Claude / Gemini）
+    Automatic,   // 服务端按前缀自动缓存（OpenAI、DeepSeek 等），不需要也不接受断点
+    Unsupported, // 未知提供商，不添加断点以免请求被拒绝
+}
+
+// 根据配置判断缓存方式
+// 只对已知接受 cache_control 的端点使用显式断点，其它代理或网关上的 Claude 模型可能会拒绝该字段
+pub fn detect_strategy(config: &AIConfig) -> CacheStrategy {
+    let model = config.model.to_lowercase();
+    let base_url = config.base_url.to_lowercase();
+
+    if base_url.contains("anthropic.com") {
+        return CacheStrategy::Explicit;
+    }
+    if base_url.contains("openrouter.ai") && (model.contains("claude") || model.contains("gemini")) {
+        return CacheStrategy::Explicit;
+    }
+    if base_url.contains("openai.com")
+        || base_url.contains("deepseek.com")
+        || model.starts_with("gpt-")
+        || model.starts_with("openai/")
+        || model.contains("deepseek")
+    {
+        return CacheStrategy::Automatic;
+    }
+    CacheStrategy::Unsupported
+}
+
+// 断点位置
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheBreakpoint {
+    pub kind: &'static str, // "system" | "tools" | "payload" | "recent"
+    pub message_index: Option<usize>,
+    pub prefix_tokens: usize, // 断点之前（含）的估算 token 数
+}
+
+// 断点放置结果
+#[derive(Debug, Clone, Serialize)]
+pub struct CachePlan {
+    pub strategy: CacheStrategy,
+    pub breakpoints: Vec<CacheBreakpoint>,
+}
+
+// 按提供商放置缓存断点：system prompt、工具定义、大负载消息和最近轮次，最多 4 个
+// 此函数是幂等的：会先清除消息和工具上已有的 cache_control
+pub fn apply_breakpoints(
+    config: &AIConfig,
+    messages: &mut [ai::ChatMessage],
+    tools: &mut [ai::ToolDefinition],
+) -> CachePlan {
+    for message in messages.iter_mut() {
+        message.cache_control = None;
+    }
+    for tool in tools.iter_mut() {
+        tool.cache_control = None;
+    }
+
+    let strategy = detect_strategy(config);
+    let mut plan = CachePlan {
+        strategy,
+        breakpoints: Vec::new(),
+    };
+    if strategy != CacheStrategy::Explicit {
+        return plan;
+    }
+
+    // 缓存前缀顺序为 tools -> system -> messages
+    let tools_tokens = context_window::estimate_tools_tokens(if tools.is_empty() { None } else { Some(tools) });
+    let mut prefix_tokens = Vec::with_capacity(messages.len());
+    let mut total = tools_tokens;
+    for message in messages.iter() {
+        total += context_window::estimate_message_tokens(message);
+        prefix_tokens.push(total);
+    }
+
+    let cacheable = |index: usize, messages: &[ai::ChatMessage]| {
+        prefix_tokens[index] >= MIN_CACHEABLE_PREFIX_TOKENS
+            && messages[index].content.as_deref().is_some_and(|c| !c.is_empty())
+    };
+    let mut selected: Vec<(usize, &'static str)> = Vec::new();
+
+    // 1. system prompt（同时覆盖工具定义）
+    let system_index = messages.iter().rposition(|m| m.role == "system");
+    if let Some(index) = system_index.filter(|&i| cacheable(i, messages)) {
+        selected.push((index, "system"));
+    } else if system_index.is_none() && !tools.is_empty() && tools_tokens >= MIN_CACHEABLE_PREFIX_TOKENS {
+        // 2. 没有 system prompt 时单独缓存工具定义
+        if let Some(last_tool) = tools.last_mut() {
+            last_tool.cache_control = Some(ai::CacheControl::ephemeral());
+            plan.breakpoints.push(CacheBreakpoint {
+                kind: "tools",
+                message_index: None,
+                prefix_tokens: tools_tokens,
+            });
+        }
+    }
+
+    // 3. 最近轮次：最后一条消息和上一个用户消息，使下一次请求能复用完整前缀
+    let mut recent = Vec::new();
+    if let Some(last) = messages.len().checked_sub(1) {
+        recent.push(last);
+        if let Some(previous_user) = messages[..last].iter().rposition(|m| m.role == "user") {
+            recent.push(previous_user);
+        }
+    }
+    for index in recent.into_iter().take(RECENT_TURN_BREAKPOINTS) {
+        let budget = MAX_CACHE_BREAKPOINTS - plan.breakpoints.len();
+        if selected.len() < budget && cacheable(index, messages) && !selected.iter().any(|(i, _)| *i == index) {
+            selected.push((index, "recent"));
+        }
+    }
+
+    // 4. 剩余名额给大负载消息（从后往前，越靠后的断点覆盖的前缀越长）
+    let payload_candidates: Vec<usize> = (0..messages.len())
+        .rev()
+        .filter(|&i| messages[i].role != "system")
+        .filter(|&i| context_window::estimate_message_tokens(&messages[i]) >= LARGE_PAYLOAD_TOKENS)
+        .collect();
+    for index in payload_candidates {
+        if selected.len() + plan.breakpoints.len() >= MAX_CACHE_BREAKPOINTS {
+            break;
+        }
+        if cacheable(index, messages) && !selected.iter().any(|(i, _)| *i == index) {
+            selected.push((index, "payload"));
+        }
+    }
+
+    selected.sort_by_key(|(index, _)| *index);
+    for (index, kind) in selected {
+        messages[index].cache_control = Some(ai::CacheControl::ephemeral());
+        plan.breakpoints.push(CacheBreakpoint {
+            kind,
+            message_index: Some(index),
+            prefix_tokens: prefix_tokens[index],
+        });
+    }
+
+    plan
+}
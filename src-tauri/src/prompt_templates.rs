@@ -0,0 +1,190 @@
+use crate::{db, PromptTemplate};
+use chrono::Utc;
+use std::path::PathBuf;
+
+// 模板类型
+pub const KIND_COMPRESSION: &str = "compression";
+pub const KIND_TOPICS: &str = "topics";
+pub const KIND_TITLE: &str = "title";
+
+// 没有对应语言的模板时使用的语言
+const FALLBACK_LANGUAGE: &str = "zh";
+const SECONDARY_LANGUAGE: &str = "en";
+
+const COMPRESSION_SYSTEM_ZH: &str = "你是一个专业的视频转写内容压缩助手。你的任务是将长视频的转写内容压缩成简洁的摘要，同时保留关键信息。\n\n要求：\n1. 保留所有时间戳信息（格式：[HH:MM:SS]）\n2. 保留每个时间段的主要内容要点\n3. 对于相似或重复的内容，进行合并\n4. 保持时间顺序\n5. 压缩后的内容应该保留原始内容的 20-30% 左右\n6. 输出格式：每行一个时间段，格式为 [时间戳] 内容摘要\n7. 如果某个时间段内容不重要，可以省略，但重要内容必须保留";
+const COMPRESSION_USER_ZH: &str = "请压缩以下转写内容（总时长: {{duration}} 秒，共 {{segment_count}} 个片段）：\n\n{{transcript}}";
+
+const COMPRESSION_SYSTEM_EN: &str = "You are a professional assistant for condensing video transcripts. Your task is to compress the transcript of a long video into a concise summary while keeping the key information.\n\nRequirements:\n1. Keep all timestamps (format: [HH:MM:SS])\n2. Keep the main points of each time span\n3. Merge similar or repeated content\n4. Preserve chronological order\n5. The compressed content should be roughly 20-30% of the original\n6. Output format: one time span per line, as [timestamp] summary\n7. Unimportant spans may be omitted, but important content must be kept\n8. Write the summary in {{language}}";
+const COMPRESSION_USER_EN: &str = "Compress the following transcript (total duration: {{duration}} seconds, {{segment_count}} segments):\n\n{{transcript}}";
+
+const TOPICS_SYSTEM_ZH: &str = "你是一个专业的视频内容分析助手。你的任务是从视频转写内容中提取相关的 topics（主题），并为每个 topic 标注时间范围。\n\n要求：\n1. 分析转写内容，识别出几个主要的 topics（主题）\n2. 为每个 topic 从以下10种预定义颜色中选择一个唯一的颜色（按顺序分配，第一个 topic 用第一种颜色，第二个 topic 用第二种颜色，以此类推）：\n   - #3B82F6 (蓝色)\n   - #10B981 (绿色)\n   - #F59E0B (橙色)\n   - #EF4444 (红色)\n   - #8B5CF6 (紫色)\n   - #EC4899 (粉色)\n   - #06B6D4 (青色)\n   - #84CC16 (黄绿色)\n   - #F97316 (橙红色)\n   - #6366F1 (靛蓝色)\n3. 每个 topic 的透明度设置为 0.6（用于叠加显示）\n4. 为每个 topic 提取对应的时间范围（可能有多个时间范围）\n5. 时间范围使用秒数（浮点数），与转写结果中的 offsets 字段格式一致\n6. 如果同一个 topic 的多个时间范围是连续的或接近的（间隔小于 5 秒），应该将这些时间范围合并为一个连续的时间范围\n7. 输出格式必须是有效的 JSON，格式如下：\n{\n  \"topics\": [\n    {\n      \"name\": \"topic 名称\",\n      \"color\": \"#3B82F6\",\n      \"opacity\": 0.6,\n      \"time_ranges\": [\n        {\"start\": 10.5, \"end\": 45.2},\n        {\"start\": 120.3, \"end\": 180.7}\n      ]\n    }\n  ]\n}\n8. 确保严格按照颜色列表的顺序为 topics 分配颜色，每个 topic 都有唯一的颜色";
+const TOPICS_USER_ZH: &str = "请从以下转写内容中提取 topics：\n\n{{transcript}}";
+
+const TOPICS_SYSTEM_EN: &str = "You are a professional video content analyst. Your task is to extract the main topics from a video transcript and mark the time ranges of each topic.\n\nRequirements:\n1. Analyze the transcript and identify the main topics\n2. Assign each topic a unique color from the following 10 predefined colors, in order (the first topic gets the first color, the second topic the second color, and so on):\n   - #3B82F6 (blue)\n   - #10B981 (green)\n   - #F59E0B (orange)\n   - #EF4444 (red)\n   - #8B5CF6 (purple)\n   - #EC4899 (pink)\n   - #06B6D4 (cyan)\n   - #84CC16 (lime)\n   - #F97316 (orange red)\n   - #6366F1 (indigo)\n3. Set the opacity of every topic to 0.6 (used for overlay display)\n4. Extract the time ranges of each topic (there may be several)\n5. Time ranges are in seconds (floating point), matching the offsets field of the transcript\n6. If several time ranges of the same topic are contiguous or close (less than 5 seconds apart), merge them into one continuous range\n7. Write topic names in {{language}}\n8. The output must be valid JSON in the following format:\n{\n  \"topics\": [\n    {\n      \"name\": \"topic name\",\n      \"color\": \"#3B82F6\",\n      \"opacity\": 0.6,\n      \"time_ranges\": [\n        {\"start\": 10.5, \"end\": 45.2},\n        {\"start\": 120.3, \"end\": 180.7}\n      ]\n    }\n  ]\n}\n9. Strictly follow the order of the color list so that every topic has a unique color";
+const TOPICS_USER_EN: &str = "Extract topics from the following transcript:\n\n{{transcript}}";
+
+const TITLE_SYSTEM_ZH: &str = "请根据以下对话内容，生成一个简洁的标题，纯文本，不超过 20 个字符。只返回标题，不要包含其他内容，不包含emoji。";
+const TITLE_SYSTEM_EN: &str = "Based on the following conversation, write a concise plain-text title in {{language}}, no longer than 8 words. Return only the title, without any other content or emoji.";
+
+// 内置默认模板（标题模板的 user_prompt 为空，表示直接发送对话消息）
+const DEFAULT_TEMPLATES: &[(&str, &str, &str, &str)] = &[
+    (KIND_COMPRESSION, "zh", COMPRESSION_SYSTEM_ZH, COMPRESSION_USER_ZH),
+    (KIND_COMPRESSION, "en", COMPRESSION_SYSTEM_EN, COMPRESSION_USER_EN),
+    (KIND_TOPICS, "zh", TOPICS_SYSTEM_ZH, TOPICS_USER_ZH),
+    (KIND_TOPICS, "en", TOPICS_SYSTEM_EN, TOPICS_USER_EN),
+    (KIND_TITLE, "zh", TITLE_SYSTEM_ZH, ""),
+    (KIND_TITLE, "en", TITLE_SYSTEM_EN, ""),
+];
+
+pub fn validate_kind(kind: &str) -> Result<(), String> {
+    match kind {
+        KIND_COMPRESSION | KIND_TOPICS | KIND_TITLE => Ok(()),
+        _ => Err(format!("不支持的模板类型: {}", kind)),
+    }
+}
+
+pub fn default_templates() -> Vec<PromptTemplate> {
+    let now = Utc::now().to_rfc3339();
+    DEFAULT_TEMPLATES
+        .iter()
+        .map(|(kind, language, system_prompt, user_prompt)| PromptTemplate {
+            kind: kind.to_string(),
+            language: language.to_string(),
+            system_prompt: system_prompt.to_string(),
+            user_prompt: user_prompt.to_string(),
+            is_customized: false,
+            updated_at: now.clone(),
+        })
+        .collect()
+}
+
+pub fn default_template(kind: &str, language: &str) -> Option<PromptTemplate> {
+    default_templates()
+        .into_iter()
+        .find(|t| t.kind == kind && t.language == language)
+}
+
+// 规范化语言代码（"en-US" -> "en"），"auto" 或空值返回 None
+pub fn normalize_language(language: Option<&str>) -> Option<String> {
+    let language = language?.trim().to_lowercase();
+    let primary = language.split(['-', '_']).next().unwrap_or_default().to_string();
+    if primary.is_empty() || primary == "auto" {
+        None
+    } else {
+        Some(primary)
+    }
+}
+
+// 根据文本粗略判断语言（只区分中文和其他语言）
+pub fn detect_language(text: &str) -> String {
+    let mut cjk = 0usize;
+    let mut letters = 0usize;
+    for c in text.chars().take(2000) {
+        if ('\u{4e00}'..='\u{9fff}').contains(&c) {
+            cjk += 1;
+        } else if c.is_alphabetic() {
+            letters += 1;
+        }
+    }
+    // 一个汉字大致相当于一个英文单词（约 4 个字母）
+    if cjk * 4 >= letters && cjk > 0 {
+        "zh".to_string()
+    } else {
+        SECONDARY_LANGUAGE.to_string()
+    }
+}
+
+// 用于 {{language}} 变量的语言名称
+pub fn language_name(language: &str) -> String {
+    match language {
+        "zh" => "中文",
+        "en" => "English",
+        "ja" => "日本語",
+        "ko" => "한국어",
+        "fr" => "Français",
+        "de" => "Deutsch",
+        "es" => "Español",
+        "ru" => "Русский",
+        "pt" => "Português",
+        "it" => "Italiano",
+        other => other,
+    }
+    .to_string()
+}
+
+// 替换模板中的 {{变量}}
+// 从左到右单次扫描，变量值原样写入、不再二次展开（转写文本中可能包含 {{...}}）
+// 未知变量保留原文
+pub fn render(template: &str, vars: &[(&str, &str)]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after.find("}}").and_then(|end| {
+            let name = &after[..end];
+            vars.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, value)| (*value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                result.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                result.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+// 选出的模板及其对应的内容语言
+pub struct ResolvedTemplate {
+    pub template: PromptTemplate,
+    pub language: String, // 内容语言代码（不一定等于模板语言）
+}
+
+impl ResolvedTemplate {
+    pub fn language_name(&self) -> String {
+        language_name(&self.language)
+    }
+}
+
+// 按语言选择模板：优先精确匹配，其次英文模板（非中文内容），最后中文模板
+// language 为空或 auto 时根据 sample_text 判断语言
+pub async fn resolve(
+    db_path: PathBuf,
+    kind: &str,
+    language: Option<&str>,
+    sample_text: &str,
+) -> Result<ResolvedTemplate, String> {
+    let language = normalize_language(language).unwrap_or_else(|| detect_language(sample_text));
+    let kind = kind.to_string();
+
+    let mut candidates = vec![language.clone()];
+    if language != FALLBACK_LANGUAGE && language != SECONDARY_LANGUAGE {
+        candidates.push(SECONDARY_LANGUAGE.to_string());
+    }
+    candidates.push(FALLBACK_LANGUAGE.to_string());
+
+    let template = tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        for candidate in &candidates {
+            if let Some(template) = db::get_prompt_template(&conn, &kind, candidate)
+                .map_err(|e| format!("无法读取提示词模板: {}", e))?
+            {
+                return Ok(template);
+            }
+        }
+        default_template(&kind, FALLBACK_LANGUAGE).ok_or_else(|| format!("找不到 {} 提示词模板", kind))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))??;
+
+    Ok(ResolvedTemplate { template, language })
+}
@@ -0,0 +1,137 @@
+use crate::{seconds_to_timestamp, Chapter, TranscriptionResource};
+use serde_json::Value;
+use std::path::Path;
+
+// yt-dlp --dump-json 输出中的视频元数据
+#[derive(Debug, Clone, Default)]
+pub struct UrlMetadata {
+    pub title: Option<String>,
+    pub channel: Option<String>,
+    pub upload_date: Option<String>, // YYYY-MM-DD
+    pub duration: Option<f64>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub chapters: Vec<Chapter>,
+    pub thumbnail: Option<String>,
+}
+
+// 读取 JSON 中非空的字符串字段（去除首尾空白），playlist 和 media_probe 也使用
+pub(crate) fn get_str(value: &Value, key: &str) -> Option<String> {
+    value
+        .get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+// yt-dlp 的日期格式为 YYYYMMDD
+fn format_upload_date(date: &str) -> Option<String> {
+    if date.len() == 8 && date.chars().all(|c| c.is_ascii_digit()) {
+        Some(format!("{}-{}-{}", &date[0..4], &date[4..6], &date[6..8]))
+    } else {
+        None
+    }
+}
+
+// 解析 --dump-json 输出
+pub fn parse(value: &Value) -> UrlMetadata {
+    let tags = value
+        .get("tags")
+        .and_then(|t| t.as_array())
+        .map(|tags| {
+            tags.iter()
+                .filter_map(|t| t.as_str())
+                .map(|t| t.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let chapters = value
+        .get("chapters")
+        .and_then(|c| c.as_array())
+        .map(|chapters| {
+            chapters
+                .iter()
+                .filter_map(|c| {
+                    Some(Chapter {
+                        title: get_str(c, "title")?,
+                        start_time: c.get("start_time")?.as_f64()?,
+                        end_time: c.get("end_time").and_then(|v| v.as_f64()),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    UrlMetadata {
+        title: get_str(value, "title"),
+        channel: get_str(value, "channel").or_else(|| get_str(value, "uploader")),
+        upload_date: get_str(value, "upload_date").and_then(|d| format_upload_date(&d)),
+        duration: value.get("duration").and_then(|v| v.as_f64()).filter(|d| *d > 0.0),
+        description: get_str(value, "description"),
+        tags,
+        chapters,
+        thumbnail: get_str(value, "thumbnail"),
+    }
+}
+
+// 运行一次 yt-dlp --dump-json 获取元数据（不下载视频）
+pub async fn fetch(ytdlp_path: &Path, url: &str) -> Result<UrlMetadata, String> {
+    let output = tokio::process::Command::new(ytdlp_path)
+        .arg("--dump-json")
+        .arg("--skip-download")
+        .arg("--no-playlist")
+        .arg("--no-warnings")
+        .arg(url)
+        .output()
+        .await
+        .map_err(|e| format!("无法执行 yt-dlp: {}。请确保 yt-dlp 已正确安装。", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("yt-dlp 获取视频信息失败: {}", stderr.trim()));
+    }
+
+    let value: Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("无法解析 yt-dlp 输出: {}", e))?;
+    Ok(parse(&value))
+}
+
+// 将元数据写入资源（不修改资源名称，已有的值不会被空值覆盖）
+pub fn apply(resource: &mut TranscriptionResource, metadata: &UrlMetadata) {
+    if metadata.channel.is_some() {
+        resource.uploader = metadata.channel.clone();
+    }
+    if metadata.upload_date.is_some() {
+        resource.upload_date = metadata.upload_date.clone();
+    }
+    if metadata.duration.is_some() {
+        resource.duration = metadata.duration;
+    }
+    if metadata.description.is_some() {
+        resource.description = metadata.description.clone();
+    }
+    if !metadata.tags.is_empty() {
+        resource.tags = Some(metadata.tags.clone());
+    }
+    if !metadata.chapters.is_empty() {
+        resource.chapters = Some(metadata.chapters.clone());
+    }
+    if resource.cover_url.is_none() {
+        resource.cover_url = metadata.thumbnail.clone();
+    }
+}
+
+// 将章节格式化为大纲（每行一个章节：[HH:MM:SS] 标题）
+pub fn format_outline(chapters: &[Chapter]) -> String {
+    chapters
+        .iter()
+        .map(|c| {
+            let timestamp = seconds_to_timestamp(c.start_time);
+            // seconds_to_timestamp 返回 HH:MM:SS,mmm，大纲只保留到秒
+            let timestamp = timestamp.split(',').next().unwrap_or(&timestamp).to_string();
+            format!("[{}] {}", timestamp, c.title)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,178 @@
+use crate::{db, AIConfig, UsageRecord};
+use chrono::Utc;
+use serde::Serialize;
+use rusqlite::Connection;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+// 调用来源
+pub const SOURCE_CHAT: &str = "chat";
+pub const SOURCE_AGENT: &str = "agent";
+pub const SOURCE_COMPRESSION: &str = "compression";
+pub const SOURCE_TOPICS: &str = "topics";
+pub const SOURCE_TITLE: &str = "title";
+pub const SOURCE_CONTEXT_SUMMARY: &str = "context_summary";
+
+// 统一后的 token 用量
+#[derive(Debug, Serialize, Clone, Copy, Default)]
+pub struct TokenUsage {
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cached_tokens: i64, // prompt 中命中缓存的部分
+    pub cache_write_tokens: i64, // prompt 中写入缓存的部分
+}
+
+fn get_i64(value: &Value, path: &[&str]) -> Option<i64> {
+    let mut current = value;
+    for key in path {
+        current = current.get(key)?;
+    }
+    current.as_i64()
+}
+
+// 解析响应中的 usage 字段，兼容 OpenAI、OpenRouter、DeepSeek 和 Anthropic 风格的字段名
+pub fn parse_usage(value: &Value) -> Option<TokenUsage> {
+    if !value.is_object() {
+        return None;
+    }
+
+    let completion_tokens = get_i64(value, &["completion_tokens"]).or_else(|| get_i64(value, &["output_tokens"]));
+
+    // Anthropic 风格的 input_tokens 不包含缓存读写部分，需要加回去
+    if let Some(input_tokens) = get_i64(value, &["input_tokens"]).filter(|_| value.get("prompt_tokens").is_none()) {
+        let cached_tokens = get_i64(value, &["cache_read_input_tokens"]).unwrap_or(0);
+        let cache_write_tokens = get_i64(value, &["cache_creation_input_tokens"]).unwrap_or(0);
+        return Some(TokenUsage {
+            prompt_tokens: input_tokens + cached_tokens + cache_write_tokens,
+            completion_tokens: completion_tokens.unwrap_or(0),
+            cached_tokens,
+            cache_write_tokens,
+        });
+    }
+
+    let prompt_tokens = get_i64(value, &["prompt_tokens"]);
+    if prompt_tokens.is_none() && completion_tokens.is_none() {
+        return None;
+    }
+
+    let cached_tokens = get_i64(value, &["prompt_tokens_details", "cached_tokens"])
+        .or_else(|| get_i64(value, &["prompt_cache_hit_tokens"]))
+        .or_else(|| get_i64(value, &["cache_read_input_tokens"]))
+        .unwrap_or(0);
+    let cache_write_tokens = get_i64(value, &["prompt_tokens_details", "cache_write_tokens"])
+        .or_else(|| get_i64(value, &["cache_creation_input_tokens"]))
+        .unwrap_or(0);
+
+    Some(TokenUsage {
+        prompt_tokens: prompt_tokens.unwrap_or(0),
+        completion_tokens: completion_tokens.unwrap_or(0),
+        cached_tokens,
+        cache_write_tokens,
+    })
+}
+
+// 按配置中的价格计算费用（价格单位：每百万 token），未配置价格时返回 None
+pub fn compute_cost(config: &AIConfig, usage: &TokenUsage) -> Option<f64> {
+    if config.input_price.is_none() && config.output_price.is_none() {
+        return None;
+    }
+    let input_price = config.input_price.unwrap_or(0.0);
+    let output_price = config.output_price.unwrap_or(0.0);
+    let cached_price = config.cached_input_price.unwrap_or(input_price);
+
+    let cached = usage.cached_tokens.clamp(0, usage.prompt_tokens.max(0));
+    let uncached = usage.prompt_tokens.max(0) - cached;
+    Some(
+        (uncached as f64 * input_price + cached as f64 * cached_price + usage.completion_tokens.max(0) as f64 * output_price)
+            / 1_000_000.0,
+    )
+}
+
+// 用量归属（消息、chat、任务阶段）
+#[derive(Debug, Clone, Default)]
+pub struct UsageScope {
+    pub chat_id: Option<String>,
+    pub message_id: Option<String>,
+    pub task_id: Option<String>,
+    pub resource_id: Option<String>,
+    pub event_id: Option<String>, // 流式对话的事件 ID，助手消息保存后按它关联 message_id
+}
+
+// 先于用量记录保存的助手消息（event_id -> message_id）
+// 前端在收到 done / tool_calls 事件后保存消息，此时最后一个数据块中的用量可能还没写入
+static PENDING_MESSAGE_LINKS: OnceLock<Mutex<HashMap<String, (String, Instant)>>> = OnceLock::new();
+// 没有返回用量的调用不会取走暂存的关联，超过该时长后丢弃
+const PENDING_LINK_TTL: Duration = Duration::from_secs(10 * 60);
+
+fn pending_message_links() -> &'static Mutex<HashMap<String, (String, Instant)>> {
+    PENDING_MESSAGE_LINKS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// 把流式对话的用量关联到保存后的助手消息（用量尚未写入时暂存，写入时再关联）
+pub fn link_message(conn: &Connection, event_id: &str, message_id: &str) -> rusqlite::Result<()> {
+    // 持有锁完成更新，避免与 record 中的写入交错
+    let mut links = pending_message_links().lock().unwrap_or_else(|e| e.into_inner());
+    links.retain(|_, (_, created_at)| created_at.elapsed() < PENDING_LINK_TTL);
+    if db::set_usage_message_id(conn, event_id, message_id)? == 0 {
+        links.insert(event_id.to_string(), (message_id.to_string(), Instant::now()));
+    }
+    Ok(())
+}
+
+// 保存一次调用的用量，失败时只打印日志，不影响主流程
+pub async fn record(db_path: PathBuf, config: &AIConfig, source: &str, scope: UsageScope, usage: TokenUsage) {
+    let record = UsageRecord {
+        id: Uuid::new_v4().to_string(),
+        config_id: config.id.clone(),
+        model: config.model.clone(),
+        source: source.to_string(),
+        chat_id: scope.chat_id,
+        message_id: scope.message_id,
+        task_id: scope.task_id,
+        resource_id: scope.resource_id,
+        prompt_tokens: usage.prompt_tokens,
+        completion_tokens: usage.completion_tokens,
+        cached_tokens: usage.cached_tokens,
+        cache_write_tokens: usage.cache_write_tokens,
+        cost: compute_cost(config, &usage),
+        created_at: Utc::now().to_rfc3339(),
+    };
+
+    let event_id = scope.event_id;
+    let result = tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+
+        // 任务阶段的用量同时归属到任务所属的资源
+        let mut record = record;
+        if record.resource_id.is_none() {
+            if let Some(task_id) = &record.task_id {
+                record.resource_id = db::get_task(&conn, task_id).ok().flatten().map(|t| t.resource_id);
+            }
+        }
+
+        let Some(event_id) = event_id.as_deref() else {
+            return db::create_usage_record(&conn, &record, None)
+                .map_err(|e| format!("无法保存用量记录: {}", e));
+        };
+
+        // 助手消息已经保存时直接关联
+        let mut links = pending_message_links().lock().unwrap_or_else(|e| e.into_inner());
+        if record.message_id.is_none() {
+            record.message_id = links.remove(event_id).map(|(message_id, _)| message_id);
+        }
+        db::create_usage_record(&conn, &record, Some(event_id))
+            .map_err(|e| format!("无法保存用量记录: {}", e))
+    })
+    .await;
+
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => eprintln!("[Usage] {}", e),
+        Err(e) => eprintln!("[Usage] 数据库操作失败: {}", e),
+    }
+}
@@ -0,0 +1,322 @@
+use crate::{db, detect_resource_type, ResourceType, TranscriptionParams, WatchFolder, WatchFolderFile, DUPLICATE_ACTION_LINK};
+use chrono::Utc;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::{mpsc, Notify};
+use tokio::time::{Duration, Instant};
+
+// 扫描间隔（秒）
+const SCAN_INTERVAL_SECS: u64 = 5;
+// 文件大小和修改时间保持不变多久后才认为写入完成（秒）
+const STABLE_SECS: u64 = 10;
+// 音频文件扩展名（视频由 detect_resource_type 识别）
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "wav", "m4a", "aac", "flac", "ogg", "opus", "wma", "amr"];
+
+// 文件处理状态
+pub const STATUS_PROCESSING: &str = "processing";
+pub const STATUS_COMPLETED: &str = "completed";
+pub const STATUS_FAILED: &str = "failed";
+pub const STATUS_SKIPPED: &str = "skipped";
+
+// 监视文件夹配置变化时唤醒扫描循环
+#[derive(Clone, Default)]
+pub struct WatchFolderMonitor {
+    wake: Arc<Notify>,
+}
+
+impl WatchFolderMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+}
+
+// 等待写入完成的文件
+struct Candidate {
+    size: u64,
+    modified: Option<SystemTime>,
+    unchanged_since: Instant,
+}
+
+// 待处理的文件
+struct Job {
+    folder_id: String,
+    params: TranscriptionParams,
+    path: PathBuf,
+}
+
+// 未指定参数时自动转写使用的默认参数
+pub fn default_params() -> TranscriptionParams {
+    TranscriptionParams {
+        model: Some("base".to_string()),
+        language: Some("zh".to_string()),
+        device: None,
+        compute_type: None,
+        beam_size: None,
+        best_of: None,
+        patience: None,
+        condition_on_previous_text: None,
+        initial_prompt: None,
+        word_timestamps: Some(true),
+        temperature: None,
+        compression_ratio_threshold: None,
+        log_prob_threshold: None,
+        no_speech_threshold: None,
+        translate: Some(false),
+        subtitle_languages: None,
+        allow_auto_subtitles: None,
+        preprocess: None,
+    }
+}
+
+// 是否为可转写的音视频文件（忽略隐藏文件和下载中的临时文件）
+fn is_media_file(path: &Path) -> bool {
+    let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    if file_name.starts_with('.') {
+        return false;
+    }
+    let Some(ext) = path.extension().and_then(|e| e.to_str()) else {
+        return false;
+    };
+    let ext = ext.to_lowercase();
+    let path_str = path.to_string_lossy();
+    matches!(detect_resource_type(&path_str), ResourceType::Video) || AUDIO_EXTENSIONS.contains(&ext.as_str())
+}
+
+// 列出文件夹中的音视频文件（不递归子文件夹）
+fn list_media_files(folder: &Path) -> Vec<(PathBuf, u64, Option<SystemTime>)> {
+    let Ok(entries) = std::fs::read_dir(folder) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let metadata = entry.metadata().ok()?;
+            if !metadata.is_file() || !is_media_file(&path) {
+                return None;
+            }
+            Some((path, metadata.len(), metadata.modified().ok()))
+        })
+        .collect()
+}
+
+// 将文件夹中已有的文件记录为已跳过（添加文件夹时只处理之后出现的新文件）
+pub fn mark_existing_files(conn: &rusqlite::Connection, folder: &WatchFolder) -> rusqlite::Result<usize> {
+    let files = list_media_files(Path::new(&folder.path));
+    let now = Utc::now().to_rfc3339();
+    let processed: HashSet<String> = db::get_watch_folder_file_paths(conn)?.into_iter().collect();
+    let mut count = 0;
+    for (path, _, _) in files {
+        let file_path = path.to_string_lossy().to_string();
+        if processed.contains(&file_path) {
+            continue;
+        }
+        db::upsert_watch_folder_file(conn, &WatchFolderFile {
+            file_path,
+            folder_id: folder.id.clone(),
+            status: STATUS_SKIPPED.to_string(),
+            resource_id: None,
+            task_id: None,
+            error: None,
+            processed_at: now.clone(),
+        })?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+async fn save_record(db_path: &Path, record: WatchFolderFile) -> Result<(), String> {
+    let db_path = db_path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let conn = db::init_database(&db_path)
+            .map_err(|e| format!("无法初始化数据库: {}", e))?;
+        db::upsert_watch_folder_file(&conn, &record)
+            .map_err(|e| format!("无法保存文件处理记录: {}", e))
+    })
+    .await
+    .map_err(|e| format!("数据库操作失败: {}", e))?
+}
+
+// 扫描所有启用的文件夹，把写入完成的新文件加入处理队列
+async fn scan(
+    app: &AppHandle,
+    db_path: &Path,
+    candidates: &mut HashMap<PathBuf, Candidate>,
+    jobs: &mpsc::UnboundedSender<Job>,
+) -> Result<(), String> {
+    let found = tokio::task::spawn_blocking({
+        let db_path = db_path.to_path_buf();
+        move || -> Result<Vec<(WatchFolder, PathBuf, u64, Option<SystemTime>)>, String> {
+            let conn = db::init_database(&db_path)
+                .map_err(|e| format!("无法初始化数据库: {}", e))?;
+            let folders = db::get_watch_folders(&conn)
+                .map_err(|e| format!("无法读取监视文件夹: {}", e))?;
+            let processed: HashSet<String> = db::get_watch_folder_file_paths(&conn)
+                .map_err(|e| format!("无法读取文件处理记录: {}", e))?
+                .into_iter()
+                .collect();
+            let mut found = Vec::new();
+            for folder in folders.into_iter().filter(|f| f.enabled) {
+                for (path, size, modified) in list_media_files(Path::new(&folder.path)) {
+                    if !processed.contains(path.to_string_lossy().as_ref()) {
+                        found.push((folder.clone(), path, size, modified));
+                    }
+                }
+            }
+            Ok(found)
+        }
+    })
+    .await
+    .map_err(|e| format!("扫描监视文件夹失败: {}", e))??;
+
+    let seen: HashSet<PathBuf> = found.iter().map(|(_, path, _, _)| path.clone()).collect();
+    candidates.retain(|path, _| seen.contains(path));
+
+    for (folder, path, size, modified) in found {
+        let candidate = candidates.entry(path.clone()).or_insert_with(|| Candidate {
+            size,
+            modified,
+            unchanged_since: Instant::now(),
+        });
+        if candidate.size != size || candidate.modified != modified {
+            candidate.size = size;
+            candidate.modified = modified;
+            candidate.unchanged_since = Instant::now();
+            continue;
+        }
+        if size == 0 || candidate.unchanged_since.elapsed() < Duration::from_secs(STABLE_SECS) {
+            continue;
+        }
+
+        // 先写入处理记录，避免下次扫描或重启后重复处理
+        candidates.remove(&path);
+        let record = WatchFolderFile {
+            file_path: path.to_string_lossy().to_string(),
+            folder_id: folder.id.clone(),
+            status: STATUS_PROCESSING.to_string(),
+            resource_id: None,
+            task_id: None,
+            error: None,
+            processed_at: Utc::now().to_rfc3339(),
+        };
+        save_record(db_path, record.clone()).await?;
+        let _ = app.emit("watch-folder-file-updated", &record);
+        let _ = jobs.send(Job {
+            folder_id: folder.id,
+            params: folder.params,
+            path,
+        });
+    }
+    Ok(())
+}
+
+// 处理一个文件：创建资源（内容重复时关联已有资源）、提取视频音频、创建并执行转写任务
+async fn process(app: &AppHandle, job: &Job, record: &mut WatchFolderFile) -> Result<(), String> {
+    let file_path = job.path.to_string_lossy().to_string();
+    let name = job
+        .path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| file_path.clone());
+
+    // 内容相同的文件已导入过时直接使用已有资源
+    let resource = crate::create_transcription_resource(
+        name,
+        file_path,
+        None,
+        Some(DUPLICATE_ACTION_LINK.to_string()),
+        app.clone(),
+    )
+    .await?;
+    record.resource_id = Some(resource.id.clone());
+    let _ = app.emit("watch-folder-file-updated", &*record);
+
+    // 已有资源已经转写完成时不再重复转写
+    if let Some(task_id) = resource.latest_completed_task_id.clone() {
+        record.task_id = Some(task_id);
+        let _ = app.emit("watch-folder-file-updated", &*record);
+        return Ok(());
+    }
+
+    if matches!(resource.resource_type, ResourceType::Video) {
+        crate::extract_audio_from_video(resource.id.clone(), app.clone()).await?;
+    }
+
+    let task = crate::create_transcription_task(resource.id.clone(), job.params.clone(), app.clone()).await?;
+    record.task_id = Some(task.id.clone());
+    let _ = app.emit("watch-folder-file-updated", &*record);
+
+    crate::execute_transcription_task(task.id, resource.id, app.clone()).await?;
+    Ok(())
+}
+
+// 按顺序处理队列中的文件
+async fn run_worker(app: AppHandle, db_path: PathBuf, mut jobs: mpsc::UnboundedReceiver<Job>) {
+    while let Some(job) = jobs.recv().await {
+        let mut record = WatchFolderFile {
+            file_path: job.path.to_string_lossy().to_string(),
+            folder_id: job.folder_id.clone(),
+            status: STATUS_PROCESSING.to_string(),
+            resource_id: None,
+            task_id: None,
+            error: None,
+            processed_at: Utc::now().to_rfc3339(),
+        };
+        eprintln!("[Watch Folder] 开始处理: {}", record.file_path);
+
+        match process(&app, &job, &mut record).await {
+            Ok(()) => record.status = STATUS_COMPLETED.to_string(),
+            Err(e) => {
+                eprintln!("[Watch Folder] 处理失败 {}: {}", record.file_path, e);
+                record.status = STATUS_FAILED.to_string();
+                record.error = Some(e);
+            }
+        }
+        record.processed_at = Utc::now().to_rfc3339();
+
+        if let Err(e) = save_record(&db_path, record.clone()).await {
+            eprintln!("[Watch Folder] {}", e);
+        }
+        let _ = app.emit("watch-folder-file-updated", &record);
+    }
+}
+
+// 启动监视文件夹扫描
+// 上次退出时未处理完的文件（processing）不会重新处理，避免重复创建资源
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let monitor = app.state::<WatchFolderMonitor>().inner().clone();
+        let app_data_dir = match app.path().app_data_dir() {
+            Ok(dir) => dir,
+            Err(e) => {
+                eprintln!("[Watch Folder] 无法获取应用数据目录: {}", e);
+                return;
+            }
+        };
+        let db_path = db::get_db_path(&app_data_dir);
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tauri::async_runtime::spawn(run_worker(app.clone(), db_path.clone(), receiver));
+
+        let mut candidates = HashMap::new();
+        loop {
+            if let Err(e) = scan(&app, &db_path, &mut candidates, &sender).await {
+                eprintln!("[Watch Folder] {}", e);
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(SCAN_INTERVAL_SECS)) => {}
+                _ = monitor.wake.notified() => {}
+            }
+        }
+    });
+}
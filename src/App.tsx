@@ -2,6 +2,7 @@ import "./App.css";
 import Layout from "./components/Layout";
 import { ToastProvider } from "./components/Toast";
 import ToastContainer from "./components/Toast/ToastContainer";
+import MCPToolApprovalModal from "./components/MCPToolApprovalModal";
 
 function App() {
   return (
@@ -9,6 +10,7 @@ function App() {
       <div className="h-full w-full">
         <Layout />
         <ToastContainer />
+        <MCPToolApprovalModal />
       </div>
     </ToastProvider>
   );
@@ -6,7 +6,7 @@ import { convertAIMessagesToChatMessages } from '../utils/aiMessageUtils';
 
 export class TauriAgentBackend implements IAgentBackend {
   async chatCompletion(options: IChatCompletionOptions): Promise<void> {
-    const { configId, messages, tools, systemMessage, eventId } = options;
+    const { configId, messages, tools, systemMessage, eventId, chatId } = options;
     
     // Convert framework messages to chat messages expected by backend
     // We assume convertAIMessagesToChatMessages handles the framework AIMessage type 
@@ -19,6 +19,7 @@ export class TauriAgentBackend implements IAgentBackend {
       tools: tools && tools.length > 0 ? tools : null,
       systemMessage,
       eventId,
+      chatId: chatId || null,
     });
   }
 
@@ -34,10 +35,11 @@ export class TauriAgentBackend implements IAgentBackend {
       arguments: args,
       currentResourceId: context?.currentResourceId || null,
       currentTaskId: context?.currentTaskId || null,
+      chatId: context?.chatId || null,
     });
   }
 
-  async saveMessage(message: AIMessage, chatId: string): Promise<void> {
+  async saveMessage(message: AIMessage, chatId: string, eventId?: string): Promise<void> {
     await invoke('save_message', {
       chatId,
       role: message.role,
@@ -46,6 +48,7 @@ export class TauriAgentBackend implements IAgentBackend {
       toolCallId: message.tool_call_id || null,
       name: message.name || null,
       reasoning: message.reasoning || null,
+      eventId: eventId || null,
       // Note: cache_control is not saved to database as it's only used for API requests
     });
   }
@@ -135,7 +135,7 @@ export class AgentWorkflowEngine {
                   tool_calls: finalToolCalls,
                   reasoning: hasValidReasoning ? finalReasoning : undefined
               };
-              this.backend.saveMessage(msgToSave, chatId).catch(console.error);
+              this.backend.saveMessage(msgToSave, chatId, eventId).catch(console.error);
               
               resolve({
                 content: finalContent,
@@ -155,7 +155,8 @@ export class AgentWorkflowEngine {
             messages,
             tools: toolsToSend || undefined,
             systemMessage: combinedSystemMessage,
-            eventId
+            eventId,
+            chatId
           });
         } catch (error) {
           if (cleanup) cleanup();
@@ -588,7 +589,8 @@ export class AgentWorkflowEngine {
           args,
           {
             currentResourceId: context?.currentResourceId,
-            currentTaskId: context?.currentTaskId
+            currentTaskId: context?.currentTaskId,
+            chatId
           }
         );
 
@@ -1,17 +1,18 @@
 import { useState, useEffect, useRef } from "react";
 import { useAppDispatch, useAppSelector } from "../redux/hooks";
 import { setCurrentFeature, setCurrentPage } from "../redux/slices/featureKeysSlice";
-import { loadMCPConfigs } from "../redux/slices/mcpSlice";
+import { loadMCPConfigs, updateServer } from "../redux/slices/mcpSlice";
 import { loadAIConfigs } from "../redux/slices/aiConfigSlice";
 import { getCurrentWindow } from "@tauri-apps/api/window";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import AppSideBar from "./AppSideBar";
 import AppContent from "./AppContent";
 import SidePanel from "./SidePanel";
 import { FaBars } from "react-icons/fa";
 import { PanelGroup, Panel, PanelResizeHandle } from "react-resizable-panels";
 import { useToast } from "./Toast/useToast";
-import { TranscriptionResource } from "../models";
+import { TranscriptionResource, MCPServerInfo } from "../models";
 
 // 支持转写的文件扩展名
 const SUPPORTED_AUDIO_EXTENSIONS = ['mp3', 'wav', 'm4a', 'flac', 'ogg', 'aac', 'wma'];
@@ -55,6 +56,16 @@ const Layout = () => {
     dispatch(loadAIConfigs());
   }, [dispatch]);
 
+  // 后台健康检查发现 MCP 服务器状态或工具列表变化时更新
+  useEffect(() => {
+    const unlistenPromise = listen<MCPServerInfo>("mcp-status-changed", (event) => {
+      dispatch(updateServer(event.payload));
+    });
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+    };
+  }, [dispatch]);
+
   // 全局文件拖放监听
   useEffect(() => {
     // 防止在 React StrictMode 下重复注册监听器
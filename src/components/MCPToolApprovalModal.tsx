@@ -0,0 +1,75 @@
+import { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+
+// 后端 mcp-tool-approval-request 事件（权限策略为 ask 的工具调用）
+interface MCPToolApprovalRequest {
+  request_id: string;
+  server_name: string;
+  tool_name: string;
+  arguments: unknown;
+}
+
+// 全局的 MCP 工具调用批准弹窗，多个请求按到达顺序依次处理
+const MCPToolApprovalModal = () => {
+  const [requests, setRequests] = useState<MCPToolApprovalRequest[]>([]);
+  const [responding, setResponding] = useState(false);
+
+  useEffect(() => {
+    const unlistenPromise = listen<MCPToolApprovalRequest>('mcp-tool-approval-request', (event) => {
+      setRequests((prev) => [...prev, event.payload]);
+    });
+    // 后端已处理（批准、拒绝或超时）的请求从队列中移除
+    const unlistenResolvedPromise = listen<{ request_id: string }>('mcp-tool-approval-resolved', (event) => {
+      setRequests((prev) => prev.filter((r) => r.request_id !== event.payload.request_id));
+    });
+    return () => {
+      unlistenPromise.then((unlisten) => unlisten());
+      unlistenResolvedPromise.then((unlisten) => unlisten());
+    };
+  }, []);
+
+  const current = requests[0];
+  if (!current) return null;
+
+  const respond = async (approved: boolean) => {
+    setResponding(true);
+    try {
+      await invoke('respond_mcp_tool_approval', { requestId: current.request_id, approved });
+    } catch (err) {
+      // 请求已超时或已被处理
+      console.error('响应工具调用批准请求失败:', err);
+    } finally {
+      setRequests((prev) => prev.filter((r) => r.request_id !== current.request_id));
+      setResponding(false);
+    }
+  };
+
+  return (
+    <div className="modal modal-open">
+      <div className="modal-box max-w-2xl">
+        <h3 className="font-bold text-lg">工具调用需要批准</h3>
+        <p className="py-2 text-sm text-base-content/70">
+          MCP 服务器 <span className="font-medium text-base-content">{current.server_name}</span> 请求调用工具{' '}
+          <span className="font-medium text-base-content">{current.tool_name}</span>
+        </p>
+        <pre className="bg-base-200 rounded p-3 text-xs max-h-64 overflow-auto whitespace-pre-wrap break-all">
+          {JSON.stringify(current.arguments, null, 2)}
+        </pre>
+        {requests.length > 1 && (
+          <p className="text-xs text-base-content/50 mt-2">还有 {requests.length - 1} 个请求等待处理</p>
+        )}
+        <div className="modal-action">
+          <button className="btn" onClick={() => respond(false)} disabled={responding}>
+            拒绝
+          </button>
+          <button className="btn btn-primary" onClick={() => respond(true)} disabled={responding}>
+            允许
+          </button>
+        </div>
+      </div>
+    </div>
+  );
+};
+
+export default MCPToolApprovalModal;
@@ -39,7 +39,7 @@ export interface UseCommandExecutionReturn {
   error: string | null;
   // 清除输出
   clear: () => void;
-  // 停止命令（如果支持）
+  // 停止命令
   stop: () => void;
 }
 
@@ -51,7 +51,7 @@ export interface UseCommandExecutionReturn {
  * const { execute, stdout, stderr, isRunning, result } = useCommandExecution();
  * 
  * const handleRun = async () => {
- *   await execute('ls', ['-la'], '/path/to/dir');
+ *   await execute('ffmpeg', ['-version']);
  * };
  * ```
  */
@@ -93,8 +93,14 @@ export function useCommandExecution(
     setError(null);
   }, []);
 
-  // 停止命令（目前只是清理，实际停止需要后端支持）
+  // 停止命令（通知后端终止进程）
   const stop = useCallback(() => {
+    const eventId = currentEventIdRef.current;
+    if (eventId) {
+      invoke<boolean>('cancel_command', { eventId }).catch((err) => {
+        console.error('取消命令失败:', err);
+      });
+    }
     cleanup();
     setIsRunning(false);
   }, [cleanup]);
@@ -136,6 +136,7 @@ export function useStreamResponse() {
               toolCallId: null,
               name: null,
               reasoning: hasValidReasoning ? finalReasoning : null,
+              eventId,
             }).catch((err) => {
               console.error('保存助手消息失败:', err)
             })
@@ -142,6 +142,7 @@ export function useToolCalls({
           tools: tools.length > 0 ? tools : null,
           systemMessage: systemMessage,
           eventId: eventId,
+          chatId: currentChatId,
         })
       } catch (err) {
         console.error('AI 对话失败:', err)
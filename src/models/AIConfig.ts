@@ -7,5 +7,38 @@ export interface AIConfig {
   created_at: string
   updated_at: string
   is_compression_config?: boolean
+  context_window?: number | null
+  input_price?: number | null
+  output_price?: number | null
+  cached_input_price?: number | null
+}
+
+export interface UsageRecord {
+  id: string
+  config_id: string
+  model: string
+  source: 'chat' | 'agent' | 'compression' | 'topics' | 'title' | 'context_summary'
+  chat_id: string | null
+  message_id: string | null
+  task_id: string | null
+  resource_id: string | null
+  prompt_tokens: number
+  completion_tokens: number
+  cached_tokens: number
+  cache_write_tokens: number
+  cost: number | null
+  created_at: string
+}
+
+export interface UsageReportRow {
+  key: string
+  label: string | null
+  call_count: number
+  prompt_tokens: number
+  completion_tokens: number
+  cached_tokens: number
+  cache_miss_tokens: number
+  cache_write_tokens: number
+  cost: number | null
 }
 
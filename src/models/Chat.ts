@@ -12,6 +12,14 @@ export interface ChatListItem {
   updated_at: string
   last_message_at: string | null
   message_count: number
+  contexts: ChatContext[]
+}
+
+export interface ChatContext {
+  chat_id: string
+  context_type: 'resource' | 'task'
+  context_id: string
+  created_at: string
 }
 
 export interface Message {
@@ -24,5 +32,6 @@ export interface Message {
   name: string | null
   reasoning: string | null
   created_at: string
+  parent_message_id: string | null
 }
 
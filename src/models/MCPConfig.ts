@@ -83,5 +83,8 @@ export interface MCPServerInfo {
   tools?: MCPTool[]
   error?: string
   is_default?: boolean // 是否为系统默认服务
+  latency_ms?: number // 最近一次健康检查的延迟
+  last_error?: string // 最近一次出现的错误
+  last_checked_at?: string // 最近一次健康检查时间
 }
 
@@ -0,0 +1,10 @@
+export type PromptTemplateKind = 'compression' | 'topics' | 'title'
+
+export interface PromptTemplate {
+  kind: PromptTemplateKind
+  language: string
+  system_prompt: string
+  user_prompt: string
+  is_customized: boolean
+  updated_at: string
+}
@@ -31,7 +31,45 @@ export interface Topic {
   time_ranges: TopicTimeRange[]; // 时间范围列表
 }
 
+// 章节标记（视频作者提供的章节）
+export interface Chapter {
+  title: string;
+  start_time: number; // 开始时间（秒）
+  end_time?: number; // 结束时间（秒）
+}
+
 // 转写资源模型
+// 导入时探测的媒体信息
+export interface MediaInfo {
+  container?: string; // 容器格式
+  duration?: number; // 时长（秒）
+  has_audio: boolean; // 是否包含音频流
+  has_video: boolean; // 是否包含视频流（不含音频文件的封面图片）
+  audio_codec?: string;
+  video_codec?: string;
+  sample_rate?: number; // 音频采样率（Hz）
+  channels?: number; // 音频声道数
+}
+
+// 音频提取状态
+export enum ExtractionStatus {
+  EXTRACTING = 'extracting',
+  COMPLETED = 'completed',
+  FAILED = 'failed',
+}
+
+// 音频提取进度（extraction-progress-{resource_id} 事件）
+export interface ExtractionProgress {
+  resource_id: string;
+  status: ExtractionStatus;
+  processed_seconds: number; // 已处理的媒体时长（秒）
+  duration?: number; // 媒体总时长（秒），无法探测时为空
+  percent?: number; // 0-100，总时长未知时为空
+  speed?: number; // 处理速度（相对实时播放的倍数）
+  eta_seconds?: number; // 预计剩余时间（秒）
+  error?: string;
+}
+
 export interface TranscriptionResource {
   id: string; // 唯一标识符
   name: string; // 资源名称
@@ -42,7 +80,49 @@ export interface TranscriptionResource {
   extracted_audio_path?: string; // 提取的音频路径（仅视频资源有）
   latest_completed_task_id?: string; // 最新一条转写成功的任务 ID
   cover_url?: string; // 封面 URL（仅URL资源有）
+  uploader?: string; // 上传者 / 频道（仅URL资源有）
+  duration?: number; // 时长（秒）
+  upload_date?: string; // 上传日期（YYYY-MM-DD，仅URL资源有）
+  description?: string; // 视频简介（仅URL资源有）
+  tags?: string[]; // 标签（仅URL资源有）
+  chapters?: Chapter[]; // 章节标记（仅URL资源有）
+  content_hash?: string; // 文件内容的 SHA-256（仅文件资源有，用于检测重复导入）
+  extraction_status?: ExtractionStatus; // 音频提取状态（仅视频文件资源有）
+  extraction_error?: string; // 音频提取失败的原因
+  media_info?: MediaInfo; // 导入时探测的媒体信息（仅文件资源有）
   created_at: string; // 创建时间
   updated_at: string; // 更新时间
 }
 
+
+// Bilibili 分P信息
+export interface BilibiliPage {
+  cid: number;
+  page: number; // 分P页码（从 1 开始）
+  part: string; // 分P标题
+  duration: number; // 时长（秒）
+}
+
+// Bilibili 视频信息
+export interface BilibiliVideoInfo {
+  bvid: string;
+  aid: number;
+  title: string;
+  uploader?: string;
+  duration: number; // 所有分P的总时长（秒）
+  cover_url?: string;
+  pages: BilibiliPage[];
+}
+
+// 重复导入时的处理方式
+export type DuplicateAction =
+  | 'link' // 不创建新资源，直接使用已有资源
+  | 'reuse' // 创建新资源并复制已有资源的已完成转写结果
+  | 'import'; // 仍然作为新资源导入
+
+// 导入前的重复内容检查结果
+export interface DuplicateResourceCheck {
+  content_hash: string;
+  existing?: TranscriptionResource; // 最早导入的相同内容资源
+  completed_task_count: number; // 已有资源中已完成的转写任务数
+}
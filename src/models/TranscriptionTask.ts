@@ -1,4 +1,4 @@
-import { Topic } from './TranscriptionResource'
+import { Topic, TranscriptionResource } from './TranscriptionResource'
 
 // 转写任务模型
 export interface TranscriptionTask {
@@ -40,5 +40,47 @@ export interface TranscriptionParams {
   log_prob_threshold?: number; // 对数概率阈值
   no_speech_threshold?: number; // 无语音阈值
   translate?: boolean; // 是否翻译为英文
+  subtitle_languages?: string[]; // URL 资源的字幕语言偏好（按优先级），如 ['zh-Hans', 'en']
+  allow_auto_subtitles?: boolean; // 是否接受自动生成的字幕（默认接受）
+  preprocess?: AudioPreprocessOptions; // 转写前的音频预处理（不设置时不预处理）
 }
 
+// 音频预处理选项（输出始终为 16kHz 单声道）
+export interface AudioPreprocessOptions {
+  loudness_normalization?: boolean; // 响度标准化
+  highpass_hz?: number; // 高通滤波截止频率（Hz）
+  noise_reduction?: boolean; // 降噪
+  trim_silence?: boolean; // 裁剪开头和结尾的静音
+  start_time?: number; // 裁剪开始时间（秒）
+  end_time?: number; // 裁剪结束时间（秒）
+  filter_chain?: string; // 实际使用的 ffmpeg 滤镜链（执行后写入）
+  time_offset?: number; // 预处理音频相对原始音频的起始偏移（秒）
+  resolved_end_time?: number; // 实际使用的裁剪结束时间（秒，执行后写入）
+}
+
+// 字幕轨道类型
+export enum SubtitleTrackKind {
+  MANUAL = 'manual', // 人工字幕
+  AUTO = 'auto', // 自动生成字幕
+}
+
+// URL 资源下载的字幕轨道（每条轨道是一个独立的转写结果）
+export interface SubtitleTrack {
+  id: string;
+  task_id: string;
+  language: string; // 字幕语言代码
+  kind: SubtitleTrackKind; // 人工字幕或自动字幕
+  file_path: string; // 转写结果 JSON 文件路径
+  is_primary: boolean; // 是否为任务的主转写结果
+  created_at: string;
+}
+
+
+// 播放列表 / 频道批量导入结果
+export interface PlaylistImportResult {
+  playlist_title?: string;
+  total: number; // 播放列表中的视频数
+  resources: TranscriptionResource[]; // 新建的资源
+  skipped: string[]; // 已存在而跳过的 URL
+  tasks: TranscriptionTask[]; // 加入队列的转写任务
+}
@@ -0,0 +1,24 @@
+import { TranscriptionParams } from './TranscriptionTask'
+
+// 监视文件夹：新出现的音视频文件自动创建资源并开始转写
+export interface WatchFolder {
+  id: string
+  path: string
+  enabled: boolean
+  params: TranscriptionParams // 自动创建的转写任务使用的参数
+  created_at: string
+  updated_at: string
+}
+
+export type WatchFolderFileStatus = 'processing' | 'completed' | 'failed' | 'skipped'
+
+// 文件处理记录（按文件路径去重，重启后不会重复处理）
+export interface WatchFolderFile {
+  file_path: string
+  folder_id: string
+  status: WatchFolderFileStatus
+  resource_id?: string
+  task_id?: string
+  error?: string
+  processed_at: string
+}
@@ -7,3 +7,5 @@ export * from './MCPConfig';
 export * from './MCPTool';
 export * from './Chat';
 
+export * from './PromptTemplate';
+export * from './WatchFolder';
@@ -2,7 +2,8 @@ import { useState, useEffect, useRef } from 'react'
 import { invoke } from '@tauri-apps/api/core'
 import { listen, UnlistenFn } from '@tauri-apps/api/event'
 import { openPath } from '@tauri-apps/plugin-opener'
-import { ModelInfo, ModelDownloadProgress, AIConfig, MCPServerInfo, MCPServerConfig, MCPConfig } from '../models'
+import { open, confirm } from '@tauri-apps/plugin-dialog'
+import { ModelInfo, ModelDownloadProgress, AIConfig, MCPServerInfo, MCPServerConfig, MCPConfig, WatchFolder, WatchFolderFile, WatchFolderFileStatus } from '../models'
 import { HiXCircle, HiArrowDownTray, HiFolderOpen, HiPencil, HiTrash, HiPlus, HiCheckCircle, HiChevronDown, HiChevronUp, HiArrowPath } from 'react-icons/hi2'
 import { useMessage } from '../components/Toast'
 import { useAppDispatch, useAppSelector } from '../redux/hooks'
@@ -111,6 +112,9 @@ const SettingsGeneralPage = () => {
       {/* MCP 配置管理 */}
       <MCPConfigBlock />
 
+      {/* 监视文件夹 */}
+      <WatchFolderBlock models={downloadedModels} />
+
       {/* 模型下载弹出框始终挂载以保留下载状态 */}
       <ModelDownloadModal
         isOpen={showDownloadModal}
@@ -121,6 +125,218 @@ const SettingsGeneralPage = () => {
   )
 }
 
+const WATCH_FILE_STATUS_TEXT: Record<WatchFolderFileStatus, string> = {
+  processing: '处理中',
+  completed: '已完成',
+  failed: '失败',
+  skipped: '已跳过',
+}
+
+// 监视文件夹管理组件
+const WatchFolderBlock = ({ models }: { models: ModelInfo[] }) => {
+  const message = useMessage()
+  const [folders, setFolders] = useState<WatchFolder[]>([])
+  const [loading, setLoading] = useState(false)
+  const [expandedFolderId, setExpandedFolderId] = useState<string | null>(null)
+  const [files, setFiles] = useState<WatchFolderFile[]>([])
+
+  const loadFolders = async () => {
+    try {
+      setLoading(true)
+      setFolders(await invoke<WatchFolder[]>('get_watch_folders'))
+    } catch (err) {
+      console.error('加载监视文件夹失败:', err)
+    } finally {
+      setLoading(false)
+    }
+  }
+
+  const loadFiles = async (folderId: string) => {
+    try {
+      setFiles(await invoke<WatchFolderFile[]>('get_watch_folder_files', { folderId, limit: 50 }))
+    } catch (err) {
+      console.error('加载文件处理记录失败:', err)
+    }
+  }
+
+  useEffect(() => {
+    loadFolders()
+  }, [])
+
+  // 文件处理状态变化时刷新展开的处理记录
+  useEffect(() => {
+    let unlisten: UnlistenFn | null = null
+    listen<WatchFolderFile>('watch-folder-file-updated', (event) => {
+      if (event.payload.folder_id === expandedFolderId) {
+        loadFiles(event.payload.folder_id)
+      }
+    }).then((fn) => {
+      unlisten = fn
+    })
+    return () => {
+      unlisten?.()
+    }
+  }, [expandedFolderId])
+
+  const handleAdd = async () => {
+    const selected = await open({ directory: true, multiple: false })
+    if (!selected || typeof selected !== 'string') return
+    const includeExisting = await confirm('是否同时处理文件夹中已有的音视频文件？选择"取消"则只处理之后新增的文件。', {
+      title: '添加监视文件夹',
+    })
+    try {
+      await invoke<WatchFolder>('add_watch_folder', {
+        path: selected,
+        params: null,
+        includeExisting,
+      })
+      message.success('监视文件夹已添加')
+      await loadFolders()
+    } catch (err) {
+      message.error(err instanceof Error ? err.message : String(err))
+    }
+  }
+
+  const handleUpdate = async (folder: WatchFolder, changes: Partial<Pick<WatchFolder, 'enabled' | 'params'>>) => {
+    try {
+      const updated = await invoke<WatchFolder>('update_watch_folder', {
+        folderId: folder.id,
+        enabled: changes.enabled ?? null,
+        params: changes.params ?? null,
+      })
+      setFolders((prev) => prev.map((f) => (f.id === updated.id ? updated : f)))
+    } catch (err) {
+      message.error(err instanceof Error ? err.message : String(err))
+    }
+  }
+
+  const handleRemove = async (folder: WatchFolder) => {
+    try {
+      await invoke('remove_watch_folder', { folderId: folder.id })
+      if (expandedFolderId === folder.id) {
+        setExpandedFolderId(null)
+      }
+      await loadFolders()
+    } catch (err) {
+      message.error(err instanceof Error ? err.message : String(err))
+    }
+  }
+
+  const handleToggleFiles = async (folder: WatchFolder) => {
+    if (expandedFolderId === folder.id) {
+      setExpandedFolderId(null)
+      return
+    }
+    setExpandedFolderId(folder.id)
+    setFiles([])
+    await loadFiles(folder.id)
+  }
+
+  return (
+    <div className="card card-border bg-base-100 shadow-sm">
+      <div className="card-body">
+        <div className="flex items-center justify-between mb-4">
+          <div>
+            <h2 className="card-title">监视文件夹</h2>
+            <p className="text-sm text-base-content/60 mt-1">
+              文件夹中出现的新音视频文件写入完成后，自动创建资源并开始转写
+            </p>
+          </div>
+          <button className="btn btn-sm" onClick={handleAdd} title="添加文件夹">
+            <HiPlus className="h-4 w-4" />
+            <span className="ml-1">添加文件夹</span>
+          </button>
+        </div>
+        {loading ? (
+          <div className="flex items-center justify-center py-8">
+            <span className="loading loading-spinner loading-md"></span>
+          </div>
+        ) : folders.length === 0 ? (
+          <div className="text-center py-8 text-base-content/50">
+            <p>暂无监视文件夹</p>
+          </div>
+        ) : (
+          <div className="space-y-3">
+            {folders.map((folder) => (
+              <div key={folder.id} className="border border-base-300 rounded-lg p-3">
+                <div className="flex items-center gap-3">
+                  <input
+                    type="checkbox"
+                    className="toggle toggle-primary toggle-sm"
+                    checked={folder.enabled}
+                    onChange={(e) => handleUpdate(folder, { enabled: e.target.checked })}
+                    aria-label="启用监视"
+                  />
+                  <span className="flex-1 truncate text-sm" title={folder.path}>{folder.path}</span>
+                  <div className="w-32">
+                    <Select
+                      value={folder.params.model || ''}
+                      options={
+                        models.length === 0
+                          ? [{ value: folder.params.model || '', label: folder.params.model || '无可用模型', disabled: true }]
+                          : models.map((model) => ({ value: model.name, label: model.name }))
+                      }
+                      onChange={(value) => handleUpdate(folder, { params: { ...folder.params, model: value } })}
+                      size="sm"
+                      aria-label="转写模型"
+                    />
+                  </div>
+                  <div className="w-28">
+                    <Select
+                      value={folder.params.language || 'zh'}
+                      options={[
+                        { value: 'zh', label: '中文' },
+                        { value: 'en', label: 'English' },
+                        { value: 'ja', label: '日本語' },
+                        { value: 'ko', label: '한국어' },
+                        { value: 'auto', label: '自动检测' },
+                      ]}
+                      onChange={(value) => handleUpdate(folder, { params: { ...folder.params, language: value } })}
+                      size="sm"
+                      aria-label="转写语言"
+                    />
+                  </div>
+                  <button className="btn btn-ghost btn-sm" onClick={() => handleToggleFiles(folder)} title="处理记录">
+                    {expandedFolderId === folder.id ? <HiChevronUp className="h-4 w-4" /> : <HiChevronDown className="h-4 w-4" />}
+                  </button>
+                  <button className="btn btn-ghost btn-sm text-error" onClick={() => handleRemove(folder)} title="移除">
+                    <HiTrash className="h-4 w-4" />
+                  </button>
+                </div>
+                {expandedFolderId === folder.id && (
+                  <div className="mt-3 max-h-64 overflow-y-auto text-sm">
+                    {files.length === 0 ? (
+                      <p className="text-base-content/50 text-center py-2">暂无处理记录</p>
+                    ) : (
+                      <table className="table table-xs">
+                        <tbody>
+                          {files.map((file) => (
+                            <tr key={file.file_path}>
+                              <td className="truncate max-w-xs" title={file.file_path}>
+                                {file.file_path.split(/[/\\]/).pop()}
+                              </td>
+                              <td className={file.status === 'failed' ? 'text-error' : ''} title={file.error}>
+                                {WATCH_FILE_STATUS_TEXT[file.status] ?? file.status}
+                              </td>
+                              <td className="text-base-content/60">
+                                {new Date(file.processed_at).toLocaleString('zh-CN')}
+                              </td>
+                            </tr>
+                          ))}
+                        </tbody>
+                      </table>
+                    )}
+                  </div>
+                )}
+              </div>
+            ))}
+          </div>
+        )}
+      </div>
+    </div>
+  )
+}
+
 // AI 配置管理组件
 const AIConfigBlock = () => {
   const message = useMessage()
@@ -404,15 +620,30 @@ interface AIConfigModalProps {
   onSave: () => void
 }
 
+const EMPTY_AI_CONFIG_FORM = {
+  name: '',
+  base_url: '',
+  api_key: '',
+  model: '',
+  context_window: '',
+  input_price: '',
+  output_price: '',
+  cached_input_price: '',
+}
+
+const priceToString = (price?: number | null) => (price !== null && price !== undefined ? String(price) : '')
+
+// 价格可选，留空返回 null，非法时返回 undefined
+const parsePrice = (value: string): number | null | undefined => {
+  if (!value) return null
+  const price = parseFloat(value)
+  return isNaN(price) || price < 0 ? undefined : price
+}
+
 const AIConfigModal = ({ isOpen, config, onClose, onSave }: AIConfigModalProps) => {
   const message = useMessage()
   const isEditMode = !!config
-  const [formData, setFormData] = useState({
-    name: '',
-    base_url: '',
-    api_key: '',
-    model: '',
-  })
+  const [formData, setFormData] = useState(EMPTY_AI_CONFIG_FORM)
 
   // 当 config 变化时同步 formData
   useEffect(() => {
@@ -422,9 +653,13 @@ const AIConfigModal = ({ isOpen, config, onClose, onSave }: AIConfigModalProps)
         base_url: config.base_url,
         api_key: config.api_key,
         model: config.model,
+        context_window: config.context_window ? String(config.context_window) : '',
+        input_price: priceToString(config.input_price),
+        output_price: priceToString(config.output_price),
+        cached_input_price: priceToString(config.cached_input_price),
       })
     } else {
-      setFormData({ name: '', base_url: '', api_key: '', model: '' })
+      setFormData(EMPTY_AI_CONFIG_FORM)
     }
   }, [config])
 
@@ -434,6 +669,22 @@ const AIConfigModal = ({ isOpen, config, onClose, onSave }: AIConfigModalProps)
       return
     }
 
+    // 上下文窗口可选，留空表示使用默认值
+    const contextWindow = formData.context_window ? parseInt(formData.context_window, 10) : null
+    if (contextWindow !== null && (isNaN(contextWindow) || contextWindow <= 0)) {
+      message.error('上下文窗口必须是正整数')
+      return
+    }
+
+    // 价格单位：每百万 tokens
+    const inputPrice = parsePrice(formData.input_price)
+    const outputPrice = parsePrice(formData.output_price)
+    const cachedInputPrice = parsePrice(formData.cached_input_price)
+    if (inputPrice === undefined || outputPrice === undefined || cachedInputPrice === undefined) {
+      message.error('价格必须是非负数')
+      return
+    }
+
     try {
       if (isEditMode && config) {
         await invoke('update_ai_config', {
@@ -442,6 +693,10 @@ const AIConfigModal = ({ isOpen, config, onClose, onSave }: AIConfigModalProps)
           baseUrl: formData.base_url,
           apiKey: formData.api_key,
           model: formData.model,
+          contextWindow,
+          inputPrice,
+          outputPrice,
+          cachedInputPrice,
         })
         message.success('更新成功')
       } else {
@@ -450,9 +705,13 @@ const AIConfigModal = ({ isOpen, config, onClose, onSave }: AIConfigModalProps)
           baseUrl: formData.base_url,
           apiKey: formData.api_key,
           model: formData.model,
+          contextWindow,
+          inputPrice,
+          outputPrice,
+          cachedInputPrice,
         })
         message.success('添加成功')
-        setFormData({ name: '', base_url: '', api_key: '', model: '' })
+        setFormData(EMPTY_AI_CONFIG_FORM)
       }
       onSave()
     } catch (err) {
@@ -517,6 +776,53 @@ const AIConfigModal = ({ isOpen, config, onClose, onSave }: AIConfigModalProps)
               placeholder="gpt-3.5-turbo"
             />
           </div>
+          <div>
+            <label className="label">
+              <span className="label-text">上下文窗口（tokens，可选）</span>
+            </label>
+            <input
+              type="number"
+              min={1}
+              className="input input-bordered w-full"
+              value={formData.context_window}
+              onChange={(e) => setFormData({ ...formData, context_window: e.target.value })}
+              placeholder="留空使用默认值 32000"
+            />
+          </div>
+          <div>
+            <label className="label">
+              <span className="label-text">价格（每百万 tokens，可选）</span>
+            </label>
+            <div className="grid grid-cols-3 gap-2">
+              <input
+                type="number"
+                min={0}
+                step="any"
+                className="input input-bordered w-full"
+                value={formData.input_price}
+                onChange={(e) => setFormData({ ...formData, input_price: e.target.value })}
+                placeholder="输入"
+              />
+              <input
+                type="number"
+                min={0}
+                step="any"
+                className="input input-bordered w-full"
+                value={formData.output_price}
+                onChange={(e) => setFormData({ ...formData, output_price: e.target.value })}
+                placeholder="输出"
+              />
+              <input
+                type="number"
+                min={0}
+                step="any"
+                className="input input-bordered w-full"
+                value={formData.cached_input_price}
+                onChange={(e) => setFormData({ ...formData, cached_input_price: e.target.value })}
+                placeholder="缓存输入"
+              />
+            </div>
+          </div>
         </div>
 
         <div className="modal-action">
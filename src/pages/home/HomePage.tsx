@@ -1,14 +1,24 @@
 import { useState, useEffect, useRef, useLayoutEffect } from 'react';
-import { open } from '@tauri-apps/plugin-dialog';
+import { open, confirm } from '@tauri-apps/plugin-dialog';
 import { invoke } from '@tauri-apps/api/core';
 import { useAppDispatch } from '../../redux/hooks';
 import { setCurrentPage } from '../../redux/slices/featureKeysSlice';
-import { TranscriptionResource, SourceType } from '../../models';
+import { TranscriptionResource, SourceType, BilibiliVideoInfo, PlaylistImportResult, TranscriptionParams, DuplicateAction, DuplicateResourceCheck, MediaInfo } from '../../models';
 import { HiPlus, HiDocumentText, HiLink } from 'react-icons/hi2';
 import ResourceCard from './components/ResourceCard';
 import DeleteConfirmModal from '../../components/DeleteConfirmModal';
+import DuplicateResourceModal from './components/DuplicateResourceModal';
 import { useMessage } from '../../components/Toast';
-import { isValidUrl, extractResourceNameFromUrl } from '../../utils/urlUtils';
+import { isValidUrl, isPlaylistUrl, extractResourceNameFromUrl } from '../../utils/urlUtils';
+
+// 批量导入时转写任务使用的参数（URL 资源优先下载平台字幕，没有字幕时使用模型转写）
+const PLAYLIST_TASK_PARAMS: TranscriptionParams = {
+  model: 'base',
+  language: 'zh',
+  word_timestamps: true,
+  translate: false,
+  allow_auto_subtitles: true,
+};
 
 const HomePage = () => {
   const dispatch = useAppDispatch();
@@ -20,6 +30,12 @@ const HomePage = () => {
   const [showUrlModal, setShowUrlModal] = useState(false);
   const [urlInput, setUrlInput] = useState('');
   const [urlLoading, setUrlLoading] = useState(false);
+  // 内容重复的待导入文件
+  const [pendingDuplicate, setPendingDuplicate] = useState<{
+    name: string;
+    filePath: string;
+    check: DuplicateResourceCheck;
+  } | null>(null);
   const message = useMessage();
   
   // 瀑布流布局相关 - 响应式，至少4列
@@ -167,17 +183,67 @@ const HomePage = () => {
         return;
       }
 
-      // 创建新的转写资源
-      const newResource = await invoke<TranscriptionResource>('create_transcription_resource', {
-        name: fileName,
-        filePath: filePath,
-      });
+      // 探测媒体信息：没有音频流的文件无法转写，探测失败时仍允许导入
+      try {
+        const mediaInfo = await invoke<MediaInfo>('probe_media_file', { filePath });
+        if (!mediaInfo.has_audio) {
+          message.error('该文件中没有音频流，无法转写');
+          return;
+        }
+      } catch (err) {
+        console.error('探测媒体信息失败:', err);
+        message.warning('无法识别文件的媒体信息，将按扩展名导入');
+      }
 
-      // 创建成功后，跳转到资源详情页
-      dispatch(setCurrentPage({ feature: 'home', page: `resource:${newResource.id}` }));
-      
-      // 重新加载资源列表
-      await loadResources();
+      // 检查文件内容是否已导入过（不同路径的相同文件）
+      const check = await invoke<DuplicateResourceCheck>('check_duplicate_resource', { filePath });
+      if (check.existing) {
+        setPendingDuplicate({ name: fileName, filePath, check });
+        return;
+      }
+
+      await createFileResource(fileName, filePath, check.content_hash);
+    } catch (err) {
+      console.error('处理文件失败:', err);
+      const errorMessage = err instanceof Error ? err.message : String(err);
+      setError(errorMessage || '处理文件失败');
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  // 创建文件资源并跳转到详情页
+  const createFileResource = async (
+    name: string,
+    filePath: string,
+    contentHash?: string,
+    duplicateAction?: DuplicateAction,
+  ) => {
+    const newResource = await invoke<TranscriptionResource>('create_transcription_resource', {
+      name,
+      filePath,
+      contentHash: contentHash ?? null,
+      duplicateAction: duplicateAction ?? null,
+    });
+
+    // 创建成功后，跳转到资源详情页
+    dispatch(setCurrentPage({ feature: 'home', page: `resource:${newResource.id}` }));
+
+    // 重新加载资源列表
+    await loadResources();
+  };
+
+  // 选择重复文件的处理方式
+  const handleDuplicateAction = async (action: DuplicateAction) => {
+    if (!pendingDuplicate) return;
+    const { name, filePath, check } = pendingDuplicate;
+    setPendingDuplicate(null);
+    try {
+      setLoading(true);
+      await createFileResource(name, filePath, check.content_hash, action);
+      if (action === 'reuse') {
+        message.success('已导入并复用已有转写结果');
+      }
     } catch (err) {
       console.error('处理文件失败:', err);
       const errorMessage = err instanceof Error ? err.message : String(err);
@@ -225,6 +291,54 @@ const HomePage = () => {
         return;
       }
 
+      // 播放列表 / 频道：为每个视频创建资源，可选地使用默认参数批量创建转写任务
+      if (isPlaylistUrl(url)) {
+        const importPlaylist = await confirm(
+          '该链接是播放列表或频道，是否导入其中的所有视频？选择"取消"则只创建当前链接的资源。',
+          { title: '导入播放列表' }
+        );
+        if (importPlaylist) {
+          const enqueueTasks = await confirm(
+            '是否为导入的视频自动创建转写任务并按顺序执行？',
+            { title: '批量转写' }
+          );
+          const result = await invoke<PlaylistImportResult>('import_playlist', {
+            url,
+            params: enqueueTasks ? PLAYLIST_TASK_PARAMS : null,
+          });
+          await loadResources();
+          setShowUrlModal(false);
+          setUrlInput('');
+          const skippedText = result.skipped.length > 0 ? `，跳过 ${result.skipped.length} 个已存在的视频` : '';
+          const tasksText = result.tasks.length > 0 ? `，已加入 ${result.tasks.length} 个转写任务` : '';
+          message.success(`已导入 ${result.resources.length}/${result.total} 个视频${skippedText}${tasksText}`);
+          return;
+        }
+      }
+
+      // Bilibili 多P视频（URL 未指定分P）可以为每个分P分别创建资源
+      const isBilibili = /bilibili\.com|b23\.tv/i.test(url);
+      if (isBilibili && !/[?&]p=\d+/.test(url)) {
+        const info = await invoke<BilibiliVideoInfo>('get_bilibili_video_info', { url }).catch((err) => {
+          console.warn('获取 Bilibili 视频信息失败:', err);
+          return null;
+        });
+        if (info && info.pages.length > 1) {
+          const createParts = await confirm(
+            `该视频共有 ${info.pages.length} 个分P，是否为每个分P分别创建资源？选择"取消"则只创建第一个分P。`,
+            { title: '多P视频' }
+          );
+          if (createParts) {
+            const partResources = await invoke<TranscriptionResource[]>('create_bilibili_part_resources', { url });
+            await loadResources();
+            setShowUrlModal(false);
+            setUrlInput('');
+            message.success(`已创建 ${partResources.length} 个分P资源`);
+            return;
+          }
+        }
+      }
+
       // 从URL提取资源名称
       const resourceName = extractResourceNameFromUrl(url);
 
@@ -352,6 +466,12 @@ const HomePage = () => {
           ))}
         </div>
       )}
+      <DuplicateResourceModal
+        check={pendingDuplicate?.check ?? null}
+        onSelect={handleDuplicateAction}
+        onCancel={() => setPendingDuplicate(null)}
+      />
+
       <DeleteConfirmModal
         isOpen={!!resourceToDelete}
         title="删除资源"
@@ -0,0 +1,46 @@
+import { DuplicateAction, DuplicateResourceCheck } from '../../../models';
+
+interface DuplicateResourceModalProps {
+  check: DuplicateResourceCheck | null;
+  onSelect: (action: DuplicateAction) => void;
+  onCancel: () => void;
+}
+
+// 导入的文件与已有资源内容相同时，选择处理方式
+const DuplicateResourceModal = ({ check, onSelect, onCancel }: DuplicateResourceModalProps) => {
+  if (!check?.existing) return null;
+
+  return (
+    <div className="modal modal-open">
+      <div className="modal-box">
+        <h3 className="font-bold text-lg">文件已导入过</h3>
+        <p className="py-4">
+          该文件与已有资源「{check.existing.name}」内容相同
+          {check.completed_task_count > 0 ? `，已有 ${check.completed_task_count} 个已完成的转写结果` : ''}。
+        </p>
+        <div className="flex flex-col gap-2">
+          <button className="btn btn-primary" onClick={() => onSelect('link')}>
+            打开已有资源
+          </button>
+          <button
+            className="btn"
+            onClick={() => onSelect('reuse')}
+            disabled={check.completed_task_count === 0}
+          >
+            导入并复用已有转写结果
+          </button>
+          <button className="btn" onClick={() => onSelect('import')}>
+            仍然导入
+          </button>
+        </div>
+        <div className="modal-action">
+          <button className="btn btn-ghost" onClick={onCancel}>
+            取消
+          </button>
+        </div>
+      </div>
+    </div>
+  );
+};
+
+export default DuplicateResourceModal;
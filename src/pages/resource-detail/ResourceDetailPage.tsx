@@ -4,7 +4,7 @@ import { listen, UnlistenFn } from '@tauri-apps/api/event';
 import { HiArrowLeft, HiTrash } from 'react-icons/hi2';
 import { useAppDispatch, useAppSelector } from '../../redux/hooks';
 import { setCurrentPage } from '../../redux/slices/featureKeysSlice';
-import { setExtracting } from '../../redux/slices/videoExtractionSlice';
+import { setExtracting, setExtractionProgress } from '../../redux/slices/videoExtractionSlice';
 import { setContext, clearContext } from '../../redux/slices/aiContextSlice';
 import { useMessage } from '../../components/Toast';
 import {
@@ -13,7 +13,8 @@ import {
   TranscriptionParams,
   ResourceType,
   SourceType,
-  Platform,
+  ExtractionStatus,
+  ExtractionProgress,
 } from '../../models';
 import { isUrl } from '../../utils/urlUtils';
 import ResourceInfoCard from './components/ResourceInfoCard';
@@ -71,7 +72,7 @@ const ResourceDetailPage = () => {
       dispatch,
     });
   // 用于存储提取事件监听器的清理函数
-  const extractionUnlistenRef = useRef<{ log?: UnlistenFn }>({});
+  const extractionUnlistenRef = useRef<{ log?: UnlistenFn; progress?: UnlistenFn }>({});
   // 用于跟踪是否已经触发过音频提取，避免重复触发
   const extractionTriggeredRef = useRef<Set<string>>(new Set());
   
@@ -141,6 +142,14 @@ const ResourceDetailPage = () => {
       }
       extractionUnlistenRef.current.log = undefined;
     }
+    if (extractionUnlistenRef.current.progress) {
+      try {
+        extractionUnlistenRef.current.progress();
+      } catch (err) {
+        console.error('清理提取进度监听器失败:', err);
+      }
+      extractionUnlistenRef.current.progress = undefined;
+    }
   }, []);
 
   // 设置提取事件监听器
@@ -155,93 +164,32 @@ const ResourceDetailPage = () => {
         console.log('提取日志:', event.payload);
       });
       extractionUnlistenRef.current.log = unlistenLog;
+
+      // 监听提取进度，提取结束时刷新资源（页面重新加载后也能收到结束事件）
+      const unlistenProgress = await listen<ExtractionProgress>(`extraction-progress-${resourceId}`, (event) => {
+        const progress = event.payload;
+        if (progress.status === ExtractionStatus.EXTRACTING) {
+          dispatch(setExtractionProgress(progress));
+          return;
+        }
+        dispatch(setExtracting({ resourceId, isExtracting: false }));
+        refreshResource();
+      });
+      extractionUnlistenRef.current.progress = unlistenProgress;
     } catch (err) {
       console.error('设置提取监听器失败:', err);
     }
-  }, [cleanupExtractionListeners]);
+  }, [cleanupExtractionListeners, dispatch, refreshResource]);
 
   // 返回列表页
   const handleBack = () => {
     dispatch(setCurrentPage({ feature: 'home', page: null }));
   };
 
-  // 直接创建并执行任务（用于URL资源，不需要参数配置）
-  const handleCreateTaskDirectly = useCallback(async () => {
-    if (!resourceId) return;
-    try {
-      // 对于 URL 资源，使用默认参数（实际上这些参数不会被使用，因为会直接下载字幕）
-      const defaultParams: TranscriptionParams = {
-        model: 'base', // 不会被使用
-        language: 'zh',
-        word_timestamps: false,
-        translate: false,
-      };
-
-      // 创建任务
-      const task = await invoke<TranscriptionTask>('create_transcription_task', {
-        resourceId: resourceId,
-        params: defaultParams,
-      });
-
-      // 立即切换到新任务并显示 loading
-      setSelectedTaskId(task.id);
-      setResultContent(null);
-      
-      // 重新加载任务列表以获取最新状态
-      await loadTasks(false);
-
-      // 异步执行转写任务（不阻塞 UI）
-      loadTasks(true);
-      
-      // 执行任务（不等待完成）
-      const executePromise = invoke<string>('execute_transcription_task', {
-        taskId: task.id,
-        resourceId: resourceId,
-      });
-      
-      // 任务开始执行后，短延迟刷新以确保状态已更新为 RUNNING
-      setTimeout(() => {
-        loadTasks(true);
-      }, 200);
-      
-      // 等待任务完成
-      executePromise.then(() => {
-        cleanupTaskListeners();
-        loadTasks(true);
-        message.success('字幕下载并转换完成');
-      }).catch((err) => {
-        console.error('执行转写任务失败:', err);
-        message.error(err instanceof Error ? err.message : '执行转写任务失败');
-        cleanupTaskListeners();
-        loadTasks(true);
-      });
-      
-      // 任务完成后重新加载字幕（loadTasks 内部会自动处理）
-    } catch (err) {
-      console.error('创建转写任务失败:', err);
-      message.error(err instanceof Error ? err.message : '创建转写任务失败');
-    }
-  }, [resourceId, setSelectedTaskId, setResultContent, loadTasks, cleanupTaskListeners, message]);
-
-  // 显示创建任务弹窗或直接创建任务（URL资源）
+  // 显示创建任务弹窗（URL 资源可在弹窗中设置字幕语言偏好）
   const handleShowCreateTaskModal = useCallback(() => {
-    // 如果是 URL 资源（特别是 YouTube），直接创建并执行任务，不需要配置参数
-    if (resource?.source_type === SourceType.URL) {
-      // 检查是否是 YouTube
-      const isYoutube = resource.platform === Platform.YOUTUBE || 
-        (resource.file_path.toLowerCase().includes('youtube.com') || 
-         resource.file_path.toLowerCase().includes('youtu.be'));
-      
-      if (isYoutube) {
-        // 直接创建并执行任务，使用默认参数（URL资源不需要这些参数）
-        handleCreateTaskDirectly();
-        return;
-      }
-    }
-    
-    // 文件资源需要配置参数，显示弹窗
     setShowCreateTaskModal(true);
-  }, [resource, handleCreateTaskDirectly]);
+  }, []);
 
   // 删除资源
   const handleDeleteResource = async () => {
@@ -450,6 +398,15 @@ const ResourceDetailPage = () => {
       // 检查是否已经在提取中
       if (isExtracting) {
         extractionTriggeredRef.current.add(resourceId);
+        setupExtractionListeners(resourceId);
+        return;
+      }
+
+      // 页面重新加载后，后台可能仍在提取，只需重新订阅进度
+      if (resource.extraction_status === ExtractionStatus.EXTRACTING) {
+        extractionTriggeredRef.current.add(resourceId);
+        dispatch(setExtracting({ resourceId, isExtracting: true }));
+        setupExtractionListeners(resourceId);
         return;
       }
 
@@ -581,6 +538,7 @@ const ResourceDetailPage = () => {
             selectedTaskId={selectedTaskId}
             resultContent={resultContent}
             resourceName={resourceName}
+            chapters={resource?.chapters}
             canCreateTask={canCreateTask}
             onSelectTask={setSelectedTaskId}
             onCreateTask={handleShowCreateTaskModal}
@@ -596,6 +554,8 @@ const ResourceDetailPage = () => {
       {/* 创建转写任务弹窗 */}
       <CreateTranscriptionTaskModal
         isOpen={showCreateTaskModal}
+        isUrlResource={resource?.source_type === SourceType.URL}
+        duration={resource?.media_info?.duration ?? resource?.duration}
         onConfirm={handleCreateTask}
         onCancel={() => setShowCreateTaskModal(false)}
       />
@@ -1,16 +1,35 @@
 import { useState, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/core';
-import { TranscriptionParams, ModelInfo } from '../../../models';
+import { TranscriptionParams, AudioPreprocessOptions, ModelInfo } from '../../../models';
 import Select from '../../../components/Select';
+import { formatDuration } from '../../../utils/format';
+
+// 各模型转写耗时相对音频时长的粗略比例，用于预估转写时间
+const MODEL_SPEED_FACTORS: Record<string, number> = {
+  tiny: 0.05,
+  base: 0.08,
+  small: 0.2,
+  medium: 0.5,
+  large: 1,
+};
+
+const estimateTranscriptionSeconds = (model: string | undefined, seconds: number) => {
+  const key = Object.keys(MODEL_SPEED_FACTORS).find((name) => model?.startsWith(name));
+  return key ? seconds * MODEL_SPEED_FACTORS[key] : null;
+};
 
 interface CreateTranscriptionTaskModalProps {
   isOpen: boolean;
+  isUrlResource?: boolean; // URL 资源优先下载平台字幕，没有字幕时才使用模型转写
+  duration?: number; // 资源时长（秒），用于预估转写耗时
   onConfirm: (params: TranscriptionParams) => void;
   onCancel: () => void;
 }
 
 const CreateTranscriptionTaskModal = ({
   isOpen,
+  isUrlResource = false,
+  duration,
   onConfirm,
   onCancel,
 }: CreateTranscriptionTaskModalProps) => {
@@ -22,6 +41,14 @@ const CreateTranscriptionTaskModal = ({
     word_timestamps: true,
     translate: false,
   });
+  const [subtitleLanguages, setSubtitleLanguages] = useState('');
+  const [preprocessEnabled, setPreprocessEnabled] = useState(false);
+  const [preprocess, setPreprocess] = useState<AudioPreprocessOptions>({
+    loudness_normalization: true,
+    highpass_hz: 80,
+    noise_reduction: false,
+    trim_silence: false,
+  });
 
   // 加载可用模型列表
   useEffect(() => {
@@ -49,19 +76,84 @@ const CreateTranscriptionTaskModal = ({
 
   const handleSubmit = (e: React.FormEvent) => {
     e.preventDefault();
-    onConfirm(params);
+    const taskParams: TranscriptionParams = {
+      ...params,
+      preprocess: preprocessEnabled ? preprocess : undefined,
+    };
+    if (!isUrlResource) {
+      onConfirm(taskParams);
+      return;
+    }
+    // 逗号分隔的语言偏好，为空时使用默认偏好
+    const languages = subtitleLanguages
+      .split(/[,，\s]+/)
+      .map((l) => l.trim())
+      .filter(Boolean);
+    onConfirm({
+      ...taskParams,
+      subtitle_languages: languages.length > 0 ? languages : undefined,
+      allow_auto_subtitles: params.allow_auto_subtitles ?? true,
+    });
   };
 
   if (!isOpen) return null;
 
   const availableModels = models.filter(m => m.downloaded);
 
+  // 实际转写的时长（预处理裁剪后）
+  const transcribedSeconds = (() => {
+    if (!duration) return null;
+    if (!preprocessEnabled) return duration;
+    const start = Math.min(preprocess.start_time ?? 0, duration);
+    const end = Math.min(preprocess.end_time ?? duration, duration);
+    return Math.max(0, end - start);
+  })();
+  const estimatedSeconds = transcribedSeconds != null
+    ? estimateTranscriptionSeconds(params.model, transcribedSeconds)
+    : null;
+
   return (
     <div className="modal modal-open">
       <div className="modal-box max-w-2xl">
         <h3 className="font-bold text-lg mb-4">创建转写任务</h3>
         <form onSubmit={handleSubmit}>
           <div className="space-y-4">
+            {isUrlResource && (
+              <>
+                {/* 字幕语言偏好 */}
+                <div className="form-control">
+                  <label className="label">
+                    <span className="label-text">字幕语言偏好</span>
+                  </label>
+                  <input
+                    type="text"
+                    className="input input-bordered w-full"
+                    placeholder="如 zh-Hans, en（留空使用默认偏好）"
+                    value={subtitleLanguages}
+                    onChange={(e) => setSubtitleLanguages(e.target.value)}
+                  />
+                  <label className="label">
+                    <span className="label-text-alt text-base-content/60">
+                      按优先级用逗号分隔，第一条匹配的字幕作为主转写结果，没有字幕时使用下方模型转写
+                    </span>
+                  </label>
+                </div>
+
+                {/* 是否接受自动字幕 */}
+                <div className="form-control">
+                  <label className="label cursor-pointer">
+                    <span className="label-text">接受自动生成的字幕</span>
+                    <input
+                      type="checkbox"
+                      className="toggle toggle-primary"
+                      checked={params.allow_auto_subtitles ?? true}
+                      onChange={(e) => setParams(prev => ({ ...prev, allow_auto_subtitles: e.target.checked }))}
+                    />
+                  </label>
+                </div>
+              </>
+            )}
+
             {/* 模型选择 */}
             <div className="form-control">
               <label className="label">
@@ -81,7 +173,7 @@ const CreateTranscriptionTaskModal = ({
                         }))
                   }
                   onChange={(value) => setParams((prev) => ({ ...prev, model: value }))}
-                  required
+                  required={!isUrlResource}
                   aria-label="选择模型"
                 />
               )}
@@ -138,8 +230,111 @@ const CreateTranscriptionTaskModal = ({
                 />
               </label>
             </div>
+
+            {/* 音频预处理 */}
+            <div className="form-control">
+              <label className="label cursor-pointer">
+                <span className="label-text">转写前预处理音频</span>
+                <input
+                  type="checkbox"
+                  className="toggle toggle-primary"
+                  checked={preprocessEnabled}
+                  onChange={(e) => setPreprocessEnabled(e.target.checked)}
+                />
+              </label>
+              <label className="label">
+                <span className="label-text-alt text-base-content/60">
+                  使用 ffmpeg 转换为 16kHz 单声道后再转写，实际使用的滤镜链会保存在任务中
+                </span>
+              </label>
+            </div>
+
+            {preprocessEnabled && (
+              <div className="space-y-2 pl-4 border-l-2 border-base-300">
+                <label className="label cursor-pointer">
+                  <span className="label-text">响度标准化</span>
+                  <input
+                    type="checkbox"
+                    className="toggle toggle-sm"
+                    checked={preprocess.loudness_normalization || false}
+                    onChange={(e) => setPreprocess(prev => ({ ...prev, loudness_normalization: e.target.checked }))}
+                  />
+                </label>
+                <label className="label cursor-pointer">
+                  <span className="label-text">降噪</span>
+                  <input
+                    type="checkbox"
+                    className="toggle toggle-sm"
+                    checked={preprocess.noise_reduction || false}
+                    onChange={(e) => setPreprocess(prev => ({ ...prev, noise_reduction: e.target.checked }))}
+                  />
+                </label>
+                <label className="label cursor-pointer">
+                  <span className="label-text">裁剪开头和结尾的静音</span>
+                  <input
+                    type="checkbox"
+                    className="toggle toggle-sm"
+                    checked={preprocess.trim_silence || false}
+                    onChange={(e) => setPreprocess(prev => ({ ...prev, trim_silence: e.target.checked }))}
+                  />
+                </label>
+                <div className="grid grid-cols-3 gap-2">
+                  <label className="form-control">
+                    <span className="label-text text-sm mb-1">高通滤波（Hz）</span>
+                    <input
+                      type="number"
+                      min={0}
+                      className="input input-bordered input-sm"
+                      placeholder="不启用"
+                      value={preprocess.highpass_hz ?? ''}
+                      onChange={(e) => setPreprocess(prev => ({
+                        ...prev,
+                        highpass_hz: e.target.value ? Number(e.target.value) : undefined,
+                      }))}
+                    />
+                  </label>
+                  <label className="form-control">
+                    <span className="label-text text-sm mb-1">开始时间（秒）</span>
+                    <input
+                      type="number"
+                      min={0}
+                      step="0.1"
+                      className="input input-bordered input-sm"
+                      placeholder="从头开始"
+                      value={preprocess.start_time ?? ''}
+                      onChange={(e) => setPreprocess(prev => ({
+                        ...prev,
+                        start_time: e.target.value ? Number(e.target.value) : undefined,
+                      }))}
+                    />
+                  </label>
+                  <label className="form-control">
+                    <span className="label-text text-sm mb-1">结束时间（秒）</span>
+                    <input
+                      type="number"
+                      min={0}
+                      step="0.1"
+                      className="input input-bordered input-sm"
+                      placeholder="到结尾"
+                      value={preprocess.end_time ?? ''}
+                      onChange={(e) => setPreprocess(prev => ({
+                        ...prev,
+                        end_time: e.target.value ? Number(e.target.value) : undefined,
+                      }))}
+                    />
+                  </label>
+                </div>
+              </div>
+            )}
           </div>
 
+          {transcribedSeconds != null && (
+            <div className="text-sm text-base-content/60 mt-4">
+              音频时长 {formatDuration(transcribedSeconds)}
+              {estimatedSeconds != null && !isUrlResource && `，预计转写耗时约 ${formatDuration(estimatedSeconds)}`}
+            </div>
+          )}
+
           <div className="modal-action">
             <button type="button" className="btn" onClick={onCancel}>
               取消
@@ -147,7 +342,7 @@ const CreateTranscriptionTaskModal = ({
             <button
               type="submit"
               className="btn btn-primary"
-              disabled={availableModels.length === 0}
+              disabled={availableModels.length === 0 && !isUrlResource}
             >
               确定
             </button>
@@ -4,6 +4,7 @@ import { TranscriptionResource, ResourceType, TranscriptionTask } from '../../..
 import { useAppSelector } from '../../../redux/hooks'
 import { EditableInput } from '../../../components/EditableInput'
 import TopicsTimeline from './TopicsTimeline'
+import { formatDuration } from '../../../utils/format'
 
 interface ResourceInfoCardProps {
   resource: TranscriptionResource
@@ -32,6 +33,7 @@ const ResourceInfoCard = memo(({
     (state) => state.videoExtraction.extractions[resource.id]
   )
   const isExtracting = extractionState?.isExtracting ?? false
+  const extractionProgress = extractionState?.progress
   const isVideo = resource.resource_type === ResourceType.VIDEO
   
   // 使用 useMemo 稳定 Player 的 props
@@ -62,9 +64,26 @@ const ResourceInfoCard = memo(({
               />
               {/* 提取进度显示 */}
               {isExtracting && (
-                <div className="flex items-center gap-2 text-sm text-base-content/70">
-                  <span className="loading loading-spinner loading-sm"></span>
-                  <span>正在提取音频...</span>
+                <div className="space-y-1 text-sm text-base-content/70">
+                  <div className="flex items-center gap-2">
+                    <span className="loading loading-spinner loading-sm"></span>
+                    <span>正在提取音频...</span>
+                    {extractionProgress?.percent != null && (
+                      <span>{Math.floor(extractionProgress.percent)}%</span>
+                    )}
+                    {extractionProgress?.eta_seconds != null && (
+                      <span className="text-base-content/50">
+                        剩余约 {formatDuration(extractionProgress.eta_seconds)}
+                      </span>
+                    )}
+                  </div>
+                  {extractionProgress?.percent != null && (
+                    <progress
+                      className="progress progress-primary w-full"
+                      value={extractionProgress.percent}
+                      max={100}
+                    ></progress>
+                  )}
                 </div>
               )}
             </div>
@@ -126,6 +145,19 @@ const ResourceInfoCard = memo(({
           </div>
         </div>
 
+        {/* 导入时探测的媒体信息 */}
+        {resource.media_info && (
+          <div className="text-xs text-base-content/60 flex flex-wrap gap-x-3 gap-y-1">
+            {resource.media_info.duration != null && (
+              <span>时长 {formatDuration(resource.media_info.duration)}</span>
+            )}
+            {resource.media_info.video_codec && <span>视频 {resource.media_info.video_codec}</span>}
+            {resource.media_info.audio_codec && <span>音频 {resource.media_info.audio_codec}</span>}
+            {resource.media_info.sample_rate != null && <span>{resource.media_info.sample_rate} Hz</span>}
+            {resource.media_info.channels != null && <span>{resource.media_info.channels} 声道</span>}
+          </div>
+        )}
+
         <div className="text-sm text-base-content/50">
           <span className="mr-1">创建于</span>
           {new Date(resource.created_at).toLocaleString('zh-CN', {
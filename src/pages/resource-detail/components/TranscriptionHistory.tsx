@@ -4,7 +4,7 @@ import { save } from '@tauri-apps/plugin-dialog';
 import { writeTextFile } from '@tauri-apps/plugin-fs';
 import { useAppSelector, useAppDispatch } from '../../../redux/hooks';
 import { clearLogs } from '../../../redux/slices/transcriptionLogsSlice';
-import { TranscriptionTask, TranscriptionTaskStatus } from '../../../models';
+import { TranscriptionTask, TranscriptionTaskStatus, Chapter, SubtitleTrack, SubtitleTrackKind } from '../../../models';
 import { TranscriptionResultJson } from '../../../models/TranscriptionResult';
 import { HiDocumentText, HiInformationCircle, HiTrash, HiStop, HiArrowDownTray, HiSparkles, HiTag, HiEllipsisVertical } from 'react-icons/hi2';
 import { getStatusText } from './transcriptionUtils';
@@ -12,6 +12,7 @@ import TranscriptionJsonView from './TranscriptionJsonView';
 import TranscriptionInfoModal from './TranscriptionInfoModal';
 import DeleteConfirmModal from '../../../components/DeleteConfirmModal';
 import { convertToSRT } from '../../../utils/srtConverter';
+import { convertToMarkdown } from '../../../utils/markdownConverter';
 import { useMessage } from '../../../components/Toast';
 import Select from '../../../components/Select';
 import { PlayerRef } from '../../../components/Player';
@@ -21,6 +22,7 @@ interface TranscriptionHistoryProps {
   selectedTaskId: string | null;
   resultContent: string | null;
   resourceName?: string; // 资源名称，用于生成默认导出文件名
+  chapters?: Chapter[]; // 资源的章节标记，导出 Markdown 时作为大纲
   canCreateTask?: boolean; // 是否可以创建任务（例如，视频资源正在提取音频时不能创建）
   onSelectTask: (taskId: string | null) => void;
   onCreateTask: () => void;
@@ -36,6 +38,7 @@ const TranscriptionHistory = ({
   selectedTaskId,
   resultContent,
   resourceName,
+  chapters,
   canCreateTask = true,
   onSelectTask,
   onCreateTask,
@@ -65,6 +68,10 @@ const TranscriptionHistory = ({
   const currentTaskLogs = useAppSelector(
     (state) => selectedTaskId ? (state.transcriptionLogs.logs[selectedTaskId] || []) : []
   );
+  // 当前任务压缩 / topics 提取的流式输出
+  const currentAIStream = useAppSelector(
+    (state) => selectedTaskId ? state.transcriptionLogs.aiStreams[selectedTaskId] : undefined
+  );
   // 使用 useMemo 缓存计算结果，避免每次渲染都重新计算
   const realtimeLog = useMemo(() => {
     if (!selectedTaskId || currentTaskLogs.length === 0) return '';
@@ -111,6 +118,58 @@ const TranscriptionHistory = ({
     return tasks.find(t => t.id === selectedTaskId) ?? null;
   }, [selectedTaskId, tasks]);
   
+  // URL 任务下载的字幕轨道
+  const [subtitleTracks, setSubtitleTracks] = useState<SubtitleTrack[]>([]);
+  const [isSwitchingTrack, setIsSwitchingTrack] = useState(false);
+  const selectedTaskStatus = selectedTask?.status;
+
+  useEffect(() => {
+    if (!selectedTaskId || selectedTaskStatus !== TranscriptionTaskStatus.COMPLETED) {
+      setSubtitleTracks([]);
+      return;
+    }
+    let isCancelled = false;
+    invoke<SubtitleTrack[]>('get_task_subtitle_tracks', { taskId: selectedTaskId })
+      .then((tracks) => {
+        if (!isCancelled) {
+          setSubtitleTracks(tracks);
+        }
+      })
+      .catch((err) => {
+        console.error('加载字幕轨道失败:', err);
+        if (!isCancelled) {
+          setSubtitleTracks([]);
+        }
+      });
+    return () => {
+      isCancelled = true;
+    };
+  }, [selectedTaskId, selectedTaskStatus]);
+
+  const primaryTrackId = subtitleTracks.find((t) => t.is_primary)?.id ?? '';
+
+  // 切换主字幕轨道（会清空基于旧轨道生成的压缩内容和 topics）
+  const handleSwitchTrack = async (trackId: string) => {
+    if (!selectedTaskId || !trackId || trackId === primaryTrackId) return;
+    try {
+      setIsSwitchingTrack(true);
+      const updatedTask = await invoke<TranscriptionTask>('set_primary_subtitle_track', {
+        taskId: selectedTaskId,
+        trackId,
+      });
+      setSubtitleTracks((prev) => prev.map((t) => ({ ...t, is_primary: t.id === trackId })));
+      if (onTaskUpdated) {
+        onTaskUpdated(selectedTaskId, updatedTask);
+      }
+      message.success('已切换字幕轨道');
+    } catch (err) {
+      console.error('切换字幕轨道失败:', err);
+      message.error(err instanceof Error ? err.message : '切换字幕轨道失败');
+    } finally {
+      setIsSwitchingTrack(false);
+    }
+  };
+
   // 删除任务
   const handleDeleteTask = async () => {
     if (!selectedTaskId) return;
@@ -286,6 +345,53 @@ const TranscriptionHistory = ({
     }
   };
 
+  // 导出转写结果为Markdown格式（有章节时包含章节大纲）
+  const handleExportMarkdown = async () => {
+    if (!resultContent || !selectedTask) {
+      message.error('没有可导出的转写结果');
+      return;
+    }
+
+    let jsonData: TranscriptionResultJson | null = null;
+    try {
+      jsonData = JSON.parse(resultContent) as TranscriptionResultJson;
+    } catch (e) {
+      message.error('转写结果格式不正确，无法导出');
+      return;
+    }
+
+    if (!jsonData || !jsonData.transcription || jsonData.transcription.length === 0) {
+      message.error('转写结果为空，无法导出');
+      return;
+    }
+
+    try {
+      const nameWithoutExt = resourceName?.replace(/\.[^/.]+$/, '') || '';
+      const markdownContent = convertToMarkdown(jsonData, nameWithoutExt, chapters);
+
+      const filePath = await save({
+        filters: [
+          {
+            name: 'Markdown文件',
+            extensions: ['md'],
+          },
+        ],
+        defaultPath: `${nameWithoutExt}.md`,
+      });
+
+      if (!filePath) {
+        // 用户取消了保存
+        return;
+      }
+
+      await writeTextFile(filePath, markdownContent);
+      message.success('Markdown文件导出成功');
+    } catch (err) {
+      console.error('导出Markdown失败:', err);
+      message.error(err instanceof Error ? err.message : '导出Markdown文件失败');
+    }
+  };
+
   // 点击外部关闭下拉菜单
   useEffect(() => {
     const handleClickOutside = (event: MouseEvent) => {
@@ -307,7 +413,7 @@ const TranscriptionHistory = ({
 
   // 当日志更新时，自动滚动到底部（使用节流优化性能）
   useEffect(() => {
-    if (!realtimeLog || !logEndRef.current || viewMode !== 'log') return;
+    if ((!realtimeLog && !currentAIStream) || !logEndRef.current || viewMode !== 'log') return;
     
     // 使用 requestAnimationFrame 优化滚动性能
     const timeoutId = setTimeout(() => {
@@ -317,7 +423,20 @@ const TranscriptionHistory = ({
     }, 100); // 增加延迟，减少滚动频率
     
     return () => clearTimeout(timeoutId);
-  }, [realtimeLog, viewMode]);
+  }, [realtimeLog, currentAIStream?.content, viewMode]);
+
+  // AI 阶段的流式输出预览
+  const aiStreamPreview = currentAIStream && currentAIStream.content ? (
+    <div className="mt-2 pt-2 border-t border-base-300">
+      <div className="text-xs text-base-content/70 mb-1">
+        {currentAIStream.stage === 'compression' ? '压缩输出' : 'Topics 输出'}
+        {currentAIStream.status === 'streaming' && <span className="loading loading-dots loading-xs ml-2 align-middle"></span>}
+        {currentAIStream.status === 'cancelled' && '（已中断）'}
+        {currentAIStream.status === 'error' && '（失败）'}
+      </div>
+      {currentAIStream.content}
+    </div>
+  ) : null;
 
   // 当视图模式切换时，关闭下拉菜单
   useEffect(() => {
@@ -421,6 +540,22 @@ const TranscriptionHistory = ({
               />
             </div>
           )}
+          {/* 字幕轨道切换：任务下载了多条字幕时显示 */}
+          {subtitleTracks.length > 1 && (
+            <div className="w-40 flex-shrink-0">
+              <Select
+                value={primaryTrackId}
+                options={subtitleTracks.map((track) => ({
+                  value: track.id,
+                  label: `${track.language}${track.kind === SubtitleTrackKind.AUTO ? '（自动）' : ''}`,
+                }))}
+                onChange={handleSwitchTrack}
+                disabled={isSwitchingTrack}
+                size="sm"
+                aria-label="选择字幕轨道"
+              />
+            </div>
+          )}
           {/* 【结果｜日志】切换按钮组 */}
           {selectedTask && 
            (selectedTask.status === TranscriptionTaskStatus.COMPLETED || 
@@ -546,7 +681,22 @@ const TranscriptionHistory = ({
                       }}
                     >
                       <HiArrowDownTray className="w-4 h-4" />
-                      下载
+                      下载 SRT
+                    </a>
+                  </li>
+                )}
+                {selectedTask.status === TranscriptionTaskStatus.COMPLETED && 
+                 (jsonData?.transcription?.length ?? 0) > 0 && (
+                  <li>
+                    <a
+                      onClick={(e) => {
+                        handleExportMarkdown();
+                        setIsDropdownOpen(false);
+                        e.preventDefault();
+                      }}
+                    >
+                      <HiArrowDownTray className="w-4 h-4" />
+                      下载 Markdown
                     </a>
                   </li>
                 )}
@@ -608,6 +758,7 @@ const TranscriptionHistory = ({
                       等待日志输出...
                     </div>
                   )}
+                  {aiStreamPreview}
                   <div ref={logEndRef} />
                 </div>
               </div>
@@ -702,6 +853,7 @@ const TranscriptionHistory = ({
                         : '暂无日志'}
                     </div>
                   )}
+                  {aiStreamPreview}
                   <div ref={logEndRef} />
                 </div>
               </div>
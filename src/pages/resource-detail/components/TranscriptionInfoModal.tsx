@@ -100,6 +100,12 @@ const TranscriptionInfoModal = ({ isOpen, data, onClose }: TranscriptionInfoModa
                     <span className="text-base-content/50">翻译:</span>
                     <span className="ml-2 font-medium">{data.params.translate ? '是' : '否'}</span>
                   </div>
+                  {data.params.preprocess?.filter_chain && (
+                    <div className="col-span-2">
+                      <span className="text-base-content/50">预处理滤镜链:</span>
+                      <code className="ml-2 text-xs break-all">{data.params.preprocess.filter_chain}</code>
+                    </div>
+                  )}
                 </div>
               </div>
             </div>
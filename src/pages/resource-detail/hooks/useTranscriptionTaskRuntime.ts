@@ -10,7 +10,7 @@ import {
 import { invoke } from '@tauri-apps/api/core'
 import { listen, UnlistenFn } from '@tauri-apps/api/event'
 import { TranscriptionTask, TranscriptionTaskStatus } from '../../../models'
-import { appendLog } from '../../../redux/slices/transcriptionLogsSlice'
+import { appendLog, updateAIStream } from '../../../redux/slices/transcriptionLogsSlice'
 import { AppDispatch } from '../../../redux/store'
 
 type UseTranscriptionTaskRuntimeParams = {
@@ -35,6 +35,7 @@ const useTranscriptionTaskRuntime = ({
   const unlistenRef = useRef<{
     stdout?: UnlistenFn
     stderr?: UnlistenFn
+    aiStream?: UnlistenFn
     taskId?: string
   }>({})
   const isSettingUpRef = useRef<boolean>(false)
@@ -69,6 +70,14 @@ const useTranscriptionTaskRuntime = ({
       }
       unlistenRef.current.stderr = undefined
     }
+    if (unlistenRef.current.aiStream) {
+      try {
+        unlistenRef.current.aiStream()
+      } catch (err) {
+        console.error('清理 AI 流式输出监听器失败:', err)
+      }
+      unlistenRef.current.aiStream = undefined
+    }
     unlistenRef.current.taskId = undefined
     isSettingUpRef.current = false
     settingUpTaskIdRef.current = null
@@ -108,6 +117,14 @@ const useTranscriptionTaskRuntime = ({
           }
           unlistenRef.current.stderr = undefined
         }
+        if (unlistenRef.current.aiStream) {
+          try {
+            unlistenRef.current.aiStream()
+          } catch (err) {
+            console.error('清理旧的 AI 流式输出监听器失败:', err)
+          }
+          unlistenRef.current.aiStream = undefined
+        }
         
         // 设置 taskId，这样回调函数可以检查
         unlistenRef.current.taskId = taskId
@@ -185,6 +202,21 @@ const useTranscriptionTaskRuntime = ({
           return
         }
 
+        // 压缩 / topics 提取的流式输出（失败时不影响日志监听）
+        try {
+          const capturedTaskId = taskId
+          unlistenRef.current.aiStream = await listen<{
+            stage: 'compression' | 'topics'
+            type: 'start' | 'delta' | 'done' | 'cancelled' | 'error'
+            content?: string
+          }>(`transcription-ai-stream-${taskId}`, (event) => {
+            if (unlistenRef.current.taskId !== capturedTaskId) return
+            dispatch(updateAIStream({ taskId: capturedTaskId, ...event.payload }))
+          })
+        } catch (err) {
+          console.error('[setupTaskListeners] 设置 AI 流式输出监听器失败:', err)
+        }
+
         // 设置完成，清除设置标志
         isSettingUpRef.current = false
         settingUpTaskIdRef.current = null
@@ -366,6 +398,33 @@ const useTranscriptionTaskRuntime = ({
     }
   }, [selectedTaskId, selectedTaskStatus, setupTaskListeners, cleanupTaskListeners])
 
+  // 切换主字幕轨道后任务结果指向新的文件，需要重新读取结果
+  const selectedTaskResult = useMemo(() => {
+    if (!selectedTaskId) return null
+    return tasks.find((item) => item.id === selectedTaskId)?.result ?? null
+  }, [selectedTaskId, tasks])
+  const previousSelectedTaskResultRef = useRef<{ taskId: string; result: string } | null>(null)
+
+  useEffect(() => {
+    if (!selectedTaskId || !selectedTaskResult || selectedTaskStatus !== TranscriptionTaskStatus.COMPLETED) return
+    const previous = previousSelectedTaskResultRef.current
+    previousSelectedTaskResultRef.current = { taskId: selectedTaskId, result: selectedTaskResult }
+    if (!previous || previous.taskId !== selectedTaskId || previous.result === selectedTaskResult) return
+
+    let isCancelled = false
+    invoke<string>('read_transcription_result', { taskId: selectedTaskId })
+      .then((content) => {
+        if (!isCancelled) {
+          setResultContent(content)
+        }
+      })
+      .catch((err) => console.error('读取结果失败:', err))
+
+    return () => {
+      isCancelled = true
+    }
+  }, [selectedTaskId, selectedTaskStatus, selectedTaskResult])
+
   useEffect(() => {
     return () => {
       if (unlistenRef.current.stdout) {
@@ -374,8 +433,12 @@ const useTranscriptionTaskRuntime = ({
       if (unlistenRef.current.stderr) {
         unlistenRef.current.stderr()
       }
+      if (unlistenRef.current.aiStream) {
+        unlistenRef.current.aiStream()
+      }
       unlistenRef.current.stdout = undefined
       unlistenRef.current.stderr = undefined
+      unlistenRef.current.aiStream = undefined
       unlistenRef.current.taskId = undefined
       isSettingUpRef.current = false
       settingUpTaskIdRef.current = null
@@ -9,7 +9,7 @@ import { convertAIMessagesToChatMessages } from '../../utils/aiMessageUtils'
 
 export class TauriReActBackend implements IReActBackend {
   async chatCompletion(options: IChatCompletionOptions): Promise<void> {
-    const { configId, messages, tools, systemMessage, eventId } = options
+    const { configId, messages, tools, systemMessage, eventId, chatId } = options
 
     const chatMessages = convertAIMessagesToChatMessages(messages as any)
 
@@ -19,6 +19,7 @@ export class TauriReActBackend implements IReActBackend {
       tools: tools && tools.length > 0 ? tools : null,
       systemMessage,
       eventId,
+      chatId: chatId || null,
     })
   }
 
@@ -34,10 +35,11 @@ export class TauriReActBackend implements IReActBackend {
       arguments: args,
       currentResourceId: context?.currentResourceId || null,
       currentTaskId: context?.currentTaskId || null,
+      chatId: context?.chatId || null,
     })
   }
 
-  async saveMessage(message: AIMessage, chatId: string): Promise<void> {
+  async saveMessage(message: AIMessage, chatId: string, eventId?: string): Promise<void> {
     await invoke('save_message', {
       chatId,
       role: message.role,
@@ -46,6 +48,7 @@ export class TauriReActBackend implements IReActBackend {
       toolCallId: message.tool_call_id || null,
       name: message.name || null,
       reasoning: message.reasoning || null,
+      eventId: eventId || null,
     })
   }
 
@@ -13,6 +13,7 @@ export interface IChatCompletionOptions {
   tools?: any[]
   systemMessage: string
   eventId: string
+  chatId?: string // 所属对话，后端用于上下文摘要和用量统计
 }
 
 /**
@@ -48,8 +49,9 @@ export interface IReActBackend {
 
   /**
    * 保存消息
+   * eventId 为生成该助手消息的流式对话事件 ID，用于关联 token 用量
    */
-  saveMessage(message: AIMessage, chatId: string): Promise<void>
+  saveMessage(message: AIMessage, chatId: string, eventId?: string): Promise<void>
 
   /**
    * 监听流式响应
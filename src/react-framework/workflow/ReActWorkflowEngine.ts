@@ -218,7 +218,7 @@ export class ReActWorkflowEngine {
                 ...(finalAction && { action: finalAction }),
               }
               this.backend
-                .saveMessage(msgToSave, chatId)
+                .saveMessage(msgToSave, chatId, eventId)
                 .catch(console.error)
             }
             resolve({
@@ -252,6 +252,7 @@ export class ReActWorkflowEngine {
           tools: tools.length > 0 ? tools : undefined,
           systemMessage,
           eventId,
+          chatId,
         })
       } catch (error) {
         reject(error)
@@ -264,6 +265,7 @@ export class ReActWorkflowEngine {
    */
   private async executeToolCall(
     toolCall: ToolCall,
+    chatId: string,
     currentResourceId?: string | null,
     currentTaskId?: string | null,
   ): Promise<string> {
@@ -288,6 +290,7 @@ export class ReActWorkflowEngine {
       {
         currentResourceId: currentResourceId || null,
         currentTaskId: currentTaskId || null,
+        chatId,
       },
     )
 
@@ -591,6 +594,7 @@ export class ReActWorkflowEngine {
             try {
               const toolResult = await this.executeToolCall(
                 toolCall,
+                chatId,
                 currentResourceId,
                 currentTaskId,
               )
@@ -675,6 +679,7 @@ export class ReActWorkflowEngine {
       try {
         const toolResult = await this.executeToolCall(
           toolCall,
+          chatId,
           currentResourceId,
           currentTaskId,
         )
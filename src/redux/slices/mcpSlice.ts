@@ -57,6 +57,14 @@ const mcpSlice = createSlice({
       state.servers = action.payload;
       state.lastUpdated = Date.now();
     },
+    // 后台健康检查推送的单个服务器状态（mcp-status-changed 事件）
+    updateServer: (state, action: PayloadAction<MCPServerInfo>) => {
+      const index = state.servers.findIndex((server) => server.key === action.payload.key);
+      if (index >= 0) {
+        state.servers[index] = { ...action.payload, is_default: state.servers[index].is_default };
+        state.lastUpdated = Date.now();
+      }
+    },
   },
   extraReducers: (builder) => {
     builder
@@ -93,6 +101,6 @@ const mcpSlice = createSlice({
   },
 });
 
-export const { clearError, setServers } = mcpSlice.actions;
+export const { clearError, setServers, updateServer } = mcpSlice.actions;
 export default mcpSlice.reducer;
 
@@ -1,12 +1,22 @@
 import { createSlice, PayloadAction } from '@reduxjs/toolkit';
 
+// 压缩 / topics 提取的流式输出
+export interface TranscriptionAIStream {
+  stage: 'compression' | 'topics';
+  content: string;
+  status: 'streaming' | 'done' | 'cancelled' | 'error';
+}
+
 export interface TranscriptionLogsState {
   // 按 taskId 存储日志，每个任务的日志是一个字符串数组
   logs: Record<string, string[]>;
+  // 按 taskId 存储当前 AI 阶段的流式输出
+  aiStreams: Record<string, TranscriptionAIStream>;
 }
 
 const initialState: TranscriptionLogsState = {
   logs: {},
+  aiStreams: {},
 };
 
 // 限制每个任务的日志条数，避免内存无限增长
@@ -37,20 +47,46 @@ const transcriptionLogsSlice = createSlice({
       }
       // 如果 log 为空，至少确保数组已初始化（通过上面的 if 语句）
     },
+    // 处理 AI 阶段的流式事件（start 时重置内容）
+    updateAIStream: (
+      state,
+      action: PayloadAction<{
+        taskId: string;
+        stage: TranscriptionAIStream['stage'];
+        type: 'start' | 'delta' | TranscriptionAIStream['status'];
+        content?: string;
+      }>,
+    ) => {
+      const { taskId, stage, type, content } = action.payload;
+      const current = state.aiStreams[taskId];
+      if (type === 'start' || !current || current.stage !== stage) {
+        state.aiStreams[taskId] = { stage, content: '', status: 'streaming' };
+      }
+      const stream = state.aiStreams[taskId];
+      if (type === 'delta') {
+        stream.content += content ?? '';
+      } else if (type !== 'start') {
+        stream.status = type;
+      }
+    },
     // 清空指定任务的日志
     clearLogs: (state, action: PayloadAction<string>) => {
       const taskId = action.payload;
       if (state.logs[taskId]) {
         delete state.logs[taskId];
       }
+      if (state.aiStreams[taskId]) {
+        delete state.aiStreams[taskId];
+      }
     },
     // 清空所有日志
     clearAllLogs: (state) => {
       state.logs = {};
+      state.aiStreams = {};
     },
   },
 });
 
-export const { appendLog, clearLogs, clearAllLogs } = transcriptionLogsSlice.actions;
+export const { appendLog, updateAIStream, clearLogs, clearAllLogs } = transcriptionLogsSlice.actions;
 export default transcriptionLogsSlice.reducer;
 
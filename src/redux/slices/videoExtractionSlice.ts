@@ -1,9 +1,11 @@
 import { createSlice, PayloadAction } from '@reduxjs/toolkit';
+import { ExtractionProgress } from '../../models';
 
 export interface VideoExtractionState {
   // 按 resourceId 存储提取状态
   extractions: Record<string, {
     isExtracting: boolean;
+    progress?: ExtractionProgress; // 最近一次收到的提取进度
   }>;
 }
 
@@ -22,6 +24,17 @@ const videoExtractionSlice = createSlice({
         state.extractions[resourceId] = { isExtracting: false };
       }
       state.extractions[resourceId].isExtracting = isExtracting;
+      if (!isExtracting) {
+        delete state.extractions[resourceId].progress;
+      }
+    },
+    // 更新提取进度
+    setExtractionProgress: (state, action: PayloadAction<ExtractionProgress>) => {
+      const progress = action.payload;
+      if (!state.extractions[progress.resource_id]) {
+        state.extractions[progress.resource_id] = { isExtracting: true };
+      }
+      state.extractions[progress.resource_id].progress = progress;
     },
     // 清除提取状态
     clearExtraction: (state, action: PayloadAction<string>) => {
@@ -33,6 +46,6 @@ const videoExtractionSlice = createSlice({
   },
 });
 
-export const { setExtracting, clearExtraction } = videoExtractionSlice.actions;
+export const { setExtracting, setExtractionProgress, clearExtraction } = videoExtractionSlice.actions;
 export default videoExtractionSlice.reducer;
 
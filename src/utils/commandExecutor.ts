@@ -17,6 +17,8 @@ export interface CommandExecutorOptions {
   onStderr?: (line: string) => void;
   // 是否自动清理事件监听器（默认 true）
   autoCleanup?: boolean;
+  // 超时时间（秒），不能超过后端为该命令设置的上限
+  timeoutSecs?: number;
 }
 
 // 命令执行器类
@@ -27,7 +29,7 @@ export class CommandExecutor {
   /**
    * 执行命令
    * 
-   * @param command 命令路径
+   * @param command 命令名称（仅支持 whisper-cli、ffmpeg、yt-dlp）
    * @param args 命令参数
    * @param options 执行选项
    * @returns 执行结果
@@ -35,7 +37,7 @@ export class CommandExecutor {
    * @example
    * ```ts
    * const executor = new CommandExecutor();
-   * const result = await executor.execute('ls', ['-la'], {
+   * const result = await executor.execute('ffmpeg', ['-version'], {
    *   onStdout: (line) => console.log('stdout:', line),
    *   onStderr: (line) => console.error('stderr:', line),
    * });
@@ -51,6 +53,7 @@ export class CommandExecutor {
       onStdout,
       onStderr,
       autoCleanup = true,
+      timeoutSecs,
     } = options;
 
     try {
@@ -84,6 +87,7 @@ export class CommandExecutor {
         args,
         eventId,
         workingDir: workingDir || null,
+        timeoutSecs: timeoutSecs ?? null,
       });
 
       // 如果设置了自动清理，清理事件监听器
@@ -113,6 +117,16 @@ export class CommandExecutor {
     this.currentEventId = null;
   }
 
+  /**
+   * 取消正在执行的命令
+   */
+  async cancel(): Promise<boolean> {
+    if (!this.currentEventId) {
+      return false;
+    }
+    return invoke<boolean>('cancel_command', { eventId: this.currentEventId });
+  }
+
   /**
    * 获取当前事件 ID
    */
@@ -151,3 +165,9 @@ export async function executeCommandWithOutput(
   });
 }
 
+
+// 通过对话框选择命令使用的文件或目录
+// 命令只能访问应用数据目录和通过此函数选择的路径，取消选择时返回 null
+export async function pickCommandPath(mode: 'open' | 'save' | 'folder' = 'open'): Promise<string | null> {
+  return invoke<string | null>('pick_command_path', { mode });
+}
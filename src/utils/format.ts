@@ -30,3 +30,16 @@ export const formatDateTime = (date: string | Date): string => {
   return dayjs(date).format('YYYY-MM-DD HH:mm:ss')
 }
 
+
+/**
+ * 格式化时长（秒）为中文描述
+ * @param seconds 秒数
+ * @returns 如 "45 秒"、"3 分 20 秒"、"1 小时 5 分"
+ */
+export const formatDuration = (seconds: number): string => {
+  const total = Math.max(0, Math.round(seconds))
+  if (total < 60) return `${total} 秒`
+  const minutes = Math.floor(total / 60)
+  if (minutes < 60) return `${minutes} 分 ${total % 60} 秒`
+  return `${Math.floor(minutes / 60)} 小时 ${minutes % 60} 分`
+}
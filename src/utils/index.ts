@@ -1,4 +1,4 @@
-export { CommandExecutor, executeCommand, executeCommandWithOutput } from './commandExecutor';
+export { CommandExecutor, executeCommand, executeCommandWithOutput, pickCommandPath } from './commandExecutor';
 export type { CommandExecutorOptions } from './commandExecutor';
 export { formatSubtitleTime as formatTime } from './format';
 export { wait } from './wait';
@@ -0,0 +1,67 @@
+import { TranscriptionResultJson } from '../models/TranscriptionResult';
+import { Chapter } from '../models';
+
+/**
+ * 将 HH:MM:SS,mmm 格式的时间戳解析为秒数
+ */
+function timestampToSeconds(timeStr: string): number {
+  const match = timeStr.match(/^(\d+):(\d{2}):(\d{2})[,.](\d{1,3})$/);
+  if (!match) {
+    const seconds = parseFloat(timeStr);
+    return isNaN(seconds) ? 0 : seconds;
+  }
+  const [, hours, minutes, secs, ms] = match;
+  return Number(hours) * 3600 + Number(minutes) * 60 + Number(secs) + Number(ms.padEnd(3, '0')) / 1000;
+}
+
+/**
+ * 将秒数格式化为 HH:MM:SS
+ */
+function formatSeconds(seconds: number): string {
+  const hours = Math.floor(seconds / 3600);
+  const minutes = Math.floor((seconds % 3600) / 60);
+  const secs = Math.floor(seconds % 60);
+  return `${String(hours).padStart(2, '0')}:${String(minutes).padStart(2, '0')}:${String(secs).padStart(2, '0')}`;
+}
+
+/**
+ * 将转写结果转换为 Markdown 格式
+ * 如果资源有章节标记，先输出章节大纲，并在正文中按章节分段
+ * @param jsonData 转写结果的JSON数据
+ * @param title 文档标题（资源名称）
+ * @param chapters 章节标记
+ * @returns Markdown 格式的字符串
+ */
+export function convertToMarkdown(
+  jsonData: TranscriptionResultJson,
+  title?: string,
+  chapters: Chapter[] = []
+): string {
+  const segments = jsonData.transcription ?? [];
+  const sortedChapters = [...chapters].sort((a, b) => a.start_time - b.start_time);
+  const lines: string[] = [`# ${title || '转写结果'}`, ''];
+
+  if (sortedChapters.length > 0) {
+    lines.push('## 大纲', '');
+    sortedChapters.forEach((chapter) => {
+      lines.push(`- [${formatSeconds(chapter.start_time)}] ${chapter.title}`);
+    });
+    lines.push('', '## 转写内容', '');
+  }
+
+  let chapterIndex = -1;
+  segments.forEach((segment) => {
+    const start = timestampToSeconds(segment.timestamps.from);
+    // 进入新章节时输出章节标题
+    while (chapterIndex + 1 < sortedChapters.length && sortedChapters[chapterIndex + 1].start_time <= start) {
+      chapterIndex += 1;
+      lines.push('', `### ${sortedChapters[chapterIndex].title}`, '');
+    }
+    const text = segment.text.trim();
+    if (text) {
+      lines.push(`[${formatSeconds(start)}] ${text}  `);
+    }
+  });
+
+  return lines.join('\n').replace(/\n{3,}/g, '\n\n') + '\n';
+}
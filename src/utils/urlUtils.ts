@@ -45,6 +45,26 @@ export function isUrl(path: string): boolean {
   return pathLower.startsWith('http://') || pathLower.startsWith('https://');
 }
 
+/**
+ * 检测是否为播放列表或频道 URL（YouTube 播放列表 / 频道，Bilibili 空间 / 合集 / 收藏夹）
+ * 带 list= 参数的单个视频链接也视为播放列表
+ * @param url URL字符串
+ * @returns 是否为播放列表或频道
+ */
+export function isPlaylistUrl(url: string): boolean {
+  const urlLower = url.toLowerCase();
+  if (urlLower.includes('youtube.com') || urlLower.includes('youtu.be')) {
+    return /[?&]list=/.test(urlLower) ||
+      /youtube\.com\/(@[^/?#]+|channel\/|c\/|user\/)/.test(urlLower);
+  }
+  if (urlLower.includes('bilibili.com')) {
+    return urlLower.includes('space.bilibili.com') ||
+      /bilibili\.com\/(medialist|list|festival)\//.test(urlLower) ||
+      /[?&](fid|sid|media_id)=/.test(urlLower);
+  }
+  return false;
+}
+
 /**
  * 验证URL格式
  * @param url URL字符串